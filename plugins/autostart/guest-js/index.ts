@@ -4,14 +4,58 @@
 
 import { invoke } from "@tauri-apps/api/tauri";
 
+/**
+ * Whether there is an autostart entry for the app, even if it is stale, see
+ * {@link status}.
+ */
 export async function isEnabled(): Promise<boolean> {
   return await invoke("plugin:autostart|is_enabled");
 }
 
-export async function enable(): Promise<void> {
-  await invoke("plugin:autostart|enable");
+/**
+ * Whether autostart is enabled. `stale` means its entry launches the app from
+ * another path, e.g. after the app was moved, so it no longer starts it.
+ */
+export async function status(): Promise<"disabled" | "enabled" | "stale"> {
+  return await invoke("plugin:autostart|status");
+}
+
+/**
+ * Rewrites the autostart entry with the current path of the app if it is
+ * stale. Resolves to whether it was.
+ */
+export async function repair(): Promise<boolean> {
+  return await invoke("plugin:autostart|repair");
+}
+
+/**
+ * Enables autostart. If `args` are given, they replace the arguments passed to
+ * the app on startup. An existing autostart entry is always rewritten, so it
+ * launches the app from its current path.
+ */
+export async function enable(args?: string[]): Promise<void> {
+  await invoke("plugin:autostart|enable", { args });
 }
 
 export async function disable(): Promise<void> {
   await invoke("plugin:autostart|disable");
 }
+
+/**
+ * Replaces the arguments passed to the app on startup. The autostart entry is
+ * rewritten with them if autostart is enabled, otherwise they are used the
+ * next time it is enabled.
+ */
+export async function setAutostartArgs(args: string[]): Promise<void> {
+  await invoke("plugin:autostart|set_args", { args });
+}
+
+/**
+ * The arguments of the registered autostart entry, or the ones used the next
+ * time autostart is enabled if it is disabled. Login items created with the
+ * AppleScript launcher on macOS can't have arguments, so they are empty there
+ * while autostart is enabled.
+ */
+export async function getAutostartArgs(): Promise<string[]> {
+  return await invoke("plugin:autostart|get_args");
+}
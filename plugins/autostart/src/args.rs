@@ -0,0 +1,337 @@
+// Copyright 2019-2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Quoting of the launch command for each platform's autostart entry, and
+//! reading the registered command back. `auto-launch` joins the path and the
+//! arguments with spaces as they are, so they are quoted before being passed to it.
+
+/// Quotes the app path for the autostart entry.
+pub(crate) fn quote_path(path: &str) -> String {
+    // macOS checks that the path exists, it is written as is
+    if cfg!(target_os = "macos") {
+        path.to_string()
+    } else {
+        quote_arg(path)
+    }
+}
+
+/// Quotes a launch argument for the autostart entry.
+#[cfg(target_os = "linux")]
+pub(crate) fn quote_arg(arg: &str) -> String {
+    // the quoting rules of the `Exec` key of desktop entries
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(',
+        ')', '`',
+    ];
+    let arg = arg.replace('%', "%%");
+    let arg = if arg.is_empty() || arg.contains(RESERVED) {
+        let mut quoted = String::from('"');
+        for c in arg.chars() {
+            if matches!(c, '"' | '`' | '$' | '\\') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    } else {
+        arg
+    };
+    // the escapes of desktop entry strings, applied on top of the quoting
+    arg.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+/// Quotes a launch argument for the autostart entry.
+#[cfg(windows)]
+pub(crate) fn quote_arg(arg: &str) -> String {
+    // the rules of `CommandLineToArgvW`, which most programs parse their
+    // command line with
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes a launch argument for the autostart entry.
+#[cfg(target_os = "macos")]
+pub(crate) fn quote_arg(arg: &str) -> String {
+    // each argument is a `<string>` of the launch agent's property list
+    arg.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Reads the registered command, the app path followed by the arguments, or
+/// `None` if there is no autostart entry for `app_name`.
+#[cfg(target_os = "linux")]
+pub(crate) fn registered_command(
+    app_name: &str,
+    _use_launch_agent: bool,
+) -> std::io::Result<Option<Vec<String>>> {
+    let home = match tauri::api::path::home_dir() {
+        Some(home) => home,
+        None => return Ok(None),
+    };
+    let file = home
+        .join(".config")
+        .join("autostart")
+        .join(format!("{app_name}.desktop"));
+    let entry = match std::fs::read_to_string(file) {
+        Ok(entry) => entry,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(entry
+        .lines()
+        .find_map(|line| line.strip_prefix("Exec="))
+        .map(split_exec))
+}
+
+/// Splits the value of a desktop entry `Exec` key into its arguments.
+#[cfg(target_os = "linux")]
+fn split_exec(exec: &str) -> Vec<String> {
+    let mut unescaped = String::new();
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('s') => unescaped.push(' '),
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some('r') => unescaped.push('\r'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+
+    let mut args = Vec::new();
+    let mut chars = unescaped.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ').is_some() {}
+        let first = match chars.next() {
+            Some(first) => first,
+            None => break,
+        };
+        let mut arg = String::new();
+        if first == '"' {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => arg.extend(chars.next()),
+                    _ => arg.push(c),
+                }
+            }
+        } else {
+            arg.push(first);
+            while let Some(c) = chars.next_if(|c| *c != ' ') {
+                arg.push(c);
+            }
+        }
+        args.push(arg.replace("%%", "%"));
+    }
+    args
+}
+
+/// Reads the registered command, the app path followed by the arguments, or
+/// `None` if there is no autostart entry for `app_name`.
+#[cfg(windows)]
+pub(crate) fn registered_command(
+    app_name: &str,
+    _use_launch_agent: bool,
+) -> std::io::Result<Option<Vec<String>>> {
+    use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+
+    let command = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run")?
+        .get_value::<String, _>(app_name);
+    match command {
+        Ok(command) => Ok(Some(split_command_line(&command))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Splits a command line into its arguments like `CommandLineToArgvW`.
+#[cfg(windows)]
+fn split_command_line(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = command.chars().peekable();
+    loop {
+        while chars.next_if(|c| matches!(c, ' ' | '\t')).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut arg = String::new();
+        let mut in_quotes = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    let mut backslashes = 1;
+                    while chars.next_if(|c| *c == '\\').is_some() {
+                        backslashes += 1;
+                    }
+                    if chars.peek() == Some(&'"') {
+                        arg.extend(std::iter::repeat('\\').take(backslashes / 2));
+                        if backslashes % 2 == 1 {
+                            arg.push('"');
+                            chars.next();
+                        }
+                    } else {
+                        arg.extend(std::iter::repeat('\\').take(backslashes));
+                    }
+                }
+                '"' if in_quotes && chars.peek() == Some(&'"') => {
+                    arg.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = !in_quotes,
+                ' ' | '\t' if !in_quotes => break,
+                _ => arg.push(c),
+            }
+        }
+        args.push(arg);
+    }
+    args
+}
+
+/// Reads the registered command, the app path followed by the arguments, or
+/// `None` if there is no autostart entry for `app_name`.
+///
+/// Login items created with AppleScript have no arguments, only the path is
+/// returned for them.
+#[cfg(target_os = "macos")]
+pub(crate) fn registered_command(
+    app_name: &str,
+    use_launch_agent: bool,
+) -> std::io::Result<Option<Vec<String>>> {
+    if !use_launch_agent {
+        let output = std::process::Command::new("osascript")
+            .args([
+                "-e",
+                &format!(
+                    "tell application \"System Events\" to get the path of login item \"{app_name}\""
+                ),
+            ])
+            .output()?;
+        return Ok(output.status.success().then(|| {
+            vec![String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string()]
+        }));
+    }
+
+    let home = match tauri::api::path::home_dir() {
+        Some(home) => home,
+        None => return Ok(None),
+    };
+    let file = home
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{app_name}.plist"));
+    let plist = match std::fs::read_to_string(file) {
+        Ok(plist) => plist,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let arguments = plist
+        .split_once("<key>ProgramArguments</key>")
+        .and_then(|(_, rest)| rest.split_once("</array>"))
+        .map_or("", |(array, _)| array);
+    Ok(Some(
+        arguments
+            .split("<string>")
+            .skip(1)
+            .filter_map(|s| s.split_once("</string>"))
+            .map(|(arg, _)| {
+                arg.replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&")
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn quotes_desktop_entry_arguments() {
+        let cases = [
+            ("--minimized", "--minimized"),
+            ("", r#""""#),
+            ("two words", r#""two words""#),
+            ("it's", r#""it's""#),
+            (r#"say "hi""#, r#""say \\"hi\\"""#),
+            ("100%", "100%%"),
+            ("$HOME", r#""\\$HOME""#),
+            (r"C:\dir", r#""C:\\\\dir""#),
+            ("a\tb", r#""a\tb""#),
+        ];
+        for (arg, quoted) in cases {
+            assert_eq!(quote_arg(arg), quoted, "{arg:?}");
+            assert_eq!(split_exec(&quote_arg(arg)), [arg], "{arg:?}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn splits_exec_keys() {
+        let args = ["/opt/My App/app", "--flag", "", "50%", "$x", r#""q""#];
+        let exec = args.map(quote_arg).join(" ");
+        assert_eq!(split_exec(&exec), args);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn quotes_command_line_arguments() {
+        let cases = [
+            ("--minimized", "--minimized"),
+            ("", r#""""#),
+            ("two words", r#""two words""#),
+            (r#"say "hi""#, r#""say \"hi\"""#),
+            (r"C:\dir\", r"C:\dir\"),
+            (r"C:\my dir\", r#""C:\my dir\\""#),
+            ("100%", "100%"),
+            ("$HOME", "$HOME"),
+        ];
+        for (arg, quoted) in cases {
+            assert_eq!(quote_arg(arg), quoted, "{arg:?}");
+            assert_eq!(split_command_line(&quote_arg(arg)), [arg], "{arg:?}");
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn escapes_property_list_strings() {
+        assert_eq!(quote_arg("a & <b>"), "a &amp; &lt;b&gt;");
+        assert_eq!(quote_arg("two words %s $x"), "two words %s $x");
+    }
+}
@@ -8,20 +8,54 @@ use log::info;
 use serde::{ser::Serializer, Serialize};
 use tauri::{
     command,
-    plugin::{Builder, TauriPlugin},
+    plugin::{Builder as PluginBuilder, TauriPlugin},
     Manager, Runtime, State,
 };
 
-use std::env::current_exe;
+#[cfg(target_os = "macos")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env::current_exe, sync::Mutex};
+#[cfg(target_os = "macos")]
+use tauri::RunEvent;
+
+mod args;
+#[cfg(target_os = "linux")]
+mod systemd;
 
 type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone)]
 pub enum MacosLauncher {
+    #[default]
     LaunchAgent,
     AppleScript,
 }
 
+/// How autostart is registered on Linux.
+#[derive(Debug, Default, Copy, Clone)]
+pub enum LinuxLauncher {
+    /// A desktop entry in `~/.config/autostart`, started by desktop
+    /// environments following the XDG autostart specification.
+    #[default]
+    XdgAutostart,
+    /// A systemd user unit in `~/.config/systemd/user`, started with the
+    /// graphical session and restarted if the app crashes. Falls back to
+    /// [`Self::XdgAutostart`] on systems without systemd.
+    Systemd,
+}
+
+/// Whether autostart is enabled, see [`AutoLaunchManager::status`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+    Disabled,
+    Enabled,
+    /// The autostart entry launches the app from another path, e.g. after the
+    /// app was moved or updated to another install location, so it no longer
+    /// starts it.
+    Stale,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -39,29 +73,295 @@ impl Serialize for Error {
     }
 }
 
-pub struct AutoLaunchManager(AutoLaunch);
+pub struct AutoLaunchManager {
+    app_name: String,
+    app_path: String,
+    macos_launcher: MacosLauncher,
+    #[cfg(target_os = "linux")]
+    linux_launcher: LinuxLauncher,
+    launch_hidden: bool,
+    args: Mutex<Vec<String>>,
+}
 
 impl AutoLaunchManager {
+    /// Whether autostart is registered with a systemd user unit.
+    #[cfg(target_os = "linux")]
+    fn uses_systemd(&self) -> bool {
+        matches!(self.linux_launcher, LinuxLauncher::Systemd) && systemd::is_available()
+    }
+
+    /// The app path followed by the arguments.
+    #[cfg(target_os = "linux")]
+    fn command(&self) -> Vec<String> {
+        std::iter::once(self.app_path.clone())
+            .chain(self.entry_args())
+            .collect()
+    }
+
+    /// The arguments of the autostart entry, with [`HIDDEN_ARG`] appended if
+    /// the app is launched hidden.
+    fn entry_args(&self) -> Vec<String> {
+        let mut args = self.args.lock().unwrap().clone();
+        if self.launch_hidden && !args.iter().any(|arg| arg == HIDDEN_ARG) {
+            args.push(HIDDEN_ARG.into());
+        }
+        args
+    }
+
+    /// The arguments configured for the registered `command`, without the
+    /// ones the plugin adds.
+    fn configured_args(&self, command: Vec<String>) -> Vec<String> {
+        let mut args = command.into_iter().skip(1).collect::<Vec<_>>();
+        #[cfg(target_os = "macos")]
+        if let Some(i) = args.iter().position(|arg| arg == "--args") {
+            args.drain(..=i);
+        }
+        if self.launch_hidden && args.last().map(String::as_str) == Some(HIDDEN_ARG) {
+            args.pop();
+        }
+        args
+    }
+
+    /// The app bundle to launch hidden with `open`, if the app is launched
+    /// hidden by a launch agent and runs from a bundle.
+    #[cfg(target_os = "macos")]
+    fn hidden_bundle(&self) -> Option<String> {
+        if !self.launch_hidden || !matches!(self.macos_launcher, MacosLauncher::LaunchAgent) {
+            return None;
+        }
+        let (bundle, _) = self.app_path.split_once(".app/")?;
+        Some(format!("{bundle}.app"))
+    }
+
+    /// The path of the app the autostart entry launches.
+    fn launched_path(&self) -> String {
+        #[cfg(target_os = "macos")]
+        if let Some(bundle) = self.hidden_bundle() {
+            return bundle;
+        }
+        self.app_path.clone()
+    }
+
+    /// The registered command, the app path followed by the arguments, or
+    /// `None` if there is no autostart entry.
+    fn registered_command(&self) -> Result<Option<Vec<String>>> {
+        #[cfg(target_os = "linux")]
+        if self.uses_systemd() {
+            return Ok(systemd::registered_command(&self.app_name)?);
+        }
+        let auto_launch = self.auto_launch()?;
+        Ok(args::registered_command(
+            auto_launch.get_app_name(),
+            matches!(self.macos_launcher, MacosLauncher::LaunchAgent),
+        )?)
+    }
+
+    fn auto_launch(&self) -> Result<AutoLaunch> {
+        #[allow(unused_mut)]
+        let mut path = args::quote_path(&self.app_path);
+        #[allow(unused_mut)]
+        let mut entry_args = self.entry_args();
+        // `open -j` launches the app hidden, like the hidden flag of login items
+        #[cfg(target_os = "macos")]
+        if let Some(bundle) = self.hidden_bundle() {
+            entry_args = ["-j", "-g", "-a", &bundle, "--args"]
+                .into_iter()
+                .map(String::from)
+                .chain(entry_args)
+                .collect();
+            path = OPEN.into();
+        }
+        let args = entry_args
+            .iter()
+            .map(|arg| args::quote_arg(arg))
+            .collect::<Vec<_>>();
+        AutoLaunchBuilder::new()
+            .set_app_name(&self.app_name)
+            .set_app_path(&path)
+            .set_args(&args)
+            .set_use_launch_agent(matches!(self.macos_launcher, MacosLauncher::LaunchAgent))
+            .build()
+            .map_err(|e| e.to_string())
+            .map_err(Error::Anyhow)
+    }
+
+    /// Enables autostart. An existing autostart entry is always rewritten, so
+    /// it launches the app from its current path.
     pub fn enable(&self) -> Result<()> {
-        self.0
+        let auto_launch = self.auto_launch()?;
+        // the entry of the other Linux launcher would start a second instance
+        #[cfg(target_os = "linux")]
+        if self.uses_systemd() {
+            auto_launch
+                .disable()
+                .map_err(|e| e.to_string())
+                .map_err(Error::Anyhow)?;
+            return Ok(systemd::enable(&self.app_name, &self.command())?);
+        } else {
+            systemd::disable(&self.app_name)?;
+        }
+        // AppleScript adds another login item instead of replacing the existing one
+        #[cfg(target_os = "macos")]
+        if matches!(self.macos_launcher, MacosLauncher::AppleScript) && self.is_enabled()? {
+            self.disable()?;
+        }
+        auto_launch
             .enable()
             .map_err(|e| e.to_string())
             .map_err(Error::Anyhow)
     }
 
+    /// Replaces the arguments passed to the app on startup, then enables
+    /// autostart with them.
+    pub fn enable_with_args(
+        &self,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<()> {
+        *self.args.lock().unwrap() = args.into_iter().map(Into::into).collect();
+        self.enable()
+    }
+
     pub fn disable(&self) -> Result<()> {
-        self.0
+        #[cfg(target_os = "linux")]
+        systemd::disable(&self.app_name)?;
+        self.auto_launch()?
             .disable()
             .map_err(|e| e.to_string())
             .map_err(Error::Anyhow)
     }
 
+    /// Whether there is an autostart entry for the app, even if it is stale,
+    /// see [`Self::status`].
     pub fn is_enabled(&self) -> Result<bool> {
-        self.0
+        #[cfg(target_os = "linux")]
+        if self.uses_systemd() {
+            return Ok(systemd::is_enabled(&self.app_name)?);
+        }
+        self.auto_launch()?
             .is_enabled()
             .map_err(|e| e.to_string())
             .map_err(Error::Anyhow)
     }
+
+    /// Replaces the arguments passed to the app on startup. The autostart
+    /// entry is rewritten with them if autostart is enabled, otherwise they
+    /// are used by the next [`Self::enable`].
+    pub fn set_args(&self, args: impl IntoIterator<Item = impl Into<String>>) -> Result<()> {
+        let enabled = self.is_enabled()?;
+        *self.args.lock().unwrap() = args.into_iter().map(Into::into).collect();
+        if enabled {
+            self.enable()?;
+        }
+        Ok(())
+    }
+
+    /// The arguments of the registered autostart entry, or the ones the next
+    /// [`Self::enable`] uses if autostart is disabled.
+    ///
+    /// Login items created with [`MacosLauncher::AppleScript`] can't have
+    /// arguments, so they are always empty while it is enabled.
+    pub fn args(&self) -> Result<Vec<String>> {
+        match self.registered_command()? {
+            Some(command) => Ok(self.configured_args(command)),
+            None => Ok(self.args.lock().unwrap().clone()),
+        }
+    }
+
+    /// Whether autostart is enabled, and if so whether its entry still
+    /// launches the app from its current path.
+    pub fn status(&self) -> Result<Status> {
+        if !self.is_enabled()? {
+            return Ok(Status::Disabled);
+        }
+        let command = match self.registered_command()? {
+            Some(command) => command,
+            None => return Ok(Status::Enabled),
+        };
+        match registered_path(&command) {
+            Some(path) if !same_path(path, &self.launched_path()) => Ok(Status::Stale),
+            _ => Ok(Status::Enabled),
+        }
+    }
+
+    /// Rewrites the autostart entry with the current path of the app if it is
+    /// stale. Returns whether it was.
+    pub fn repair(&self) -> Result<bool> {
+        let stale = self.status()? == Status::Stale;
+        if stale {
+            self.enable()?;
+        }
+        Ok(stale)
+    }
+}
+
+/// The launcher of app bundles on macOS.
+#[cfg(target_os = "macos")]
+const OPEN: &str = "/usr/bin/open";
+
+/// The path of the app the registered `command` launches.
+fn registered_path(command: &[String]) -> Option<&str> {
+    #[cfg(target_os = "macos")]
+    if command.first().map(String::as_str) == Some(OPEN) {
+        let i = command.iter().position(|arg| arg == "-a")?;
+        return command.get(i + 1).map(String::as_str);
+    }
+    command.first().map(String::as_str)
+}
+
+fn same_path(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim_end_matches('/'), b.trim_end_matches('/'));
+    // paths are case insensitive on Windows
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// The argument passed to the app when it is launched hidden, see
+/// [`Builder::launch_hidden`].
+pub const HIDDEN_ARG: &str = "--hidden";
+
+#[cfg(target_os = "macos")]
+static LAUNCHED_AS_LOGIN_ITEM: AtomicBool = AtomicBool::new(false);
+
+/// Whether the app was started by its autostart entry with
+/// [`Builder::launch_hidden`], e.g. to keep its window hidden.
+///
+/// This checks for [`HIDDEN_ARG`] in the arguments of the app. Login items of
+/// [`MacosLauncher::AppleScript`] can't have arguments, they are recognized
+/// from the Apple event the app is launched with once it is ready, so call this
+/// from `RunEvent::Ready` or later there.
+pub fn was_launched_via_autostart() -> bool {
+    #[cfg(target_os = "macos")]
+    if LAUNCHED_AS_LOGIN_ITEM.load(Ordering::Relaxed) {
+        return true;
+    }
+    std::env::args().skip(1).any(|arg| arg == HIDDEN_ARG)
+}
+
+/// Whether the Apple event being handled launched the app as a login item.
+#[cfg(target_os = "macos")]
+fn launched_as_login_item() -> bool {
+    use objc::{class, msg_send, runtime::Object, sel, sel_impl};
+
+    // the four-character codes `keyAEPropData` and `keyAELaunchedAsLogInItem`
+    const PROP_DATA: u32 = u32::from_be_bytes(*b"prop");
+    const LAUNCHED_AS_LOG_IN_ITEM: u32 = u32::from_be_bytes(*b"lgit");
+
+    unsafe {
+        let manager: *mut Object = msg_send![class!(NSAppleEventManager), sharedAppleEventManager];
+        let event: *mut Object = msg_send![manager, currentAppleEvent];
+        if event.is_null() {
+            return false;
+        }
+        let descriptor: *mut Object = msg_send![event, paramDescriptorForKeyword: PROP_DATA];
+        if descriptor.is_null() {
+            return false;
+        }
+        let code: u32 = msg_send![descriptor, enumCodeValue];
+        code == LAUNCHED_AS_LOG_IN_ITEM
+    }
 }
 
 pub trait ManagerExt<R: Runtime> {
@@ -75,8 +375,11 @@ impl<R: Runtime, T: Manager<R>> ManagerExt<R> for T {
 }
 
 #[command]
-async fn enable(manager: State<'_, AutoLaunchManager>) -> Result<()> {
-    manager.enable()
+async fn enable(manager: State<'_, AutoLaunchManager>, args: Option<Vec<String>>) -> Result<()> {
+    match args {
+        Some(args) => manager.enable_with_args(args),
+        None => manager.enable(),
+    }
 }
 
 #[command]
@@ -89,6 +392,26 @@ async fn is_enabled(manager: State<'_, AutoLaunchManager>) -> Result<bool> {
     manager.is_enabled()
 }
 
+#[command]
+async fn status(manager: State<'_, AutoLaunchManager>) -> Result<Status> {
+    manager.status()
+}
+
+#[command]
+async fn repair(manager: State<'_, AutoLaunchManager>) -> Result<bool> {
+    manager.repair()
+}
+
+#[command]
+async fn set_args(manager: State<'_, AutoLaunchManager>, args: Vec<String>) -> Result<()> {
+    manager.set_args(args)
+}
+
+#[command]
+async fn get_args(manager: State<'_, AutoLaunchManager>) -> Result<Vec<String>> {
+    manager.args()
+}
+
 /// Initializes the plugin.
 ///
 /// `args` - are passed to your app on startup.
@@ -96,53 +419,135 @@ pub fn init<R: Runtime>(
     macos_launcher: MacosLauncher,
     args: Option<Vec<&'static str>>,
 ) -> TauriPlugin<R> {
-    Builder::new("autostart")
-        .invoke_handler(tauri::generate_handler![enable, disable, is_enabled])
-        .setup(move |app| {
-            let mut builder = AutoLaunchBuilder::new();
-            builder.set_app_name(&app.package_info().name);
-            if let Some(args) = args {
-                builder.set_args(&args);
-            }
-            builder.set_use_launch_agent(matches!(macos_launcher, MacosLauncher::LaunchAgent));
-
-            let current_exe = current_exe()?;
-
-            #[cfg(windows)]
-            builder.set_app_path(&current_exe.display().to_string());
-            #[cfg(target_os = "macos")]
-            {
-                // on macOS, current_exe gives path to /Applications/Example.app/MacOS/Example
-                // but this results in seeing a Unix Executable in macOS login items
-                // It must be: /Applications/Example.app
-                // If it didn't find exactly a single occurance of .app, it will default to
-                // exe path to not break it.
-                let exe_path = current_exe.canonicalize()?.display().to_string();
-                let parts: Vec<&str> = exe_path.split(".app/").collect();
-                let app_path =
-                    if parts.len() == 2 && matches!(macos_launcher, MacosLauncher::AppleScript) {
+    Builder::new()
+        .macos_launcher(macos_launcher)
+        .args(args.unwrap_or_default())
+        .build()
+}
+
+/// Builder for the autostart plugin.
+#[derive(Default)]
+pub struct Builder {
+    macos_launcher: MacosLauncher,
+    linux_launcher: LinuxLauncher,
+    launch_hidden: bool,
+    repair_on_init: bool,
+    args: Vec<String>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How autostart is registered on macOS, a launch agent by default.
+    pub fn macos_launcher(mut self, macos_launcher: MacosLauncher) -> Self {
+        self.macos_launcher = macos_launcher;
+        self
+    }
+
+    /// How autostart is registered on Linux, an XDG autostart desktop entry by
+    /// default. Enabling autostart removes the entry of the other launcher.
+    pub fn linux_launcher(mut self, linux_launcher: LinuxLauncher) -> Self {
+        self.linux_launcher = linux_launcher;
+        self
+    }
+
+    /// Launch the app hidden, e.g. to start it minimized to the tray.
+    /// [`HIDDEN_ARG`] is passed to it, so [`was_launched_via_autostart`] can
+    /// tell it to keep its window hidden. On macOS, login items of
+    /// [`MacosLauncher::AppleScript`] get the hidden flag of LaunchServices and
+    /// launch agents start the app with `open -j`, so it is launched hidden.
+    pub fn launch_hidden(mut self, launch_hidden: bool) -> Self {
+        self.launch_hidden = launch_hidden;
+        self
+    }
+
+    /// Repair a stale autostart entry when the plugin is initialized, see
+    /// [`AutoLaunchManager::repair`]. Failing to repair it is logged, it
+    /// doesn't fail the initialization.
+    pub fn repair_on_init(mut self, repair_on_init: bool) -> Self {
+        self.repair_on_init = repair_on_init;
+        self
+    }
+
+    /// The arguments passed to your app on startup.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        let Self {
+            macos_launcher,
+            linux_launcher,
+            launch_hidden,
+            repair_on_init,
+            args,
+        } = self;
+        let builder = PluginBuilder::new("autostart")
+            .invoke_handler(tauri::generate_handler![
+                enable, disable, is_enabled, status, repair, set_args, get_args
+            ])
+            .setup(move |app| {
+                let current_exe = current_exe()?;
+
+                #[cfg(windows)]
+                let app_path = current_exe.display().to_string();
+                #[cfg(target_os = "macos")]
+                let app_path = {
+                    // on macOS, current_exe gives path to /Applications/Example.app/MacOS/Example
+                    // but this results in seeing a Unix Executable in macOS login items
+                    // It must be: /Applications/Example.app
+                    // If it didn't find exactly a single occurance of .app, it will default to
+                    // exe path to not break it.
+                    let exe_path = current_exe.canonicalize()?.display().to_string();
+                    let parts: Vec<&str> = exe_path.split(".app/").collect();
+                    let app_path = if parts.len() == 2
+                        && matches!(macos_launcher, MacosLauncher::AppleScript)
+                    {
                         format!("{}.app", parts.get(0).unwrap().to_string())
                     } else {
                         exe_path
                     };
-                info!("auto_start path {}", &app_path);
-                builder.set_app_path(&app_path);
-            }
-            #[cfg(target_os = "linux")]
-            if let Some(appimage) = app
-                .env()
-                .appimage
-                .and_then(|p| p.to_str().map(|s| s.to_string()))
-            {
-                builder.set_app_path(&appimage);
-            } else {
-                builder.set_app_path(&current_exe.display().to_string());
-            }
+                    info!("auto_start path {}", &app_path);
+                    app_path
+                };
+                #[cfg(target_os = "linux")]
+                let app_path = if let Some(appimage) = app
+                    .env()
+                    .appimage
+                    .and_then(|p| p.to_str().map(|s| s.to_string()))
+                {
+                    appimage
+                } else {
+                    current_exe.display().to_string()
+                };
 
-            app.manage(AutoLaunchManager(
-                builder.build().map_err(|e| e.to_string())?,
-            ));
-            Ok(())
-        })
-        .build()
+                app.manage(AutoLaunchManager {
+                    app_name: app.package_info().name.clone(),
+                    app_path,
+                    macos_launcher,
+                    #[cfg(target_os = "linux")]
+                    linux_launcher,
+                    launch_hidden,
+                    args: Mutex::new(args),
+                });
+                if repair_on_init {
+                    match app.autolaunch().repair() {
+                        Ok(true) => log::info!("repaired the stale autostart entry"),
+                        Ok(false) => {}
+                        Err(e) => log::warn!("failed to repair the autostart entry: {e}"),
+                    }
+                }
+                Ok(())
+            });
+        #[cfg(target_os = "macos")]
+        let builder = builder.on_event(|_app, event| {
+            if let RunEvent::Ready = event {
+                LAUNCHED_AS_LOGIN_ITEM.store(launched_as_login_item(), Ordering::Relaxed);
+            }
+        });
+        builder.build()
+    }
 }
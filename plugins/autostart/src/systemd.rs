@@ -0,0 +1,229 @@
+// Copyright 2019-2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Autostart with a systemd user unit, see [`crate::LinuxLauncher::Systemd`].
+
+use std::{
+    fs,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Whether the system was booted with systemd, the check of `sd_booted`.
+pub(crate) fn is_available() -> bool {
+    Path::new("/run/systemd/system").exists()
+}
+
+/// Installs and enables the unit starting `command`, the app path followed by
+/// its arguments, when the graphical session starts.
+pub(crate) fn enable(app_name: &str, command: &[String]) -> Result<()> {
+    let exec_start = command
+        .iter()
+        .map(|arg| quote_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let unit = format!(
+        "[Unit]\n\
+        Description={app_name}\n\
+        PartOf=graphical-session.target\n\
+        After=graphical-session.target\n\
+        \n\
+        [Service]\n\
+        ExecStart={exec_start}\n\
+        Restart=on-failure\n\
+        \n\
+        [Install]\n\
+        WantedBy=graphical-session.target\n"
+    );
+
+    let file = unit_file(app_name)?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&file, unit)?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", &unit_name(app_name)])
+}
+
+/// Disables and removes the unit, if it is installed.
+pub(crate) fn disable(app_name: &str) -> Result<()> {
+    let file = unit_file(app_name)?;
+    if !file.exists() {
+        return Ok(());
+    }
+    // without systemd, e.g. after switching the init system, only the file is left
+    if !is_available() {
+        return fs::remove_file(file);
+    }
+    systemctl(&["disable", &unit_name(app_name)])?;
+    fs::remove_file(file)?;
+    systemctl(&["daemon-reload"])
+}
+
+pub(crate) fn is_enabled(app_name: &str) -> Result<bool> {
+    if !unit_file(app_name)?.exists() {
+        return Ok(false);
+    }
+    let status = Command::new("systemctl")
+        .args(["--user", "--quiet", "is-enabled", &unit_name(app_name)])
+        .status()?;
+    Ok(status.success())
+}
+
+/// Reads the command of the unit, the app path followed by the arguments, or
+/// `None` if it isn't installed.
+pub(crate) fn registered_command(app_name: &str) -> Result<Option<Vec<String>>> {
+    let unit = match fs::read_to_string(unit_file(app_name)?) {
+        Ok(unit) => unit,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(unit
+        .lines()
+        .find_map(|line| line.strip_prefix("ExecStart="))
+        .map(split_exec_start))
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "systemctl --user {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// The unit name for the app, escaped like `systemd-escape` does.
+fn unit_name(app_name: &str) -> String {
+    let mut name = String::new();
+    for (i, b) in app_name.bytes().enumerate() {
+        if b.is_ascii_alphanumeric() || b == b'_' || b == b':' || (b == b'.' && i > 0) {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("\\x{b:02x}"));
+        }
+    }
+    format!("{name}.service")
+}
+
+fn unit_file(app_name: &str) -> Result<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| tauri::api::path::home_dir().map(|home| home.join(".config")))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no home directory"))?;
+    Ok(config_dir
+        .join("systemd")
+        .join("user")
+        .join(unit_name(app_name)))
+}
+
+/// Quotes an argument of `ExecStart`, escaping the `%` specifiers and `$`
+/// variable expansion.
+fn quote_arg(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@%$".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return arg;
+    }
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Splits an `ExecStart` command written by [`enable`] into its arguments.
+fn split_exec_start(exec_start: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = exec_start.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        let first = match chars.next() {
+            Some(first) => first,
+            None => break,
+        };
+        let mut arg = String::new();
+        if first == '"' {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => arg.push('\n'),
+                        Some('t') => arg.push('\t'),
+                        Some('r') => arg.push('\r'),
+                        Some(c) => arg.push(c),
+                        None => {}
+                    },
+                    _ => arg.push(c),
+                }
+            }
+        } else {
+            arg.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_ascii_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg.replace("%%", "%").replace("$$", "$"));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_exec_start_arguments() {
+        let cases = [
+            ("--minimized", "--minimized"),
+            ("", r#""""#),
+            ("two words", r#""two words""#),
+            ("it's", r#""it's""#),
+            (r#"say "hi""#, r#""say \"hi\"""#),
+            ("100%", "100%%"),
+            ("$HOME", "$$HOME"),
+            ("${HOME} %h", r#""$${HOME} %%h""#),
+            (r"C:\dir", r#""C:\\dir""#),
+            ("a\nb", r#""a\nb""#),
+        ];
+        for (arg, quoted) in cases {
+            assert_eq!(quote_arg(arg), quoted, "{arg:?}");
+            assert_eq!(split_exec_start(&quote_arg(arg)), [arg], "{arg:?}");
+        }
+    }
+
+    #[test]
+    fn splits_exec_start() {
+        let args = ["/opt/My App/app", "--flag", "", "50%", "$x", r#""q""#];
+        let exec_start = args.map(quote_arg).join(" ");
+        assert_eq!(split_exec_start(&exec_start), args);
+    }
+
+    #[test]
+    fn escapes_unit_names() {
+        assert_eq!(unit_name("my-app"), r"my\x2dapp.service");
+        assert_eq!(unit_name("My App.v2"), r"My\x20App.v2.service");
+        assert_eq!(unit_name(".app"), r"\x2eapp.service");
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2019-2023 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+import { invoke } from "@tauri-apps/api/tauri";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
+
+/**
+ * The scope an entry is persisted for, `asset` requires the `protocol-asset` feature of the plugin.
+ */
+export type ScopeKind = "fs" | "asset";
+
+export type Access = "allowed" | "forbidden";
+
+/**
+ * Whether the entry is a file, a directory with its files, or a directory with all of its contents.
+ */
+export type TargetType = "file" | "directory" | "recursiveDirectory";
+
+/**
+ * A persisted path.
+ */
+export interface ScopeEntry {
+  scope: ScopeKind;
+  access: Access;
+  path: string;
+  target: TargetType;
+  /**
+   * When the entry was granted or last passed to {@link recordUse}, in milliseconds since the Unix epoch.
+   * Always `null` for forbidden entries.
+   */
+  lastUsed: number | null;
+}
+
+/**
+ * Lists the persisted entries of the fs and asset protocol scopes, without the ones of the app config.
+ */
+export async function entries(): Promise<ScopeEntry[]> {
+  return await invoke("plugin:persisted-scope|entries");
+}
+
+/**
+ * Removes an allowed entry from the saved scope and from the live one, which takes effect immediately.
+ * Forbidden entries can't be removed. Requires the plugin's `Builder::with_scope_editing`.
+ *
+ * @returns Whether there was such an entry.
+ */
+export async function removeEntry(
+  entry: Pick<ScopeEntry, "scope" | "access" | "path">,
+): Promise<boolean> {
+  return await invoke("plugin:persisted-scope|remove_entry", { entry });
+}
+
+/**
+ * Marks the allowed entries that give access to `path` as used now.
+ */
+export async function recordUse(
+  path: string,
+  scope: ScopeKind = "fs",
+): Promise<void> {
+  await invoke("plugin:persisted-scope|record_use", { scope, path });
+}
+
+/**
+ * Removes the allowed entries that haven't been used for `days` days. Requires the plugin's `Builder::with_scope_editing`.
+ *
+ * @returns The removed entries.
+ */
+export async function removeUnused(days: number): Promise<ScopeEntry[]> {
+  return await invoke("plugin:persisted-scope|remove_unused", { days });
+}
+
+/**
+ * How a granted directory is persisted: as it was granted, with all of its contents, or only the directory itself.
+ */
+export type DirectoryGrants = "asGranted" | "recursive" | "literal";
+
+/**
+ * Sets how the allowed directory `path` is persisted, overriding the plugin's `Builder::with_directory_grants`.
+ * The directory stays granted as it is until the app restarts.
+ * It can only be persisted with less of its contents than it is, e.g. `literal`, more takes `Builder::with_directory_grants`, otherwise this rejects.
+ *
+ * @returns Whether there is such an entry.
+ */
+export async function persistDirectory(
+  path: string,
+  directoryGrants: DirectoryGrants,
+  scope: ScopeKind = "fs",
+): Promise<boolean> {
+  return await invoke("plugin:persisted-scope|persist_directory", {
+    scope,
+    path,
+    directoryGrants,
+  });
+}
+
+/**
+ * An entry added to or removed from a persisted scope.
+ */
+export interface ScopeChange {
+  scope: ScopeKind;
+  access: Access;
+  change: "added" | "removed";
+  path: string;
+  /**
+   * The patterns saved for the entry, or the ones it was saved with before it was removed.
+   */
+  patterns: string[];
+  /**
+   * A path picked in a dialog or granted by the app from Rust, a path dropped on a window, or a command of this plugin.
+   */
+  source: "dialog" | "drop" | "api";
+}
+
+/**
+ * Listens to the entries added to and removed from the persisted scopes, e.g. to refresh which files are shown as accessible.
+ */
+export async function onScopeChange(
+  handler: (change: ScopeChange) => void,
+): Promise<UnlistenFn> {
+  return await listen<ScopeChange>("persisted-scope://change", (event) =>
+    handler(event.payload),
+  );
+}
+
+/**
+ * The persisted entries, as returned by {@link exportScope}.
+ */
+export interface ExportedScope {
+  /**
+   * The version of the format, exports of older versions are migrated on import.
+   */
+  version: number;
+  entries: ScopeEntry[];
+}
+
+export interface ImportOptions {
+  /**
+   * Whether the imported entries are added to the persisted ones, or replace the allowed ones. Defaults to `merge`.
+   */
+  mode?: "merge" | "replace";
+}
+
+export interface ImportReport {
+  imported: number;
+  /**
+   * How many persisted allowed entries were removed with the `replace` mode.
+   */
+  removed: number;
+  /**
+   * Paths that don't exist on this machine, which were skipped.
+   */
+  missing: string[];
+  /**
+   * Paths that aren't absolute, files imported as directories, or paths of a scope that isn't persisted, which were skipped.
+   */
+  invalid: string[];
+  /**
+   * Allowed paths that the scope doesn't allow already, which were skipped since an import can't grant access.
+   */
+  denied: string[];
+}
+
+/**
+ * Exports the persisted entries, e.g. to sync them to another machine with {@link importScope}.
+ */
+export async function exportScope(): Promise<ExportedScope> {
+  return await invoke("plugin:persisted-scope|export_scope");
+}
+
+/**
+ * Imports entries exported by {@link exportScope}. The entries are checked before any is applied, then the live scopes and the saved ones are updated at once.
+ * Allowed entries are only imported if the scope already allows them. Requires the plugin's `Builder::with_scope_editing`.
+ */
+export async function importScope(
+  data: ExportedScope,
+  options?: ImportOptions,
+): Promise<ImportReport> {
+  return await invoke("plugin:persisted-scope|import_scope", {
+    data,
+    options,
+  });
+}
@@ -3,22 +3,50 @@
 // SPDX-License-Identifier: MIT
 
 use aho_corasick::AhoCorasick;
-use serde::{Deserialize, Serialize};
+use glob::MatchOptions;
+use serde::{ser::Serializer, Deserialize, Serialize};
 use tauri::{
-    plugin::{Builder, TauriPlugin},
-    FsScope, FsScopeEvent, Manager, Runtime,
+    command,
+    plugin::{Builder as PluginBuilder, TauriPlugin},
+    FileDropEvent, FsScope, FsScopeEvent, Manager, Runtime, State, WindowEvent,
 };
 
 use std::{
-    fs::{create_dir_all, File},
+    cell::Cell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::{create_dir_all, rename, File},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-// Using 2 separate files so that we don't have to think about write conflicts and not break backwards compat.
+// Using separate files so that we don't have to think about write conflicts and not break backwards compat.
 const SCOPE_STATE_FILENAME: &str = ".persisted-scope";
+const SCOPE_USAGE_FILENAME: &str = ".persisted-scope-usage";
 #[cfg(feature = "protocol-asset")]
 const ASSET_SCOPE_STATE_FILENAME: &str = ".persisted-scope-asset";
+#[cfg(feature = "protocol-asset")]
+const ASSET_SCOPE_USAGE_FILENAME: &str = ".persisted-scope-asset-usage";
+
+// The options the fs scope matches paths with, so `/dir/*` doesn't mark the files in subdirectories as used.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// The event emitted to all windows when a persisted scope gains or loses entries.
+const CHANGE_EVENT: &str = "persisted-scope://change";
+
+/// The version of the format of `export_scope`, to be increased when it changes so older exports can be migrated.
+const EXPORT_VERSION: u64 = 1;
 
 // Most of these patterns are just added to try to fix broken files in the wild.
 // After a while we can hopefully reduce it to something like [r"[?]", r"[*]", r"\\?\\\?\"]
@@ -33,6 +61,8 @@ const PATTERNS: &[&str] = &[
 ];
 const REPLACE_WITH: &[&str] = &[r"[", r"]", r"?", r"*", r"\?", r"\\?\", r"\\?\"];
 
+type Result<T> = std::result::Result<T, Error>;
+
 #[derive(Debug, thiserror::Error)]
 enum Error {
     #[error(transparent)]
@@ -43,9 +73,33 @@ enum Error {
     TauriApi(#[from] tauri::api::Error),
     #[error(transparent)]
     Bincode(#[from] Box<bincode::ErrorKind>),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("the {0} scope isn't persisted")]
+    NotPersisted(ScopeKind),
+    #[error("unsupported scope export version {0}, expected at most {EXPORT_VERSION}")]
+    UnsupportedVersion(u64),
+    #[error("changing the persisted entries isn't enabled, see `Builder::with_scope_editing`")]
+    EditingDisabled,
+    #[error("forbidden entries can't be removed")]
+    RemoveForbidden,
+    #[error("{0} can't be persisted with more of its contents than it is, see `Builder::with_directory_grants`")]
+    Broadening(String),
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Eq, PartialEq, Hash)]
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
+
+#[derive(
+    Debug, Default, Clone, Copy, Deserialize, Serialize, Eq, PartialEq, Ord, PartialOrd, Hash,
+)]
+#[serde(rename_all = "camelCase")]
 enum TargetType {
     #[default]
     File,
@@ -59,6 +113,155 @@ struct Scope {
     forbidden_patterns: Vec<String>,
 }
 
+/// The scope of an entry.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum ScopeKind {
+    Fs,
+    Asset,
+}
+
+impl fmt::Display for ScopeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fs => "fs",
+            Self::Asset => "asset",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum Access {
+    Allowed,
+    Forbidden,
+}
+
+/// A persisted path, which may be made of several patterns.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    scope: ScopeKind,
+    access: Access,
+    path: String,
+    target: TargetType,
+    /// Milliseconds since the Unix epoch, only tracked for allowed entries.
+    last_used: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntryRef {
+    scope: ScopeKind,
+    access: Access,
+    path: String,
+}
+
+/// Where a change of the scope comes from.
+#[derive(Debug, Clone, Copy, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum Source {
+    /// A path picked in a dialog, or granted by the app on the scope itself.
+    Dialog,
+    /// A path dropped on a window.
+    Drop,
+    /// A command of this plugin, e.g. `remove_entry` or `import_scope`.
+    Api,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Change {
+    Added,
+    Removed,
+}
+
+/// The payload of [`CHANGE_EVENT`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopeChange {
+    scope: ScopeKind,
+    access: Access,
+    change: Change,
+    path: String,
+    /// The patterns saved for the entry, or the ones it was saved with before it was removed.
+    patterns: Vec<String>,
+    source: Source,
+}
+
+/// How the directories granted by a dialog or a file drop are persisted, which is restored after a restart.
+/// They stay granted as they were for the rest of the session.
+#[derive(Debug, Default, Clone, Copy, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DirectoryGrants {
+    /// As they were granted, with their files or with all of their contents.
+    #[default]
+    AsGranted,
+    /// With all of their contents, even if only their files were granted.
+    Recursive,
+    /// Only the directories themselves, so their contents have to be granted again after a restart.
+    Literal,
+}
+
+impl DirectoryGrants {
+    /// The target a granted directory is persisted as, `None` for the one it was granted as.
+    fn target(self) -> Option<TargetType> {
+        match self {
+            Self::AsGranted => None,
+            Self::Recursive => Some(TargetType::RecursiveDirectory),
+            Self::Literal => Some(TargetType::File),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ExportedScope {
+    version: u64,
+    entries: Vec<Entry>,
+}
+
+impl ExportedScope {
+    /// Reads an export, migrating the ones of older versions.
+    fn read(data: serde_json::Value) -> Result<Self> {
+        match data
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or_default()
+        {
+            EXPORT_VERSION => serde_json::from_value(data).map_err(Into::into),
+            version => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ImportMode {
+    /// Adds the imported entries to the persisted ones.
+    #[default]
+    Merge,
+    /// Removes the persisted entries that aren't imported.
+    Replace,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ImportOptions {
+    mode: ImportMode,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ImportReport {
+    imported: usize,
+    /// Allowed entries removed with [`ImportMode::Replace`], forbidden ones are kept.
+    removed: usize,
+    /// Paths that don't exist on this machine.
+    missing: Vec<String>,
+    /// Paths that aren't absolute, files imported as directories, or paths of a scope that isn't persisted.
+    invalid: Vec<String>,
+    /// Allowed paths that the scope doesn't allow already, which an import can't grant.
+    denied: Vec<String>,
+}
+
 fn fix_pattern(ac: &AhoCorasick, s: &str) -> String {
     let s = ac.replace_all(s, REPLACE_WITH);
 
@@ -129,109 +332,789 @@ fn forbid_path(scope: &FsScope, path: &str) {
     }
 }
 
-fn save_scopes(scope: &FsScope, app_dir: &Path, scope_state_path: &Path) {
-    let scope = Scope {
-        allowed_paths: scope
-            .allowed_patterns()
-            .into_iter()
-            .map(|p| p.to_string())
-            .collect(),
-        forbidden_patterns: scope
-            .forbidden_patterns()
-            .into_iter()
-            .map(|p| p.to_string())
-            .collect(),
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// The path an entry is listed with, without the verbatim prefix Windows paths may be allowed with as well.
+fn normalize_path(path: &str) -> String {
+    let path = if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{unc}")
+    } else {
+        path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+    };
+    Path::new(&path)
+        .components()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The path of the entry `pattern` belongs to.
+fn entry_path(ac: &AhoCorasick, pattern: &str) -> String {
+    let path = fix_directory(pattern).to_string_lossy();
+    normalize_path(&fix_pattern(ac, &path))
+}
+
+/// The patterns `path` is saved with as `target`, the ones `FsScope` allows it with.
+fn target_patterns(path: &str, target: TargetType) -> Vec<String> {
+    let path = glob::Pattern::escape(path);
+    let suffix = match target {
+        TargetType::File => return vec![path],
+        TargetType::Directory => DIRECTORY_SUFFIX,
+        TargetType::RecursiveDirectory => RESURSIVE_DIRECTORY_SUFFIX,
     };
+    vec![format!("{path}{MAIN_SEPARATOR}{suffix}"), path]
+}
+
+thread_local! {
+    /// Set while this plugin's commands change the scope on the current thread, scope events are emitted synchronously.
+    static API_CHANGE: Cell<bool> = Cell::new(false);
+}
 
+fn api_change<T>(f: impl FnOnce() -> T) -> T {
+    API_CHANGE.with(|c| c.set(true));
+    let result = f();
+    API_CHANGE.with(|c| c.set(false));
+    result
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    path.with_extension("tmp")
+}
+
+// The file is replaced by a complete temporary one, so it's never left half written.
+fn write_file<T: Serialize>(app_dir: &Path, path: &Path, value: &T) {
+    let temp_path = temp_path(path);
     let _ = create_dir_all(app_dir)
-        .and_then(|_| File::create(scope_state_path))
+        .and_then(|_| File::create(&temp_path))
         .map_err(Error::Io)
         .and_then(|mut f| {
-            f.write_all(&bincode::serialize(&scope).map_err(Error::from)?)
+            f.write_all(&bincode::serialize(value).map_err(Error::from)?)
                 .map_err(Into::into)
-        });
+        })
+        .and_then(|_| rename(&temp_path, path).map_err(Into::into));
 }
 
-pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    Builder::new("persisted-scope")
-        .setup(|app| {
-            let fs_scope = app.fs_scope();
-            #[cfg(feature = "protocol-asset")]
-            let asset_protocol_scope = app.asset_protocol_scope();
-            let app = app.clone();
-            let app_dir = app.path_resolver().app_data_dir();
-
-            if let Some(app_dir) = app_dir {
-                let fs_scope_state_path = app_dir.join(SCOPE_STATE_FILENAME);
-                #[cfg(feature = "protocol-asset")]
-                let asset_scope_state_path = app_dir.join(ASSET_SCOPE_STATE_FILENAME);
-
-                let _ = fs_scope.forbid_file(&fs_scope_state_path);
-                #[cfg(feature = "protocol-asset")]
-                let _ = asset_protocol_scope.forbid_file(&asset_scope_state_path);
-
-                // We're trying to fix broken .persisted-scope files seamlessly, so we'll be running this on the values read on the saved file.
-                // We will still save some semi-broken values because the scope events are quite spammy and we don't want to reduce runtime performance any further.
-                let ac = AhoCorasick::new(PATTERNS).unwrap(/* This should be impossible to fail since we're using a small static input */);
-
-                if fs_scope_state_path.exists() {
-                    let scope: Scope = tauri::api::file::read_binary(&fs_scope_state_path)
-                        .map_err(Error::from)
-                        .and_then(|scope| bincode::deserialize(&scope).map_err(Into::into))
-                        .unwrap_or_default();
-
-                    for allowed in &scope.allowed_paths {
-                        let allowed = fix_pattern(&ac, allowed);
-                        allow_path(&fs_scope, &allowed);
-                    }
-                    for forbidden in &scope.forbidden_patterns {
-                        let forbidden = fix_pattern(&ac, forbidden);
-                        forbid_path(&fs_scope, &forbidden);
-                    }
+#[derive(Default)]
+struct Removed {
+    /// Allowed patterns that aren't saved anymore.
+    allowed: HashSet<String>,
+    /// Forbidden patterns that aren't saved, the removed ones and the ones forbidding removed allowed patterns for the rest of the session.
+    forbidden: HashSet<String>,
+}
+
+/// What the persisted scopes share with the plugin.
+#[derive(Clone)]
+struct Shared {
+    directory_grants: DirectoryGrants,
+    /// The paths dragged over a window, which are granted by the app if they're dropped.
+    dropping: Arc<Mutex<HashSet<String>>>,
+    emit: Arc<dyn Fn(ScopeChange) + Send + Sync>,
+}
+
+struct PersistedScope {
+    kind: ScopeKind,
+    shared: Shared,
+    scope: FsScope,
+    app_dir: PathBuf,
+    state_path: PathBuf,
+    usage_path: PathBuf,
+    ac: AhoCorasick,
+    /// Paths of the app config and of the plugin's own files, which aren't entries.
+    builtin_allowed: HashSet<String>,
+    builtin_forbidden: HashSet<String>,
+    removed: Mutex<Removed>,
+    /// Set while entries are imported, to save them all at once.
+    importing: AtomicBool,
+    /// When the allowed entries were last used, by path.
+    usage: Mutex<HashMap<String, u64>>,
+    /// The directories saved for another target than they were granted as, by path.
+    persisted_as: Mutex<HashMap<String, TargetType>>,
+}
+
+impl PersistedScope {
+    fn new(
+        kind: ScopeKind,
+        scope: FsScope,
+        app_dir: &Path,
+        state_filename: &str,
+        usage_filename: &str,
+        ac: &AhoCorasick,
+        shared: Shared,
+    ) -> Self {
+        let state_path = app_dir.join(state_filename);
+        let usage_path = app_dir.join(usage_filename);
+
+        for path in [&state_path, &usage_path] {
+            let _ = scope.forbid_file(path);
+            let _ = scope.forbid_file(temp_path(path));
+        }
+
+        Self {
+            kind,
+            shared,
+            builtin_allowed: scope
+                .allowed_patterns()
+                .iter()
+                .map(|p| entry_path(ac, p.as_str()))
+                .collect(),
+            builtin_forbidden: scope
+                .forbidden_patterns()
+                .iter()
+                .map(|p| entry_path(ac, p.as_str()))
+                .collect(),
+            scope,
+            app_dir: app_dir.to_path_buf(),
+            state_path,
+            usage_path,
+            ac: ac.clone(),
+            removed: Default::default(),
+            importing: Default::default(),
+            usage: Default::default(),
+            persisted_as: Default::default(),
+        }
+    }
+
+    fn load(&self) {
+        if !self.state_path.exists() {
+            return;
+        }
+
+        let scope: Scope = tauri::api::file::read_binary(&self.state_path)
+            .map_err(Error::from)
+            .and_then(|scope| bincode::deserialize(&scope).map_err(Into::into))
+            .unwrap_or_default();
+
+        for allowed in &scope.allowed_paths {
+            let allowed = fix_pattern(&self.ac, allowed);
+            allow_path(&self.scope, &allowed);
+        }
+        for forbidden in &scope.forbidden_patterns {
+            let forbidden = fix_pattern(&self.ac, forbidden);
+            forbid_path(&self.scope, &forbidden);
+        }
+
+        let mut usage: HashMap<String, u64> = tauri::api::file::read_binary(&self.usage_path)
+            .map_err(Error::from)
+            .and_then(|usage| bincode::deserialize(&usage).map_err(Into::into))
+            .unwrap_or_default();
+        // Entries saved before their usage was tracked count as used now.
+        let now = now();
+        for entry in self.entries(Access::Allowed) {
+            usage.entry(entry.path).or_insert(now);
+        }
+        *self.usage.lock().unwrap() = usage;
+
+        // Manually save the fixed scopes to disk once.
+        // This is needed to fix broken .peristed-scope files in case the app doesn't update the scope itself.
+        self.save();
+    }
+
+    fn save(&self) {
+        if self.importing.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let scope = Scope {
+            allowed_paths: self.persisted(Access::Allowed, false),
+            forbidden_patterns: self.persisted(Access::Forbidden, false),
+        };
+        write_file(&self.app_dir, &self.state_path, &scope);
+        self.save_usage();
+    }
+
+    fn save_usage(&self) {
+        write_file(
+            &self.app_dir,
+            &self.usage_path,
+            &*self.usage.lock().unwrap(),
+        );
+    }
+
+    /// The patterns of `access` that are saved, optionally without the ones of builtin paths.
+    fn saved(&self, access: Access, skip_builtin: bool) -> Vec<String> {
+        let removed = self.removed.lock().unwrap();
+        let (patterns, builtin, removed) = match access {
+            Access::Allowed => (
+                self.scope.allowed_patterns(),
+                &self.builtin_allowed,
+                &removed.allowed,
+            ),
+            Access::Forbidden => (
+                self.scope.forbidden_patterns(),
+                &self.builtin_forbidden,
+                &removed.forbidden,
+            ),
+        };
+        patterns
+            .iter()
+            .map(ToString::to_string)
+            .filter(|p| !removed.contains(p))
+            .filter(|p| !(skip_builtin && builtin.contains(&entry_path(&self.ac, p))))
+            .collect()
+    }
+
+    /// The saved patterns of `access`, with the ones of the directories in `persisted_as` for their target.
+    fn persisted(&self, access: Access, skip_builtin: bool) -> Vec<String> {
+        let saved = self.saved(access, skip_builtin);
+        if access == Access::Forbidden {
+            return saved;
+        }
+
+        let persisted_as = self.persisted_as.lock().unwrap();
+        let mut patterns: Vec<String> = saved
+            .into_iter()
+            .filter(|p| !persisted_as.contains_key(&entry_path(&self.ac, p)))
+            .collect();
+        for (path, target) in persisted_as.iter() {
+            patterns.extend(target_patterns(path, *target));
+        }
+        patterns
+    }
+
+    /// The persisted patterns of the entry of `access` for `path`.
+    fn entry_patterns(&self, access: Access, path: &str) -> Vec<String> {
+        self.persisted(access, true)
+            .into_iter()
+            .filter(|p| entry_path(&self.ac, p) == path)
+            .collect()
+    }
 
-                    // Manually save the fixed scopes to disk once.
-                    // This is needed to fix broken .peristed-scope files in case the app doesn't update the scope itself.
-                    save_scopes(&fs_scope, &app_dir, &fs_scope_state_path);
+    fn emit(
+        &self,
+        access: Access,
+        change: Change,
+        path: String,
+        patterns: Vec<String>,
+        source: Source,
+    ) {
+        (self.shared.emit)(ScopeChange {
+            scope: self.kind,
+            access,
+            change,
+            path,
+            patterns,
+            source,
+        });
+    }
+
+    fn entries(&self, access: Access) -> Vec<Entry> {
+        // A directory is allowed along with its contents, so they are listed as one entry.
+        let mut targets = BTreeMap::new();
+        for pattern in self.persisted(access, true) {
+            let target = detect_scope_type(&pattern);
+            let entry = targets
+                .entry(entry_path(&self.ac, &pattern))
+                .or_insert(target);
+            *entry = target.max(*entry);
+        }
+
+        let usage = self.usage.lock().unwrap();
+        targets
+            .into_iter()
+            .map(|(path, target)| Entry {
+                scope: self.kind,
+                access,
+                last_used: match access {
+                    Access::Allowed => usage.get(&path).copied(),
+                    Access::Forbidden => None,
+                },
+                path,
+                target,
+            })
+            .collect()
+    }
+
+    /// Removes the allowed entry for `path`, returns whether there was one. Forbidden entries are never removed.
+    fn remove(&self, path: &str) -> bool {
+        let path = normalize_path(path);
+        let patterns: Vec<String> = self
+            .saved(Access::Allowed, true)
+            .into_iter()
+            .filter(|p| entry_path(&self.ac, p) == path)
+            .collect();
+        if patterns.is_empty() {
+            return false;
+        }
+        let persisted = self.entry_patterns(Access::Allowed, &path);
+
+        self.removed
+            .lock()
+            .unwrap()
+            .allowed
+            .extend(patterns.iter().cloned());
+
+        // Patterns can't be removed from a scope, so the entry is forbidden until the app restarts instead.
+        let forbidden = self.scope.forbidden_patterns();
+        for pattern in &patterns {
+            forbid_path(&self.scope, &fix_pattern(&self.ac, pattern));
+        }
+        let revoking = self
+            .scope
+            .forbidden_patterns()
+            .difference(&forbidden)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        self.removed.lock().unwrap().forbidden.extend(revoking);
+
+        self.usage.lock().unwrap().remove(&path);
+        self.persisted_as.lock().unwrap().remove(&path);
+
+        self.save();
+        self.emit(
+            Access::Allowed,
+            Change::Removed,
+            path,
+            persisted,
+            Source::Api,
+        );
+        true
+    }
+
+    /// Whether the live scope already allows `path` as `target`, with all of the contents the target gives access to.
+    /// Directories are only covered by the directories allowed with all of their contents, or by themselves.
+    fn allows(&self, path: &Path, target: TargetType) -> bool {
+        if !self.scope.is_allowed(path) {
+            return false;
+        }
+        if target == TargetType::File {
+            return true;
+        }
+
+        let path = normalize_path(
+            &path
+                .canonicalize()
+                .unwrap_or_else(|_| path.to_path_buf())
+                .to_string_lossy(),
+        );
+        let path = Path::new(&path);
+        let saved: HashSet<String> = self.saved(Access::Allowed, false).into_iter().collect();
+        saved.iter().any(|p| {
+            let allowed = entry_path(&self.ac, p);
+            match detect_scope_type(p) {
+                TargetType::RecursiveDirectory => path.starts_with(allowed),
+                TargetType::Directory => {
+                    target == TargetType::Directory && path == Path::new(&allowed)
                 }
+                TargetType::File => false,
+            }
+        })
+    }
 
-                #[cfg(feature = "protocol-asset")]
-                if asset_scope_state_path.exists() {
-                    let scope: Scope = tauri::api::file::read_binary(&asset_scope_state_path)
-                        .map_err(Error::from)
-                        .and_then(|scope| bincode::deserialize(&scope).map_err(Into::into))
-                        .unwrap_or_default();
+    /// Persists `path` again if it was removed, and marks it as used.
+    fn granted(&self, path: &Path) {
+        let source = if API_CHANGE.with(Cell::get) {
+            Source::Api
+        } else if self
+            .shared
+            .dropping
+            .lock()
+            .unwrap()
+            .contains(&normalize_path(&path.to_string_lossy()))
+        {
+            Source::Drop
+        } else {
+            Source::Dialog
+        };
 
-                    for allowed in &scope.allowed_paths {
-                        let allowed = fix_pattern(&ac, allowed);
-                        allow_path(&asset_protocol_scope, &allowed);
-                    }
-                    for forbidden in &scope.forbidden_patterns {
-                        let forbidden = fix_pattern(&ac, forbidden);
-                        forbid_path(&asset_protocol_scope, &forbidden);
-                    }
+        let path = normalize_path(&path.to_string_lossy());
+        self.removed
+            .lock()
+            .unwrap()
+            .allowed
+            .retain(|p| entry_path(&self.ac, p) != path);
+        self.usage.lock().unwrap().insert(path.clone(), now());
+        // Imported directories are persisted as they were exported, and directories granted as files aren't
+        // directory grants.
+        if let Some(target) = self.shared.directory_grants.target() {
+            let directory = self.saved(Access::Allowed, true).iter().any(|p| {
+                detect_scope_type(p) != TargetType::File && entry_path(&self.ac, p) == path
+            });
+            if directory && source != Source::Api {
+                self.persisted_as
+                    .lock()
+                    .unwrap()
+                    .insert(path.clone(), target);
+            }
+        }
+        self.save();
 
-                    // Manually save the fixed scopes to disk once.
-                    save_scopes(&asset_protocol_scope, &app_dir, &asset_scope_state_path);
+        let patterns = self.entry_patterns(Access::Allowed, &path);
+        self.emit(Access::Allowed, Change::Added, path, patterns, source);
+    }
+
+    /// Persists the allowed directory `path` as set by `directory_grants`, returns whether there is such an entry.
+    /// The webview can only narrow how a directory is persisted, persisting more of its contents is up to the app.
+    fn persist_directory(&self, path: &str, directory_grants: DirectoryGrants) -> Result<bool> {
+        let path = normalize_path(path);
+        let current = match self
+            .entries(Access::Allowed)
+            .into_iter()
+            .find(|e| e.path == path)
+        {
+            Some(entry) if Path::new(&path).is_dir() => entry.target,
+            _ => return Ok(false),
+        };
+        let granted = self
+            .saved(Access::Allowed, true)
+            .iter()
+            .filter(|p| entry_path(&self.ac, p) == path)
+            .map(|p| detect_scope_type(p))
+            .max()
+            .unwrap_or_default();
+        let target = directory_grants.target().unwrap_or(granted);
+        if target > current {
+            return Err(Error::Broadening(path));
+        }
+
+        let mut persisted_as = self.persisted_as.lock().unwrap();
+        if target == granted {
+            persisted_as.remove(&path);
+        } else {
+            persisted_as.insert(path, target);
+        }
+        drop(persisted_as);
+        self.save();
+        Ok(true)
+    }
+
+    /// Marks the allowed entries `path` is in as used.
+    fn used(&self, path: &Path) {
+        let path: PathBuf = path
+            .canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .components()
+            .collect();
+        let saved: HashSet<String> = self.saved(Access::Allowed, true).into_iter().collect();
+        let used: Vec<String> = self
+            .scope
+            .allowed_patterns()
+            .iter()
+            .filter(|p| saved.contains(p.as_str()) && p.matches_path_with(&path, MATCH_OPTIONS))
+            .map(|p| entry_path(&self.ac, p.as_str()))
+            .collect();
+        if used.is_empty() {
+            return;
+        }
+
+        let now = now();
+        let mut usage = self.usage.lock().unwrap();
+        for path in used {
+            usage.insert(path, now);
+        }
+        drop(usage);
+        self.save_usage();
+    }
+
+    /// Adds `entries` to the live scope, after removing the other allowed ones with `replace`, and saves them at once.
+    fn import(&self, entries: Vec<Entry>, replace: bool, report: &mut ImportReport) {
+        self.importing.store(true, Ordering::SeqCst);
+
+        if replace {
+            for entry in self.entries(Access::Allowed) {
+                let imported = entries
+                    .iter()
+                    .any(|e| e.access == Access::Allowed && normalize_path(&e.path) == entry.path);
+                if !imported && self.remove(&entry.path) {
+                    report.removed += 1;
                 }
+            }
+        }
 
-                #[cfg(feature = "protocol-asset")]
-                let app_dir_ = app_dir.clone();
-                let fs_scope_ = fs_scope.clone();
-                fs_scope.listen(move |event| {
-                    if let FsScopeEvent::PathAllowed(_) = event {
-                        save_scopes(&fs_scope_, &app_dir, &fs_scope_state_path);
-                    }
-                });
-                #[cfg(feature = "protocol-asset")]
-                {
-                    let asset_protocol_scope_ = asset_protocol_scope.clone();
-                    asset_protocol_scope.listen(move |event| {
-                    if let FsScopeEvent::PathAllowed(_) = event {
-                        save_scopes(&asset_protocol_scope_, &app_dir_, &asset_scope_state_path);
-                    }
-                });}
+        let now = now();
+        for entry in entries {
+            let _ = api_change(|| match (entry.access, entry.target) {
+                (Access::Allowed, TargetType::File) => self.scope.allow_file(&entry.path),
+                (Access::Allowed, target) => self
+                    .scope
+                    .allow_directory(&entry.path, target == TargetType::RecursiveDirectory),
+                (Access::Forbidden, TargetType::File) => self.scope.forbid_file(&entry.path),
+                (Access::Forbidden, target) => self
+                    .scope
+                    .forbid_directory(&entry.path, target == TargetType::RecursiveDirectory),
+            });
+            let path = normalize_path(&entry.path);
+            match entry.access {
+                Access::Allowed => {
+                    self.usage
+                        .lock()
+                        .unwrap()
+                        .insert(path, entry.last_used.unwrap_or(now));
+                }
+                // Allowed paths are emitted by `granted`, there is no listener for forbidden ones.
+                Access::Forbidden => {
+                    let patterns = self.entry_patterns(Access::Forbidden, &path);
+                    self.emit(
+                        Access::Forbidden,
+                        Change::Added,
+                        path,
+                        patterns,
+                        Source::Api,
+                    );
+                }
             }
+            report.imported += 1;
+        }
+
+        self.importing.store(false, Ordering::SeqCst);
+        self.save();
+    }
+}
+
+struct PersistedScopes {
+    scopes: Vec<Arc<PersistedScope>>,
+    /// Whether the commands that remove and import entries are enabled.
+    editing: bool,
+}
+
+impl PersistedScopes {
+    fn get(&self, kind: ScopeKind) -> Result<&PersistedScope> {
+        self.scopes
+            .iter()
+            .find(|s| s.kind == kind)
+            .map(AsRef::as_ref)
+            .ok_or(Error::NotPersisted(kind))
+    }
+
+    fn check_editing(&self) -> Result<()> {
+        if self.editing {
             Ok(())
+        } else {
+            Err(Error::EditingDisabled)
+        }
+    }
+}
+
+#[command]
+async fn entries(scopes: State<'_, PersistedScopes>) -> Result<Vec<Entry>> {
+    Ok(scopes
+        .scopes
+        .iter()
+        .flat_map(|s| {
+            [Access::Allowed, Access::Forbidden]
+                .into_iter()
+                .flat_map(move |access| s.entries(access))
         })
-        .build()
+        .collect())
+}
+
+#[command]
+async fn remove_entry(scopes: State<'_, PersistedScopes>, entry: EntryRef) -> Result<bool> {
+    scopes.check_editing()?;
+    let scope = scopes.get(entry.scope)?;
+    match entry.access {
+        Access::Allowed => Ok(scope.remove(&entry.path)),
+        Access::Forbidden => Err(Error::RemoveForbidden),
+    }
+}
+
+#[command]
+async fn record_use(
+    scopes: State<'_, PersistedScopes>,
+    scope: ScopeKind,
+    path: PathBuf,
+) -> Result<()> {
+    scopes.get(scope)?.used(&path);
+    Ok(())
+}
+
+#[command]
+async fn remove_unused(scopes: State<'_, PersistedScopes>, days: u64) -> Result<Vec<Entry>> {
+    scopes.check_editing()?;
+    let since = now().saturating_sub(days.saturating_mul(DAY_MS));
+    let mut removed = Vec::new();
+    for scope in &scopes.scopes {
+        for entry in scope.entries(Access::Allowed) {
+            if entry.last_used.map_or(false, |t| t < since) && scope.remove(&entry.path) {
+                removed.push(entry);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[command]
+async fn persist_directory(
+    scopes: State<'_, PersistedScopes>,
+    scope: ScopeKind,
+    path: String,
+    directory_grants: DirectoryGrants,
+) -> Result<bool> {
+    scopes
+        .get(scope)?
+        .persist_directory(&path, directory_grants)
+}
+
+#[command]
+async fn export_scope(scopes: State<'_, PersistedScopes>) -> Result<ExportedScope> {
+    Ok(ExportedScope {
+        version: EXPORT_VERSION,
+        entries: entries(scopes).await?,
+    })
+}
+
+#[command]
+async fn import_scope(
+    scopes: State<'_, PersistedScopes>,
+    data: serde_json::Value,
+    options: Option<ImportOptions>,
+) -> Result<ImportReport> {
+    scopes.check_editing()?;
+    let options = options.unwrap_or_default();
+    let exported = ExportedScope::read(data)?;
+
+    // Everything is checked before the scopes are changed.
+    let mut report = ImportReport::default();
+    let mut imported = Vec::new();
+    for entry in exported.entries {
+        let path = Path::new(&entry.path);
+        let scope = match scopes.get(entry.scope) {
+            Ok(scope) if path.is_absolute() => scope,
+            _ => {
+                report.invalid.push(entry.path);
+                continue;
+            }
+        };
+        if !path.exists() {
+            report.missing.push(entry.path);
+        } else if entry.target != TargetType::File && !path.is_dir() {
+            report.invalid.push(entry.path);
+        } else if entry.access == Access::Allowed && !scope.allows(path, entry.target) {
+            // an import only restores what the app allows, it can't widen the scope
+            report.denied.push(entry.path);
+        } else {
+            imported.push(entry);
+        }
+    }
+
+    let replace = matches!(options.mode, ImportMode::Replace);
+    for scope in &scopes.scopes {
+        let (entries, rest) = imported.into_iter().partition(|e| e.scope == scope.kind);
+        imported = rest;
+        scope.import(entries, replace, &mut report);
+    }
+    Ok(report)
+}
+
+#[derive(Default)]
+pub struct Builder {
+    directory_grants: DirectoryGrants,
+    scope_editing: bool,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the directories granted by a dialog or a file drop are persisted, as they were granted by default.
+    /// The `persist_directory` command can only narrow it for a granted directory, e.g. to [`DirectoryGrants::Literal`].
+    pub fn with_directory_grants(mut self, directory_grants: DirectoryGrants) -> Self {
+        self.directory_grants = directory_grants;
+        self
+    }
+
+    /// Enables the `remove_entry`, `remove_unused` and `import_scope` commands, which are disabled by default.
+    /// Imports can only restore allowed entries the scope already allows, and forbidden entries are never removed.
+    pub fn with_scope_editing(mut self, enabled: bool) -> Self {
+        self.scope_editing = enabled;
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        let directory_grants = self.directory_grants;
+        let editing = self.scope_editing;
+        let dropping: Arc<Mutex<HashSet<String>>> = Default::default();
+        let dropping_ = dropping.clone();
+
+        PluginBuilder::new("persisted-scope")
+            .invoke_handler(tauri::generate_handler![
+                entries,
+                remove_entry,
+                record_use,
+                remove_unused,
+                persist_directory,
+                export_scope,
+                import_scope
+            ])
+            .setup(move |app| {
+                let mut scopes = Vec::new();
+
+                if let Some(app_dir) = app.path_resolver().app_data_dir() {
+                    // We're trying to fix broken .persisted-scope files seamlessly, so we'll be running this on the values read on the saved file.
+                    // We will still save some semi-broken values because the scope events are quite spammy and we don't want to reduce runtime performance any further.
+                    let ac = AhoCorasick::new(PATTERNS).unwrap(/* This should be impossible to fail since we're using a small static input */);
+
+                    let app_ = app.clone();
+                    let shared = Shared {
+                        directory_grants,
+                        dropping,
+                        emit: Arc::new(move |change| {
+                            let _ = app_.emit_all(CHANGE_EVENT, change);
+                        }),
+                    };
+
+                    scopes.push(Arc::new(PersistedScope::new(
+                        ScopeKind::Fs,
+                        app.fs_scope(),
+                        &app_dir,
+                        SCOPE_STATE_FILENAME,
+                        SCOPE_USAGE_FILENAME,
+                        &ac,
+                        shared.clone(),
+                    )));
+                    #[cfg(feature = "protocol-asset")]
+                    scopes.push(Arc::new(PersistedScope::new(
+                        ScopeKind::Asset,
+                        app.asset_protocol_scope(),
+                        &app_dir,
+                        ASSET_SCOPE_STATE_FILENAME,
+                        ASSET_SCOPE_USAGE_FILENAME,
+                        &ac,
+                        shared.clone(),
+                    )));
+
+                    for scope in &scopes {
+                        scope.load();
+
+                        let scope_ = scope.clone();
+                        scope.scope.listen(move |event| {
+                            if let FsScopeEvent::PathAllowed(path) = event {
+                                scope_.granted(path);
+                            }
+                        });
+                    }
+                }
+
+                app.manage(PersistedScopes { scopes, editing });
+                Ok(())
+            })
+            .on_webview_ready(move |window| {
+                // Tauri grants dropped paths before the plugins see the drop, so they are tracked from when they're
+                // dragged over the window, to tell them apart from the ones picked in a dialog.
+                let dropping = dropping_.clone();
+                window.on_window_event(move |event| {
+                    if let WindowEvent::FileDrop(event) = event {
+                        let mut dropping = dropping.lock().unwrap();
+                        dropping.clear();
+                        if let FileDropEvent::Hovered(paths) = event {
+                            dropping.extend(
+                                paths
+                                    .iter()
+                                    .map(|p| normalize_path(&p.to_string_lossy())),
+                            );
+                        }
+                    }
+                });
+            })
+            .build()
+    }
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::default().build()
 }
@@ -35,3 +35,83 @@ export async function moveWindow(to: Position): Promise<void> {
     position: to,
   });
 }
+
+/**
+ * A distance in logical pixels, converted with the scale factor of the monitor
+ * the window is moved to. Positive values move the window right and down.
+ */
+export interface Offset {
+  x: number;
+  y: number;
+}
+
+/**
+ * A window position the fixed {@link Position}s can't express.
+ *
+ * - `{ anchor, offset }` is the position of `anchor`, moved by `offset`, e.g.
+ *   `{ anchor: Position.BottomRight, offset: { x: -16, y: -16 } }` is the
+ *   bottom right corner with a margin of 16 pixels.
+ * - `{ percent, offset }` is a position in the current monitor. The point of
+ *   the window at `percent` of its size is put at `percent` of the monitor,
+ *   like CSS `background-position`, e.g. `{ x: 50, y: 20 }` is centered
+ *   horizontally, a fifth of the way down. On Windows it is a position in the
+ *   work area, the part of the monitor that isn't covered by the taskbar.
+ */
+export type Placement =
+  | { anchor: Position; offset?: Offset }
+  | { percent: { x: number; y: number }; offset?: Offset };
+
+/**
+ * Moves the `Window` to the given {@link Placement} using
+ * `WindowExt.move_window_to()`.
+ *
+ * @param placement The {@link Placement} to move to.
+ */
+export async function moveWindowTo(placement: Placement): Promise<void> {
+  await invoke("plugin:positioner|move_window_to", {
+    placement,
+  });
+}
+
+/**
+ * How the speed of an animation changes over its duration.
+ */
+export type Easing = "linear" | "easeIn" | "easeOut" | "easeInOut";
+
+export interface AnimationOptions {
+  /** The duration of the animation in milliseconds. Defaults to `200`. */
+  durationMs?: number;
+  /** Defaults to `"easeOut"`. */
+  easing?: Easing;
+  /**
+   * Moves the window instantly. Defaults to whether the user prefers reduced
+   * motion, according to the `prefers-reduced-motion` media query.
+   */
+  reducedMotion?: boolean;
+}
+
+/**
+ * Moves the `Window` to the given {@link Position} like {@link moveWindow},
+ * animated using `WindowExt.move_window_animated()`. Another move of the window
+ * cancels the animation.
+ *
+ * @param to The {@link Position} to move to.
+ * @param options The duration and easing of the animation.
+ * @returns Whether the window reached the position, `false` if the animation
+ * was cancelled.
+ */
+export async function moveWindowAnimated(
+  to: Position,
+  options: AnimationOptions = {},
+): Promise<boolean> {
+  return await invoke("plugin:positioner|move_window_animated", {
+    position: to,
+    animation: {
+      ...options,
+      reducedMotion:
+        options.reducedMotion ??
+        window.matchMedia?.("(prefers-reduced-motion: reduce)").matches ??
+        false,
+    },
+  });
+}
@@ -3,10 +3,19 @@
 
 #[cfg(feature = "system-tray")]
 use crate::Tray;
+use serde::Deserialize;
 use serde_repr::Deserialize_repr;
-#[cfg(feature = "system-tray")]
-use tauri::Manager;
-use tauri::{PhysicalPosition, PhysicalSize, Result, Runtime, Window};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tauri::{
+    LogicalPosition, Manager, Monitor, PhysicalPosition, PhysicalSize, Result, Runtime, Window,
+};
+
+/// The interval between two steps of an animation, about 60 per second.
+const FRAME: Duration = Duration::from_millis(16);
 
 /// Well known window positions.
 #[derive(Debug, Deserialize_repr)]
@@ -35,171 +44,455 @@ pub enum Position {
     TrayBottomCenter,
 }
 
+/// A window position that the fixed [`Position`]s can't express, for
+/// [`WindowExt::move_window_to`].
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Placement {
+    /// The position of `anchor`, moved by `offset`, e.g. the bottom right
+    /// corner with a margin of 16 pixels with an offset of `(-16, -16)`.
+    Anchor {
+        anchor: Position,
+        #[serde(default)]
+        offset: Offset,
+    },
+    /// A position in the current monitor, moved by `offset`. The point of the
+    /// window at `percent` of its size is put at `percent` of the monitor,
+    /// like CSS `background-position`: `(0, 0)` is the top left corner, `(50,
+    /// 50)` the center and `(100, 100)` the bottom right corner. On Windows
+    /// it is a position in the work area, the part of the monitor that isn't
+    /// covered by the taskbar.
+    Percent {
+        percent: Percent,
+        #[serde(default)]
+        offset: Offset,
+    },
+}
+
+/// A distance in logical pixels, converted to physical pixels with the scale
+/// factor of the monitor the window is moved to. Positive values move the
+/// window right and down.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct Offset {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Percentages of the width and height, from 0 to 100.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct Percent {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// How the speed of an animation changes over its duration.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// The progress of the animation at `t`, both from 0 to 1.
+    fn apply(self, t: f64) -> f64 {
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t * t,
+            Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Self::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Self::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+        }
+    }
+}
+
+/// The animation of [`WindowExt::move_window_animated`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Animation {
+    /// 200 milliseconds by default.
+    pub duration_ms: u64,
+    pub easing: Easing,
+    /// Moves the window instantly, e.g. when the user prefers reduced motion.
+    pub reduced_motion: bool,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            duration_ms: 200,
+            easing: Easing::default(),
+            reduced_motion: false,
+        }
+    }
+}
+
+/// The generation of the latest move of each window, a move cancels the
+/// animations of the previous ones.
+#[derive(Default)]
+pub(crate) struct Animations(Mutex<HashMap<String, u64>>);
+
+impl Animations {
+    /// Starts a move of the window, and returns its generation.
+    fn start(&self, label: &str) -> u64 {
+        let mut generations = self.0.lock().unwrap();
+        let generation = generations.entry(label.to_string()).or_default();
+        *generation += 1;
+        *generation
+    }
+
+    /// Runs `step` of the move of `generation`, unless it was cancelled by a
+    /// later move, in which case `None` is returned. The lock is held while
+    /// the step runs, so it can't be applied after a later move.
+    fn step<T>(&self, label: &str, generation: u64, step: impl FnOnce() -> T) -> Option<T> {
+        let generations = self.0.lock().unwrap();
+        (generations.get(label) == Some(&generation)).then(step)
+    }
+}
+
 /// A [`Window`] extension that provides extra methods related to positioning.
 pub trait WindowExt {
     /// Moves the [`Window`] to the given [`Position`]
     ///
-    /// All positions are relative to the **current** screen.
+    /// All positions are relative to the **current** screen, except the tray
+    /// positions, which are relative to the tray icon and within the work area
+    /// of the screen it is on.
     fn move_window(&self, position: Position) -> Result<()>;
+
+    /// Moves the [`Window`] to the given [`Position`] like
+    /// [`WindowExt::move_window`], animated over the duration of `animation`.
+    ///
+    /// Blocks until the window is at the position, so it must not be called on
+    /// the main thread. Requires the plugin to be attached. Returns `false` if the animation was cancelled by
+    /// another move of the window before it ended.
+    fn move_window_animated(&self, position: Position, animation: Animation) -> Result<bool>;
+
+    /// Moves the [`Window`] to the given [`Placement`], a [`Position`] with an
+    /// offset or a percentage of the current screen, of its work area on
+    /// Windows.
+    fn move_window_to(&self, placement: Placement) -> Result<()>;
 }
 
 impl<R: Runtime> WindowExt for Window<R> {
     fn move_window(&self, pos: Position) -> Result<()> {
-        use Position::*;
+        if let Some(animations) = self.try_state::<Animations>() {
+            animations.start(self.label());
+        }
+        self.set_position(tauri::Position::Physical(target_position(self, &pos)?.0))
+    }
 
-        let screen = self.current_monitor()?.unwrap();
-        let screen_position = screen.position();
-        let screen_size = PhysicalSize::<i32> {
-            width: screen.size().width as i32,
-            height: screen.size().height as i32,
+    fn move_window_to(&self, placement: Placement) -> Result<()> {
+        if let Some(animations) = self.try_state::<Animations>() {
+            animations.start(self.label());
+        }
+        let (position, scale, offset) = match placement {
+            Placement::Anchor { anchor, offset } => {
+                let (position, scale) = target_position(self, &anchor)?;
+                (position, scale, offset)
+            }
+            Placement::Percent { percent, offset } => {
+                let screen = self.current_monitor()?.unwrap();
+                let monitor = Rect::of_monitor(&screen);
+                let area = crate::work_area::work_area(monitor).unwrap_or(monitor);
+                let window_size = self.outer_size()?;
+                let free_width = (area.width - window_size.width as i32) as f64;
+                let free_height = (area.height - window_size.height as i32) as f64;
+                let position = PhysicalPosition {
+                    x: area.x + (free_width * percent.x / 100.0).round() as i32,
+                    y: area.y + (free_height * percent.y / 100.0).round() as i32,
+                };
+                (position, screen.scale_factor(), offset)
+            }
         };
-        let window_size = PhysicalSize::<i32> {
-            width: self.outer_size()?.width as i32,
-            height: self.outer_size()?.height as i32,
+        self.set_position(tauri::Position::Physical(PhysicalPosition {
+            x: position.x + (offset.x * scale).round() as i32,
+            y: position.y + (offset.y * scale).round() as i32,
+        }))
+    }
+
+    fn move_window_animated(&self, pos: Position, animation: Animation) -> Result<bool> {
+        let (end, _) = target_position(self, &pos)?;
+        let animations = self.state::<Animations>();
+        let label = self.label();
+        let generation = animations.start(label);
+        if animation.reduced_motion || animation.duration_ms == 0 {
+            self.set_position(tauri::Position::Physical(end))?;
+            return Ok(true);
+        }
+
+        // macOS positions windows in points, the other platforms in pixels,
+        // the path is interpolated in those so it stays continuous when the
+        // window crosses to a monitor with another scale factor
+        let scale = if cfg!(target_os = "macos") {
+            self.scale_factor()?
+        } else {
+            1.0
         };
-        #[cfg(feature = "system-tray")]
-        let (tray_position, tray_size) = self
-            .state::<Tray>()
-            .0
-            .lock()
-            .unwrap()
-            .map(|(pos, size)| {
-                (
-                    Some((pos.x as i32, pos.y as i32)),
-                    Some((size.width as i32, size.height as i32)),
-                )
-            })
-            .unwrap_or_default();
-
-        let physical_pos = match pos {
-            TopLeft => *screen_position,
-            TopRight => PhysicalPosition {
-                x: screen_position.x + (screen_size.width - window_size.width),
-                y: screen_position.y,
-            },
-            BottomLeft => PhysicalPosition {
-                x: screen_position.x,
-                y: screen_size.height - (window_size.height - screen_position.y),
-            },
-            BottomRight => PhysicalPosition {
-                x: screen_position.x + (screen_size.width - window_size.width),
-                y: screen_size.height - (window_size.height - screen_position.y),
-            },
-            TopCenter => PhysicalPosition {
-                x: screen_position.x + ((screen_size.width / 2) - (window_size.width / 2)),
-                y: screen_position.y,
-            },
-            BottomCenter => PhysicalPosition {
-                x: screen_position.x + ((screen_size.width / 2) - (window_size.width / 2)),
-                y: screen_size.height - (window_size.height - screen_position.y),
-            },
-            LeftCenter => PhysicalPosition {
-                x: screen_position.x,
-                y: screen_position.y + (screen_size.height / 2) - (window_size.height / 2),
-            },
-            RightCenter => PhysicalPosition {
-                x: screen_position.x + (screen_size.width - window_size.width),
-                y: screen_position.y + (screen_size.height / 2) - (window_size.height / 2),
-            },
-            Center => PhysicalPosition {
-                x: screen_position.x + ((screen_size.width / 2) - (window_size.width / 2)),
-                y: screen_position.y + (screen_size.height / 2) - (window_size.height / 2),
-            },
-            #[cfg(feature = "system-tray")]
-            TrayLeft => {
-                if let (Some((tray_x, tray_y)), Some((_, _tray_height))) =
-                    (tray_position, tray_size)
-                {
-                    let y = tray_y - window_size.height;
-                    // Choose y value based on the target OS
-                    #[cfg(target_os = "windows")]
-                    let y = if y < 0 { tray_y + _tray_height } else { y };
-
-                    #[cfg(target_os = "macos")]
-                    let y = if y < 0 { tray_y } else { y };
-
-                    PhysicalPosition { x: tray_x, y }
-                } else {
-                    panic!("Tray position not set");
-                }
+        let start = self.outer_position()?;
+        let (from_x, from_y) = (start.x as f64 / scale, start.y as f64 / scale);
+        let (to_x, to_y) = (end.x as f64 / scale, end.y as f64 / scale);
+        let duration = Duration::from_millis(animation.duration_ms).as_secs_f64();
+        let started = Instant::now();
+        loop {
+            std::thread::sleep(FRAME);
+            let t = started.elapsed().as_secs_f64() / duration;
+            if t >= 1.0 {
+                break;
             }
-            #[cfg(feature = "system-tray")]
-            TrayBottomLeft => {
-                if let Some((tray_x, tray_y)) = tray_position {
-                    PhysicalPosition {
-                        x: tray_x,
-                        y: tray_y,
-                    }
-                } else {
-                    panic!("Tray position not set");
-                }
+            let progress = animation.easing.apply(t);
+            let x = from_x + (to_x - from_x) * progress;
+            let y = from_y + (to_y - from_y) * progress;
+            let position = if cfg!(target_os = "macos") {
+                tauri::Position::Logical(LogicalPosition { x, y })
+            } else {
+                tauri::Position::Physical(PhysicalPosition {
+                    x: x.round() as i32,
+                    y: y.round() as i32,
+                })
+            };
+            match animations.step(label, generation, || self.set_position(position)) {
+                Some(result) => result?,
+                None => return Ok(false),
             }
-            #[cfg(feature = "system-tray")]
-            TrayRight => {
-                if let (Some((tray_x, tray_y)), Some((tray_width, _tray_height))) =
-                    (tray_position, tray_size)
-                {
-                    let y = tray_y - window_size.height;
-                    // Choose y value based on the target OS
-                    #[cfg(target_os = "windows")]
-                    let y = if y < 0 { tray_y + _tray_height } else { y };
-
-                    #[cfg(target_os = "macos")]
-                    let y = if y < 0 { tray_y } else { y };
-
-                    PhysicalPosition {
-                        x: tray_x + tray_width,
-                        y,
-                    }
-                } else {
-                    panic!("Tray position not set");
-                }
+        }
+
+        // the window is rescaled when it moves to a monitor with another scale
+        // factor, and the position depends on its size
+        let (end, _) = target_position(self, &pos)?;
+        match animations.step(label, generation, || {
+            self.set_position(tauri::Position::Physical(end))
+        }) {
+            Some(result) => result.map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+/// The outer position of `window` at `pos`, and the scale factor of the
+/// monitor it is on.
+fn target_position<R: Runtime>(
+    window: &Window<R>,
+    pos: &Position,
+) -> Result<(PhysicalPosition<i32>, f64)> {
+    use Position::*;
+
+    let screen = window.current_monitor()?.unwrap();
+    let screen_position = screen.position();
+    let screen_size = PhysicalSize::<i32> {
+        width: screen.size().width as i32,
+        height: screen.size().height as i32,
+    };
+    let window_size = PhysicalSize::<i32> {
+        width: window.outer_size()?.width as i32,
+        height: window.outer_size()?.height as i32,
+    };
+
+    let physical_pos = match pos {
+        TopLeft => *screen_position,
+        TopRight => PhysicalPosition {
+            x: screen_position.x + (screen_size.width - window_size.width),
+            y: screen_position.y,
+        },
+        BottomLeft => PhysicalPosition {
+            x: screen_position.x,
+            y: screen_size.height - (window_size.height - screen_position.y),
+        },
+        BottomRight => PhysicalPosition {
+            x: screen_position.x + (screen_size.width - window_size.width),
+            y: screen_size.height - (window_size.height - screen_position.y),
+        },
+        TopCenter => PhysicalPosition {
+            x: screen_position.x + ((screen_size.width / 2) - (window_size.width / 2)),
+            y: screen_position.y,
+        },
+        BottomCenter => PhysicalPosition {
+            x: screen_position.x + ((screen_size.width / 2) - (window_size.width / 2)),
+            y: screen_size.height - (window_size.height - screen_position.y),
+        },
+        LeftCenter => PhysicalPosition {
+            x: screen_position.x,
+            y: screen_position.y + (screen_size.height / 2) - (window_size.height / 2),
+        },
+        RightCenter => PhysicalPosition {
+            x: screen_position.x + (screen_size.width - window_size.width),
+            y: screen_position.y + (screen_size.height / 2) - (window_size.height / 2),
+        },
+        Center => PhysicalPosition {
+            x: screen_position.x + ((screen_size.width / 2) - (window_size.width / 2)),
+            y: screen_position.y + (screen_size.height / 2) - (window_size.height / 2),
+        },
+        #[cfg(feature = "system-tray")]
+        TrayLeft | TrayBottomLeft | TrayRight | TrayBottomRight | TrayCenter | TrayBottomCenter => {
+            let tray = window
+                .state::<Tray>()
+                .0
+                .lock()
+                .unwrap()
+                .expect("Tray position not set");
+            return tray_relative(window, pos, tray, screen);
+        }
+    };
+
+    Ok((physical_pos, screen.scale_factor()))
+}
+
+/// A rectangle in physical pixels.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rect {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+}
+
+impl Rect {
+    fn of_monitor(monitor: &Monitor) -> Self {
+        Self {
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width as i32,
+            height: monitor.size().height as i32,
+        }
+    }
+}
+
+#[cfg(feature = "system-tray")]
+impl Rect {
+    fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    /// The squared distance of the point `(x, y)` to the rectangle, 0 inside.
+    fn distance(&self, x: i32, y: i32) -> i64 {
+        let dx = (self.x - x).max(x - self.right()).max(0) as i64;
+        let dy = (self.y - y).max(y - self.bottom()).max(0) as i64;
+        dx * dx + dy * dy
+    }
+
+    /// The rectangle of a monitor without the taskbar or menu bar containing
+    /// `tray`, which is on the edge of the monitor closest to the icon.
+    fn without_bar(self, tray: Rect) -> Self {
+        let left = tray.x - self.x;
+        let right = self.right() - tray.right();
+        let top = tray.y - self.y;
+        let bottom = self.bottom() - tray.bottom();
+        let closest = left.min(right).min(top).min(bottom);
+        if closest == top {
+            Self {
+                y: tray.bottom(),
+                height: self.bottom() - tray.bottom(),
+                ..self
             }
-            #[cfg(feature = "system-tray")]
-            TrayBottomRight => {
-                if let (Some((tray_x, tray_y)), Some((tray_width, _))) = (tray_position, tray_size)
-                {
-                    PhysicalPosition {
-                        x: tray_x + tray_width,
-                        y: tray_y,
-                    }
-                } else {
-                    panic!("Tray position not set");
-                }
+        } else if closest == bottom {
+            Self {
+                height: tray.y - self.y,
+                ..self
             }
-            #[cfg(feature = "system-tray")]
-            TrayCenter => {
-                if let (Some((tray_x, tray_y)), Some((tray_width, _tray_height))) =
-                    (tray_position, tray_size)
-                {
-                    let x = tray_x + tray_width / 2 - window_size.width / 2;
-                    let y = tray_y - window_size.height;
-                    // Choose y value based on the target OS
-                    #[cfg(target_os = "windows")]
-                    let y = if y < 0 { tray_y + _tray_height } else { y };
-
-                    #[cfg(target_os = "macos")]
-                    let y = if y < 0 { tray_y } else { y };
-
-                    PhysicalPosition { x, y }
-                } else {
-                    panic!("Tray position not set");
-                }
+        } else if closest == left {
+            Self {
+                x: tray.right(),
+                width: self.right() - tray.right(),
+                ..self
             }
-            #[cfg(feature = "system-tray")]
-            TrayBottomCenter => {
-                if let (Some((tray_x, tray_y)), Some((tray_width, _))) = (tray_position, tray_size)
-                {
-                    PhysicalPosition {
-                        x: tray_x + (tray_width / 2) - (window_size.width / 2),
-                        y: tray_y,
-                    }
-                } else {
-                    panic!("Tray position not set");
-                }
+        } else {
+            Self {
+                width: tray.x - self.x,
+                ..self
             }
-        };
+        }
+    }
+}
+
+/// The position of the window next to the tray icon, within the work area of
+/// the monitor the icon is on. The window is flipped to the other side of the
+/// icon if it would overflow the work area, like native tray popups are.
+#[cfg(feature = "system-tray")]
+fn tray_relative<R: Runtime>(
+    window: &Window<R>,
+    pos: &Position,
+    (tray_position, tray_size): (PhysicalPosition<f64>, PhysicalSize<f64>),
+    current_monitor: Monitor,
+) -> Result<(PhysicalPosition<i32>, f64)> {
+    use Position::*;
+
+    let tray = Rect {
+        x: tray_position.x as i32,
+        y: tray_position.y as i32,
+        width: tray_size.width as i32,
+        height: tray_size.height as i32,
+    };
+    let (center_x, center_y) = (tray.x + tray.width / 2, tray.y + tray.height / 2);
+    let monitor = window
+        .available_monitors()?
+        .into_iter()
+        .min_by_key(|monitor| Rect::of_monitor(monitor).distance(center_x, center_y))
+        .unwrap_or(current_monitor);
+    let area = crate::work_area::work_area(tray)
+        .unwrap_or_else(|| Rect::of_monitor(&monitor).without_bar(tray));
 
-        self.set_position(tauri::Position::Physical(physical_pos))
+    // the window is resized to the scale factor of the monitor it is moved to
+    let scale = monitor.scale_factor() / window.scale_factor()?;
+    let window_size = window.outer_size()?;
+    let width = (window_size.width as f64 * scale).round() as i32;
+    let height = (window_size.height as f64 * scale).round() as i32;
+
+    let x = match pos {
+        TrayLeft | TrayBottomLeft => {
+            flip(tray.x, tray.right() - width, width, area.x, area.right())
+        }
+        TrayRight | TrayBottomRight => {
+            flip(tray.right(), tray.x - width, width, area.x, area.right())
+        }
+        _ => tray.x + tray.width / 2 - width / 2,
+    };
+    let y = match pos {
+        TrayLeft | TrayRight | TrayCenter => flip(
+            tray.y - height,
+            tray.bottom(),
+            height,
+            area.y,
+            area.bottom(),
+        ),
+        _ => flip(
+            tray.bottom(),
+            tray.y - height,
+            height,
+            area.y,
+            area.bottom(),
+        ),
+    };
+
+    Ok((
+        PhysicalPosition {
+            x: clamp(x, width, area.x, area.right()),
+            y: clamp(y, height, area.y, area.bottom()),
+        },
+        monitor.scale_factor(),
+    ))
+}
+
+/// The `preferred` start of a window of length `len`, or the `flipped` one if
+/// only that one fits within `start..end`.
+#[cfg(feature = "system-tray")]
+fn flip(preferred: i32, flipped: i32, len: i32, start: i32, end: i32) -> i32 {
+    let fits = |p: i32| p >= start && p + len <= end;
+    if !fits(preferred) && fits(flipped) {
+        flipped
+    } else {
+        preferred
     }
 }
+
+/// Moves the start of a window of length `len` into `start..end`, at `start`
+/// if the window is longer.
+#[cfg(feature = "system-tray")]
+fn clamp(p: i32, len: i32, start: i32, end: i32) -> i32 {
+    p.min(end - len).max(start)
+}
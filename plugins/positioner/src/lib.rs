@@ -8,8 +8,11 @@
 //! - **system-tray**: Enables system-tray-relative positions.
 //!   
 //!   Note: This requires attaching the Tauri plugin, *even* when using the trait extension only.
+//!
+//! [`WindowExt::move_window_animated`] also requires attaching the Tauri plugin.
 
 mod ext;
+mod work_area;
 
 pub use ext::*;
 use tauri::{
@@ -17,8 +20,10 @@ use tauri::{
     Result, Runtime,
 };
 
+use tauri::Manager;
+
 #[cfg(feature = "system-tray")]
-use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, SystemTrayEvent};
+use tauri::{AppHandle, PhysicalPosition, PhysicalSize, SystemTrayEvent};
 
 #[cfg(feature = "system-tray")]
 struct Tray(std::sync::Mutex<Option<(PhysicalPosition<f64>, PhysicalSize<f64>)>>);
@@ -56,16 +61,38 @@ async fn move_window<R: Runtime>(window: tauri::Window<R>, position: Position) -
     window.move_window(position)
 }
 
-/// The Tauri plugin that exposes [`WindowExt::move_window`] to the webview.
-pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    let plugin =
-        plugin::Builder::new("positioner").invoke_handler(tauri::generate_handler![move_window]);
+#[tauri::command]
+async fn move_window_to<R: Runtime>(window: tauri::Window<R>, placement: Placement) -> Result<()> {
+    window.move_window_to(placement)
+}
 
-    #[cfg(feature = "system-tray")]
-    let plugin = plugin.setup(|app_handle| {
-        app_handle.manage(Tray(std::sync::Mutex::new(None)));
-        Ok(())
-    });
+#[tauri::command]
+async fn move_window_animated<R: Runtime>(
+    window: tauri::Window<R>,
+    position: Position,
+    animation: Option<Animation>,
+) -> Result<bool> {
+    tauri::async_runtime::spawn_blocking(move || {
+        window.move_window_animated(position, animation.unwrap_or_default())
+    })
+    .await?
+}
 
-    plugin.build()
+/// The Tauri plugin that exposes [`WindowExt::move_window`],
+/// [`WindowExt::move_window_to`] and [`WindowExt::move_window_animated`] to the
+/// webview.
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    plugin::Builder::new("positioner")
+        .invoke_handler(tauri::generate_handler![
+            move_window,
+            move_window_to,
+            move_window_animated
+        ])
+        .setup(|app_handle| {
+            app_handle.manage(ext::Animations::default());
+            #[cfg(feature = "system-tray")]
+            app_handle.manage(Tray(std::sync::Mutex::new(None)));
+            Ok(())
+        })
+        .build()
 }
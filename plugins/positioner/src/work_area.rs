@@ -0,0 +1,47 @@
+// Copyright 2021 Jonas Kruckenberg
+// SPDX-License-Identifier: MIT
+
+use crate::ext::Rect;
+
+/// The work area of the monitor containing `rect`, the part of it that isn't
+/// covered by the taskbar, in physical pixels.
+#[cfg(target_os = "windows")]
+pub(crate) fn work_area(rect: Rect) -> Option<Rect> {
+    use windows_sys::Win32::{
+        Foundation::RECT,
+        Graphics::Gdi::{GetMonitorInfoW, MonitorFromRect, MONITORINFO, MONITOR_DEFAULTTONEAREST},
+    };
+
+    let rect = RECT {
+        left: rect.x,
+        top: rect.y,
+        right: rect.x + rect.width,
+        bottom: rect.y + rect.height,
+    };
+    let mut info = MONITORINFO {
+        cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+        rcMonitor: rect,
+        rcWork: rect,
+        dwFlags: 0,
+    };
+    unsafe {
+        let monitor = MonitorFromRect(&rect, MONITOR_DEFAULTTONEAREST);
+        if monitor == 0 || GetMonitorInfoW(monitor, &mut info) == 0 {
+            return None;
+        }
+    }
+    let work = info.rcWork;
+    Some(Rect {
+        x: work.left,
+        y: work.top,
+        width: work.right - work.left,
+        height: work.bottom - work.top,
+    })
+}
+
+/// The work area isn't queried on the other platforms, the tray positions infer
+/// it from the position of the tray icon instead.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn work_area(_rect: Rect) -> Option<Rect> {
+    None
+}
@@ -1,3 +1,7 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 use tauri::{plugin::TauriPlugin, AppHandle, Manager, Runtime};
 
 #[cfg(target_os = "windows")]
@@ -11,12 +15,241 @@ mod platform_impl;
 mod platform_impl;
 
 pub(crate) type SingleInstanceCallback<R> =
-    dyn FnMut(&AppHandle<R>, Vec<String>, String) + Send + Sync + 'static;
+    dyn FnMut(&AppHandle<R>, SecondInstance) -> Reply + Send + Sync + 'static;
+
+type InstanceKey = dyn Fn(&[String]) -> Option<String> + Send + Sync + 'static;
+
+type OnReply = dyn FnOnce(Option<Reply>) + Send + Sync + 'static;
+
+/// How long the second instance waits for the [`Reply`] by default.
+const DEFAULT_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The exit code of the second instance if the running one didn't reply.
+const NO_REPLY_EXIT_CODE: i32 = 1;
+
+/// The launch of a second instance, passed to the callback of the running one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct SecondInstance {
+    /// The arguments the second instance was launched with.
+    pub argv: Vec<String>,
+    /// The working directory of the second instance.
+    pub cwd: String,
+    /// The environment variables of [`Builder::env`] that are set in the
+    /// second instance. It is empty if the second instance is an older version
+    /// of your app, from before this was sent.
+    pub env: HashMap<String, String>,
+    /// The key of the instance, see [`Builder::instance_key`], or `None` for
+    /// the single instance of the app.
+    pub key: Option<String>,
+}
+
+impl SecondInstance {
+    /// The launch of this process, with the variables of `env` that are set.
+    pub(crate) fn current(env: &[String]) -> Self {
+        Self {
+            argv: std::env::args().collect(),
+            cwd: std::env::current_dir()
+                .unwrap_or_default()
+                .to_str()
+                .unwrap_or_default()
+                .to_string(),
+            env: env
+                .iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| (name.clone(), value)))
+                .collect(),
+            key: None,
+        }
+    }
+
+    /// Drops the variables that aren't in `env`, received ones aren't trusted to
+    /// be allowlisted.
+    pub(crate) fn retain_env(mut self, env: &[String]) -> Self {
+        self.env.retain(|name, _| env.contains(name));
+        self
+    }
+}
+
+/// The reply of the running instance to a second one, returned by the callback
+/// of [`Builder::build`] and passed to [`Builder::on_reply`] in the second
+/// instance, which then exits with [`Reply::exit_code`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Reply {
+    pub exit_code: i32,
+    pub response: JsonValue,
+}
+
+impl Reply {
+    /// A reply with `response` and an exit code of 0. It is `null` if it can't
+    /// be serialized.
+    pub fn new(response: impl Serialize) -> Self {
+        Self {
+            exit_code: 0,
+            response: serde_json::to_value(response).unwrap_or_default(),
+        }
+    }
+
+    pub fn exit_code(mut self, exit_code: i32) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+}
+
+impl From<()> for Reply {
+    fn from(_: ()) -> Self {
+        Self::default()
+    }
+}
+
+/// What the platform implementations need besides the callback.
+// there is no second instance on macOS, which doesn't use the options
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub(crate) struct Options {
+    pub(crate) env: Vec<String>,
+    pub(crate) key: Option<String>,
+    pub(crate) reply_timeout: Duration,
+    on_reply: Option<Box<OnReply>>,
+}
+
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+impl Options {
+    /// Hands the reply of the running instance to [`Builder::on_reply`], and
+    /// returns the exit code of the second instance.
+    pub(crate) fn replied(&mut self, reply: Option<Reply>) -> i32 {
+        let exit_code = reply
+            .as_ref()
+            .map_or(NO_REPLY_EXIT_CODE, |reply| reply.exit_code);
+        if let Some(on_reply) = self.on_reply.take() {
+            on_reply(reply);
+        }
+        exit_code
+    }
+}
+
+pub struct Builder {
+    env: Vec<String>,
+    instance_key: Option<Box<InstanceKey>>,
+    reply_timeout: Duration,
+    on_reply: Option<Box<OnReply>>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            env: Vec::new(),
+            instance_key: None,
+            reply_timeout: DEFAULT_REPLY_TIMEOUT,
+            on_reply: None,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The names of the environment variables the second instance sends to the
+    /// running one, see [`SecondInstance::env`]. None are sent by default.
+    pub fn env(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.env = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Computes the key of the instance from its arguments, to run one instance
+    /// per key instead of one for the app, e.g. one per `--profile`. A second
+    /// launch with the same key is passed to the instance running with it, one
+    /// with another key starts a new instance. `None` is the key of the single
+    /// instance the app has by default.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// Builder::new().instance_key(|argv| {
+    ///     let i = argv.iter().position(|arg| arg == "--profile")?;
+    ///     argv.get(i + 1).cloned()
+    /// })
+    /// ```
+    pub fn instance_key<F: Fn(&[String]) -> Option<String> + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.instance_key = Some(Box::new(f));
+        self
+    }
+
+    /// Called in the second instance with the reply of the running one before
+    /// it exits, e.g. to print the result of a command line invocation. It is
+    /// `None` if the running instance didn't reply within
+    /// [`Builder::reply_timeout`], the second instance then exits with 1.
+    ///
+    /// An older version of the app running doesn't reply with a response, its
+    /// reply is the default one.
+    pub fn on_reply<F: FnOnce(Option<Reply>) + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.on_reply = Some(Box::new(f));
+        self
+    }
+
+    /// How long the second instance waits for the running one to reply, 5
+    /// seconds by default.
+    pub fn reply_timeout(mut self, reply_timeout: Duration) -> Self {
+        self.reply_timeout = reply_timeout;
+        self
+    }
+
+    /// Builds the plugin with the callback of the running instance, called for
+    /// each second instance. What it returns is sent to the second instance as
+    /// its [`Reply`], `()` being the default reply.
+    pub fn build<R, F, T>(self, mut f: F) -> TauriPlugin<R>
+    where
+        R: Runtime,
+        F: FnMut(&AppHandle<R>, SecondInstance) -> T + Send + Sync + 'static,
+        T: Into<Reply>,
+    {
+        let key = self
+            .instance_key
+            .and_then(|instance_key| instance_key(&std::env::args().collect::<Vec<_>>()));
+        platform_impl::init(
+            Box::new(move |app, instance| f(app, instance).into()),
+            Options {
+                env: self.env,
+                key,
+                reply_timeout: self.reply_timeout,
+                on_reply: self.on_reply,
+            },
+        )
+    }
+}
+
+/// Encodes an instance key into ASCII letters, digits and `_`, which all the
+/// platforms allow in the names of the IPC objects. Distinct keys map to
+/// distinct names, long ones are shortened to a hash.
+pub(crate) fn encode_key(key: &str) -> String {
+    let mut encoded = String::new();
+    for b in key.bytes() {
+        if b.is_ascii_alphanumeric() {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("_{b:02x}"));
+        }
+    }
+    if encoded.len() > 64 {
+        // FNV-1a, which unlike the std hashers is stable across Rust versions,
+        // `_h` can't be the start of an escaped byte
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        encoded = format!("_h{hash:016x}");
+    }
+    encoded
+}
 
 pub fn init<R: Runtime, F: FnMut(&AppHandle<R>, Vec<String>, String) + Send + Sync + 'static>(
-    f: F,
+    mut f: F,
 ) -> TauriPlugin<R> {
-    platform_impl::init(Box::new(f))
+    Builder::new().build(move |app, instance| f(app, instance.argv, instance.cwd))
 }
 
 pub fn destroy<R: Runtime, M: Manager<R>>(manager: &M) {
@@ -1,45 +1,102 @@
 #![cfg(target_os = "linux")]
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::mpsc, time::Duration};
 
-use crate::SingleInstanceCallback;
+use crate::{encode_key, Options, Reply, SecondInstance, SingleInstanceCallback};
 use tauri::{
     plugin::{self, TauriPlugin},
-    AppHandle, Config, Manager, RunEvent, Runtime,
+    AppHandle, Manager, RunEvent, Runtime,
 };
 use zbus::{
     blocking::{Connection, ConnectionBuilder},
     dbus_interface,
 };
 
-struct ConnectionHandle(Connection);
+struct ConnectionHandle {
+    connection: Connection,
+    dbus_name: String,
+}
 
 struct SingleInstanceDBus<R: Runtime> {
     callback: Box<SingleInstanceCallback<R>>,
     app_handle: AppHandle<R>,
+    env: Vec<String>,
+    key: Option<String>,
 }
 
 #[dbus_interface(name = "org.SingleInstance.DBus")]
 impl<R: Runtime> SingleInstanceDBus<R> {
+    /// Called by older versions of the app, without the environment.
     fn execute_callback(&mut self, argv: Vec<String>, cwd: String) {
-        (self.callback)(&self.app_handle, argv, cwd);
+        self.execute_callback_v3(argv, cwd, HashMap::new());
+    }
+
+    /// Called by older versions of the app, which don't wait for a reply.
+    fn execute_callback_v2(
+        &mut self,
+        argv: Vec<String>,
+        cwd: String,
+        env: HashMap<String, String>,
+    ) {
+        self.execute_callback_v3(argv, cwd, env);
     }
-}
 
-fn dbus_id(config: Arc<Config>) -> String {
-    config.tauri.bundle.identifier.replace(['.', '-'], "_")
+    /// Returns the [`Reply`] serialized as JSON.
+    fn execute_callback_v3(
+        &mut self,
+        argv: Vec<String>,
+        cwd: String,
+        env: HashMap<String, String>,
+    ) -> String {
+        let instance = SecondInstance {
+            argv,
+            cwd,
+            env,
+            // the instance was reached by its name, derived from the key
+            key: self.key.clone(),
+        }
+        .retain_env(&self.env);
+        let reply = (self.callback)(&self.app_handle, instance);
+        serde_json::to_string(&reply).unwrap_or_default()
+    }
 }
 
-pub fn init<R: Runtime>(f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
+pub(crate) fn init<R: Runtime>(
+    f: Box<SingleInstanceCallback<R>>,
+    mut options: Options,
+) -> TauriPlugin<R> {
     plugin::Builder::new("single-instance")
-        .setup(|app| {
-            let id = dbus_id(app.config());
+        .setup(move |app| {
+            let id = app
+                .config()
+                .tauri
+                .bundle
+                .identifier
+                .replace(['.', '-'], "_");
+            // elements of names and paths can't start with a digit
+            let key_element = options
+                .key
+                .as_deref()
+                .map(|key| format!("K{}", encode_key(key)))
+                .unwrap_or_default();
+            let (dbus_name, dbus_path) = if key_element.is_empty() {
+                (
+                    format!("org.{id}.SingleInstance"),
+                    format!("/org/{id}/SingleInstance"),
+                )
+            } else {
+                (
+                    format!("org.{id}.SingleInstance.{key_element}"),
+                    format!("/org/{id}/SingleInstance/{key_element}"),
+                )
+            };
+            let instance = SecondInstance::current(&options.env);
             let single_instance_dbus = SingleInstanceDBus {
                 callback: f,
                 app_handle: app.clone(),
+                env: options.env.clone(),
+                key: options.key.clone(),
             };
-            let dbus_name = format!("org.{id}.SingleInstance");
-            let dbus_path = format!("/org/{id}/SingleInstance");
 
             match ConnectionBuilder::session()
                 .unwrap()
@@ -50,25 +107,22 @@ pub fn init<R: Runtime>(f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
                 .build()
             {
                 Ok(connection) => {
-                    app.manage(ConnectionHandle(connection));
+                    app.manage(ConnectionHandle {
+                        connection,
+                        dbus_name,
+                    });
                 }
                 Err(zbus::Error::NameTaken) => {
-                    if let Ok(connection) = Connection::session() {
-                        let _ = connection.call_method(
-                            Some(dbus_name.as_str()),
-                            dbus_path.as_str(),
-                            Some("org.SingleInstance.DBus"),
-                            "ExecuteCallback",
-                            &(
-                                std::env::args().collect::<Vec<String>>(),
-                                std::env::current_dir()
-                                    .unwrap_or_default()
-                                    .to_str()
-                                    .unwrap_or_default(),
-                            ),
-                        );
-                    }
-                    std::process::exit(0)
+                    let reply = Connection::session().ok().and_then(|connection| {
+                        send(
+                            connection,
+                            dbus_name,
+                            dbus_path,
+                            instance,
+                            options.reply_timeout,
+                        )
+                    });
+                    std::process::exit(options.replied(reply))
                 }
                 _ => {}
             }
@@ -83,9 +137,124 @@ pub fn init<R: Runtime>(f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
         .build()
 }
 
+/// Sends the launch of this instance to the running one, and waits up to
+/// `timeout` for its reply.
+fn send(
+    connection: Connection,
+    dbus_name: String,
+    dbus_path: String,
+    instance: SecondInstance,
+    timeout: Duration,
+) -> Option<Reply> {
+    with_timeout(timeout, move || {
+        let call_method = |method, body: &(&Vec<String>, &String, &HashMap<String, String>)| {
+            connection.call_method(
+                Some(dbus_name.as_str()),
+                dbus_path.as_str(),
+                Some("org.SingleInstance.DBus"),
+                method,
+                body,
+            )
+        };
+        let body = (&instance.argv, &instance.cwd, &instance.env);
+        match call_method("ExecuteCallbackV3", &body) {
+            Ok(message) => message
+                .body::<String>()
+                .ok()
+                .and_then(|reply| serde_json::from_str(&reply).ok()),
+            // an older version of the app doesn't reply, and may only have
+            // `ExecuteCallback`
+            Err(e) if is_unknown_method(&e) => match call_method("ExecuteCallbackV2", &body) {
+                Err(e) if is_unknown_method(&e) => connection.call_method(
+                    Some(dbus_name.as_str()),
+                    dbus_path.as_str(),
+                    Some("org.SingleInstance.DBus"),
+                    "ExecuteCallback",
+                    &(&instance.argv, &instance.cwd),
+                ),
+                result => result,
+            }
+            .ok()
+            .map(|_| Reply::default()),
+            // the callback may have run, calling it again would run it twice
+            Err(_) => None,
+        }
+    })
+}
+
+/// Runs the blocking `call` on a thread of its own, and waits up to `timeout`
+/// for its result. The blocking calls can't time out, so the thread is
+/// abandoned if they hang.
+fn with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    call: impl FnOnce() -> Option<T> + Send + 'static,
+) -> Option<T> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(call());
+    });
+    rx.recv_timeout(timeout).ok().flatten()
+}
+
+/// Whether the running instance doesn't have the called method, because it
+/// is an older version of the app.
+fn is_unknown_method(error: &zbus::Error) -> bool {
+    match error {
+        zbus::Error::MethodError(name, _, _) => name.as_str() == UNKNOWN_METHOD,
+        zbus::Error::FDO(error) => matches!(**error, zbus::fdo::Error::UnknownMethod(_)),
+        _ => false,
+    }
+}
+
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
 pub fn destroy<R: Runtime, M: Manager<R>>(manager: &M) {
-    if let Some(connection) = manager.try_state::<ConnectionHandle>() {
-        let dbus_name = format!("org.{}.SingleInstance", dbus_id(manager.config()));
-        let _ = connection.0.release_name(dbus_name);
+    if let Some(handle) = manager.try_state::<ConnectionHandle>() {
+        let _ = handle.connection.release_name(handle.dbus_name.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn gives_up_waiting_after_the_timeout() {
+        let start = Instant::now();
+        let reply = with_timeout(Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_secs(5));
+            Some(Reply::default())
+        });
+        assert!(reply.is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn returns_the_reply_received_in_time() {
+        assert_eq!(with_timeout(Duration::from_secs(5), || Some(1)), Some(1));
+        assert_eq!(with_timeout(Duration::from_secs(5), || None::<u8>), None);
+    }
+
+    #[test]
+    fn falls_back_only_to_methods_unknown_to_the_running_instance() {
+        let fdo = |error| zbus::Error::FDO(Box::new(error));
+        assert!(is_unknown_method(&fdo(zbus::fdo::Error::UnknownMethod(
+            "ExecuteCallbackV3".into()
+        ))));
+
+        let errors = [
+            fdo(zbus::fdo::Error::NoReply("timed out".into())),
+            fdo(zbus::fdo::Error::AccessDenied("denied".into())),
+            fdo(zbus::fdo::Error::UnknownObject("no object".into())),
+            zbus::Error::InputOutput(std::sync::Arc::new(std::io::Error::from(
+                std::io::ErrorKind::BrokenPipe,
+            ))),
+            zbus::Error::InvalidReply,
+        ];
+        for error in errors {
+            assert!(!is_unknown_method(&error), "{error}");
+        }
     }
 }
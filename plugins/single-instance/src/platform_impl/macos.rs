@@ -1,11 +1,14 @@
 #![cfg(target_os = "macos")]
 
-use crate::SingleInstanceCallback;
+use crate::{Options, SingleInstanceCallback};
 use tauri::{
     plugin::{self, TauriPlugin},
     Manager, Runtime,
 };
-pub fn init<R: Runtime>(_f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
+pub(crate) fn init<R: Runtime>(
+    _f: Box<SingleInstanceCallback<R>>,
+    _options: Options,
+) -> TauriPlugin<R> {
     plugin::Builder::new("single-instance").build()
 }
 
@@ -1,7 +1,10 @@
 #![cfg(target_os = "windows")]
 
-use crate::SingleInstanceCallback;
-use std::ffi::CStr;
+use crate::{encode_key, Options, Reply, SecondInstance, SingleInstanceCallback};
+use std::{
+    ffi::CStr,
+    time::{Duration, Instant},
+};
 use tauri::{
     plugin::{self, TauriPlugin},
     AppHandle, Manager, RunEvent, Runtime,
@@ -15,21 +18,51 @@ use windows_sys::Win32::{
     },
     UI::WindowsAndMessaging::{
         self as w32wm, CreateWindowExW, DefWindowProcW, DestroyWindow, FindWindowW,
-        RegisterClassExW, SendMessageW, GWL_STYLE, GWL_USERDATA, WINDOW_LONG_PTR_INDEX,
-        WM_COPYDATA, WM_DESTROY, WNDCLASSEXW, WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW,
-        WS_EX_TRANSPARENT, WS_OVERLAPPED, WS_POPUP, WS_VISIBLE,
+        RegisterClassExW, SendMessageTimeoutW, SendMessageW, GWL_STYLE, GWL_USERDATA, HWND_MESSAGE,
+        SMTO_ABORTIFHUNG, WINDOW_LONG_PTR_INDEX, WM_COPYDATA, WM_DESTROY, WNDCLASSEXW,
+        WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TRANSPARENT, WS_OVERLAPPED,
+        WS_POPUP, WS_VISIBLE,
     },
 };
 
 struct MutexHandle(isize);
 struct TargetWindowHandle(isize);
 
+/// `cwd|argv` joined with `|`, sent by older versions of the app.
 const WMCOPYDATA_SINGLE_INSTANCE_DATA: usize = 1542;
+/// A [`SecondInstance`] serialized as JSON.
+const WMCOPYDATA_SINGLE_INSTANCE_DATA_V2: usize = 1543;
+/// The result of handling [`WMCOPYDATA_SINGLE_INSTANCE_DATA_V2`]. Older
+/// versions of the app return 1 for any `WM_COPYDATA`, it tells the second
+/// instance to fall back to [`WMCOPYDATA_SINGLE_INSTANCE_DATA`].
+const WMCOPYDATA_SINGLE_INSTANCE_DATA_V2_HANDLED: LRESULT = 2;
+/// A [`SecondInstance`] serialized as JSON, with the window of the second
+/// instance the [`Reply`] is sent to as the `wParam`.
+const WMCOPYDATA_SINGLE_INSTANCE_DATA_V3: usize = 1544;
+/// The result of handling [`WMCOPYDATA_SINGLE_INSTANCE_DATA_V3`], older versions
+/// of the app return 1 or [`WMCOPYDATA_SINGLE_INSTANCE_DATA_V2_HANDLED`].
+const WMCOPYDATA_SINGLE_INSTANCE_DATA_V3_HANDLED: LRESULT = 3;
+/// A [`Reply`] serialized as JSON, sent back to the second instance.
+const WMCOPYDATA_SINGLE_INSTANCE_REPLY: usize = 1545;
+
+/// The state of the event target window.
+struct WindowData<R: Runtime> {
+    app_handle: AppHandle<R>,
+    callback: Box<SingleInstanceCallback<R>>,
+    env: Vec<String>,
+    key: Option<String>,
+}
 
-pub fn init<R: Runtime>(f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
+pub(crate) fn init<R: Runtime>(
+    f: Box<SingleInstanceCallback<R>>,
+    mut options: Options,
+) -> TauriPlugin<R> {
     plugin::Builder::new("single-instance")
-        .setup(|app| {
-            let id = &app.config().tauri.bundle.identifier;
+        .setup(move |app| {
+            let mut id = app.config().tauri.bundle.identifier.clone();
+            if let Some(key) = &options.key {
+                id = format!("{id}-{}", encode_key(key));
+            }
 
             let class_name = encode_wide(format!("{id}-sic"));
             let window_name = encode_wide(format!("{id}-siw"));
@@ -43,22 +76,9 @@ pub fn init<R: Runtime>(f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
                     let hwnd = FindWindowW(class_name.as_ptr(), window_name.as_ptr());
 
                     if hwnd != 0 {
-                        let data = format!(
-                            "{}|{}\0",
-                            std::env::current_dir()
-                                .unwrap_or_default()
-                                .to_str()
-                                .unwrap_or_default(),
-                            std::env::args().collect::<Vec<String>>().join("|")
-                        );
-                        let bytes = data.as_bytes();
-                        let cds = COPYDATASTRUCT {
-                            dwData: WMCOPYDATA_SINGLE_INSTANCE_DATA,
-                            cbData: bytes.len() as _,
-                            lpData: bytes.as_ptr() as _,
-                        };
-                        SendMessageW(hwnd, WM_COPYDATA, 0, &cds as *const _ as _);
-                        app.exit(0);
+                        let instance = SecondInstance::current(&options.env);
+                        let reply = send(hwnd, &id, &instance, options.reply_timeout);
+                        app.exit(options.replied(reply));
                     }
                 }
             } else {
@@ -69,7 +89,12 @@ pub fn init<R: Runtime>(f: Box<SingleInstanceCallback<R>>) -> TauriPlugin<R> {
                     SetWindowLongPtrW(
                         hwnd,
                         GWL_USERDATA,
-                        Box::into_raw(Box::new((app.clone(), f))) as _,
+                        Box::into_raw(Box::new(WindowData {
+                            app_handle: app.clone(),
+                            callback: f,
+                            env: options.env.clone(),
+                            key: options.key.clone(),
+                        })) as _,
                     )
                 };
 
@@ -104,21 +129,56 @@ unsafe extern "system" fn single_instance_window_proc<R: Runtime>(
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
-    let data_ptr = GetWindowLongPtrW(hwnd, GWL_USERDATA)
-        as *mut (AppHandle<R>, Box<SingleInstanceCallback<R>>);
-    let (app_handle, callback) = &mut *data_ptr;
+    let data_ptr = GetWindowLongPtrW(hwnd, GWL_USERDATA) as *mut WindowData<R>;
+    let data = &mut *data_ptr;
 
     match msg {
         WM_COPYDATA => {
             let cds_ptr = lparam as *const COPYDATASTRUCT;
-            if (*cds_ptr).dwData == WMCOPYDATA_SINGLE_INSTANCE_DATA {
-                let data = CStr::from_ptr((*cds_ptr).lpData as _).to_string_lossy();
-                let mut s = data.split('|');
-                let cwd = s.next().unwrap();
-                let args = s.map(|s| s.to_string()).collect();
-                callback(app_handle, args, cwd.to_string());
+            match (*cds_ptr).dwData {
+                WMCOPYDATA_SINGLE_INSTANCE_DATA => {
+                    let text = CStr::from_ptr((*cds_ptr).lpData as _).to_string_lossy();
+                    let mut s = text.split('|');
+                    let cwd = s.next().unwrap();
+                    let argv = s.map(|s| s.to_string()).collect();
+                    let instance = SecondInstance {
+                        argv,
+                        cwd: cwd.to_string(),
+                        key: data.key.clone(),
+                        ..Default::default()
+                    };
+                    (data.callback)(&data.app_handle, instance);
+                    1
+                }
+                kind
+                @ (WMCOPYDATA_SINGLE_INSTANCE_DATA_V2 | WMCOPYDATA_SINGLE_INSTANCE_DATA_V3) => {
+                    match serde_json::from_slice::<SecondInstance>(copy_data_bytes(cds_ptr)) {
+                        Ok(mut instance) => {
+                            // the window was found by its name, derived from the key
+                            instance.key = data.key.clone();
+                            let reply =
+                                (data.callback)(&data.app_handle, instance.retain_env(&data.env));
+                            if kind == WMCOPYDATA_SINGLE_INSTANCE_DATA_V3 {
+                                // the second instance handles it while it waits for this message
+                                let reply = serde_json::to_vec(&reply).unwrap_or_default();
+                                send_copy_data(
+                                    wparam as HWND,
+                                    hwnd,
+                                    WMCOPYDATA_SINGLE_INSTANCE_REPLY,
+                                    &reply,
+                                );
+                            }
+                        }
+                        Err(e) => log::error!("invalid single instance data: {e}"),
+                    }
+                    if kind == WMCOPYDATA_SINGLE_INSTANCE_DATA_V3 {
+                        WMCOPYDATA_SINGLE_INSTANCE_DATA_V3_HANDLED
+                    } else {
+                        WMCOPYDATA_SINGLE_INSTANCE_DATA_V2_HANDLED
+                    }
+                }
+                _ => 1,
             }
-            1
         }
 
         WM_DESTROY => {
@@ -129,6 +189,155 @@ unsafe extern "system" fn single_instance_window_proc<R: Runtime>(
     }
 }
 
+/// Sends the launch of this instance to the window `hwnd` of the running one,
+/// and waits up to `timeout` for its reply.
+unsafe fn send(
+    hwnd: HWND,
+    id: &str,
+    instance: &SecondInstance,
+    timeout: Duration,
+) -> Option<Reply> {
+    let deadline = Instant::now() + timeout;
+    let remaining = || deadline.saturating_duration_since(Instant::now());
+    let data = serde_json::to_vec(instance).unwrap_or_default();
+
+    let class_name = encode_wide(format!("{id}-sirc"));
+    let reply_hwnd = create_reply_window(&class_name);
+    let mut reply = None::<Reply>;
+    SetWindowLongPtrW(reply_hwnd, GWL_USERDATA, &mut reply as *mut _ as _);
+    let result = send_copy_data_timeout(
+        hwnd,
+        reply_hwnd,
+        WMCOPYDATA_SINGLE_INSTANCE_DATA_V3,
+        &data,
+        remaining(),
+    );
+    SetWindowLongPtrW(reply_hwnd, GWL_USERDATA, 0);
+    DestroyWindow(reply_hwnd);
+    if result? == WMCOPYDATA_SINGLE_INSTANCE_DATA_V3_HANDLED {
+        return Some(reply.unwrap_or_default());
+    }
+
+    // an older version of the app doesn't reply
+    let result = send_copy_data_timeout(
+        hwnd,
+        0,
+        WMCOPYDATA_SINGLE_INSTANCE_DATA_V2,
+        &data,
+        remaining(),
+    )?;
+    if result != WMCOPYDATA_SINGLE_INSTANCE_DATA_V2_HANDLED {
+        let data = format!("{}|{}\0", instance.cwd, instance.argv.join("|"));
+        send_copy_data_timeout(
+            hwnd,
+            0,
+            WMCOPYDATA_SINGLE_INSTANCE_DATA,
+            data.as_bytes(),
+            remaining(),
+        )?;
+    }
+    Some(Reply::default())
+}
+
+unsafe fn send_copy_data(hwnd: HWND, sender: HWND, kind: usize, data: &[u8]) -> LRESULT {
+    let cds = COPYDATASTRUCT {
+        dwData: kind,
+        cbData: data.len() as _,
+        lpData: data.as_ptr() as _,
+    };
+    SendMessageW(hwnd, WM_COPYDATA, sender as _, &cds as *const _ as _)
+}
+
+/// Like [`send_copy_data`], or `None` if the window didn't handle the message
+/// within `timeout` or is hung.
+unsafe fn send_copy_data_timeout(
+    hwnd: HWND,
+    sender: HWND,
+    kind: usize,
+    data: &[u8],
+    timeout: Duration,
+) -> Option<LRESULT> {
+    let cds = COPYDATASTRUCT {
+        dwData: kind,
+        cbData: data.len() as _,
+        lpData: data.as_ptr() as _,
+    };
+    let mut result = 0;
+    // unlike `SMTO_BLOCK`, messages sent to this thread are handled while it
+    // waits, which the reply is
+    let sent = SendMessageTimeoutW(
+        hwnd,
+        WM_COPYDATA,
+        sender as _,
+        &cds as *const _ as _,
+        SMTO_ABORTIFHUNG,
+        timeout.as_millis().try_into().unwrap_or(u32::MAX),
+        &mut result,
+    );
+    (sent != 0).then_some(result as LRESULT)
+}
+
+unsafe fn copy_data_bytes<'a>(cds_ptr: *const COPYDATASTRUCT) -> &'a [u8] {
+    if (*cds_ptr).lpData.is_null() {
+        return &[];
+    }
+    std::slice::from_raw_parts((*cds_ptr).lpData as *const u8, (*cds_ptr).cbData as usize)
+}
+
+unsafe extern "system" fn reply_window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_COPYDATA {
+        let cds_ptr = lparam as *const COPYDATASTRUCT;
+        let reply_ptr = GetWindowLongPtrW(hwnd, GWL_USERDATA) as *mut Option<Reply>;
+        if (*cds_ptr).dwData == WMCOPYDATA_SINGLE_INSTANCE_REPLY && !reply_ptr.is_null() {
+            *reply_ptr = serde_json::from_slice(copy_data_bytes(cds_ptr)).ok();
+        }
+        return 1;
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+/// Creates the message-only window the second instance receives the reply with.
+fn create_reply_window(class_name: &[u16]) -> HWND {
+    unsafe {
+        let class = WNDCLASSEXW {
+            cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+            style: 0,
+            lpfnWndProc: Some(reply_window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: GetModuleHandleW(std::ptr::null()),
+            hIcon: 0,
+            hCursor: 0,
+            hbrBackground: 0,
+            lpszMenuName: std::ptr::null(),
+            lpszClassName: class_name.as_ptr(),
+            hIconSm: 0,
+        };
+
+        RegisterClassExW(&class);
+
+        CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            std::ptr::null(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            0,
+            GetModuleHandleW(std::ptr::null()),
+            std::ptr::null(),
+        )
+    }
+}
+
 fn create_event_target_window<R: Runtime>(class_name: &[u16], window_name: &[u16]) -> HWND {
     unsafe {
         let class = WNDCLASSEXW {
@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 export interface QueryResult {
   /** The number of rows affected by the query. */
@@ -14,6 +15,392 @@ export interface QueryResult {
   lastInsertId: number;
 }
 
+/**
+ * Values bound to a query. Either a positional array or an object whose keys
+ * are referenced in the query as `:name`. A named value may be referenced
+ * multiple times but is only passed once.
+ */
+export type BindValues = unknown[] | Record<string, unknown>;
+
+/** Options applied when connecting to a database. */
+export interface ConnectionOptions {
+  /**
+   * SQLCipher key used to encrypt the database file at rest.
+   *
+   * Only available on SQLite, and requires the `sqlcipher` Cargo feature.
+   */
+  encryptionKey?: string;
+  /** The number of connections the pool keeps open, even when idle. */
+  minConnections?: number;
+  /**
+   * How long to wait for a connection from the pool, in milliseconds.
+   * Defaults to 30 seconds.
+   */
+  acquireTimeout?: number;
+  /**
+   * Opens the `minConnections` before `load` resolves, instead of in the
+   * background, so the first queries don't wait for a connection.
+   */
+  warmUp?: boolean;
+  /**
+   * Connects through a Unix domain socket instead of TCP. On Postgres this is
+   * the directory containing the socket, e.g. `/var/run/postgresql`, on MySQL
+   * the path of the socket file. Not available on SQLite.
+   */
+  socket?: string;
+  /**
+   * The default timeout of every statement, in milliseconds, after which the
+   * server cancels it. Postgres sets `statement_timeout` and MySQL
+   * `max_execution_time`, which only applies to `SELECT` statements. The
+   * timeout of {@link Database.queryWithTimeoutAndRetry} overrides it. Not
+   * available on SQLite.
+   */
+  statementTimeout?: number;
+}
+
+/** The database server version and the features it supports. */
+export interface DatabaseInfo {
+  backend: "sqlite" | "mysql" | "mariadb" | "postgres";
+  /** The full version string reported by the database. */
+  version: string;
+  major: number;
+  minor: number;
+  capabilities: {
+    /** `INSERT ... RETURNING`. */
+    returning: boolean;
+    /** `INSERT ... ON CONFLICT` or `ON DUPLICATE KEY UPDATE`. */
+    upsert: boolean;
+    /** Window functions like `ROW_NUMBER() OVER (...)`. */
+    windowFunctions: boolean;
+    /** `LISTEN` / `NOTIFY`. */
+    listenNotify: boolean;
+  };
+}
+
+/** Options for {@link Database.queryWithTimeoutAndRetry}. */
+export interface RetryOptions {
+  /**
+   * Timeout of a single attempt, in milliseconds. On MySQL and Postgres it is
+   * also the statement timeout of the query, instead of `statementTimeout`.
+   */
+  timeout?: number;
+  /** Total number of attempts, including the first one. Defaults to `3`. */
+  attempts?: number;
+  /**
+   * Delay before the first retry, in milliseconds. Doubles on every retry.
+   * Defaults to `100`.
+   */
+  backoff?: number;
+}
+
+/** Options for {@link Database.select}. */
+export interface ExplainOptions {
+  /** Also returns the values in bind order. Off by default, as they may be sensitive. */
+  includeValues?: boolean;
+  /**
+   * Also returns the plan of the database for the query, the rows of its
+   * `EXPLAIN` (`EXPLAIN QUERY PLAN` on SQLite). Fails for queries other than
+   * selects.
+   */
+  plan?: boolean;
+}
+
+export interface ExplainedQuery {
+  /** The SQL sent to the database, with the driver's placeholders. */
+  sql: string;
+  /** The number of values bound to the query. */
+  parameterCount: number;
+  /** The values in bind order, with `includeValues`. */
+  values?: unknown[];
+  /** The rows of the query's `EXPLAIN`, with `plan`. */
+  plan?: Array<Record<string, unknown>>;
+}
+
+/** The prepared statements cached by the idle connections of the pool. */
+export interface StatementCache {
+  /** The number of idle connections. */
+  connections: number;
+  /** The number of cached statements, summed over the idle connections. */
+  statements: number;
+}
+
+/** A migration applied to the database, see {@link Database.migrationHistory}. */
+export interface AppliedMigration {
+  version: number;
+  description: string;
+  /** When the migration was applied, in RFC 3339. */
+  installedOn: string;
+  /** `false` for a migration that failed and left the database dirty. */
+  success: boolean;
+  /**
+   * How long applying the migration took in milliseconds, including its
+   * hooks, or `null` if it wasn't recorded.
+   */
+  executionTimeMs: number | null;
+}
+
+/**
+ * The progress of applying the migrations of a database, see
+ * {@link Database.onMigrationProgress}.
+ */
+export type MigrationProgress =
+  | {
+      status: "applying";
+      db: string;
+      version: number;
+      description: string;
+      /** The 1-based position of the migration among the `total` pending ones. */
+      index: number;
+      total: number;
+      /** The milliseconds since applying the migrations started. */
+      elapsedMs: number;
+    }
+  | {
+      status: "completed";
+      db: string;
+      /** The number of migrations applied. */
+      applied: number;
+      elapsedMs: number;
+    }
+  | {
+      status: "failed";
+      db: string;
+      /** The migration that failed, `null` if none was being applied. */
+      version: number | null;
+      error: string;
+      elapsedMs: number;
+    };
+
+/** Options for {@link Database.jsonPath}. */
+export interface JsonPathOptions {
+  /** Extracts the last segment as text with `->>`, rather than as `jsonb`. */
+  asText?: boolean;
+  /** The number of the first placeholder, to follow the other bound values. Defaults to `1`. */
+  firstParameter?: number;
+}
+
+export interface JsonPath {
+  /** The path expression, e.g. `"data" -> $2::text ->> $3::int`. */
+  sql: string;
+  /** The path segments, to bind after the other values. */
+  values: Array<string | number>;
+}
+
+/** Options for {@link Database.jsonbMerge}. */
+export interface JsonbMergeOptions {
+  /** The condition selecting the rows to update, e.g. `id = $1`. */
+  where: string;
+  /** The values bound to the placeholders of `where`. The patch follows them. */
+  values?: unknown[];
+  /**
+   * Merges nested objects and removes the keys set to `null`, like a JSON
+   * Merge Patch, rather than replacing the top-level keys.
+   */
+  deep?: boolean;
+}
+
+/** Options for {@link Database.copyToCsv}. */
+export interface CopyOptions {
+  /** Writes the CSV to this file instead of returning it. Must be allowed by the `fs` scope. */
+  path?: string;
+  /** The column separator, a single ASCII character. Defaults to `,`. */
+  delimiter?: string;
+  /** Starts the CSV with a line of the column names. */
+  header?: boolean;
+}
+
+export interface CopyResult {
+  /** The number of exported rows, not counting the header. */
+  rows: number;
+  /** The size of the CSV in bytes. */
+  bytes: number;
+  /** The CSV, unless it was written to a file. */
+  csv?: string;
+}
+
+export interface IntegrityOptions {
+  /** Also runs `PRAGMA foreign_key_check`. */
+  foreignKeys?: boolean;
+}
+
+export interface ForeignKeyViolation {
+  table: string;
+  /** `null` for tables without rowid. */
+  rowid: number | null;
+  parent: string;
+  /** The index of the violated foreign key in the table's list. */
+  fkid: number;
+}
+
+export interface IntegrityReport {
+  /** Whether no problem was found. */
+  ok: boolean;
+  /** The problems reported by `PRAGMA integrity_check`. */
+  problems: string[];
+  /** The rows violating a foreign key constraint, with `foreignKeys`. */
+  foreignKeyViolations?: ForeignKeyViolation[];
+}
+
+export interface SelectOptions {
+  /**
+   * Returns every column as the text the database produced, without any
+   * type-aware decoding. The query is sent unprepared, so it can't have bound
+   * values.
+   */
+  rawText?: boolean;
+  /**
+   * The maximum size of a `bytea` value in bytes, overriding the limit set
+   * with `DecodeOptions::max_bytea_size`. Only available on Postgres.
+   */
+  maxByteaSize?: number;
+  /**
+   * Decodes numeric arrays to a {@link PackedArray}, overriding
+   * `DecodeOptions::packed_arrays`. Only available on Postgres.
+   */
+  packedArrays?: boolean;
+  /**
+   * Gzips `text`, `varchar` and `bytea` values larger than this many bytes to
+   * a {@link CompressedValue}, overriding `DecodeOptions::compress_threshold`.
+   * Only available on Postgres.
+   */
+  compressThreshold?: number;
+  /**
+   * Runs the query again, once, if the connection is closed while it runs,
+   * e.g. after a network blip. Only `select` supports this, as retrying a
+   * write could apply it twice.
+   */
+  reconnect?: boolean;
+  /**
+   * Makes the query fail if it tries to write, as a defense against injected
+   * statements. Runs on a separate read-only connection on SQLite, and in a
+   * read-only transaction on MySQL and Postgres. Can't be combined with
+   * `rawText`.
+   */
+  readOnly?: boolean;
+  /**
+   * Sent as a leading `/* label *\/` comment of the query, so the database
+   * shows which part of the app issued it, e.g. in Postgres'
+   * `pg_stat_activity`. Characters that would end the comment are escaped.
+   * Each distinct label is a distinct prepared statement.
+   */
+  label?: string;
+}
+
+/** Options for `execute`. */
+export interface ExecuteOptions {
+  /**
+   * Sent as a leading `/* label *\/` comment of the query, so the database
+   * shows which part of the app issued it, e.g. in Postgres'
+   * `pg_stat_activity`. Characters that would end the comment are escaped.
+   * Each distinct label is a distinct prepared statement.
+   */
+  label?: string;
+}
+
+/**
+ * A Postgres array decoded with `DecodeOptions::array_dimensions`.
+ */
+export interface ArrayWithDimensions<T = unknown> {
+  /** The `[lower, upper]` bounds of each dimension, empty for an empty array. */
+  dims: [number, number][];
+  /** The elements, nested for each dimension. */
+  data: T[];
+}
+
+/**
+ * A lexeme of a Postgres `tsvector`, decoded with
+ * `TsVectorFormat::Structured`.
+ */
+export interface TsVectorLexeme {
+  word: string;
+  /** Empty for lexemes stored without positions. */
+  positions: { position: number; weight: "A" | "B" | "C" | "D" }[];
+}
+
+/**
+ * A one-dimensional Postgres numeric array without `NULL` elements, decoded
+ * with `packedArrays` as the base64 of its little-endian values.
+ */
+export interface PackedArray {
+  typedArray:
+    | "Int16Array"
+    | "Int32Array"
+    | "BigInt64Array"
+    | "Float32Array"
+    | "Float64Array";
+  data: string;
+}
+
+const typedArrays = {
+  Int16Array,
+  Int32Array,
+  BigInt64Array,
+  Float32Array,
+  Float64Array,
+};
+
+/**
+ * **unpackArray**
+ *
+ * Reads a {@link PackedArray} as the typed array it was packed from.
+ *
+ * @example
+ * ```ts
+ * const [row] = await db.select<{ samples: PackedArray }>(
+ *    "SELECT samples FROM recordings WHERE id = $1",
+ *    [id],
+ *    { packedArrays: true },
+ * );
+ * const samples = unpackArray(row.samples) as Float64Array;
+ * ```
+ */
+export function unpackArray(
+  packed: PackedArray,
+): Int16Array | Int32Array | BigInt64Array | Float32Array | Float64Array {
+  const bytes = Uint8Array.from(atob(packed.data), (c) => c.charCodeAt(0));
+  return new typedArrays[packed.typedArray](bytes.buffer);
+}
+
+/**
+ * A Postgres `text` or `bytea` value gzipped with `compressThreshold`, with
+ * `data` the base64 of the compressed value.
+ */
+export interface CompressedValue {
+  encoding: "gzip";
+  type: "text" | "bytea";
+  data: string;
+}
+
+/**
+ * **decompress**
+ *
+ * Decompresses a {@link CompressedValue} to the string or the bytes it was
+ * compressed from.
+ *
+ * @example
+ * ```ts
+ * const [row] = await db.select<{ body: string | CompressedValue }>(
+ *    "SELECT body FROM documents WHERE id = $1",
+ *    [id],
+ *    { compressThreshold: 64 * 1024 },
+ * );
+ * const body =
+ *   typeof row.body === "string" ? row.body : await decompress(row.body);
+ * ```
+ */
+export async function decompress(
+  value: CompressedValue,
+): Promise<string | Uint8Array> {
+  const bytes = Uint8Array.from(atob(value.data), (c) => c.charCodeAt(0));
+  const stream = new Blob([bytes])
+    .stream()
+    .pipeThrough(new DecompressionStream(value.encoding));
+  const decompressed = new Uint8Array(await new Response(stream).arrayBuffer());
+  return value.type === "text"
+    ? new TextDecoder().decode(decompressed)
+    : decompressed;
+}
+
 /**
  * **Database**
  *
@@ -40,15 +427,48 @@ export default class Database {
    * ```ts
    * const db = await Database.load("sqlite:test.db");
    * ```
+   *
+   * @param options - Connection options. When omitted, the options registered
+   * for this database on the Rust side are used.
    */
-  static async load(path: string): Promise<Database> {
+  static async load(
+    path: string,
+    options?: ConnectionOptions,
+  ): Promise<Database> {
     const _path = await invoke<string>("plugin:sql|load", {
       db: path,
+      options,
     });
 
     return new Database(_path);
   }
 
+  /**
+   * **onMigrationProgress**
+   *
+   * Listens to the progress of applying migrations when databases are loaded,
+   * if enabled with `Builder::migration_progress` on the Rust side. Call it
+   * before {@link Database.load} to follow the migrations it applies.
+   *
+   * @example
+   * ```ts
+   * const unlisten = await Database.onMigrationProgress((progress) => {
+   *   if (progress.status === "applying") {
+   *     setProgress(progress.index / progress.total);
+   *   }
+   * });
+   * const db = await Database.load("sqlite:test.db");
+   * unlisten();
+   * ```
+   */
+  static async onMigrationProgress(
+    handler: (progress: MigrationProgress) => void,
+  ): Promise<UnlistenFn> {
+    return await listen<MigrationProgress>("sql://migration", (event) =>
+      handler(event.payload),
+    );
+  }
+
   /**
    * **get**
    *
@@ -69,11 +489,105 @@ export default class Database {
     return new Database(path);
   }
 
+  /**
+   * **info**
+   *
+   * Returns the database server version and the features it supports.
+   *
+   * @example
+   * ```ts
+   * const info = await db.info();
+   * if (info.capabilities.returning) {
+   *   // ...
+   * }
+   * ```
+   */
+  async info(): Promise<DatabaseInfo> {
+    return await invoke<DatabaseInfo>("plugin:sql|info", {
+      db: this.path,
+    });
+  }
+
+  /**
+   * **migrationHistory**
+   *
+   * Returns the applied migrations in version order, with when each ran and
+   * how long it took. Empty if no migration was ever run on the database.
+   *
+   * @example
+   * ```ts
+   * for (const m of await db.migrationHistory()) {
+   *   console.log(`${m.version} ${m.description}: ${m.installedOn}, ${m.executionTimeMs}ms`);
+   * }
+   * ```
+   */
+  async migrationHistory(): Promise<AppliedMigration[]> {
+    return await invoke<AppliedMigration[]>("plugin:sql|migration_history", {
+      db: this.path,
+    });
+  }
+
+  /**
+   * **statementCache**
+   *
+   * Returns the number of prepared statements cached by the idle connections.
+   */
+  async statementCache(): Promise<StatementCache> {
+    return await invoke<StatementCache>("plugin:sql|statement_cache", {
+      db: this.path,
+    });
+  }
+
+  /**
+   * **clearStatementCache**
+   *
+   * Drops the prepared statements cached by the idle connections, e.g. after
+   * a schema change made them stale. Resolves with the number of dropped
+   * statements. Connections in use keep their statements.
+   *
+   * @example
+   * ```ts
+   * await db.execute("ALTER TABLE todos ADD COLUMN due DATE");
+   * await db.clearStatementCache();
+   * ```
+   */
+  async clearStatementCache(): Promise<StatementCache> {
+    return await invoke<StatementCache>("plugin:sql|clear_statement_cache", {
+      db: this.path,
+    });
+  }
+
+  /**
+   * **resetConnection**
+   *
+   * Discards the session state of the idle connections, e.g. session
+   * variables, a changed `search_path` or temporary tables, so it doesn't leak
+   * into later queries. Resolves with the number of reset connections.
+   * Connections in use keep their state. Only available on MySQL and Postgres.
+   *
+   * @example
+   * ```ts
+   * await db.execute("SET search_path = reporting");
+   * // ...
+   * await db.resetConnection();
+   * ```
+   */
+  async resetConnection(): Promise<number> {
+    return await invoke<number>("plugin:sql|reset_connection", {
+      db: this.path,
+    });
+  }
+
   /**
    * **execute**
    *
    * Passes a SQL expression to the database for execution.
    *
+   * The `plugin:sql|execute` command returns a {@link QueryResult} object on
+   * every driver. It used to return a `[rowsAffected, lastInsertId]` tuple, so
+   * code invoking the command directly instead of through this method has to
+   * read the fields of the object instead.
+   *
    * @example
    * ```ts
    * // for sqlite & postgres
@@ -99,22 +613,64 @@ export default class Database {
    *    "UPDATE todos SET title = ?, completed = ? WHERE id = ?",
    *    [ todos.title, todos.status, todos.id ]
    * );
+   *
+   * // named parameters, for all drivers
+   * const result = await db.execute(
+   *    "UPDATE todos SET title = :title WHERE id = :id",
+   *    { title: todos.title, id: todos.id }
+   * );
+   *
+   * // shown as `/* todos:rename *\/ UPDATE ...` in `pg_stat_activity`
+   * const result = await db.execute(
+   *    "UPDATE todos SET title = $1 WHERE id = $2",
+   *    [ todos.title, todos.id ],
+   *    { label: "todos:rename" }
+   * );
    * ```
    */
-  async execute(query: string, bindValues?: unknown[]): Promise<QueryResult> {
-    const [rowsAffected, lastInsertId] = await invoke<[number, number]>(
-      "plugin:sql|execute",
-      {
-        db: this.path,
-        query,
-        values: bindValues ?? [],
-      },
-    );
-    return {
-      lastInsertId,
-      rowsAffected,
-    };
+  async execute(
+    query: string,
+    bindValues?: BindValues,
+    options?: ExecuteOptions,
+  ): Promise<QueryResult> {
+    return await invoke<QueryResult>("plugin:sql|execute", {
+      db: this.path,
+      query,
+      values: bindValues ?? [],
+      options,
+    });
+  }
+
+  /**
+   * **executeWithFile**
+   *
+   * Like `execute`, with the contents of the file at `path` as the parameter
+   * after `bindValues`, or `:file` with named values. The file is streamed to
+   * the database on the Rust side, so it is never transferred to the webview.
+   * The path must be allowed by the `fs` scope. Only available on Postgres.
+   *
+   * @example
+   * ```ts
+   * const result = await db.executeWithFile(
+   *    "INSERT INTO files (name, data) VALUES ($1, $2)",
+   *    [ "video.mp4" ],
+   *    "/home/user/Videos/video.mp4"
+   * );
+   * ```
+   */
+  async executeWithFile(
+    query: string,
+    bindValues: BindValues,
+    path: string,
+  ): Promise<QueryResult> {
+    return await invoke<QueryResult>("plugin:sql|execute_with_file", {
+      db: this.path,
+      query,
+      values: bindValues,
+      path,
+    });
   }
+
   /**
    * **select**
    *
@@ -131,18 +687,395 @@ export default class Database {
    * const result = await db.select(
    *    "SELECT * from todos WHERE id = ?", id
    * );
+   *
+   * // named parameters, for all drivers
+   * const result = await db.select(
+   *    "SELECT * from todos WHERE owner = :user OR assignee = :user", { user }
+   * );
+   *
+   * // every column as text, e.g. `{ price: "1.50" }`
+   * const result = await db.select(
+   *    "SELECT price from products", [], { rawText: true }
+   * );
    * ```
    */
-  async select<T>(query: string, bindValues?: unknown[]): Promise<T> {
+  async select<T>(
+    query: string,
+    bindValues?: BindValues,
+    options?: SelectOptions,
+  ): Promise<T> {
     const result = await invoke<T>("plugin:sql|select", {
       db: this.path,
       query,
       values: bindValues ?? [],
+      options,
     });
 
     return result;
   }
 
+  /**
+   * **explainQuery**
+   *
+   * Returns the SQL sent to the database for a query, after named parameters
+   * are rewritten into the driver's placeholders, and the number of bound
+   * values. The query is not executed. The values themselves are only
+   * included with `includeValues`, and the database's plan for a select
+   * query with `plan`.
+   *
+   * @example
+   * ```ts
+   * const { sql, parameterCount } = await db.explainQuery(
+   *    "SELECT * from todos WHERE owner = :user OR assignee = :user", { user }
+   * );
+   * // "SELECT * from todos WHERE owner = $1 OR assignee = $1", 1
+   * ```
+   */
+  async explainQuery(
+    query: string,
+    bindValues?: BindValues,
+    options?: ExplainOptions,
+  ): Promise<ExplainedQuery> {
+    return await invoke<ExplainedQuery>("plugin:sql|explain_query", {
+      db: this.path,
+      query,
+      values: bindValues ?? [],
+      options,
+    });
+  }
+
+  /**
+   * **jsonPath**
+   *
+   * Builds a `jsonb` path expression for a column from a path of object keys
+   * and array indices, which are bound to the query rather than written into
+   * it. Only available on Postgres.
+   *
+   * @example
+   * ```ts
+   * const path = await db.jsonPath("data", ["address", 0, "city"], {
+   *    asText: true,
+   *    firstParameter: 2,
+   * });
+   * const rows = await db.select(
+   *    `SELECT ${path.sql} AS city FROM users WHERE id = $1`,
+   *    [id, ...path.values],
+   * );
+   * ```
+   */
+  async jsonPath(
+    column: string,
+    path: Array<string | number>,
+    options?: JsonPathOptions,
+  ): Promise<JsonPath> {
+    return await invoke<JsonPath>("plugin:sql|json_path", {
+      column,
+      path,
+      options,
+    });
+  }
+
+  /**
+   * **jsonbMerge**
+   *
+   * Merges a patch object into a `jsonb` column in a single `UPDATE`, so
+   * concurrent updates of other keys aren't lost. Resolves with the number of
+   * updated rows. Only available on Postgres.
+   *
+   * @example
+   * ```ts
+   * await db.jsonbMerge("users", "settings", { font: { size: 14 } }, {
+   *    where: "id = $1",
+   *    values: [id],
+   *    deep: true,
+   * });
+   * ```
+   */
+  async jsonbMerge(
+    table: string,
+    column: string,
+    patch: Record<string, unknown>,
+    options: JsonbMergeOptions,
+  ): Promise<number> {
+    return await invoke<number>("plugin:sql|jsonb_merge", {
+      db: this.path,
+      table,
+      column,
+      patch,
+      options,
+    });
+  }
+
+  /**
+   * **batchInsert**
+   *
+   * Inserts many rows into a table with multi-row `INSERT` statements,
+   * running as few statements as the driver's bound parameter limit allows,
+   * in a single transaction. Resolves with the number of inserted rows.
+   *
+   * @example
+   * ```ts
+   * const inserted = await db.batchInsert("todos", ["title", "status"], [
+   *    ["buy milk", "open"],
+   *    ["walk the dog", "done"],
+   * ]);
+   * ```
+   */
+  async batchInsert(
+    table: string,
+    columns: string[],
+    rows: unknown[][],
+  ): Promise<number> {
+    return await invoke<number>("plugin:sql|batch_insert", {
+      db: this.path,
+      table,
+      columns,
+      rows,
+    });
+  }
+
+  /**
+   * **batchUpsert**
+   *
+   * Inserts many rows given as objects, updating the existing rows that
+   * conflict with them on `conflictColumns`, like `batchInsert` in a single
+   * transaction. Every row must have the same keys, which are the columns.
+   * Rows with the same conflict values are rejected on Postgres, and applied
+   * in order on SQLite and MySQL. Resolves with the number of affected rows
+   * as counted by the driver.
+   *
+   * @example
+   * ```ts
+   * await db.batchUpsert(
+   *    "todos",
+   *    [
+   *      { id: 1, title: "buy milk", status: "done" },
+   *      { id: 3, title: "water plants", status: "open" },
+   *    ],
+   *    ["id"]
+   * );
+   * ```
+   */
+  async batchUpsert(
+    table: string,
+    rows: Array<Record<string, unknown>>,
+    conflictColumns: string[],
+  ): Promise<number> {
+    return await invoke<number>("plugin:sql|batch_upsert", {
+      db: this.path,
+      table,
+      rows,
+      conflictColumns,
+    });
+  }
+
+  /**
+   * **exists**
+   *
+   * Whether a select query returns at least one row. The query is wrapped in
+   * `SELECT EXISTS (...)`, so the database stops at the first matching row and
+   * no rows are fetched.
+   *
+   * @example
+   * ```ts
+   * const taken = await db.exists(
+   *    "SELECT 1 FROM users WHERE email = $1",
+   *    [ email ]
+   * );
+   * ```
+   */
+  async exists(query: string, bindValues?: BindValues): Promise<boolean> {
+    return await invoke<boolean>("plugin:sql|exists", {
+      db: this.path,
+      query,
+      values: bindValues ?? [],
+    });
+  }
+
+  /**
+   * **truncate**
+   *
+   * Removes every row of a table and restarts its identity or
+   * `AUTOINCREMENT` sequence. Rejects unless `confirm` is `true`, so an
+   * accidental call can't wipe data.
+   *
+   * @example
+   * ```ts
+   * await db.truncate("todos", { confirm: true });
+   * ```
+   */
+  async truncate(table: string, options: { confirm: true }): Promise<void> {
+    await invoke("plugin:sql|truncate", {
+      db: this.path,
+      table,
+      confirm: options.confirm,
+    });
+  }
+
+  /**
+   * **tableExists**
+   *
+   * Whether a table or view exists, e.g. to guard a migration. The name may
+   * be schema qualified and is compared as stored by the database, without
+   * folding its case like an unquoted identifier.
+   *
+   * @example
+   * ```ts
+   * if (!(await db.tableExists("public.todos"))) {
+   *   // ...
+   * }
+   * ```
+   */
+  async tableExists(table: string): Promise<boolean> {
+    return await invoke<boolean>("plugin:sql|table_exists", {
+      db: this.path,
+      table,
+    });
+  }
+
+  /**
+   * **columnExists**
+   *
+   * Whether a table or view, optionally schema qualified, has a column.
+   *
+   * @example
+   * ```ts
+   * const hasDueDates = await db.columnExists("todos", "due_at");
+   * ```
+   */
+  async columnExists(table: string, column: string): Promise<boolean> {
+    return await invoke<boolean>("plugin:sql|column_exists", {
+      db: this.path,
+      table,
+      column,
+    });
+  }
+
+  /**
+   * **queryWithTimeoutAndRetry**
+   *
+   * Like `select`, but every attempt is bounded by `timeout` and the query is
+   * retried with an exponential backoff when the connection to the database
+   * fails. SQL errors and timeouts are not retried.
+   *
+   * @example
+   * ```ts
+   * const result = await db.queryWithTimeoutAndRetry(
+   *    "SELECT * from todos WHERE id = $1", [ id ], { timeout: 5000, attempts: 5 }
+   * );
+   * ```
+   */
+  async queryWithTimeoutAndRetry<T>(
+    query: string,
+    bindValues?: BindValues,
+    options?: RetryOptions,
+  ): Promise<T> {
+    const result = await invoke<T>("plugin:sql|query_with_timeout_and_retry", {
+      db: this.path,
+      query,
+      values: bindValues ?? [],
+      options,
+    });
+
+    return result;
+  }
+
+  /**
+   * **integrityCheck**
+   *
+   * Checks a SQLite database for corruption with `PRAGMA integrity_check`,
+   * and for foreign key violations with `foreignKeys`.
+   *
+   * @example
+   * ```ts
+   * const report = await db.integrityCheck({ foreignKeys: true });
+   * if (!report.ok) {
+   *   console.error(report.problems, report.foreignKeyViolations);
+   * }
+   * ```
+   */
+  async integrityCheck(options?: IntegrityOptions): Promise<IntegrityReport> {
+    return await invoke<IntegrityReport>("plugin:sql|integrity_check", {
+      db: this.path,
+      options,
+    });
+  }
+
+  /**
+   * **advisoryLock**
+   *
+   * Acquires a Postgres advisory lock, waiting until it is available.
+   *
+   * Advisory locks belong to a database session, so the lock keeps one of
+   * the pool's connections checked out until `advisoryUnlock` is called or
+   * the database is closed.
+   *
+   * @example
+   * ```ts
+   * await db.advisoryLock(42);
+   * try {
+   *   // exclusive work
+   * } finally {
+   *   await db.advisoryUnlock(42);
+   * }
+   * ```
+   */
+  async advisoryLock(key: number): Promise<void> {
+    await invoke("plugin:sql|advisory_lock", {
+      db: this.path,
+      key,
+    });
+  }
+
+  /**
+   * **tryAdvisoryLock**
+   *
+   * Acquires a Postgres advisory lock if it is available, without waiting.
+   * Returns whether the lock was acquired.
+   */
+  async tryAdvisoryLock(key: number): Promise<boolean> {
+    return await invoke<boolean>("plugin:sql|try_advisory_lock", {
+      db: this.path,
+      key,
+    });
+  }
+
+  /**
+   * **advisoryUnlock**
+   *
+   * Releases a Postgres advisory lock acquired with `advisoryLock` or
+   * `tryAdvisoryLock`. Returns whether the lock was held.
+   */
+  async advisoryUnlock(key: number): Promise<boolean> {
+    return await invoke<boolean>("plugin:sql|advisory_unlock", {
+      db: this.path,
+      key,
+    });
+  }
+
+  /**
+   * **copyToCsv**
+   *
+   * Exports the rows of a query as CSV with `COPY (query) TO STDOUT`, which
+   * is much faster than selecting them for large tables. Only available on
+   * Postgres.
+   *
+   * @example
+   * ```ts
+   * const { rows } = await db.copyToCsv("SELECT * FROM orders", {
+   *    path: "/path/to/orders.csv",
+   *    header: true,
+   * });
+   * ```
+   */
+  async copyToCsv(query: string, options?: CopyOptions): Promise<CopyResult> {
+    return await invoke<CopyResult>("plugin:sql|copy_to_csv", {
+      db: this.path,
+      query,
+      options,
+    });
+  }
+
   /**
    * **close**
    *
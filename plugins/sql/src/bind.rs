@@ -0,0 +1,708 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Binding of the JSON values sent by the frontend to query parameters.
+
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use sqlx::{
+    database::{Database, HasArguments},
+    query::Query,
+    Arguments as _,
+};
+#[cfg(feature = "postgres")]
+use sqlx::{
+    encode::{Encode, IsNull},
+    postgres::{
+        types::{Oid, PgInterval, PgMoney},
+        PgArgumentBuffer, PgTypeInfo, PgTypeKind, Postgres,
+    },
+    Either, Executor, Statement, Type, TypeInfo,
+};
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+use std::borrow::Cow;
+
+use crate::{plugin::Db, Error};
+
+pub(crate) type BoundQuery<'q> = Query<'q, Db, Arguments<'q>>;
+
+type Arguments<'q> = <Db as HasArguments<'q>>::Arguments;
+
+#[cfg(feature = "postgres")]
+type ParameterType = PgTypeInfo;
+#[cfg(not(feature = "postgres"))]
+type ParameterType = ();
+
+/// A query with the values [`bind_values`] bound to it.
+pub(crate) struct BoundValues<'q> {
+    sql: Cow<'q, str>,
+    arguments: Arguments<'q>,
+}
+
+impl BoundValues<'_> {
+    /// Returns the query to execute, to which more values can be bound. The
+    /// values are moved into it, so it can only be taken once.
+    pub(crate) fn query(&mut self) -> BoundQuery<'_> {
+        sqlx::query_with(&self.sql, std::mem::take(&mut self.arguments))
+    }
+}
+
+/// Binds `values` to the parameters of `query`, in order: `null` as `NULL`,
+/// strings as text, date and time components as [`DateTime`] and everything
+/// else as JSON.
+///
+/// On Postgres, strings bound to `money` parameters are parsed as a decimal
+/// amount first, and the ones bound to `numeric` parameters as a decimal number,
+/// keeping all its digits. The parameter types are looked up on `conn`, which the query
+/// should then be executed on.
+pub(crate) async fn bind_values<'q>(
+    conn: &mut <Db as Database>::Connection,
+    query: &'q str,
+    values: Vec<JsonValue>,
+) -> Result<BoundValues<'q>, Error> {
+    let types = parameter_types(conn, query, &values).await;
+
+    // A string is sent as text unless it is parsed, which only the parameters
+    // of text types accept. The others were prepared with the type Postgres
+    // inferred, e.g. from a cast like `$1::uuid`, so the query is prepared
+    // again with text parameters, under another SQL text as statements are
+    // cached by their SQL.
+    #[cfg(feature = "postgres")]
+    let sql = if values
+        .iter()
+        .zip(&types)
+        .any(|(value, ty)| value.is_string() && !accepts_strings(ty))
+    {
+        Cow::Owned(format!("{query}\n-- text parameters"))
+    } else {
+        Cow::Borrowed(query)
+    };
+    #[cfg(not(feature = "postgres"))]
+    let sql = Cow::Borrowed(query);
+
+    let mut arguments = Arguments::default();
+    for (index, value) in values.into_iter().enumerate() {
+        match value {
+            JsonValue::Null => arguments.add(None::<JsonValue>),
+            JsonValue::String(value) => {
+                bind_string(&mut arguments, index, value, types.get(index))?
+            }
+            JsonValue::Object(object) => match date_time(&object) {
+                Some(Ok(DateTime::Date(date))) => arguments.add(date),
+                Some(Ok(DateTime::Time(time))) => arguments.add(time),
+                Some(Ok(DateTime::Timestamp(timestamp))) => arguments.add(timestamp),
+                Some(Err(message)) => return Err(Error::InvalidParameter(index + 1, message)),
+                None => arguments.add(JsonValue::Object(object)),
+            },
+            value => arguments.add(value),
+        }
+    }
+    Ok(BoundValues { sql, arguments })
+}
+
+/// A date, time or timestamp bound from its components, e.g.
+/// `{ "year": 2023, "month": 1, "day": 1 }`, instead of a string.
+enum DateTime {
+    Date(Date),
+    Time(Time),
+    Timestamp(PrimitiveDateTime),
+}
+
+const DATE_COMPONENTS: [&str; 3] = ["year", "month", "day"];
+const TIME_COMPONENTS: [&str; 2] = ["hour", "minute"];
+const OPTIONAL_TIME_COMPONENTS: [&str; 4] = ["second", "millisecond", "microsecond", "nanosecond"];
+
+/// Reads a date from `year`, `month` and `day`, a time from `hour`, `minute`
+/// and optionally `second`, `millisecond`, `microsecond` and `nanosecond`, or a
+/// timestamp from both. Returns `None` for other objects, which are bound as
+/// JSON, and an error message if a component is out of range.
+fn date_time(object: &JsonMap<String, JsonValue>) -> Option<Result<DateTime, String>> {
+    let count = |keys: &[&str]| keys.iter().filter(|key| object.contains_key(**key)).count();
+    let date = count(&DATE_COMPONENTS);
+    let time = count(&TIME_COMPONENTS);
+    let optional = count(&OPTIONAL_TIME_COMPONENTS);
+    if date + time + optional != object.len()
+        || (date != 0 && date != DATE_COMPONENTS.len())
+        || (time != 0 && time != TIME_COMPONENTS.len())
+        || (time == 0 && optional != 0)
+        || object.is_empty()
+    {
+        return None;
+    }
+
+    Some(match (date != 0, time != 0) {
+        (true, true) => date_components(object)
+            .and_then(|date| Ok(PrimitiveDateTime::new(date, time_components(object)?)))
+            .map(DateTime::Timestamp),
+        (true, false) => date_components(object).map(DateTime::Date),
+        _ => time_components(object).map(DateTime::Time),
+    })
+}
+
+fn date_components(object: &JsonMap<String, JsonValue>) -> Result<Date, String> {
+    let month = component::<u8>(object, "month", "1..=12")?;
+    Date::from_calendar_date(
+        component(object, "year", "-9999..=9999")?,
+        Month::try_from(month).map_err(|e| e.to_string())?,
+        component(object, "day", "1..=31")?,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn time_components(object: &JsonMap<String, JsonValue>) -> Result<Time, String> {
+    let mut nanosecond = 0;
+    for (key, scale) in [
+        ("millisecond", 1_000_000),
+        ("microsecond", 1_000),
+        ("nanosecond", 1),
+    ] {
+        let value = component::<u32>(object, key, "0..=999")?;
+        if value > 999 {
+            return Err(format!("{key} must be in the range 0..=999"));
+        }
+        nanosecond += value * scale;
+    }
+    Time::from_hms_nano(
+        component(object, "hour", "0..=23")?,
+        component(object, "minute", "0..=59")?,
+        component(object, "second", "0..=59")?,
+        nanosecond,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Reads an integer component, 0 if it is missing. `range` describes the
+/// valid values for the error message.
+fn component<T: TryFrom<i64>>(
+    object: &JsonMap<String, JsonValue>,
+    key: &str,
+    range: &str,
+) -> Result<T, String> {
+    let value = match object.get(key) {
+        Some(value) => value
+            .as_i64()
+            .ok_or_else(|| format!("{key} must be an integer"))?,
+        None => 0,
+    };
+    T::try_from(value).map_err(|_| format!("{key} must be in the range {range}"))
+}
+
+/// The parameter types of `query`, if a string is bound. Postgres infers the
+/// ones strings are bound to, and the others are given the types their values
+/// are bound as, so the statement is prepared once and cached with the types
+/// it is executed with.
+#[cfg(feature = "postgres")]
+async fn parameter_types(
+    conn: &mut <Db as Database>::Connection,
+    query: &str,
+    values: &[JsonValue],
+) -> Vec<ParameterType> {
+    if !values.iter().any(JsonValue::is_string) {
+        return Vec::new();
+    }
+
+    let types = values.iter().map(bound_type).collect::<Vec<_>>();
+    match conn.prepare_with(query, &types).await {
+        Ok(statement) => match statement.parameters() {
+            Some(Either::Left(types)) => types.to_vec(),
+            _ => Vec::new(),
+        },
+        // the query fails later with a better error
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn parameter_types(
+    _conn: &mut <Db as Database>::Connection,
+    _query: &str,
+    _values: &[JsonValue],
+) -> Vec<ParameterType> {
+    Vec::new()
+}
+
+/// The type [`bind_values`] binds a value as, or an unspecified type for
+/// strings, which Postgres then infers.
+#[cfg(feature = "postgres")]
+fn bound_type(value: &JsonValue) -> PgTypeInfo {
+    match value {
+        JsonValue::String(_) => PgTypeInfo::with_oid(Oid(0)),
+        JsonValue::Object(object) => match date_time(object) {
+            Some(Ok(DateTime::Date(_))) => Date::type_info(),
+            Some(Ok(DateTime::Time(_))) => Time::type_info(),
+            Some(Ok(DateTime::Timestamp(_))) => PrimitiveDateTime::type_info(),
+            _ => JsonValue::type_info(),
+        },
+        _ => JsonValue::type_info(),
+    }
+}
+
+/// Whether a string can be bound to a parameter of type `ty`: the text types,
+/// whose binary format is the text itself, and the types [`bind_string`]
+/// parses strings for.
+#[cfg(feature = "postgres")]
+fn accepts_strings(ty: &ParameterType) -> bool {
+    matches!(ty.kind(), PgTypeKind::Enum(_))
+        || matches!(
+            ty.name(),
+            "TEXT" | "VARCHAR" | "CHAR" | "NAME" | "MONEY" | "INTERVAL" | "NUMERIC"
+        )
+}
+
+#[cfg(feature = "postgres")]
+fn bind_string(
+    arguments: &mut Arguments<'_>,
+    index: usize,
+    value: String,
+    ty: Option<&ParameterType>,
+) -> Result<(), Error> {
+    let invalid = |message: &str| Error::InvalidParameter(index + 1, message.to_string());
+
+    match ty.map(|ty| ty.name()) {
+        Some("MONEY") => {
+            arguments.add(money(&value).ok_or_else(|| invalid("not a monetary amount"))?)
+        }
+        Some("INTERVAL") => {
+            arguments.add(interval(&value).ok_or_else(|| invalid("not an ISO 8601 duration"))?)
+        }
+        Some("NUMERIC") => arguments.add(numeric(&value).ok_or_else(|| invalid("not a number"))?),
+        _ => arguments.add(value),
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "postgres"))]
+fn bind_string(
+    arguments: &mut Arguments<'_>,
+    _index: usize,
+    value: String,
+    _ty: Option<&ParameterType>,
+) -> Result<(), Error> {
+    arguments.add(value);
+    Ok(())
+}
+
+/// Parses an amount like `-12.34` into cents, with at most
+/// [`MONEY_SCALE`](crate::decode::MONEY_SCALE) fractional digits.
+#[cfg(feature = "postgres")]
+fn money(value: &str) -> Option<PgMoney> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (units, fraction) = value.split_once('.').unwrap_or((value, ""));
+
+    let scale = crate::decode::MONEY_SCALE;
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if units.is_empty() || !digits(units) || !digits(fraction) || fraction.len() > scale as usize {
+        return None;
+    }
+
+    let units = units.parse::<i64>().ok()?;
+    // e.g. `12.5` is 1250 cents
+    let fraction = format!("{fraction:0<width$}", width = scale as usize)
+        .parse::<i64>()
+        .ok()?;
+    let cents = units
+        .checked_mul(10_i64.pow(scale))?
+        .checked_add(fraction)?;
+    Some(PgMoney(if negative { -cents } else { cents }))
+}
+
+/// A `numeric` in its binary format, see [`numeric`].
+#[cfg(feature = "postgres")]
+struct Numeric(Vec<u8>);
+
+#[cfg(feature = "postgres")]
+impl Type<Postgres> for Numeric {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_oid(Oid(1700))
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Encode<'_, Postgres> for Numeric {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        buf.extend(&self.0);
+        IsNull::No
+    }
+}
+
+/// Parses a number like `-12.340`, `1.5e-3`, `NaN` or `Infinity` into the
+/// binary format [`numeric_to_string`](crate::decode) reads, without going
+/// through a float. The scale is the number of fractional digits as written,
+/// like Postgres parses it.
+#[cfg(feature = "postgres")]
+fn numeric(value: &str) -> Option<Numeric> {
+    let value = value.trim();
+    let header = |ndigits: i16, weight: i16, sign: u16, scale: u16| {
+        [
+            ndigits.to_be_bytes(),
+            weight.to_be_bytes(),
+            sign.to_be_bytes(),
+            scale.to_be_bytes(),
+        ]
+        .concat()
+    };
+    let lower = value.to_ascii_lowercase();
+    match lower.as_str() {
+        "nan" => return Some(Numeric(header(0, 0, 0xC000, 0))),
+        "infinity" | "+infinity" | "inf" | "+inf" => return Some(Numeric(header(0, 0, 0xD000, 0))),
+        "-infinity" | "-inf" => return Some(Numeric(header(0, 0, 0xF000, 0))),
+        _ => {}
+    }
+
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (value, 0),
+    };
+    let (units, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (units.is_empty() && fraction.is_empty()) || !is_digits(units) || !is_digits(fraction) {
+        return None;
+    }
+    let scale = u16::try_from((fraction.len() as i64 - exponent as i64).max(0))
+        .ok()
+        .filter(|scale| *scale <= 0x3FFF)?;
+
+    // the digits in base 10000, aligned so the decimal point falls between two
+    // of them, e.g. `12.34` is `0012.3400`
+    let point = units.len() as i64 + exponent as i64;
+    let padding = (4 - point.rem_euclid(4)) % 4;
+    let mut digits = "0".repeat(padding as usize) + units + fraction;
+    while digits.len() % 4 != 0 {
+        digits.push('0');
+    }
+    let mut groups = digits
+        .as_bytes()
+        .chunks(4)
+        .map(|group| std::str::from_utf8(group).ok()?.parse::<i16>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let mut weight = (point + padding) / 4 - 1;
+    let leading = groups.iter().take_while(|group| **group == 0).count();
+    groups.drain(..leading);
+    weight -= leading as i64;
+    while groups.last() == Some(&0) {
+        groups.pop();
+    }
+    // zero is stored without digits, with a weight of 0 and a positive sign
+    let (weight, sign) = if groups.is_empty() {
+        (0, 0)
+    } else {
+        (
+            i16::try_from(weight).ok()?,
+            if negative { 0x4000 } else { 0 },
+        )
+    };
+
+    let mut bytes = header(i16::try_from(groups.len()).ok()?, weight, sign, scale);
+    for group in groups {
+        bytes.extend(group.to_be_bytes());
+    }
+    Some(Numeric(bytes))
+}
+
+/// Parses an ISO 8601 duration like `P1Y2M3DT4H5M6.5S` into the months, days
+/// and microseconds Postgres stores separately. Each component can be negative,
+/// or the whole duration: `-P1M3D` is `P-1M-3D`.
+#[cfg(feature = "postgres")]
+fn interval(value: &str) -> Option<PgInterval> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) if !time.is_empty() => (date, time),
+        Some(_) => return None,
+        None => (value, ""),
+    };
+    if date.is_empty() && time.is_empty() {
+        return None;
+    }
+
+    let mut interval = PgInterval {
+        months: 0,
+        days: 0,
+        microseconds: 0,
+    };
+    for (number, unit) in duration_components(date, "YMWD")? {
+        let number = number.parse::<i32>().ok()?;
+        match unit {
+            'Y' => interval.months = interval.months.checked_add(number.checked_mul(12)?)?,
+            'M' => interval.months = interval.months.checked_add(number)?,
+            'W' => interval.days = interval.days.checked_add(number.checked_mul(7)?)?,
+            _ => interval.days = interval.days.checked_add(number)?,
+        }
+    }
+    for (number, unit) in duration_components(time, "HMS")? {
+        let micros = match unit {
+            'H' => number.parse::<i64>().ok()?.checked_mul(3_600_000_000)?,
+            'M' => number.parse::<i64>().ok()?.checked_mul(60_000_000)?,
+            _ => seconds_to_micros(number)?,
+        };
+        interval.microseconds = interval.microseconds.checked_add(micros)?;
+    }
+
+    if negative {
+        interval.months = interval.months.checked_neg()?;
+        interval.days = interval.days.checked_neg()?;
+        interval.microseconds = interval.microseconds.checked_neg()?;
+    }
+    Some(interval)
+}
+
+/// Splits `1Y-2M` into `[("1", 'Y'), ("-2", 'M')]`, if the units appear at
+/// most once each and in the order of `units`.
+#[cfg(feature = "postgres")]
+fn duration_components<'a>(value: &'a str, units: &str) -> Option<Vec<(&'a str, char)>> {
+    let mut components = Vec::new();
+    let (mut start, mut next_unit) = (0, 0);
+    for (index, c) in value.char_indices() {
+        if c.is_ascii_alphabetic() {
+            let unit = units[next_unit..].find(c)? + next_unit;
+            if index == start {
+                return None;
+            }
+            components.push((&value[start..index], c));
+            (start, next_unit) = (index + 1, unit + 1);
+        }
+    }
+    (start == value.len()).then_some(components)
+}
+
+/// Parses seconds with up to six fractional digits, e.g. `-6.5`, into microseconds.
+#[cfg(feature = "postgres")]
+fn seconds_to_micros(value: &str) -> Option<i64> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if seconds.is_empty() || !digits(seconds) || !digits(fraction) || fraction.len() > 6 {
+        return None;
+    }
+
+    let fraction = format!("{fraction:0<6}").parse::<i64>().ok()?;
+    let micros = seconds
+        .parse::<i64>()
+        .ok()?
+        .checked_mul(1_000_000)?
+        .checked_add(fraction)?;
+    Some(if negative { -micros } else { micros })
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use serde_json::json;
+    use sqlx::{Connection, Row};
+
+    use super::*;
+    use crate::testing::{block_on, pool};
+
+    /// Writes `values` to a column of type `ty` and reads them back.
+    fn round_trip(ty: &str, values: &[&str]) -> Option<Vec<JsonValue>> {
+        block_on(async {
+            let pool = pool().await?;
+            let mut conn = pool.acquire().await.unwrap();
+            conn.execute(format!("CREATE TEMPORARY TABLE round_trip (id serial, v {ty})").as_str())
+                .await
+                .unwrap();
+            for value in values {
+                let query = "INSERT INTO round_trip (v) VALUES ($1)";
+                bind_values(&mut conn, query, vec![json!(value)])
+                    .await
+                    .unwrap()
+                    .query()
+                    .execute(&mut *conn)
+                    .await
+                    .unwrap();
+            }
+
+            let rows = sqlx::query("SELECT v FROM round_trip ORDER BY id")
+                .fetch_all(&mut *conn)
+                .await
+                .unwrap();
+            let options = Default::default();
+            Some(
+                rows.iter()
+                    .map(|row| crate::decode::to_json(row.try_get_raw(0).unwrap(), &options))
+                    .collect::<Result<_, _>>()
+                    .unwrap(),
+            )
+        })
+    }
+
+    #[test]
+    fn money_round_trips() {
+        if let Some(values) = round_trip("money", &["12.34", "-1234.56", "0.05", "12.5", "7"]) {
+            assert_eq!(values, ["12.34", "-1234.56", "0.05", "12.50", "7.00"]);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_money() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            for value in ["12.345", "1e3", "", "-"] {
+                assert!(matches!(
+                    bind_values(&mut conn, "SELECT $1::money", vec![json!(value)]).await,
+                    Err(Error::InvalidParameter(1, _))
+                ));
+            }
+        });
+    }
+
+    #[test]
+    fn interval_round_trips() {
+        let durations = [
+            "P1Y2M3DT4H5M6.5S",
+            "P-1Y-2M-3DT-4H-5M-6.5S",
+            "P1M-3DT0.000001S",
+            "PT-36H",
+            "PT0S",
+        ];
+        if let Some(values) = round_trip("interval", &durations) {
+            assert_eq!(values, durations);
+        }
+    }
+
+    #[test]
+    fn negates_whole_intervals() {
+        if let Some(values) = round_trip("interval", &["-P1M3D", "-PT1H30M", "P2W"]) {
+            assert_eq!(values, ["P-1M-3D", "PT-1H-30M", "P14D"]);
+        }
+    }
+
+    #[test]
+    fn numeric_round_trips_exactly() {
+        if let Some(values) = round_trip("numeric", &["123456789012345.0123456789"]) {
+            assert_eq!(values, ["123456789012345.0123456789"]);
+        }
+    }
+
+    #[test]
+    fn prepares_string_parameters_once() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            let cached = conn.cached_statements_size();
+
+            let query = "SELECT $1::numeric::text AS n, $2 || '' AS t";
+            for _ in 0..2 {
+                let values = vec![json!("1.50"), json!("x")];
+                let row = bind_values(&mut conn, query, values)
+                    .await
+                    .unwrap()
+                    .query()
+                    .fetch_one(&mut *conn)
+                    .await
+                    .unwrap();
+                assert_eq!(row.get::<String, _>("n"), "1.50");
+                assert_eq!(row.get::<String, _>("t"), "x");
+            }
+            assert_eq!(conn.cached_statements_size(), cached + 1);
+        });
+    }
+
+    #[test]
+    fn binds_strings_cast_to_other_types_as_text() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            let query = "SELECT $1::timestamptz = '2023-01-01T00:00:00Z' AS same";
+            let row = bind_values(&mut conn, query, vec![json!("2023-01-01 00:00:00+00")])
+                .await
+                .unwrap()
+                .query()
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+            assert!(row.get::<bool, _>("same"));
+        });
+    }
+
+    #[test]
+    fn binds_dates_and_times_from_components() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            let query = "SELECT $1 = DATE '2024-02-29' AS date, \
+                $2 = TIME '12:30:15.5' AS time, \
+                $3 = TIMESTAMP '2023-01-01 00:00:00.000001' AS timestamp";
+            let values = vec![
+                json!({ "year": 2024, "month": 2, "day": 29 }),
+                json!({ "hour": 12, "minute": 30, "second": 15, "millisecond": 500 }),
+                json!({ "year": 2023, "month": 1, "day": 1, "hour": 0, "minute": 0, "microsecond": 1 }),
+            ];
+            let row = bind_values(&mut conn, query, values)
+                .await
+                .unwrap()
+                .query()
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+            assert!(row.get::<bool, _>("date"));
+            assert!(row.get::<bool, _>("time"));
+            assert!(row.get::<bool, _>("timestamp"));
+
+            // other objects are still bound as JSON
+            let row = bind_values(
+                &mut conn,
+                "SELECT $1::jsonb ->> 'day' AS day",
+                vec![json!({ "day": 1 })],
+            )
+            .await
+            .unwrap()
+            .query()
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+            assert_eq!(row.get::<String, _>("day"), "1");
+        });
+    }
+
+    #[test]
+    fn rejects_components_out_of_range() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            for value in [
+                json!({ "year": 2023, "month": 13, "day": 1 }),
+                json!({ "year": 2023, "month": 2, "day": 29 }),
+                json!({ "year": 2023, "month": 1, "day": 1.5 }),
+                json!({ "hour": 24, "minute": 0 }),
+                json!({ "hour": 0, "minute": 0, "millisecond": 1000 }),
+            ] {
+                assert!(matches!(
+                    bind_values(&mut conn, "SELECT $1", vec![value]).await,
+                    Err(Error::InvalidParameter(1, _))
+                ));
+            }
+        });
+    }
+}
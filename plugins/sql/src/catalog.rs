@@ -0,0 +1,172 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Checks whether tables and columns exist, in the catalog of each backend.
+//!
+//! Names are compared as they are stored, they aren't parsed as SQL
+//! identifiers: an unquoted `Users` is stored as `users` by Postgres, while
+//! SQLite compares them case-insensitively and MySQL depends on
+//! `lower_case_table_names`.
+
+use sqlx::{Pool, Row};
+use tauri::{command, State};
+
+use crate::{
+    plugin::{Db, DbInstances},
+    Error, Result,
+};
+
+// SQLite resolves the table like any table name in a query, the first one of
+// the `temp`, `main` and attached schemas, unless it's qualified. The pragmas
+// fail on unknown schemas, so they are only queried for attached ones.
+#[cfg(feature = "sqlite")]
+const TABLE_EXISTS: &str = "SELECT CASE \
+    WHEN $1 IS NULL OR EXISTS (SELECT 1 FROM pragma_database_list WHERE name = $1) \
+    THEN EXISTS (SELECT 1 FROM pragma_table_info($2, $1)) ELSE 0 END";
+#[cfg(feature = "sqlite")]
+const COLUMN_EXISTS: &str = "SELECT CASE \
+    WHEN $1 IS NULL OR EXISTS (SELECT 1 FROM pragma_database_list WHERE name = $1) \
+    THEN EXISTS (SELECT 1 FROM pragma_table_xinfo($2, $1) WHERE name = $3 COLLATE NOCASE) \
+    ELSE 0 END";
+
+// MySQL looks up unqualified tables in the current database.
+#[cfg(feature = "mysql")]
+const TABLE_EXISTS: &str = "SELECT EXISTS (SELECT 1 FROM information_schema.tables \
+    WHERE table_schema = COALESCE(?, DATABASE()) AND table_name = ?)";
+#[cfg(feature = "mysql")]
+const COLUMN_EXISTS: &str = "SELECT EXISTS (SELECT 1 FROM information_schema.columns \
+    WHERE table_schema = COALESCE(?, DATABASE()) AND table_name = ? AND column_name = ?)";
+
+// Postgres resolves unqualified tables through the `search_path`. The parts of
+// the name are quoted so `to_regclass` doesn't fold their case.
+#[cfg(feature = "postgres")]
+const TABLE_EXISTS: &str = "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_class \
+    WHERE oid = to_regclass(concat(quote_ident($1) || '.', quote_ident($2))) \
+    AND relkind IN ('r', 'p', 'v', 'm', 'f'))";
+#[cfg(feature = "postgres")]
+const COLUMN_EXISTS: &str = "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_attribute \
+    WHERE attrelid = to_regclass(concat(quote_ident($1) || '.', quote_ident($2))) \
+    AND attname = $3 AND attnum > 0 AND NOT attisdropped)";
+
+/// Splits an optionally schema qualified table name into its schema and name.
+fn split_table(table: &str) -> (Option<&str>, &str) {
+    match table.rsplit_once('.') {
+        Some((schema, name)) => (Some(schema), name),
+        None => (None, table),
+    }
+}
+
+async fn query(pool: &Pool<Db>, query: &str, table: &str, column: Option<&str>) -> Result<bool> {
+    let (schema, name) = split_table(table);
+    let mut query = sqlx::query(query).bind(schema).bind(name);
+    if let Some(column) = column {
+        query = query.bind(column);
+    }
+    let row = query.fetch_one(pool).await?;
+    // `EXISTS` is a `boolean` on Postgres and an integer elsewhere
+    #[cfg(feature = "postgres")]
+    let exists = row.try_get::<bool, _>(0)?;
+    #[cfg(not(feature = "postgres"))]
+    let exists = row.try_get::<i64, _>(0)? != 0;
+    Ok(exists)
+}
+
+async fn pool(db_instances: &DbInstances, db: String) -> Result<Pool<Db>> {
+    db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))
+}
+
+/// Whether a table or view exists, optionally schema qualified
+/// (`schema.table`).
+#[command]
+pub(crate) async fn table_exists(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    table: String,
+) -> Result<bool> {
+    let pool = pool(&db_instances, db).await?;
+    query(&pool, TABLE_EXISTS, &table, None).await
+}
+
+/// Whether a table or view, optionally schema qualified, has a column.
+#[command]
+pub(crate) async fn column_exists(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    table: String,
+    column: String,
+) -> Result<bool> {
+    let pool = pool(&db_instances, db).await?;
+    query(&pool, COLUMN_EXISTS, &table, Some(&column)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Executor;
+
+    use super::*;
+    use crate::testing::block_on;
+
+    // temporary tables aren't listed in MySQL's `information_schema`
+    #[cfg(not(feature = "mysql"))]
+    const CREATE_TABLE: &str = "CREATE TEMPORARY TABLE catalog_items (id INT, name TEXT)";
+    #[cfg(feature = "mysql")]
+    const CREATE_TABLE: &str = "CREATE TABLE catalog_items (id INT, name TEXT)";
+
+    #[test]
+    fn finds_existing_tables_and_columns() {
+        block_on(async {
+            let pool = match crate::testing::pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            #[cfg(feature = "mysql")]
+            pool.execute("DROP TABLE IF EXISTS catalog_items")
+                .await
+                .unwrap();
+            pool.execute(CREATE_TABLE).await.unwrap();
+            let exists = |sql, table: &str, column: Option<&str>| {
+                let (pool, table) = (pool.clone(), table.to_string());
+                let column = column.map(str::to_string);
+                async move { query(&pool, sql, &table, column.as_deref()).await.unwrap() }
+            };
+            let table = |table| exists(TABLE_EXISTS, table, None);
+            let column = |table, column| exists(COLUMN_EXISTS, table, Some(column));
+
+            assert!(table("catalog_items").await);
+            assert!(!table("missing").await);
+            assert!(column("catalog_items", "name").await);
+            assert!(!column("catalog_items", "missing").await);
+            assert!(!column("missing", "name").await);
+
+            // schema qualified names
+            #[cfg(feature = "sqlite")]
+            {
+                assert!(table("temp.catalog_items").await);
+                assert!(!table("main.catalog_items").await);
+            }
+            #[cfg(feature = "mysql")]
+            {
+                assert!(table("information_schema.COLUMNS").await);
+                assert!(!table("information_schema.catalog_items").await);
+            }
+            #[cfg(feature = "postgres")]
+            {
+                assert!(table("pg_catalog.pg_class").await);
+                assert!(!table("pg_catalog.catalog_items").await);
+                assert!(column("pg_catalog.pg_class", "relname").await);
+            }
+            assert!(!table("missing.catalog_items").await);
+            assert!(!column("missing.catalog_items", "name").await);
+
+            #[cfg(feature = "mysql")]
+            pool.execute("DROP TABLE catalog_items").await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Postgres `COPY ... TO STDOUT` exports.
+//!
+//! The server sends the rows as CSV, so they are neither decoded nor converted
+//! to JSON, which makes exporting large tables much faster than selecting them.
+
+use serde::{Deserialize, Serialize};
+use sqlx::Pool;
+use tauri::{command, AppHandle, Manager, Runtime, State};
+use tokio::io::AsyncWriteExt;
+
+use std::{future::poll_fn, path::PathBuf};
+
+use crate::{
+    plugin::{Db, DbInstances},
+    Error, Result,
+};
+
+/// Options for [`copy_to_csv`].
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct CopyOptions {
+    /// Writes the CSV to this file instead of returning it. The path must be
+    /// allowed by the fs scope.
+    path: Option<PathBuf>,
+    /// The column separator, a single ASCII character.
+    delimiter: char,
+    /// Starts with a line of the column names.
+    header: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            delimiter: ',',
+            header: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CopyResult {
+    /// The number of exported rows, not counting the header.
+    rows: u64,
+    /// The size of the CSV in bytes.
+    bytes: u64,
+    /// The CSV, unless it was written to a file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    csv: Option<String>,
+}
+
+/// Exports the rows of `query` as CSV with `COPY (query) TO STDOUT`, either
+/// returning it or writing it to a file.
+#[command]
+pub(crate) async fn copy_to_csv<R: Runtime>(
+    app: AppHandle<R>,
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    query: String,
+    options: Option<CopyOptions>,
+) -> Result<CopyResult> {
+    let options = options.unwrap_or_default();
+    if let Some(path) = &options.path {
+        if !app.fs_scope().is_allowed(path) {
+            return Err(Error::PathNotAllowed(path.clone()));
+        }
+    }
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    copy_out(&pool, &query, &options).await
+}
+
+async fn copy_out(pool: &Pool<Db>, query: &str, options: &CopyOptions) -> Result<CopyResult> {
+    let delimiter = options.delimiter;
+    // quotes, escapes and line breaks can't separate columns
+    if !delimiter.is_ascii() || matches!(delimiter, '\'' | '"' | '\\' | '\r' | '\n') {
+        return Err(Error::InvalidCopyDelimiter(delimiter));
+    }
+    let statement = format!(
+        "COPY ({query}) TO STDOUT WITH (FORMAT csv, DELIMITER '{delimiter}', HEADER {})",
+        options.header
+    );
+
+    let mut conn = pool.acquire().await?;
+    let mut stream = conn.copy_out_raw(&statement).await?;
+
+    let mut file = match &options.path {
+        Some(path) => Some(tokio::fs::File::create(path).await?),
+        None => None,
+    };
+    let mut csv = Vec::new();
+    let mut lines = CsvLines::default();
+    while let Some(chunk) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+        let chunk = chunk?;
+        lines.count(&chunk);
+        match &mut file {
+            Some(file) => file.write_all(&chunk).await?,
+            None => csv.extend_from_slice(&chunk),
+        }
+    }
+    if let Some(file) = &mut file {
+        file.sync_all().await?;
+    }
+
+    let rows = lines.count - u64::from(options.header && lines.count > 0);
+    Ok(CopyResult {
+        rows,
+        bytes: lines.bytes,
+        csv: file
+            .is_none()
+            .then(|| String::from_utf8_lossy(&csv).into_owned()),
+    })
+}
+
+/// Counts the lines of a CSV received in chunks. Line breaks inside quoted
+/// values don't end a line.
+#[derive(Default)]
+struct CsvLines {
+    count: u64,
+    bytes: u64,
+    quoted: bool,
+}
+
+impl CsvLines {
+    fn count(&mut self, chunk: &[u8]) {
+        self.bytes += chunk.len() as u64;
+        for byte in chunk {
+            match byte {
+                // an escaped quote `""` toggles twice
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => self.count += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Executor;
+
+    use super::*;
+    use crate::testing::{block_on, pool};
+
+    #[test]
+    fn exports_a_table_as_csv() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute("CREATE TEMPORARY TABLE exported (id int, name text)")
+                .await
+                .unwrap();
+            pool.execute(
+                "INSERT INTO exported VALUES (1, 'plain'), (2, 'a;b'), (3, E'two\\nlines \"quoted\"'), (4, NULL)",
+            )
+            .await
+            .unwrap();
+            let query = "SELECT * FROM exported ORDER BY id";
+
+            let options = CopyOptions {
+                delimiter: ';',
+                header: true,
+                ..Default::default()
+            };
+            let result = copy_out(&pool, query, &options).await.unwrap();
+            let csv = "id;name\n1;plain\n2;\"a;b\"\n3;\"two\nlines \"\"quoted\"\"\"\n4;\n";
+            assert_eq!(result.csv.as_deref(), Some(csv));
+            assert_eq!(result.rows, 4);
+            assert_eq!(result.bytes, csv.len() as u64);
+
+            let path =
+                std::env::temp_dir().join(format!("tauri-plugin-sql-{}.csv", std::process::id()));
+            let options = CopyOptions {
+                path: Some(path.clone()),
+                ..Default::default()
+            };
+            let result = copy_out(&pool, query, &options).await.unwrap();
+            let written = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(result.csv.is_none());
+            assert_eq!(result.rows, 4);
+            let csv = "1,plain\n2,a;b\n3,\"two\nlines \"\"quoted\"\"\"\n4,\n";
+            assert_eq!(written, csv);
+
+            let options = CopyOptions {
+                delimiter: '"',
+                ..Default::default()
+            };
+            assert!(matches!(
+                copy_out(&pool, query, &options).await,
+                Err(Error::InvalidCopyDelimiter('"'))
+            ));
+        });
+    }
+}
@@ -9,7 +9,326 @@ mod sqlite;
 pub(crate) use mysql::to_json;
 
 #[cfg(feature = "postgres")]
-pub(crate) use postgres::to_json;
+pub(crate) use postgres::{resolve_regconfig_names, to_json, MONEY_SCALE};
 
 #[cfg(feature = "sqlite")]
 pub(crate) use sqlite::to_json;
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::Error;
+
+use std::collections::HashMap;
+#[cfg(not(feature = "sqlite"))]
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+use time::{macros::format_description, PrimitiveDateTime};
+
+/// Options controlling how column values are converted to JSON.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DecodeOptions {
+    /// How timestamps are formatted, including range bounds.
+    pub timestamp_format: TimestampFormat,
+    /// Convert timestamps with a time zone to UTC before formatting them.
+    pub utc: bool,
+    /// Decode empty text values as `null`, for schemas that use both
+    /// interchangeably. Off by default, so values are returned as stored.
+    pub empty_strings_as_null: bool,
+    /// The maximum size of a `bytea` value in bytes. Queries returning a
+    /// larger value fail instead of transferring it to the frontend.
+    /// Unlimited by default.
+    #[cfg(feature = "postgres")]
+    pub max_bytea_size: Option<usize>,
+    /// How `bytea` values are represented.
+    #[cfg(feature = "postgres")]
+    pub bytea_format: ByteaFormat,
+    /// Decode one-dimensional `int2[]`, `int4[]`, `int8[]`, `float4[]` and
+    /// `float8[]` arrays without `NULL` elements to
+    /// `{ typedArray, data }`, the base64 of their little-endian values, for
+    /// the frontend to read as e.g. a `Float64Array`. Much more compact than a
+    /// JSON array for large arrays. Off by default.
+    #[cfg(feature = "postgres")]
+    pub packed_arrays: bool,
+    /// Decode Postgres arrays to `{ dims, data }`, where `dims` are the
+    /// `[lower, upper]` bounds of each dimension and `data` the elements, nested
+    /// for each dimension, so the shape and lower bounds of the array can be
+    /// recovered. Off by default, so arrays are the nested elements only.
+    #[cfg(feature = "postgres")]
+    pub array_dimensions: bool,
+    /// Decode Postgres enum values to `{ label, ordinal }`, where `ordinal` is
+    /// the 0-based position of the label in the enum's definition order, e.g.
+    /// to sort by it. Off by default, so enum values are their label.
+    #[cfg(feature = "postgres")]
+    pub enum_ordinals: bool,
+    /// Gzip `text`, `varchar` and `bytea` values larger than this many bytes,
+    /// decoding them to `{ encoding: "gzip", type, data }`, where `type` is
+    /// `text` or `bytea` and `data` the base64 of the compressed value, for the
+    /// frontend to decompress. Values that don't get smaller are left as is.
+    /// Off by default.
+    #[cfg(feature = "postgres")]
+    pub compress_threshold: Option<usize>,
+    /// How Postgres `tsvector` values are represented.
+    #[cfg(feature = "postgres")]
+    pub tsvector_format: TsVectorFormat,
+    /// How the values of specific types are represented, by type name, e.g.
+    /// `int8` or `numeric` (case-insensitive). Types not listed keep their
+    /// default: integers and floats are numbers, `numeric` values strings.
+    pub number_formats: HashMap<String, NumberFormat>,
+    /// What to do when several columns of a result have the same name, e.g.
+    /// the `id` of both tables of a join.
+    pub duplicate_columns: DuplicateColumns,
+}
+
+/// How rows with several columns of the same name are converted to objects,
+/// which can only have one value per key.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateColumns {
+    /// The first column keeps its name, the next ones get a `_2`, `_3`, ...
+    /// suffix, skipping names already used by other columns: `id`, `id_2`.
+    #[default]
+    Suffix,
+    /// Fail the query.
+    Error,
+}
+
+/// The keys of the columns named `names` in the row objects, see
+/// [`DecodeOptions::duplicate_columns`].
+pub(crate) fn column_names<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    options: &DecodeOptions,
+) -> Result<Vec<String>, Error> {
+    let names = names.into_iter().collect::<Vec<_>>();
+    let mut keys = Vec::<String>::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+        if !names[..i].contains(name) {
+            keys.push(name.to_string());
+            continue;
+        }
+        if let DuplicateColumns::Error = options.duplicate_columns {
+            return Err(Error::DuplicateColumn(name.to_string()));
+        }
+        let key = (2..)
+            .map(|n| format!("{name}_{n}"))
+            .find(|key| !names.contains(&key.as_str()) && !keys.contains(key))
+            .unwrap();
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// The string representation of timestamps.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimestampFormat {
+    /// The `Display` representation of the `time` crate, e.g. `2023-01-01 12:00:00.0 +00:00:00`.
+    #[default]
+    Display,
+    /// RFC 3339, e.g. `2023-01-01T12:00:00Z`. Timestamps without a time zone
+    /// omit the offset.
+    Rfc3339,
+}
+
+/// The JSON representation of a Postgres `bytea` value.
+#[cfg(feature = "postgres")]
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ByteaFormat {
+    /// An array of the bytes as numbers, e.g. `[222, 173]`.
+    #[default]
+    Array,
+    /// A standard base64 string, e.g. `"3q0="`.
+    Base64,
+    /// A lowercase hex string like `encode(value, 'hex')`, e.g. `"dead"`,
+    /// which is how hashes are usually displayed.
+    Hex,
+}
+
+/// The JSON representation of a Postgres `tsvector` value.
+#[cfg(feature = "postgres")]
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TsVectorFormat {
+    /// The text representation, e.g. `"'a':1A,3 'cat':2"`.
+    #[default]
+    Text,
+    /// An array of the lexemes, e.g.
+    /// `[{ "word": "a", "positions": [{ "position": 1, "weight": "A" }, ...] }, ...]`.
+    /// Lexemes without positions have an empty `positions` array.
+    Structured,
+}
+
+/// The JSON representation of a numeric type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NumberFormat {
+    /// A JSON number, which JavaScript reads as a double, so integers beyond
+    /// 2^53 and decimals lose precision.
+    Number,
+    /// The exact decimal representation as a string.
+    String,
+}
+
+/// Converts a decoded value of type `type_name` to the representation set for
+/// it in [`DecodeOptions::number_formats`], if any. Strings that aren't
+/// numbers, like `NaN`, stay strings.
+pub(crate) fn number_format(type_name: &str, v: JsonValue, options: &DecodeOptions) -> JsonValue {
+    let format = options
+        .number_formats
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(type_name))
+        .map(|(_, format)| *format);
+
+    match (format, v) {
+        (Some(NumberFormat::String), JsonValue::Number(n)) => JsonValue::String(n.to_string()),
+        (Some(NumberFormat::Number), JsonValue::String(s)) => {
+            s.parse().map_or(JsonValue::String(s), JsonValue::Number)
+        }
+        (_, v) => v,
+    }
+}
+
+/// Converts a text value, which may be empty, to JSON.
+pub(crate) fn text(v: String, options: &DecodeOptions) -> JsonValue {
+    if v.is_empty() && options.empty_strings_as_null {
+        JsonValue::Null
+    } else {
+        JsonValue::String(v)
+    }
+}
+
+/// Formats a timestamp with a time zone.
+#[cfg(not(feature = "sqlite"))]
+pub(crate) fn offset_date_time(v: OffsetDateTime, options: &DecodeOptions) -> JsonValue {
+    let v = if options.utc {
+        v.to_offset(UtcOffset::UTC)
+    } else {
+        v
+    };
+
+    match options.timestamp_format {
+        TimestampFormat::Display => JsonValue::String(v.to_string()),
+        TimestampFormat::Rfc3339 => v
+            .format(&Rfc3339)
+            .map(JsonValue::String)
+            .unwrap_or(JsonValue::Null),
+    }
+}
+
+/// Formats a timestamp without a time zone.
+pub(crate) fn primitive_date_time(v: PrimitiveDateTime, options: &DecodeOptions) -> JsonValue {
+    match options.timestamp_format {
+        TimestampFormat::Display => JsonValue::String(v.to_string()),
+        TimestampFormat::Rfc3339 => {
+            let format = if v.nanosecond() == 0 {
+                format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]")
+            } else {
+                format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]")
+            };
+            v.format(format)
+                .map(JsonValue::String)
+                .unwrap_or(JsonValue::Null)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Executor, Row};
+
+    use super::*;
+    use crate::testing::{block_on, pool};
+
+    #[test]
+    fn decodes_empty_strings_as_null_when_set() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute("CREATE TEMPORARY TABLE strings (id INT, v TEXT)")
+                .await
+                .unwrap();
+            pool.execute("INSERT INTO strings (id, v) VALUES (1, ''), (2, 'a'), (3, NULL)")
+                .await
+                .unwrap();
+            let rows = sqlx::query("SELECT v FROM strings ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+            let decode = |options: &DecodeOptions| {
+                rows.iter()
+                    .map(|row| to_json(row.try_get_raw(0).unwrap(), options).unwrap())
+                    .collect::<Vec<_>>()
+            };
+
+            let options = DecodeOptions::default();
+            assert_eq!(decode(&options), ["".into(), "a".into(), JsonValue::Null]);
+            let options = DecodeOptions {
+                empty_strings_as_null: true,
+                ..Default::default()
+            };
+            assert_eq!(
+                decode(&options),
+                [JsonValue::Null, "a".into(), JsonValue::Null]
+            );
+        });
+    }
+
+    fn number_formats(formats: &[(&str, NumberFormat)]) -> DecodeOptions {
+        DecodeOptions {
+            number_formats: formats
+                .iter()
+                .map(|(name, format)| (name.to_string(), *format))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn formats_numbers_by_type_name() {
+        let options = number_formats(&[
+            ("int8", NumberFormat::String),
+            ("numeric", NumberFormat::Number),
+        ]);
+        let big = JsonValue::from(9_007_199_254_740_993_i64);
+        assert_eq!(
+            number_format("INT8", big.clone(), &options),
+            "9007199254740993"
+        );
+        assert_eq!(number_format("INT4", big.clone(), &options), big);
+        assert_eq!(
+            number_format("NUMERIC", "1.50".into(), &options),
+            serde_json::json!(1.5)
+        );
+        assert_eq!(number_format("NUMERIC", "NaN".into(), &options), "NaN");
+    }
+
+    #[test]
+    fn overrides_the_default_format_of_a_column_type() {
+        #[cfg(feature = "sqlite")]
+        let (query, ty) = ("SELECT 9007199254740993", "integer");
+        #[cfg(feature = "mysql")]
+        let (query, ty) = ("SELECT CAST(9007199254740993 AS SIGNED)", "bigint");
+        #[cfg(feature = "postgres")]
+        let (query, ty) = ("SELECT 9007199254740993::int8", "int8");
+
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let row = sqlx::query(query).fetch_one(&pool).await.unwrap();
+            let decode =
+                |options: &DecodeOptions| to_json(row.try_get_raw(0).unwrap(), options).unwrap();
+
+            assert_eq!(
+                decode(&DecodeOptions::default()),
+                JsonValue::from(9_007_199_254_740_993_i64)
+            );
+            let options = number_formats(&[(ty, NumberFormat::String)]);
+            assert_eq!(decode(&options), "9007199254740993");
+        });
+    }
+}
@@ -2,17 +2,19 @@ use serde_json::Value as JsonValue;
 use sqlx::{mysql::MySqlValueRef, TypeInfo, Value, ValueRef};
 use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
 
+use super::DecodeOptions;
 use crate::Error;
 
-pub(crate) fn to_json(v: MySqlValueRef) -> Result<JsonValue, Error> {
+pub(crate) fn to_json(v: MySqlValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
     if v.is_null() {
         return Ok(JsonValue::Null);
     }
 
-    let res = match v.type_info().name() {
+    let type_info = v.type_info();
+    let res = match type_info.name() {
         "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM" => {
             if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::String(v)
+                super::text(v, options)
             } else {
                 JsonValue::Null
             }
@@ -69,14 +71,14 @@ pub(crate) fn to_json(v: MySqlValueRef) -> Result<JsonValue, Error> {
         }
         "DATETIME" => {
             if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
+                super::primitive_date_time(v, options)
             } else {
                 JsonValue::Null
             }
         }
         "TIMESTAMP" => {
             if let Ok(v) = ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
-                JsonValue::String(v.to_string())
+                super::offset_date_time(v, options)
             } else {
                 JsonValue::Null
             }
@@ -90,8 +92,12 @@ pub(crate) fn to_json(v: MySqlValueRef) -> Result<JsonValue, Error> {
             }
         }
         "NULL" => JsonValue::Null,
-        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+        _ => return Err(Error::UnsupportedDatatype(type_info.name().to_string())),
     };
 
-    Ok(res)
+    if options.number_formats.is_empty() {
+        Ok(res)
+    } else {
+        Ok(super::number_format(type_info.name(), res, options))
+    }
 }
@@ -1,18 +1,634 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
 use serde_json::Value as JsonValue;
-use sqlx::{postgres::PgValueRef, TypeInfo, Value, ValueRef};
+use sqlx::{
+    error::BoxDynError,
+    postgres::{
+        types::{Oid, PgInterval, PgMoney, PgRange, PgRecordDecoder},
+        PgConnection, PgHasArrayType, PgTypeInfo, PgTypeKind, PgValue, PgValueFormat, PgValueRef,
+        Postgres,
+    },
+    Decode, Type, TypeInfo, Value, ValueRef,
+};
 use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
 
+use std::{collections::HashMap, ops::Bound};
+
+use super::{ByteaFormat, DecodeOptions, TsVectorFormat};
 use crate::Error;
 
-pub(crate) fn to_json(v: PgValueRef) -> Result<JsonValue, Error> {
+/// An array element or record field, decoded separately through [`to_json`]
+/// so every type supported as a column is also supported inside an array or
+/// record, e.g. `jsonb[]` elements are parsed JSON rather than strings.
+///
+/// Prepared queries return arrays in the binary format, where a `NULL` element
+/// has a length of -1 rather than 0, so it stays a null value distinct from an
+/// empty string, e.g. `{NULL,"",x}` is `[null, "", "x"]`. The text format can't
+/// tell a `NULL` element from the string `"NULL"`.
+struct Element(PgValue);
+
+impl Type<Postgres> for Element {
+    fn type_info() -> PgTypeInfo {
+        // only used for text-format results, which carry no element type
+        PgTypeInfo::with_name("TEXT")
+    }
+
+    fn compatible(_ty: &PgTypeInfo) -> bool {
+        // record fields carry their own type, checked by `to_json`
+        true
+    }
+}
+
+impl PgHasArrayType for Element {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("TEXT[]")
+    }
+
+    fn array_compatible(ty: &PgTypeInfo) -> bool {
+        matches!(ty.kind(), PgTypeKind::Array(_))
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Element {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(Self(ValueRef::to_owned(&value)))
+    }
+}
+
+/// Packs a numeric array for [`DecodeOptions::packed_arrays`], or returns
+/// `None` if it has `NULL` elements or more than one dimension.
+fn packed_array(v: &PgValueRef) -> Option<JsonValue> {
+    fn pack<T, const N: usize>(values: Vec<T>, to_le_bytes: fn(T) -> [u8; N]) -> Vec<u8> {
+        values.into_iter().flat_map(to_le_bytes).collect()
+    }
+
+    let value = ValueRef::to_owned(v);
+    let (typed_array, data) = match v.type_info().name() {
+        "INT2[]" => (
+            "Int16Array",
+            pack(value.try_decode().ok()?, i16::to_le_bytes),
+        ),
+        "INT4[]" => (
+            "Int32Array",
+            pack(value.try_decode().ok()?, i32::to_le_bytes),
+        ),
+        "INT8[]" => (
+            "BigInt64Array",
+            pack(value.try_decode().ok()?, i64::to_le_bytes),
+        ),
+        "FLOAT4[]" => (
+            "Float32Array",
+            pack(value.try_decode().ok()?, f32::to_le_bytes),
+        ),
+        "FLOAT8[]" => (
+            "Float64Array",
+            pack(value.try_decode().ok()?, f64::to_le_bytes),
+        ),
+        _ => return None,
+    };
+    Some(serde_json::json!({
+        "typedArray": typed_array,
+        "data": STANDARD.encode(data),
+    }))
+}
+
+/// Gzips a value larger than [`DecodeOptions::compress_threshold`] to
+/// `{ encoding, type, data }`, if that makes it smaller.
+fn compress(v: &[u8], ty: &str, options: &DecodeOptions) -> Option<JsonValue> {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    if v.len() <= options.compress_threshold? {
+        return None;
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(v).ok()?;
+    let data = STANDARD.encode(encoder.finish().ok()?);
+    (data.len() < v.len()).then(|| {
+        serde_json::json!({
+            "encoding": "gzip",
+            "type": ty,
+            "data": data,
+        })
+    })
+}
+
+fn bytea_to_json(v: Vec<u8>, format: ByteaFormat) -> JsonValue {
+    match format {
+        ByteaFormat::Array => {
+            JsonValue::Array(v.into_iter().map(|n| JsonValue::Number(n.into())).collect())
+        }
+        ByteaFormat::Base64 => JsonValue::String(STANDARD.encode(v)),
+        ByteaFormat::Hex => JsonValue::String(v.iter().map(|b| format!("{b:02x}")).collect()),
+    }
+}
+
+/// Decodes an enum value to its label, or `{ label, ordinal }` with
+/// [`DecodeOptions::enum_ordinals`]. The ordinal is `null` for labels added
+/// after the connection loaded the enum's type.
+fn enum_to_json(v: &PgValueRef, variants: &[String], options: &DecodeOptions) -> JsonValue {
+    match v.as_str() {
+        Ok(label) => enum_label_to_json(label, variants, options),
+        Err(_) => JsonValue::Null,
+    }
+}
+
+fn enum_label_to_json(label: &str, variants: &[String], options: &DecodeOptions) -> JsonValue {
+    if options.enum_ordinals {
+        let ordinal = variants.iter().position(|variant| variant == label);
+        serde_json::json!({ "label": label, "ordinal": ordinal })
+    } else {
+        JsonValue::String(label.to_string())
+    }
+}
+
+/// The `[lower, upper]` bounds of each dimension of an array, from the header
+/// of its binary format: the number of dimensions, a flags word and the OID of
+/// the elements, then the length and lower bound of each dimension. `None` for
+/// text-format values, which come from unprepared queries.
+fn array_dims(v: &PgValueRef) -> Option<Vec<(i32, i32)>> {
+    if !matches!(v.format(), PgValueFormat::Binary) {
+        return None;
+    }
+    let bytes = v.as_bytes().ok()?;
+    let int = |i: usize| -> Option<i32> {
+        Some(i32::from_be_bytes(
+            bytes.get(i * 4..i * 4 + 4)?.try_into().ok()?,
+        ))
+    };
+    (0..int(0)?.max(0) as usize)
+        .map(|i| {
+            let (len, lower) = (int(3 + i * 2)?, int(4 + i * 2)?);
+            Some((lower, lower + len - 1))
+        })
+        .collect()
+}
+
+/// Decodes an array to a JSON array, nested for each dimension, or to
+/// `{ dims, data }` with [`DecodeOptions::array_dimensions`].
+///
+/// sqlx only decodes one-dimensional arrays starting at 1, whose elements are
+/// decoded through [`to_json`] like columns are. The elements of the other
+/// arrays are read from the binary format by [`binary_element_to_json`].
+fn array_to_json(v: &PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
+    let dims = array_dims(v);
+    let data = match &dims {
+        Some(dims) if dims.len() > 1 || dims.first().map_or(false, |(lower, _)| *lower != 1) => {
+            nested_array(v, dims, options)?
+        }
+        _ => match ValueRef::to_owned(v).try_decode::<Vec<Element>>() {
+            Ok(elements) => JsonValue::Array(
+                elements
+                    .iter()
+                    .map(|e| to_json(e.0.as_ref(), options))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Err(_) => return Ok(JsonValue::Null),
+        },
+    };
+    if options.array_dimensions {
+        let dims = dims
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(lower, upper)| serde_json::json!([lower, upper]))
+            .collect::<Vec<_>>();
+        Ok(serde_json::json!({ "dims": dims, "data": data }))
+    } else {
+        Ok(data)
+    }
+}
+
+/// Decodes the elements of an array with the bounds `dims` to nested arrays,
+/// in row-major order like Postgres stores them. After the header, each
+/// element is its length, -1 for `NULL`, and its binary value.
+fn nested_array(
+    v: &PgValueRef,
+    dims: &[(i32, i32)],
+    options: &DecodeOptions,
+) -> Result<JsonValue, Error> {
+    fn nest(
+        dims: &[(i32, i32)],
+        next: &mut impl FnMut() -> Result<JsonValue, Error>,
+    ) -> Result<JsonValue, Error> {
+        match dims.split_first() {
+            None => next(),
+            Some(((lower, upper), inner)) => (*lower..=*upper)
+                .map(|_| nest(inner, next))
+                .collect::<Result<_, _>>()
+                .map(JsonValue::Array),
+        }
+    }
+
+    let invalid = || Error::UnsupportedDatatype(format!("invalid {}", v.type_info().name()));
+    let element_type = match v.type_info().kind() {
+        PgTypeKind::Array(element_type) => element_type.clone(),
+        _ => return Err(invalid()),
+    };
+    let mut bytes = v.as_bytes().map_err(|_| invalid())?;
+    bytes = bytes.get(12 + dims.len() * 8..).ok_or_else(invalid)?;
+    nest(dims, &mut || {
+        let len = bytes
+            .get(..4)
+            .map(|len| i32::from_be_bytes(len.try_into().unwrap()))
+            .ok_or_else(invalid)?;
+        bytes = &bytes[4..];
+        if len < 0 {
+            return Ok(JsonValue::Null);
+        }
+        let element = bytes.get(..len as usize).ok_or_else(invalid)?;
+        bytes = &bytes[len as usize..];
+        binary_element_to_json(&element_type, element, options)
+    })
+}
+
+/// Decodes an element of an array that sqlx can't decode from its binary
+/// format, for the element types that are most common in such arrays.
+fn binary_element_to_json(
+    ty: &PgTypeInfo,
+    bytes: &[u8],
+    options: &DecodeOptions,
+) -> Result<JsonValue, Error> {
+    // the Postgres epoch of dates and timestamps
+    const EPOCH: Date = time::macros::date!(2000 - 01 - 01);
+
+    fn be<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+        bytes.try_into().ok()
+    }
+
+    let name = ty.name();
+    let v = match name {
+        "BOOL" => be::<1>(bytes).map(|[b]| JsonValue::Bool(b != 0)),
+        "INT2" => be(bytes).map(|b| JsonValue::from(i16::from_be_bytes(b))),
+        "INT4" => be(bytes).map(|b| JsonValue::from(i32::from_be_bytes(b))),
+        "INT8" => be(bytes).map(|b| JsonValue::from(i64::from_be_bytes(b))),
+        "FLOAT4" => be(bytes).map(|b| JsonValue::from(f32::from_be_bytes(b))),
+        "FLOAT8" => be(bytes).map(|b| JsonValue::from(f64::from_be_bytes(b))),
+        "NUMERIC" => numeric_to_string(bytes).map(JsonValue::String),
+        "CHAR" | "VARCHAR" | "TEXT" | "NAME" => std::str::from_utf8(bytes)
+            .ok()
+            .map(|v| super::text(v.to_string(), options)),
+        "JSON" => serde_json::from_slice(bytes).ok(),
+        // the binary format of jsonb is a version byte and the text
+        "JSONB" => bytes.get(1..).and_then(|v| serde_json::from_slice(v).ok()),
+        "BYTEA" => {
+            let size = bytes.len();
+            if let Some(limit) = options.max_bytea_size.filter(|limit| size > *limit) {
+                return Err(Error::ByteaTooLarge { size, limit });
+            }
+            Some(bytea_to_json(bytes.to_vec(), options.bytea_format))
+        }
+        "DATE" => be(bytes)
+            .and_then(|b| EPOCH.checked_add(time::Duration::days(i32::from_be_bytes(b).into())))
+            .map(|v| JsonValue::String(v.to_string())),
+        "TIMESTAMP" | "TIMESTAMPTZ" => be(bytes)
+            .and_then(|b| {
+                EPOCH
+                    .midnight()
+                    .checked_add(time::Duration::microseconds(i64::from_be_bytes(b)))
+            })
+            .map(|v| {
+                if name == "TIMESTAMP" {
+                    super::primitive_date_time(v, options)
+                } else {
+                    super::offset_date_time(v.assume_utc(), options)
+                }
+            }),
+        _ => match ty.kind() {
+            PgTypeKind::Enum(variants) => std::str::from_utf8(bytes)
+                .ok()
+                .map(|label| enum_label_to_json(label, variants, options)),
+            _ => return Err(Error::UnsupportedDatatype(format!("{name}[]"))),
+        },
+    };
+
+    let v = v.unwrap_or(JsonValue::Null);
+    if options.number_formats.is_empty() {
+        Ok(v)
+    } else {
+        Ok(super::number_format(name, v, options))
+    }
+}
+
+/// Converts a range to `{ lower, upper, lowerInclusive, upperInclusive }`,
+/// where unbounded sides are `null`. Empty ranges are the string `"empty"`.
+fn range_to_json<T>(
+    v: &PgValueRef,
+    range: PgRange<T>,
+    bound_to_json: impl Fn(T) -> JsonValue,
+) -> JsonValue {
+    // the empty flag is lost when decoding, it is the first byte of the range
+    if v.as_bytes()
+        .map_or(false, |b| b.first() == Some(&RANGE_EMPTY))
+    {
+        return JsonValue::String("empty".into());
+    }
+
+    let (lower, lower_inclusive) = bound_to_json_pair(range.start, &bound_to_json);
+    let (upper, upper_inclusive) = bound_to_json_pair(range.end, &bound_to_json);
+    serde_json::json!({
+        "lower": lower,
+        "upper": upper,
+        "lowerInclusive": lower_inclusive,
+        "upperInclusive": upper_inclusive,
+    })
+}
+
+fn bound_to_json_pair<T>(bound: Bound<T>, to_json: impl Fn(T) -> JsonValue) -> (JsonValue, bool) {
+    match bound {
+        Bound::Included(v) => (to_json(v), true),
+        Bound::Excluded(v) => (to_json(v), false),
+        Bound::Unbounded => (JsonValue::Null, false),
+    }
+}
+
+const RANGE_EMPTY: u8 = 0x01;
+
+/// The number of fractional digits of `money` values. It depends on the
+/// `lc_monetary` setting of the database, and is 2 for most locales.
+pub(crate) const MONEY_SCALE: u32 = 2;
+
+/// Formats an amount in cents as a decimal string, e.g. `-12.34`.
+fn money_to_json(money: PgMoney) -> JsonValue {
+    let factor = 10_u64.pow(MONEY_SCALE);
+    let sign = if money.0 < 0 { "-" } else { "" };
+    let cents = money.0.unsigned_abs();
+    JsonValue::String(format!(
+        "{sign}{}.{:0width$}",
+        cents / factor,
+        cents % factor,
+        width = MONEY_SCALE as usize
+    ))
+}
+
+/// Formats an `interval` as an ISO 8601 duration like the `iso_8601` interval
+/// style of Postgres does, e.g. `P1Y2M3DT4H5M6.5S`. The months, days and time
+/// are stored separately and each keep their own sign, e.g. `P-1M3D`.
+fn interval_to_json(interval: PgInterval) -> JsonValue {
+    let mut duration = String::from("P");
+    let (years, months) = (interval.months / 12, interval.months % 12);
+    for (value, unit) in [(years, 'Y'), (months, 'M'), (interval.days, 'D')] {
+        if value != 0 {
+            duration.push_str(&format!("{value}{unit}"));
+        }
+    }
+
+    if interval.microseconds != 0 {
+        let sign = if interval.microseconds < 0 { "-" } else { "" };
+        let micros = interval.microseconds.unsigned_abs();
+        let (hours, minutes) = (micros / 3_600_000_000, micros / 60_000_000 % 60);
+        let (seconds, fraction) = (micros / 1_000_000 % 60, micros % 1_000_000);
+
+        duration.push('T');
+        for (value, unit) in [(hours, 'H'), (minutes, 'M')] {
+            if value != 0 {
+                duration.push_str(&format!("{sign}{value}{unit}"));
+            }
+        }
+        if seconds != 0 || fraction != 0 {
+            duration.push_str(&format!("{sign}{seconds}"));
+            if fraction != 0 {
+                let fraction = format!("{fraction:06}");
+                duration.push_str(&format!(".{}", fraction.trim_end_matches('0')));
+            }
+            duration.push('S');
+        }
+    }
+
+    if duration == "P" {
+        duration.push_str("T0S");
+    }
+    JsonValue::String(duration)
+}
+
+/// Formats a `numeric` from its binary format, like Postgres does, e.g.
+/// `-12.340` with a scale of 3. The digits are stored in base 10000, with the
+/// weight of the first one, so `12.34` is `[12, 3400]` with a weight of 0.
+fn numeric_to_string(bytes: &[u8]) -> Option<String> {
+    let word = |i: usize| -> Option<i16> {
+        Some(i16::from_be_bytes(
+            bytes.get(i * 2..i * 2 + 2)?.try_into().ok()?,
+        ))
+    };
+    let (ndigits, weight, sign, scale) = (word(0)?, word(1)?, word(2)? as u16, word(3)?);
+    match sign {
+        0xC000 => return Some("NaN".into()),
+        0xD000 => return Some("Infinity".into()),
+        0xF000 => return Some("-Infinity".into()),
+        _ => {}
+    }
+    let digits = (0..ndigits.max(0) as usize)
+        .map(|i| word(4 + i))
+        .collect::<Option<Vec<_>>>()?;
+    let digit = |i: i32| -> i16 {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i).copied())
+            .unwrap_or(0)
+    };
+
+    let mut s = String::new();
+    if sign == 0x4000 {
+        s.push('-');
+    }
+    if weight < 0 {
+        s.push('0');
+    } else {
+        s.push_str(&digit(0).to_string());
+        for i in 1..=weight as i32 {
+            s.push_str(&format!("{:04}", digit(i)));
+        }
+    }
+    if scale > 0 {
+        let mut fraction = String::new();
+        let mut i = weight as i32 + 1;
+        while fraction.len() < scale as usize {
+            fraction.push_str(&format!("{:04}", digit(i)));
+            i += 1;
+        }
+        fraction.truncate(scale as usize);
+        s.push('.');
+        s.push_str(&fraction);
+    }
+    Some(s)
+}
+
+/// Formats the internal single-byte `"char"` type like Postgres does: `0` is
+/// the empty string and bytes that aren't ASCII are octal escapes, e.g. `\201`.
+fn internal_char_to_json(c: i8) -> JsonValue {
+    let c = c as u8;
+    JsonValue::String(match c {
+        0 => String::new(),
+        c if c.is_ascii() => char::from(c).to_string(),
+        c => format!("\\{c:03o}"),
+    })
+}
+
+/// Replaces the OIDs that the `columns` of type `regconfig` are decoded to with
+/// the names of the text search configurations, as Postgres formats them, e.g.
+/// `english`. OIDs that don't belong to a configuration are kept.
+pub(crate) async fn resolve_regconfig_names(
+    conn: &mut PgConnection,
+    columns: &[String],
+    rows: &mut [HashMap<String, JsonValue>],
+) -> Result<(), Error> {
+    let oids = rows
+        .iter()
+        .flat_map(|row| columns.iter().filter_map(|c| row.get(c)?.as_i64()))
+        .collect::<Vec<_>>();
+    if oids.is_empty() {
+        return Ok(());
+    }
+
+    let names: HashMap<i64, String> = sqlx::query_as(
+        "SELECT oid::int8, oid::regconfig::text FROM pg_ts_config WHERE oid::int8 = ANY($1)",
+    )
+    .bind(oids)
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .collect();
+    for row in rows {
+        for column in columns {
+            if let Some(value) = row.get_mut(column) {
+                if let Some(name) = value.as_i64().and_then(|oid| names.get(&oid)) {
+                    *value = JsonValue::String(name.clone());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A `tsvector` value, its lexemes in the order Postgres sorts them.
+#[derive(Serialize)]
+struct TsVector(Vec<Lexeme>);
+
+#[derive(Serialize)]
+struct Lexeme {
+    word: String,
+    positions: Vec<LexemeMeta>,
+}
+
+/// A position of a lexeme in the document, with its weight from `A` to `D`.
+#[derive(Serialize)]
+struct LexemeMeta {
+    position: u16,
+    weight: char,
+}
+
+impl Type<Postgres> for TsVector {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("tsvector")
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for TsVector {
+    /// The binary format is the number of lexemes, then for each lexeme its
+    /// NUL-terminated text, its number of positions and the positions, with
+    /// the weight in their two high bits, all big-endian.
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], BoxDynError> {
+            if bytes.len() < n {
+                return Err("invalid tsvector".into());
+            }
+            let (head, tail) = bytes.split_at(n);
+            *bytes = tail;
+            Ok(head)
+        }
+
+        let mut bytes = value.as_bytes()?;
+        let count = u32::from_be_bytes(take(&mut bytes, 4)?.try_into()?);
+        let mut lexemes = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            let end = bytes
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or("invalid tsvector")?;
+            let word = std::str::from_utf8(take(&mut bytes, end)?)?.to_string();
+            take(&mut bytes, 1)?;
+            let npos = u16::from_be_bytes(take(&mut bytes, 2)?.try_into()?);
+            let positions = (0..npos)
+                .map(|_| {
+                    let pos = u16::from_be_bytes(take(&mut bytes, 2)?.try_into()?);
+                    Ok(LexemeMeta {
+                        position: pos & 0x3FFF,
+                        weight: ['D', 'C', 'B', 'A'][(pos >> 14) as usize],
+                    })
+                })
+                .collect::<Result<_, BoxDynError>>()?;
+            lexemes.push(Lexeme { word, positions });
+        }
+        Ok(Self(lexemes))
+    }
+}
+
+impl TsVector {
+    /// Formats the value like Postgres does, e.g. `'a':1A,3 'cat':2`: lexemes
+    /// are quoted, with `'` doubled and `\` escaped, and the default weight
+    /// `D` is omitted.
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (i, lexeme) in self.0.iter().enumerate() {
+            if i > 0 {
+                text.push(' ');
+            }
+            text.push('\'');
+            for c in lexeme.word.chars() {
+                match c {
+                    '\'' => text.push_str("''"),
+                    '\\' => text.push_str("\\\\"),
+                    c => text.push(c),
+                }
+            }
+            text.push('\'');
+            for (i, meta) in lexeme.positions.iter().enumerate() {
+                text.push(if i == 0 { ':' } else { ',' });
+                text.push_str(&meta.position.to_string());
+                if meta.weight != 'D' {
+                    text.push(meta.weight);
+                }
+            }
+        }
+        text
+    }
+}
+
+/// The fields of an anonymous `record`, e.g. `ROW(1, 'a')`, each with the type
+/// given in the record itself.
+fn record_fields(v: &PgValueRef) -> Result<Vec<Element>, BoxDynError> {
+    // the binary format starts with the number of fields
+    let count = match v.as_bytes()? {
+        [a, b, c, d, ..] => u32::from_be_bytes([*a, *b, *c, *d]),
+        _ => return Err("invalid record".into()),
+    };
+    let mut decoder = PgRecordDecoder::new(v.clone())?;
+    (0..count)
+        .map(|_| decoder.try_decode::<Element>())
+        .collect()
+}
+
+pub(crate) fn to_json(v: PgValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
     if v.is_null() {
         return Ok(JsonValue::Null);
     }
 
-    let res = match v.type_info().name() {
+    let type_info = v.type_info();
+    if let PgTypeKind::Enum(variants) = type_info.kind() {
+        return Ok(enum_to_json(&v, variants, options));
+    }
+
+    let res = match type_info.name() {
         "CHAR" | "VARCHAR" | "TEXT" | "NAME" => {
-            if let Ok(v) = ValueRef::to_owned(&v).try_decode() {
-                JsonValue::String(v)
+            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<String>() {
+                compress(v.as_bytes(), "text", options).unwrap_or_else(|| super::text(v, options))
+            } else {
+                JsonValue::Null
+            }
+        }
+        // the internal type used by catalogs, e.g. `pg_class.relkind`
+        "\"CHAR\"" => {
+            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<i8>() {
+                internal_char_to_json(v)
             } else {
                 JsonValue::Null
             }
@@ -75,29 +691,449 @@ pub(crate) fn to_json(v: PgValueRef) -> Result<JsonValue, Error> {
         }
         "TIMESTAMP" => {
             if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
+                super::primitive_date_time(v, options)
             } else {
                 JsonValue::Null
             }
         }
         "TIMESTAMPTZ" => {
             if let Ok(v) = ValueRef::to_owned(&v).try_decode::<OffsetDateTime>() {
-                JsonValue::String(v.to_string())
+                super::offset_date_time(v, options)
+            } else {
+                JsonValue::Null
+            }
+        }
+        "NUMERIC" => {
+            if let Some(v) = v.as_bytes().ok().and_then(numeric_to_string) {
+                JsonValue::String(v)
+            } else {
+                JsonValue::Null
+            }
+        }
+        "MONEY" => {
+            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PgMoney>() {
+                money_to_json(v)
+            } else {
+                JsonValue::Null
+            }
+        }
+        "INTERVAL" => {
+            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<PgInterval>() {
+                interval_to_json(v)
             } else {
                 JsonValue::Null
             }
         }
         "JSON" | "JSONB" => ValueRef::to_owned(&v).try_decode().unwrap_or_default(),
         "BYTEA" => {
+            let size = v.as_bytes().map_or(0, <[u8]>::len);
+            if let Some(limit) = options.max_bytea_size.filter(|limit| size > *limit) {
+                return Err(Error::ByteaTooLarge { size, limit });
+            }
             if let Ok(v) = ValueRef::to_owned(&v).try_decode::<Vec<u8>>() {
-                JsonValue::Array(v.into_iter().map(|n| JsonValue::Number(n.into())).collect())
+                compress(&v, "bytea", options)
+                    .unwrap_or_else(|| bytea_to_json(v, options.bytea_format))
+            } else {
+                JsonValue::Null
+            }
+        }
+        "INT4RANGE" => {
+            if let Ok(r) = ValueRef::to_owned(&v).try_decode::<PgRange<i32>>() {
+                range_to_json(&v, r, JsonValue::from)
+            } else {
+                JsonValue::Null
+            }
+        }
+        "INT8RANGE" => {
+            if let Ok(r) = ValueRef::to_owned(&v).try_decode::<PgRange<i64>>() {
+                range_to_json(&v, r, JsonValue::from)
+            } else {
+                JsonValue::Null
+            }
+        }
+        "DATERANGE" => {
+            if let Ok(r) = ValueRef::to_owned(&v).try_decode::<PgRange<Date>>() {
+                range_to_json(&v, r, |d| JsonValue::String(d.to_string()))
+            } else {
+                JsonValue::Null
+            }
+        }
+        "TSRANGE" => {
+            if let Ok(r) = ValueRef::to_owned(&v).try_decode::<PgRange<PrimitiveDateTime>>() {
+                range_to_json(&v, r, |t| super::primitive_date_time(t, options))
+            } else {
+                JsonValue::Null
+            }
+        }
+        "TSTZRANGE" => {
+            if let Ok(r) = ValueRef::to_owned(&v).try_decode::<PgRange<OffsetDateTime>>() {
+                range_to_json(&v, r, |t| super::offset_date_time(t, options))
+            } else {
+                JsonValue::Null
+            }
+        }
+        "RECORD" => {
+            // the fields of anonymous records have no names
+            if let Ok(fields) = record_fields(&v) {
+                let fields = fields
+                    .iter()
+                    .map(|f| to_json(f.0.as_ref(), options))
+                    .collect::<Result<_, _>>()?;
+                JsonValue::Array(fields)
             } else {
                 JsonValue::Null
             }
         }
         "VOID" => JsonValue::Null,
-        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+        "tsvector" => {
+            if let Ok(v) = ValueRef::to_owned(&v).try_decode::<TsVector>() {
+                match options.tsvector_format {
+                    TsVectorFormat::Text => JsonValue::String(v.to_text()),
+                    TsVectorFormat::Structured => serde_json::to_value(v).unwrap_or_default(),
+                }
+            } else {
+                JsonValue::Null
+            }
+        }
+        // resolved to names by `resolve_regconfig_names` for whole columns
+        "regconfig" => {
+            if let Ok(v) = ValueRef::to_owned(&v).try_decode_unchecked::<Oid>() {
+                JsonValue::from(v.0)
+            } else {
+                JsonValue::Null
+            }
+        }
+        _ if matches!(v.type_info().kind(), PgTypeKind::Array(_)) => {
+            if options.packed_arrays {
+                if let Some(packed) = packed_array(&v) {
+                    return Ok(packed);
+                }
+            }
+            array_to_json(&v, options)?
+        }
+        _ => return Err(Error::UnsupportedDatatype(type_info.name().to_string())),
     };
 
-    Ok(res)
+    if options.number_formats.is_empty() {
+        Ok(res)
+    } else {
+        Ok(super::number_format(type_info.name(), res, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Row;
+
+    use super::*;
+    use crate::{
+        decode::TimestampFormat,
+        testing::{block_on, pool},
+    };
+
+    /// Decodes the first column of the single row of a prepared `query`.
+    fn try_decode(query: &str, options: &DecodeOptions) -> Option<Result<JsonValue, Error>> {
+        block_on(async {
+            let pool = pool().await?;
+            let row = sqlx::query(query).fetch_one(&pool).await.unwrap();
+            Some(to_json(row.try_get_raw(0).unwrap(), options))
+        })
+    }
+
+    fn decode(query: &str, options: &DecodeOptions) -> Option<JsonValue> {
+        try_decode(query, options).map(Result::unwrap)
+    }
+
+    #[test]
+    fn parses_jsonb_array_elements() {
+        let query = r#"SELECT ARRAY['{"a": 1}', '{"b": [true, null]}']::jsonb[]"#;
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, serde_json::json!([{ "a": 1 }, { "b": [true, null] }]));
+        }
+    }
+
+    #[test]
+    fn formats_tstzrange_bounds_like_timestamptz() {
+        let options = DecodeOptions {
+            timestamp_format: TimestampFormat::Rfc3339,
+            utc: true,
+            ..Default::default()
+        };
+        let timestamp = "'2023-01-01 12:00:00.5+02'::timestamptz";
+        let range = decode(
+            &format!("SELECT tstzrange({timestamp}, NULL, '[)')"),
+            &options,
+        );
+        let scalar = decode(&format!("SELECT {timestamp}"), &options);
+        if let (Some(range), Some(scalar)) = (range, scalar) {
+            assert_eq!(scalar, "2023-01-01T10:00:00.5Z");
+            assert_eq!(range["lower"], scalar);
+            assert_eq!(range["upper"], JsonValue::Null);
+        }
+    }
+
+    #[test]
+    fn decodes_jsonb_to_record_fields() {
+        let query = r#"SELECT t FROM jsonb_to_record('{"a": 1, "b": "x", "c": [1.5], "d": null}')
+            AS t(a int4, b text, c jsonb, d date)"#;
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, serde_json::json!([1, "x", [1.5], null]));
+        }
+    }
+
+    #[test]
+    fn decodes_internal_char_arrays() {
+        // `pg_proc.proargmodes` is a `"char"[]` catalog column
+        let query = "SELECT proargmodes FROM pg_proc WHERE oid = 'pg_get_keywords'::regproc";
+        if let Some(v) = decode(query, &Default::default()) {
+            // its number of output columns depends on the server version
+            let modes = v.as_array().unwrap();
+            assert!(!modes.is_empty() && modes.iter().all(|mode| mode == "o"));
+        }
+        let query = r#"SELECT ARRAY['a', NULL, '']::"char"[]"#;
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, serde_json::json!(["a", null, ""]));
+        }
+    }
+
+    #[test]
+    fn keeps_null_elements_distinct_from_empty_strings() {
+        let query = r#"SELECT '{NULL,"",x}'::text[]"#;
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, serde_json::json!([null, "", "x"]));
+        }
+        // decoded by `nested_array` rather than sqlx
+        let query = r#"SELECT '[0:1][1:2]={{NULL,""},{x,NULL}}'::text[]"#;
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, serde_json::json!([[null, ""], ["x", null]]));
+        }
+        let options = DecodeOptions {
+            empty_strings_as_null: true,
+            ..Default::default()
+        };
+        if let Some(v) = decode(r#"SELECT '{NULL,"",x}'::text[]"#, &options) {
+            assert_eq!(v, serde_json::json!([null, null, "x"]));
+        }
+    }
+
+    #[test]
+    fn rejects_bytea_values_over_the_limit() {
+        let options = DecodeOptions {
+            max_bytea_size: Some(4),
+            bytea_format: ByteaFormat::Hex,
+            ..Default::default()
+        };
+        if let Some(v) = decode(r"SELECT '\xdeadbeef'::bytea", &options) {
+            assert_eq!(v, "deadbeef");
+        }
+        for query in [
+            r"SELECT '\xdeadbeef00'::bytea",
+            r#"SELECT '{"\\x00", "\\xdeadbeef00"}'::bytea[]"#,
+        ] {
+            if let Some(result) = try_decode(query, &options) {
+                assert!(matches!(
+                    result,
+                    Err(Error::ByteaTooLarge { size: 5, limit: 4 })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn packs_numeric_arrays_into_typed_arrays() {
+        let options = DecodeOptions {
+            packed_arrays: true,
+            ..Default::default()
+        };
+        let unpack = |v: JsonValue, typed_array: &str| {
+            assert_eq!(v["typedArray"], typed_array);
+            STANDARD.decode(v["data"].as_str().unwrap()).unwrap()
+        };
+
+        if let Some(v) = decode("SELECT ARRAY[1.5, -2, 1e300]::float8[]", &options) {
+            let values = unpack(v, "Float64Array")
+                .chunks(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(values, [1.5, -2.0, 1e300]);
+        }
+        if let Some(v) = decode("SELECT ARRAY[1, -2, 2147483647]::int4[]", &options) {
+            let values = unpack(v, "Int32Array")
+                .chunks(4)
+                .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(values, [1, -2, i32::MAX]);
+        }
+        // arrays that can't be packed, and the default, are plain arrays
+        if let Some(v) = decode("SELECT ARRAY[1, NULL]::int4[]", &options) {
+            assert_eq!(v, serde_json::json!([1, null]));
+        }
+        if let Some(v) = decode("SELECT ARRAY[1.5]::float8[]", &Default::default()) {
+            assert_eq!(v, serde_json::json!([1.5]));
+        }
+    }
+
+    #[test]
+    fn formats_bytea_hashes() {
+        let query = "SELECT sha256('abc')";
+        let format = |bytea_format| DecodeOptions {
+            bytea_format,
+            ..Default::default()
+        };
+        if let Some(v) = decode(query, &format(ByteaFormat::Hex)) {
+            assert_eq!(
+                v,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+        }
+        if let Some(v) = decode(query, &format(ByteaFormat::Base64)) {
+            assert_eq!(v, "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=");
+        }
+        if let Some(v) = decode(r"SELECT '\xdead'::bytea", &format(ByteaFormat::Array)) {
+            assert_eq!(v, serde_json::json!([222, 173]));
+        }
+    }
+
+    #[test]
+    fn decodes_enum_ordinals_in_definition_order() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            // `meh` is defined after `ok` but sorts before it
+            for statement in [
+                "CREATE TYPE pg_temp.mood AS ENUM ('sad', 'ok', 'happy')",
+                "ALTER TYPE pg_temp.mood ADD VALUE 'meh' BEFORE 'ok'",
+            ] {
+                sqlx::query(statement).execute(&pool).await.unwrap();
+            }
+            let row = sqlx::query("SELECT 'ok'::pg_temp.mood, '{happy,meh,NULL}'::pg_temp.mood[]")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            let decode =
+                |i, options: &DecodeOptions| to_json(row.try_get_raw(i).unwrap(), options).unwrap();
+
+            assert_eq!(decode(0, &Default::default()), "ok");
+            let options = DecodeOptions {
+                enum_ordinals: true,
+                ..Default::default()
+            };
+            assert_eq!(
+                decode(0, &options),
+                serde_json::json!({ "label": "ok", "ordinal": 2 })
+            );
+            assert_eq!(
+                decode(1, &options),
+                serde_json::json!([
+                    { "label": "happy", "ordinal": 3 },
+                    { "label": "meh", "ordinal": 1 },
+                    null
+                ])
+            );
+        });
+    }
+
+    #[test]
+    fn round_trips_compressed_large_values() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let options = DecodeOptions {
+            compress_threshold: Some(1024),
+            ..Default::default()
+        };
+        let decompress = |v: &JsonValue, ty: &str| {
+            assert_eq!(v["encoding"], "gzip");
+            assert_eq!(v["type"], ty);
+            let data = STANDARD.decode(v["data"].as_str().unwrap()).unwrap();
+            let mut value = Vec::new();
+            GzDecoder::new(data.as_slice())
+                .read_to_end(&mut value)
+                .unwrap();
+            value
+        };
+
+        let text = r#"SELECT string_agg('{"id": ' || i || ', "name": "item"}', ',') FROM generate_series(1, 500) i"#;
+        if let (Some(v), Some(plain)) = (decode(text, &options), decode(text, &Default::default()))
+        {
+            let value = String::from_utf8(decompress(&v, "text")).unwrap();
+            assert_eq!(value, plain.as_str().unwrap());
+            assert!(v["data"].as_str().unwrap().len() < value.len());
+        }
+        let bytea = "SELECT convert_to(repeat('abc', 1000), 'UTF8')";
+        if let Some(v) = decode(bytea, &options) {
+            assert_eq!(decompress(&v, "bytea"), "abc".repeat(1000).as_bytes());
+        }
+        // values up to the threshold, and those that don't compress, stay plain
+        if let Some(v) = decode("SELECT repeat('a', 1024)", &options) {
+            assert_eq!(v, "a".repeat(1024));
+        }
+        let random = "SELECT string_agg(md5(i::text), '') FROM generate_series(1, 100) i";
+        if let Some(v) = decode(&format!("SELECT decode(({random}), 'hex')"), &options) {
+            assert!(v.is_array());
+        }
+    }
+
+    #[test]
+    fn decodes_tsvector_positions_and_weights() {
+        let query = r"SELECT 'cat:2 a:1A,3,5B fat:4C quote''s\\ plain'::tsvector";
+        let text = "'a':1A,3,5B 'cat':2 'fat':4C 'plain' 'quote''s\\\\'";
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, text);
+        }
+        let options = DecodeOptions {
+            tsvector_format: TsVectorFormat::Structured,
+            ..Default::default()
+        };
+        if let Some(v) = decode(query, &options) {
+            assert_eq!(
+                v,
+                serde_json::json!([
+                    { "word": "a", "positions": [
+                        { "position": 1, "weight": "A" },
+                        { "position": 3, "weight": "D" },
+                        { "position": 5, "weight": "B" },
+                    ] },
+                    { "word": "cat", "positions": [{ "position": 2, "weight": "D" }] },
+                    { "word": "fat", "positions": [{ "position": 4, "weight": "C" }] },
+                    { "word": "plain", "positions": [] },
+                    { "word": "quote's\\", "positions": [] },
+                ])
+            );
+        }
+        // the text form is the one Postgres formats
+        if let Some(v) = decode(&format!("SELECT ({query})::text"), &Default::default()) {
+            assert_eq!(v, text);
+        }
+    }
+
+    #[test]
+    fn reports_the_bounds_of_arrays_with_custom_lower_bounds() {
+        let query = "SELECT '[0:1][-1:1]={{1,2,3},{4,NULL,6}}'::int4[]";
+        let data = serde_json::json!([[1, 2, 3], [4, null, 6]]);
+        if let Some(v) = decode(query, &Default::default()) {
+            assert_eq!(v, data);
+        }
+        let options = DecodeOptions {
+            array_dimensions: true,
+            ..Default::default()
+        };
+        if let Some(v) = decode(query, &options) {
+            assert_eq!(
+                v,
+                serde_json::json!({ "dims": [[0, 1], [-1, 1]], "data": data })
+            );
+        }
+        if let Some(v) = decode("SELECT '[3:4]={a,b}'::text[]", &options) {
+            assert_eq!(
+                v,
+                serde_json::json!({ "dims": [[3, 4]], "data": ["a", "b"] })
+            );
+        }
+        if let Some(v) = decode("SELECT '{}'::int4[]", &options) {
+            assert_eq!(v, serde_json::json!({ "dims": [], "data": [] }));
+        }
+    }
 }
@@ -2,17 +2,19 @@ use serde_json::Value as JsonValue;
 use sqlx::{sqlite::SqliteValueRef, TypeInfo, Value, ValueRef};
 use time::{Date, PrimitiveDateTime, Time};
 
+use super::DecodeOptions;
 use crate::Error;
 
-pub(crate) fn to_json(v: SqliteValueRef) -> Result<JsonValue, Error> {
+pub(crate) fn to_json(v: SqliteValueRef, options: &DecodeOptions) -> Result<JsonValue, Error> {
     if v.is_null() {
         return Ok(JsonValue::Null);
     }
 
-    let res = match v.type_info().name() {
+    let type_info = v.type_info();
+    let res = match type_info.name() {
         "TEXT" => {
             if let Ok(v) = v.to_owned().try_decode() {
-                JsonValue::String(v)
+                super::text(v, options)
             } else {
                 JsonValue::Null
             }
@@ -54,7 +56,7 @@ pub(crate) fn to_json(v: SqliteValueRef) -> Result<JsonValue, Error> {
         }
         "DATETIME" => {
             if let Ok(v) = v.to_owned().try_decode::<PrimitiveDateTime>() {
-                JsonValue::String(v.to_string())
+                super::primitive_date_time(v, options)
             } else {
                 JsonValue::Null
             }
@@ -67,8 +69,12 @@ pub(crate) fn to_json(v: SqliteValueRef) -> Result<JsonValue, Error> {
             }
         }
         "NULL" => JsonValue::Null,
-        _ => return Err(Error::UnsupportedDatatype(v.type_info().name().to_string())),
+        _ => return Err(Error::UnsupportedDatatype(type_info.name().to_string())),
     };
 
-    Ok(res)
+    if options.number_formats.is_empty() {
+        Ok(res)
+    } else {
+        Ok(super::number_format(type_info.name(), res, options))
+    }
 }
@@ -0,0 +1,181 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use serde::Serialize;
+use sqlx::Pool;
+
+use crate::{plugin::Db, Error};
+
+/// The database server version and the features it supports.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseInfo {
+    /// `sqlite`, `mysql`, `mariadb` or `postgres`.
+    pub backend: &'static str,
+    /// The full version string reported by the database.
+    pub version: String,
+    pub major: u32,
+    pub minor: u32,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// `INSERT ... RETURNING`.
+    pub returning: bool,
+    /// `INSERT ... ON CONFLICT` or `ON DUPLICATE KEY UPDATE`.
+    pub upsert: bool,
+    /// Window functions like `ROW_NUMBER() OVER (...)`.
+    pub window_functions: bool,
+    /// `LISTEN` / `NOTIFY`.
+    pub listen_notify: bool,
+}
+
+#[cfg(feature = "sqlite")]
+const VERSION_QUERY: &str = "SELECT sqlite_version()";
+#[cfg(feature = "mysql")]
+const VERSION_QUERY: &str = "SELECT version()";
+#[cfg(feature = "postgres")]
+const VERSION_QUERY: &str = "SHOW server_version";
+
+pub(crate) async fn query(pool: &Pool<Db>) -> Result<DatabaseInfo, Error> {
+    let (version,): (String,) = sqlx::query_as(VERSION_QUERY).fetch_one(pool).await?;
+    Ok(DatabaseInfo::from_version(version))
+}
+
+impl DatabaseInfo {
+    fn from_version(version: String) -> Self {
+        let (major, minor) = parse_version(&version);
+        let at_least = |m: u32, n: u32| (major, minor) >= (m, n);
+
+        #[cfg(feature = "sqlite")]
+        let (backend, capabilities) = (
+            "sqlite",
+            Capabilities {
+                returning: at_least(3, 35),
+                upsert: at_least(3, 24),
+                window_functions: at_least(3, 25),
+                listen_notify: false,
+            },
+        );
+        #[cfg(feature = "mysql")]
+        let (backend, capabilities) = if version.contains("MariaDB") {
+            (
+                "mariadb",
+                Capabilities {
+                    returning: at_least(10, 5),
+                    upsert: true,
+                    window_functions: at_least(10, 2),
+                    listen_notify: false,
+                },
+            )
+        } else {
+            (
+                "mysql",
+                Capabilities {
+                    returning: false,
+                    upsert: true,
+                    window_functions: at_least(8, 0),
+                    listen_notify: false,
+                },
+            )
+        };
+        #[cfg(feature = "postgres")]
+        let (backend, capabilities) = (
+            "postgres",
+            Capabilities {
+                returning: true,
+                upsert: at_least(9, 5),
+                window_functions: true,
+                listen_notify: true,
+            },
+        );
+
+        Self {
+            backend,
+            version,
+            major,
+            minor,
+            capabilities,
+        }
+    }
+}
+
+/// Parses the leading `major.minor` of a version string such as `3.42.0`,
+/// `10.11.2-MariaDB-1` or `15.4 (Debian 15.4-1)`. Missing parts are `0`.
+fn parse_version(version: &str) -> (u32, u32) {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    // only a `.` separates the major and minor versions, `16beta1` has no minor
+    let minor = if version
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .starts_with('.')
+    {
+        parts.next().unwrap_or(0)
+    } else {
+        0
+    };
+    (major, minor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions() {
+        // sqlite
+        assert_eq!(parse_version("3.42.0"), (3, 42));
+        // mysql and mariadb
+        assert_eq!(parse_version("8.0.34"), (8, 0));
+        assert_eq!(parse_version("8.0.34-0ubuntu0.22.04.1"), (8, 0));
+        assert_eq!(
+            parse_version("10.11.2-MariaDB-1:10.11.2+maria~ubu2204"),
+            (10, 11)
+        );
+        // postgres
+        assert_eq!(parse_version("15.4 (Debian 15.4-1.pgdg120+1)"), (15, 4));
+        assert_eq!(parse_version("16beta1"), (16, 0));
+        assert_eq!(parse_version("16"), (16, 0));
+        assert_eq!(parse_version(""), (0, 0));
+    }
+
+    #[test]
+    fn detects_capabilities() {
+        #[cfg(feature = "sqlite")]
+        {
+            let info = DatabaseInfo::from_version("3.31.1".into());
+            assert_eq!((info.backend, info.major, info.minor), ("sqlite", 3, 31));
+            assert!(!info.capabilities.returning);
+            assert!(info.capabilities.upsert);
+            assert!(
+                DatabaseInfo::from_version("3.42.0".into())
+                    .capabilities
+                    .returning
+            );
+        }
+        #[cfg(feature = "mysql")]
+        {
+            let info = DatabaseInfo::from_version("10.11.2-MariaDB-1".into());
+            assert_eq!((info.backend, info.major, info.minor), ("mariadb", 10, 11));
+            assert!(info.capabilities.returning);
+            let info = DatabaseInfo::from_version("5.7.42".into());
+            assert_eq!(info.backend, "mysql");
+            assert!(!info.capabilities.returning);
+            assert!(!info.capabilities.window_functions);
+        }
+        #[cfg(feature = "postgres")]
+        {
+            let info = DatabaseInfo::from_version("9.4.26".into());
+            assert_eq!((info.backend, info.major, info.minor), ("postgres", 9, 4));
+            assert!(!info.capabilities.upsert);
+            assert!(info.capabilities.listen_notify);
+            let info = DatabaseInfo::from_version("15.4 (Debian 15.4-1.pgdg120+1)".into());
+            assert!(info.capabilities.upsert);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! SQLite integrity checks, to detect a corrupted database file.
+
+use serde::{Deserialize, Serialize};
+use sqlx::Pool;
+use tauri::{command, State};
+
+use crate::{
+    plugin::{Db, DbInstances},
+    Error, Result,
+};
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct IntegrityOptions {
+    /// Also runs `PRAGMA foreign_key_check`.
+    foreign_keys: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IntegrityReport {
+    /// Whether no problem was found.
+    ok: bool,
+    /// The problems reported by `PRAGMA integrity_check`.
+    problems: Vec<String>,
+    /// The rows violating a foreign key constraint, if checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    foreign_key_violations: Option<Vec<ForeignKeyViolation>>,
+}
+
+/// A row of `PRAGMA foreign_key_check`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ForeignKeyViolation {
+    table: String,
+    /// `None` for tables without rowid.
+    rowid: Option<i64>,
+    parent: String,
+    /// The index of the violated foreign key in the table's list.
+    fkid: i64,
+}
+
+/// Runs `PRAGMA integrity_check`, and optionally `PRAGMA foreign_key_check`,
+/// on a connection checked out for the whole check. The check reads the whole
+/// database, so it can take a while on large files.
+#[command]
+pub(crate) async fn integrity_check(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    options: Option<IntegrityOptions>,
+) -> Result<IntegrityReport> {
+    let options = options.unwrap_or_default();
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    check(&pool, &options).await
+}
+
+async fn check(pool: &Pool<Db>, options: &IntegrityOptions) -> Result<IntegrityReport> {
+    let mut conn = pool.acquire().await?;
+
+    // a single `ok` row, or one row per problem
+    let mut problems: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut *conn)
+        .await?;
+    if problems.len() == 1 && problems[0] == "ok" {
+        problems.clear();
+    }
+
+    let foreign_key_violations = if options.foreign_keys {
+        let rows: Vec<(String, Option<i64>, String, i64)> =
+            sqlx::query_as("PRAGMA foreign_key_check")
+                .fetch_all(&mut *conn)
+                .await?;
+        let violations = rows
+            .into_iter()
+            .map(|(table, rowid, parent, fkid)| ForeignKeyViolation {
+                table,
+                rowid,
+                parent,
+                fkid,
+            })
+            .collect();
+        Some(violations)
+    } else {
+        None
+    };
+
+    Ok(IntegrityReport {
+        ok: problems.is_empty() && foreign_key_violations.as_ref().map_or(true, Vec::is_empty),
+        problems,
+        foreign_key_violations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, pool};
+
+    #[test]
+    fn reports_a_fresh_database_as_ok() {
+        block_on(async {
+            let pool = pool().await.unwrap();
+            sqlx::query("CREATE TABLE parents (id INTEGER PRIMARY KEY)")
+                .execute(&pool)
+                .await
+                .unwrap();
+            let options = IntegrityOptions { foreign_keys: true };
+            let report = check(&pool, &options).await.unwrap();
+            assert!(report.ok);
+            assert!(report.problems.is_empty());
+            assert_eq!(report.foreign_key_violations.map(|v| v.len()), Some(0));
+        });
+    }
+}
@@ -14,6 +14,23 @@ compile_error!(
     "Database driver not defined. Please set the feature flag for the driver of your choice."
 );
 
+mod bind;
+mod catalog;
+#[cfg(feature = "postgres")]
+mod copy;
 mod decode;
+mod info;
+#[cfg(feature = "sqlite")]
+mod integrity;
+#[cfg(feature = "postgres")]
+mod locks;
 mod plugin;
+mod rewrite;
+mod statements;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "postgres")]
+pub use decode::{ByteaFormat, TsVectorFormat};
+pub use decode::{DecodeOptions, DuplicateColumns, NumberFormat, TimestampFormat};
+pub use info::{Capabilities, DatabaseInfo};
 pub use plugin::*;
@@ -0,0 +1,183 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Postgres advisory locks.
+//!
+//! Advisory locks belong to the database session that acquired them, so a
+//! lock must be released on the same connection. Every held lock therefore
+//! keeps a connection checked out of the pool until it is unlocked, or the
+//! database is closed, which ends the session and releases its locks.
+
+use sqlx::{pool::PoolConnection, Connection, Pool};
+use tauri::{command, State};
+use tokio::sync::Mutex;
+
+use std::collections::HashMap;
+
+use crate::{
+    plugin::{Db, DbInstances},
+    Error, Result,
+};
+
+/// Connections pinned to the advisory locks they hold, by database and key.
+#[derive(Default)]
+pub(crate) struct AdvisoryLocks(Mutex<HashMap<(String, i64), PoolConnection<Db>>>);
+
+impl AdvisoryLocks {
+    /// Releases the connections holding locks on `db`, or on all databases.
+    /// They are closed rather than returned to the pool, which ends their
+    /// sessions and so releases their locks.
+    pub(crate) async fn release(&self, db: Option<&str>) {
+        let mut locks = self.0.lock().await;
+        let released: Vec<_> = locks
+            .keys()
+            .filter(|(locked_db, _)| db.map_or(true, |db| db == locked_db))
+            .cloned()
+            .collect();
+        for key in released {
+            if let Some(conn) = locks.remove(&key) {
+                let _ = conn.detach().close().await;
+            }
+        }
+    }
+
+    async fn is_held(&self, db: &str, key: i64) -> bool {
+        self.0.lock().await.contains_key(&(db.to_string(), key))
+    }
+
+    async fn lock(&self, pool: &Pool<Db>, db: String, key: i64) -> Result<()> {
+        // waiting on a lock held by our own pinned connection would never end
+        if self.is_held(&db, key).await {
+            return Err(Error::AdvisoryLockHeld(key));
+        }
+
+        let mut conn = pool.acquire().await?;
+        sqlx::query("SELECT pg_advisory_lock($1)")
+            .bind(key)
+            .execute(&mut *conn)
+            .await?;
+        self.0.lock().await.insert((db, key), conn);
+        Ok(())
+    }
+
+    async fn try_lock(&self, pool: &Pool<Db>, db: String, key: i64) -> Result<bool> {
+        if self.is_held(&db, key).await {
+            return Ok(false);
+        }
+
+        let mut conn = pool.acquire().await?;
+        let (locked,): (bool,) = sqlx::query_as("SELECT pg_try_advisory_lock($1)")
+            .bind(key)
+            .fetch_one(&mut *conn)
+            .await?;
+        if locked {
+            self.0.lock().await.insert((db, key), conn);
+        }
+        Ok(locked)
+    }
+
+    async fn unlock(&self, db: String, key: i64) -> Result<bool> {
+        let conn = self.0.lock().await.remove(&(db, key));
+        match conn {
+            Some(mut conn) => {
+                match sqlx::query_as::<_, (bool,)>("SELECT pg_advisory_unlock($1)")
+                    .bind(key)
+                    .fetch_one(&mut *conn)
+                    .await
+                {
+                    Ok((unlocked,)) => Ok(unlocked),
+                    // the lock may still be held, so the session is ended instead
+                    // of returning the connection to the pool
+                    Err(error) => {
+                        let _ = conn.detach().close().await;
+                        Err(error.into())
+                    }
+                }
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Acquires the advisory lock `key`, waiting until it is available.
+#[command]
+pub(crate) async fn advisory_lock(
+    db_instances: State<'_, DbInstances>,
+    locks: State<'_, AdvisoryLocks>,
+    db: String,
+    key: i64,
+) -> Result<()> {
+    let pool = pool(&db_instances, &db).await?;
+    locks.lock(&pool, db, key).await
+}
+
+/// Acquires the advisory lock `key` if it is available, returning whether
+/// it was acquired.
+#[command]
+pub(crate) async fn try_advisory_lock(
+    db_instances: State<'_, DbInstances>,
+    locks: State<'_, AdvisoryLocks>,
+    db: String,
+    key: i64,
+) -> Result<bool> {
+    let pool = pool(&db_instances, &db).await?;
+    locks.try_lock(&pool, db, key).await
+}
+
+/// Releases the advisory lock `key`, returning whether it was held.
+#[command]
+pub(crate) async fn advisory_unlock(
+    locks: State<'_, AdvisoryLocks>,
+    db: String,
+    key: i64,
+) -> Result<bool> {
+    locks.unlock(db, key).await
+}
+
+async fn pool(db_instances: &DbInstances, db: &str) -> Result<Pool<Db>> {
+    db_instances
+        .0
+        .lock()
+        .await
+        .get(db)
+        .cloned()
+        .ok_or_else(|| Error::DatabaseNotLoaded(db.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, pool};
+
+    const KEY: i64 = 0x7461_7572_6921;
+
+    #[test]
+    fn second_lock_attempt_fails_while_held() {
+        block_on(async {
+            // two app instances sharing the database
+            let (first_pool, second_pool) = match (pool().await, pool().await) {
+                (Some(first), Some(second)) => (first, second),
+                _ => return,
+            };
+            let (first, second) = (AdvisoryLocks::default(), AdvisoryLocks::default());
+            let db = || "test".to_string();
+
+            assert!(first.try_lock(&first_pool, db(), KEY).await.unwrap());
+            assert!(!second.try_lock(&second_pool, db(), KEY).await.unwrap());
+            // the instance holding it can't acquire it again either
+            assert!(!first.try_lock(&first_pool, db(), KEY).await.unwrap());
+            assert!(matches!(
+                first.lock(&first_pool, db(), KEY).await,
+                Err(Error::AdvisoryLockHeld(KEY))
+            ));
+
+            assert!(first.unlock(db(), KEY).await.unwrap());
+            assert!(!first.unlock(db(), KEY).await.unwrap());
+            assert!(second.try_lock(&second_pool, db(), KEY).await.unwrap());
+            second.release(None).await;
+            assert!(first.try_lock(&first_pool, db(), KEY).await.unwrap());
+            first.release(Some("test")).await;
+        });
+    }
+}
@@ -8,9 +8,11 @@ use serde_json::Value as JsonValue;
 use sqlx::{
     error::BoxDynError,
     migrate::{
-        MigrateDatabase, Migration as SqlxMigration, MigrationSource, MigrationType, Migrator,
+        Migrate, MigrateDatabase, MigrateError, Migration as SqlxMigration, MigrationSource,
+        MigrationType, Migrator,
     },
-    Column, Pool, Row,
+    pool::{PoolConnection, PoolOptions},
+    Column, Connection, Database, Executor, Pool, Row, Value, ValueRef,
 };
 use tauri::{
     command,
@@ -19,17 +21,30 @@ use tauri::{
 };
 use tokio::sync::Mutex;
 
-use std::collections::HashMap;
+use crate::{bind::bind_values, DatabaseInfo, DecodeOptions};
+
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
 
 #[cfg(feature = "sqlite")]
 use std::{fs::create_dir_all, path::PathBuf};
 
 #[cfg(feature = "sqlite")]
-type Db = sqlx::sqlite::Sqlite;
+pub(crate) type Db = sqlx::sqlite::Sqlite;
+#[cfg(feature = "mysql")]
+pub(crate) type Db = sqlx::mysql::MySql;
+#[cfg(feature = "postgres")]
+pub(crate) type Db = sqlx::postgres::Postgres;
+
+#[cfg(feature = "sqlite")]
+type ConnectOptions = sqlx::sqlite::SqliteConnectOptions;
 #[cfg(feature = "mysql")]
-type Db = sqlx::mysql::MySql;
+type ConnectOptions = sqlx::mysql::MySqlConnectOptions;
 #[cfg(feature = "postgres")]
-type Db = sqlx::postgres::Postgres;
+type ConnectOptions = sqlx::postgres::PgConnectOptions;
 
 #[cfg(feature = "sqlite")]
 type LastInsertId = i64;
@@ -46,6 +61,68 @@ pub enum Error {
     DatabaseNotLoaded(String),
     #[error("unsupported datatype: {0}")]
     UnsupportedDatatype(String),
+    #[error("missing value for named parameter: {0}")]
+    MissingParameter(String),
+    #[error("query timed out after {0}ms")]
+    Timeout(u64),
+    #[error("invalid identifier: {0}")]
+    InvalidIdentifier(String),
+    #[error("row {row} has {got} values, expected {expected}")]
+    RowLength {
+        row: usize,
+        expected: usize,
+        got: usize,
+    },
+    #[error("row {row} doesn't have the columns of the first row: {}", .expected.join(", "))]
+    RowColumns { row: usize, expected: Vec<String> },
+    #[error("an upsert needs at least one conflict column")]
+    MissingConflictColumns,
+    #[error("conflict column {0} is not one of the upserted columns")]
+    UnknownConflictColumn(String),
+    #[cfg(feature = "postgres")]
+    #[error(
+        "rows {first} and {row} have the same conflict values, which can't be upserted together"
+    )]
+    DuplicateConflictValues { first: usize, row: usize },
+    #[error("invalid value for parameter ${0}: {1}")]
+    InvalidParameter(usize, String),
+    #[error("raw text queries can't have bound values")]
+    RawTextParameters,
+    #[error("raw text queries can't be read-only")]
+    RawTextReadOnly,
+    #[error("duplicate column name: {0}")]
+    DuplicateColumn(String),
+    #[error("only select queries can be explained")]
+    ExplainNotSelect,
+    #[error("truncating {0} requires `confirm: true`")]
+    TruncateNotConfirmed(String),
+    #[cfg(feature = "postgres")]
+    #[error("bytea value of {size} bytes exceeds the limit of {limit} bytes")]
+    ByteaTooLarge { size: usize, limit: usize },
+    #[cfg(feature = "postgres")]
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "postgres")]
+    #[error("path not allowed on the configured scope: {}", .0.display())]
+    PathNotAllowed(std::path::PathBuf),
+    #[cfg(feature = "postgres")]
+    #[error("invalid COPY delimiter: {0:?}")]
+    InvalidCopyDelimiter(char),
+    #[cfg(feature = "postgres")]
+    #[error("invalid jsonb path: {0}")]
+    InvalidJsonPath(String),
+    #[cfg(feature = "postgres")]
+    #[error("a jsonb patch must be an object")]
+    JsonbPatchNotObject,
+    #[cfg(feature = "postgres")]
+    #[error("advisory lock {0} is already held by this app")]
+    AdvisoryLockHeld(i64),
+    #[cfg(all(feature = "sqlite", not(feature = "sqlcipher")))]
+    #[error("an encryption key was provided but the `sqlcipher` feature is not enabled")]
+    EncryptionUnsupported,
+    #[cfg(not(feature = "sqlite"))]
+    #[error("Unix domain socket not found: {}", .0.display())]
+    SocketNotFound(std::path::PathBuf),
 }
 
 impl Serialize for Error {
@@ -57,7 +134,7 @@ impl Serialize for Error {
     }
 }
 
-type Result<T> = std::result::Result<T, Error>;
+pub(crate) type Result<T> = std::result::Result<T, Error>;
 
 #[cfg(feature = "sqlite")]
 /// Resolves the App's **file path** from the `AppHandle` context
@@ -89,16 +166,205 @@ fn path_mapper(mut app_path: PathBuf, connection_string: &str) -> String {
 }
 
 #[derive(Default)]
-struct DbInstances(Mutex<HashMap<String, Pool<Db>>>);
+pub(crate) struct DbInstances(pub(crate) Mutex<HashMap<String, Pool<Db>>>);
 
 struct Migrations(Mutex<HashMap<String, MigrationList>>);
 
+/// The default statement timeout of each loaded database that has one, see
+/// [`ConnectionOptions::statement_timeout`], which [`reset_connection`] sets
+/// again after discarding the session state.
+#[cfg(feature = "postgres")]
+#[derive(Default)]
+struct StatementTimeouts(Mutex<HashMap<String, u64>>);
+
+#[cfg(feature = "postgres")]
+impl StatementTimeouts {
+    async fn set(&self, db: &str, timeout: Option<u64>) {
+        let mut timeouts = self.0.lock().await;
+        match timeout {
+            Some(timeout) => timeouts.insert(db.to_string(), timeout),
+            None => timeouts.remove(db),
+        };
+    }
+
+    async fn get(&self, db: &str) -> Option<u64> {
+        self.0.lock().await.get(db).copied()
+    }
+}
+
+struct ConnectionConfigs(HashMap<String, ConnectionOptions>);
+
 #[derive(Default, Deserialize)]
 pub struct PluginConfig {
     #[serde(default)]
     preload: Vec<String>,
 }
 
+/// Options applied when opening a database connection pool.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionOptions {
+    /// SQLCipher key used to encrypt the database file at rest.
+    ///
+    /// It is applied with `PRAGMA key` on every new pooled connection, before
+    /// any other statement. Requires the `sqlcipher` feature.
+    #[cfg(feature = "sqlite")]
+    pub encryption_key: Option<String>,
+    /// The number of connections the pool keeps open, even when idle.
+    pub min_connections: Option<u32>,
+    /// How long to wait for a connection from the pool, in milliseconds.
+    /// Defaults to 30 seconds.
+    pub acquire_timeout: Option<u64>,
+    /// Opens the `min_connections` while loading, instead of in the
+    /// background, so the first queries don't wait for a connection.
+    #[serde(default)]
+    pub warm_up: bool,
+    /// Connects through a Unix domain socket instead of TCP. On Postgres this
+    /// is the directory containing the socket, e.g. `/var/run/postgresql`, on
+    /// MySQL the path of the socket file.
+    #[cfg(not(feature = "sqlite"))]
+    pub socket: Option<std::path::PathBuf>,
+    /// The default timeout of every statement on the connections, in
+    /// milliseconds, after which the server cancels it. Postgres sets
+    /// `statement_timeout` and MySQL `max_execution_time`, which only applies
+    /// to `SELECT` statements. The timeout of `queryWithTimeoutAndRetry`
+    /// overrides it.
+    #[cfg(not(feature = "sqlite"))]
+    pub statement_timeout: Option<u64>,
+}
+
+#[cfg(feature = "sqlcipher")]
+fn with_encryption_key(
+    connect_options: ConnectOptions,
+    options: &ConnectionOptions,
+) -> Result<ConnectOptions> {
+    Ok(match &options.encryption_key {
+        // `key` is the first pragma set on a connection, so creating the file
+        // through it encrypts it from the first page
+        Some(key) => connect_options
+            .pragma("key", format!("'{}'", key.replace('\'', "''")))
+            .create_if_missing(true),
+        None => connect_options,
+    })
+}
+
+#[cfg(all(feature = "sqlite", not(feature = "sqlcipher")))]
+fn with_encryption_key(
+    connect_options: ConnectOptions,
+    options: &ConnectionOptions,
+) -> Result<ConnectOptions> {
+    match options.encryption_key {
+        Some(_) => Err(Error::EncryptionUnsupported),
+        None => Ok(connect_options),
+    }
+}
+
+/// Adds the Unix domain socket to connect through to a database URL, so
+/// creating the database uses it too.
+#[cfg(not(feature = "sqlite"))]
+fn with_socket(url: &str, socket: &std::path::Path) -> Result<String> {
+    if !socket.exists() {
+        return Err(Error::SocketNotFound(socket.to_owned()));
+    }
+    #[cfg(feature = "mysql")]
+    let param = "socket";
+    // a `host` starting with `/` is a socket
+    #[cfg(feature = "postgres")]
+    let param = "host";
+
+    let socket: String = socket
+        .to_string_lossy()
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+                char::from(b).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect();
+    let separator = if url.contains('?') { '&' } else { '?' };
+    Ok(format!("{url}{separator}{param}={socket}"))
+}
+
+/// Creates the database if needed and opens a connection pool to it.
+async fn connect(fqdb: &str, options: &ConnectionOptions) -> Result<Pool<Db>> {
+    #[cfg(not(feature = "sqlite"))]
+    let fqdb = &match &options.socket {
+        Some(socket) => with_socket(fqdb, socket)?,
+        None => fqdb.to_string(),
+    };
+    // `create_database` opens the new file without the key, which would leave
+    // it unencrypted, so encrypted databases are created when connecting
+    #[cfg(feature = "sqlite")]
+    let create = options.encryption_key.is_none();
+    #[cfg(not(feature = "sqlite"))]
+    let create = true;
+    if create && !Db::database_exists(fqdb).await.unwrap_or(false) {
+        Db::create_database(fqdb).await?;
+    }
+
+    let connect_options: ConnectOptions = fqdb.parse()?;
+    #[cfg(feature = "sqlite")]
+    let connect_options = with_encryption_key(connect_options, options)?;
+
+    let mut pool_options = PoolOptions::<Db>::new();
+    if let Some(min_connections) = options.min_connections {
+        pool_options = pool_options.min_connections(min_connections);
+    }
+    if let Some(acquire_timeout) = options.acquire_timeout {
+        pool_options = pool_options.acquire_timeout(Duration::from_millis(acquire_timeout));
+    }
+    let acquire_timeout = pool_options.get_acquire_timeout();
+    #[cfg(not(feature = "sqlite"))]
+    if let Some(timeout) = options.statement_timeout {
+        pool_options = pool_options.after_connect(move |conn, _| {
+            Box::pin(async move {
+                conn.execute(crate::statements::statement_timeout(timeout).as_str())
+                    .await?;
+                Ok(())
+            })
+        });
+    }
+
+    let pool = pool_options.connect_with(connect_options).await?;
+    if options.warm_up {
+        tokio::time::timeout(acquire_timeout, warm_up(&pool))
+            .await
+            .map_err(|_| sqlx::Error::PoolTimedOut)??;
+    }
+    Ok(pool)
+}
+
+/// Opens the pool's minimum number of connections, by holding that many at
+/// the same time.
+async fn warm_up(pool: &Pool<Db>) -> Result<()> {
+    let mut connections = Vec::new();
+    for _ in 0..pool.options().get_min_connections() {
+        connections.push(pool.acquire().await?);
+    }
+    return_to_pool(connections).await;
+    Ok(())
+}
+
+/// Values bound to a query, either by position or by `:name`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BindValues {
+    Positional(Vec<JsonValue>),
+    Named(serde_json::Map<String, JsonValue>),
+}
+
+impl BindValues {
+    /// Resolves named parameters into the positional form the driver expects.
+    fn resolve(self, query: String) -> Result<(String, Vec<JsonValue>)> {
+        match self {
+            Self::Positional(values) => Ok((query, values)),
+            Self::Named(params) => crate::rewrite::expand_named(&query, &params),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum MigrationKind {
     Up,
@@ -123,14 +389,96 @@ pub struct Migration {
     pub kind: MigrationKind,
 }
 
-#[derive(Debug)]
-struct MigrationList(Vec<Migration>);
+/// Rust code run before or after a migration, e.g. to backfill data, see
+/// [`Builder::before_migration`].
+pub type MigrationHook =
+    dyn for<'c> Fn(&'c mut <Db as Database>::Connection) -> BoxFuture<'c, Result<()>> + Send + Sync;
+
+#[derive(Default)]
+struct MigrationHooks {
+    before: HashMap<i64, Vec<Box<MigrationHook>>>,
+    after: HashMap<i64, Vec<Box<MigrationHook>>>,
+}
+
+impl std::fmt::Debug for MigrationHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the hooks themselves can't be printed, only their versions
+        f.debug_struct("MigrationHooks")
+            .field("before", &self.before.keys().collect::<Vec<_>>())
+            .field("after", &self.after.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl MigrationHooks {
+    fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    fn contains(&self, version: i64) -> bool {
+        self.before.contains_key(&version) || self.after.contains_key(&version)
+    }
+}
+
+async fn run_hooks(
+    hooks: Option<&Vec<Box<MigrationHook>>>,
+    conn: &mut <Db as Database>::Connection,
+) -> Result<()> {
+    for hook in hooks.into_iter().flatten() {
+        hook(conn).await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct MigrationList {
+    migrations: Vec<Migration>,
+    hooks: MigrationHooks,
+    /// Whether applying the migrations emits [`MIGRATION_EVENT`]s, see
+    /// [`Builder::migration_progress`].
+    progress: bool,
+}
+
+/// The event a [`MigrationProgress`] is emitted as.
+const MIGRATION_EVENT: &str = "sql://migration";
+
+/// The progress of applying the migrations of a database, emitted to the
+/// frontend as [`MIGRATION_EVENT`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+enum MigrationProgress<'a> {
+    /// A migration is being applied, the `index`th of the `total` pending ones.
+    #[serde(rename_all = "camelCase")]
+    Applying {
+        db: &'a str,
+        version: i64,
+        description: &'a str,
+        index: usize,
+        total: usize,
+        elapsed_ms: f64,
+    },
+    /// All pending migrations were applied.
+    #[serde(rename_all = "camelCase")]
+    Completed {
+        db: &'a str,
+        applied: usize,
+        elapsed_ms: f64,
+    },
+    /// Applying the migrations failed, at `version` if a migration failed.
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        db: &'a str,
+        version: Option<i64>,
+        error: String,
+        elapsed_ms: f64,
+    },
+}
 
 impl MigrationSource<'static> for MigrationList {
     fn resolve(self) -> BoxFuture<'static, std::result::Result<Vec<SqlxMigration>, BoxDynError>> {
         Box::pin(async move {
             let mut migrations = Vec::new();
-            for migration in self.0 {
+            for migration in self.migrations {
                 if matches!(migration.kind, MigrationKind::Up) {
                     migrations.push(SqlxMigration::new(
                         migration.version,
@@ -145,12 +493,302 @@ impl MigrationSource<'static> for MigrationList {
     }
 }
 
+/// Runs the pending migrations of a database, emitting their progress to the
+/// frontend if enabled for them.
+async fn run_migrations<R: Runtime>(
+    app: &AppHandle<R>,
+    db: &str,
+    pool: &Pool<Db>,
+    migrations: MigrationList,
+) -> Result<()> {
+    if !migrations.progress {
+        return apply_migrations(pool, migrations, |_| {}).await;
+    }
+    report_migrations(db, pool, migrations, |progress| {
+        let _ = app.emit_all(MIGRATION_EVENT, progress);
+    })
+    .await
+}
+
+/// Runs the pending migrations of a database, passing their progress to `emit`.
+async fn report_migrations(
+    db: &str,
+    pool: &Pool<Db>,
+    migrations: MigrationList,
+    mut emit: impl FnMut(MigrationProgress<'_>) + Send,
+) -> Result<()> {
+    let start = Instant::now();
+    let elapsed_ms = || start.elapsed().as_secs_f64() * 1e3;
+    let mut current = None;
+    let mut applied = 0;
+    let result = apply_migrations(pool, migrations, |step| {
+        current = Some(step.version);
+        applied = step.index;
+        emit(MigrationProgress::Applying {
+            db,
+            version: step.version,
+            description: step.description,
+            index: step.index,
+            total: step.total,
+            elapsed_ms: elapsed_ms(),
+        });
+    })
+    .await;
+    let progress = match &result {
+        Ok(()) => MigrationProgress::Completed {
+            db,
+            applied,
+            elapsed_ms: elapsed_ms(),
+        },
+        Err(e) => MigrationProgress::Failed {
+            db,
+            version: current,
+            error: e.to_string(),
+            elapsed_ms: elapsed_ms(),
+        },
+    };
+    emit(progress);
+    result
+}
+
+/// A pending migration about to be applied by [`apply_migrations`].
+struct MigrationStep<'a> {
+    version: i64,
+    description: &'a str,
+    /// The 1-based position among the `total` pending migrations.
+    index: usize,
+    total: usize,
+}
+
+/// Applies the pending migrations of a database, calling `on_step` before each
+/// if their progress is emitted. Migrations with hooks are applied in a
+/// transaction together with their hooks, so a failing hook rolls the migration
+/// back, except for statements that commit implicitly on MySQL.
+async fn apply_migrations(
+    pool: &Pool<Db>,
+    mut migrations: MigrationList,
+    on_step: impl FnMut(MigrationStep<'_>) + Send,
+) -> Result<()> {
+    let hooks = std::mem::take(&mut migrations.hooks);
+    let progress = migrations.progress;
+    let migrator = Migrator::new(migrations).await?;
+    if hooks.is_empty() && !progress {
+        migrator.run(pool).await?;
+        return Ok(());
+    }
+
+    let mut conn = LockedConnection::lock(pool).await?;
+    let result = apply_pending(&mut conn, &migrator, &hooks, on_step).await;
+    // released even if a migration failed, so the next attempt doesn't wait on it
+    let unlocked = conn.unlock().await;
+    result.and(unlocked)
+}
+
+/// The same steps as `Migrator::run`, on a connection holding the migration lock.
+async fn apply_pending(
+    conn: &mut <Db as Database>::Connection,
+    migrator: &Migrator,
+    hooks: &MigrationHooks,
+    mut on_step: impl FnMut(MigrationStep<'_>) + Send,
+) -> Result<()> {
+    conn.ensure_migrations_table().await?;
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(MigrateError::Dirty(version).into());
+    }
+    let applied: HashMap<_, _> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum))
+        .collect();
+    if let Some(version) = applied
+        .keys()
+        .find(|version| !migrator.iter().any(|m| m.version == **version))
+    {
+        return Err(MigrateError::VersionMissing(*version).into());
+    }
+
+    // checked before applying any, so a later failure is in the migration being applied
+    if let Some(migration) = migrator.iter().find(|m| {
+        applied
+            .get(&m.version)
+            .map_or(false, |checksum| *checksum != m.checksum)
+    }) {
+        return Err(MigrateError::VersionMismatch(migration.version).into());
+    }
+
+    let pending = migrator
+        .iter()
+        .filter(|m| !applied.contains_key(&m.version))
+        .collect::<Vec<_>>();
+    for (i, migration) in pending.iter().enumerate() {
+        on_step(MigrationStep {
+            version: migration.version,
+            description: &migration.description,
+            index: i + 1,
+            total: pending.len(),
+        });
+        if hooks.contains(migration.version) {
+            let start = Instant::now();
+            let mut tx = conn.begin().await?;
+            run_hooks(hooks.before.get(&migration.version), &mut tx).await?;
+            // nested in the transaction as a savepoint
+            tx.apply(migration).await?;
+            run_hooks(hooks.after.get(&migration.version), &mut tx).await?;
+            // the recorded execution time includes the hooks
+            sqlx::query(&format!(
+                "UPDATE _sqlx_migrations SET execution_time = {} WHERE version = {}",
+                crate::statements::placeholder(1),
+                crate::statements::placeholder(2)
+            ))
+            .bind(start.elapsed().as_nanos() as i64)
+            .bind(migration.version)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        } else {
+            conn.apply(migration).await?;
+        }
+    }
+    Ok(())
+}
+
+/// A connection holding the migration lock, which is a session lock on MySQL
+/// and Postgres. If it's dropped without being unlocked, e.g. because the
+/// future applying the migrations was dropped, it is closed rather than
+/// returned to the pool, which ends the session and releases the lock.
+struct LockedConnection(Option<PoolConnection<Db>>);
+
+impl LockedConnection {
+    async fn lock(pool: &Pool<Db>) -> Result<Self> {
+        let mut conn = pool.acquire().await?;
+        conn.lock().await?;
+        Ok(Self(Some(conn)))
+    }
+
+    async fn unlock(mut self) -> Result<()> {
+        let mut conn = self.0.take().expect("the connection is only taken here");
+        if let Err(error) = conn.unlock().await {
+            let _ = conn.detach().close().await;
+            return Err(error.into());
+        }
+        Ok(())
+    }
+}
+
+impl Deref for LockedConnection {
+    type Target = <Db as Database>::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+            .as_ref()
+            .expect("the connection is only taken on unlock")
+    }
+}
+
+impl DerefMut for LockedConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+            .as_mut()
+            .expect("the connection is only taken on unlock")
+    }
+}
+
+impl Drop for LockedConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.0.take() {
+            // dropping the detached connection closes it
+            drop(conn.detach());
+        }
+    }
+}
+
+/// An applied migration, as recorded in the `_sqlx_migrations` table.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppliedMigration {
+    version: i64,
+    description: String,
+    /// When the migration was applied, in RFC 3339.
+    installed_on: String,
+    /// `false` for a migration that failed and left the database dirty.
+    success: bool,
+    /// How long applying the migration took in milliseconds, including its
+    /// hooks, or `null` if it wasn't recorded.
+    execution_time_ms: Option<f64>,
+}
+
+#[cfg(feature = "sqlite")]
+type InstalledOn = time::PrimitiveDateTime;
+#[cfg(not(feature = "sqlite"))]
+type InstalledOn = time::OffsetDateTime;
+
+#[cfg(feature = "sqlite")]
+const MIGRATIONS_TABLE_EXISTS: &str =
+    "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'";
+#[cfg(feature = "mysql")]
+const MIGRATIONS_TABLE_EXISTS: &str = "SELECT count(*) FROM information_schema.tables \
+    WHERE table_schema = DATABASE() AND table_name = '_sqlx_migrations'";
+#[cfg(feature = "postgres")]
+const MIGRATIONS_TABLE_EXISTS: &str = "SELECT count(to_regclass('_sqlx_migrations'))";
+
+/// Returns the applied migrations in version order, empty if no migration was
+/// ever run on the database.
+#[command]
+async fn migration_history(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+) -> Result<Vec<AppliedMigration>> {
+    let instances = db_instances.0.lock().await;
+    let pool = instances.get(&db).ok_or(Error::DatabaseNotLoaded(db))?;
+    applied_migrations(pool).await
+}
+
+async fn applied_migrations(pool: &Pool<Db>) -> Result<Vec<AppliedMigration>> {
+    let exists: i64 = sqlx::query_scalar(MIGRATIONS_TABLE_EXISTS)
+        .fetch_one(pool)
+        .await?;
+    if exists == 0 {
+        return Ok(Vec::new());
+    }
+
+    let rows: Vec<(i64, String, InstalledOn, bool, i64)> = sqlx::query_as(
+        "SELECT version, description, installed_on, success, execution_time \
+        FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(version, description, installed_on, success, execution_time)| {
+                #[cfg(feature = "sqlite")]
+                let installed_on = installed_on.assume_utc();
+                AppliedMigration {
+                    version,
+                    description,
+                    installed_on: installed_on
+                        .to_offset(time::UtcOffset::UTC)
+                        .format(&time::format_description::well_known::Rfc3339)
+                        .unwrap_or_default(),
+                    success,
+                    // sqlx records -1 until the migration is committed
+                    execution_time_ms: (execution_time >= 0).then_some(execution_time as f64 / 1e6),
+                }
+            },
+        )
+        .collect())
+}
+
 #[command]
 async fn load<R: Runtime>(
     #[allow(unused_variables)] app: AppHandle<R>,
     db_instances: State<'_, DbInstances>,
     migrations: State<'_, Migrations>,
+    connection_configs: State<'_, ConnectionConfigs>,
     db: String,
+    options: Option<ConnectionOptions>,
 ) -> Result<String> {
     #[cfg(feature = "sqlite")]
     let fqdb = path_mapper(app_path(&app), &db);
@@ -160,25 +798,175 @@ async fn load<R: Runtime>(
     #[cfg(feature = "sqlite")]
     create_dir_all(app_path(&app)).expect("Problem creating App directory!");
 
-    if !Db::database_exists(&fqdb).await.unwrap_or(false) {
-        Db::create_database(&fqdb).await?;
-    }
-    let pool = Pool::connect(&fqdb).await?;
+    let options = options
+        .or_else(|| connection_configs.0.get(&db).cloned())
+        .unwrap_or_default();
+    let pool = connect(&fqdb, &options).await?;
 
     if let Some(migrations) = migrations.0.lock().await.remove(&db) {
-        let migrator = Migrator::new(migrations).await?;
-        migrator.run(&pool).await?;
+        run_migrations(&app, &db, &pool, migrations).await?;
     }
 
+    #[cfg(feature = "postgres")]
+    app.state::<StatementTimeouts>()
+        .set(&db, options.statement_timeout)
+        .await;
     db_instances.0.lock().await.insert(db.clone(), pool);
     Ok(db)
 }
 
+/// Returns the database server version and the features it supports.
+#[command]
+async fn info(db_instances: State<'_, DbInstances>, db: String) -> Result<DatabaseInfo> {
+    let instances = db_instances.0.lock().await;
+    let pool = instances.get(&db).ok_or(Error::DatabaseNotLoaded(db))?;
+    crate::info::query(pool).await
+}
+
+/// The prepared statements cached by the idle connections of a pool.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatementCache {
+    connections: usize,
+    statements: usize,
+}
+
+/// Takes all the idle connections of the pool, without opening new ones.
+/// Holding them makes sure every one of them is only taken once.
+fn idle_connections(pool: &Pool<Db>) -> Vec<PoolConnection<Db>> {
+    std::iter::from_fn(|| pool.try_acquire()).collect()
+}
+
+/// Returns connections to the pool, so they are idle again once it returns
+/// rather than when the tasks spawned on drop run.
+async fn return_to_pool(connections: Vec<PoolConnection<Db>>) {
+    for mut conn in connections {
+        conn.return_to_pool().await;
+    }
+}
+
+/// Returns the number of prepared statements cached by the idle connections.
+#[command]
+async fn statement_cache(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+) -> Result<StatementCache> {
+    let instances = db_instances.0.lock().await;
+    let pool = instances.get(&db).ok_or(Error::DatabaseNotLoaded(db))?;
+    Ok(cached_statements(pool).await)
+}
+
+async fn cached_statements(pool: &Pool<Db>) -> StatementCache {
+    let connections = idle_connections(pool);
+    let cache = StatementCache {
+        connections: connections.len(),
+        statements: connections.iter().map(|c| c.cached_statements_size()).sum(),
+    };
+    return_to_pool(connections).await;
+    cache
+}
+
+/// Drops the prepared statements cached by the idle connections, e.g. after a
+/// schema change made them stale. Returns the number of dropped statements.
+/// Connections in use keep their statements.
+#[command]
+async fn clear_statement_cache(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+) -> Result<StatementCache> {
+    let instances = db_instances.0.lock().await;
+    let pool = instances.get(&db).ok_or(Error::DatabaseNotLoaded(db))?;
+    clear_cached_statements(pool).await
+}
+
+async fn clear_cached_statements(pool: &Pool<Db>) -> Result<StatementCache> {
+    let mut connections = idle_connections(pool);
+    let mut statements = 0;
+    for conn in &mut connections {
+        statements += conn.cached_statements_size();
+        conn.clear_cached_statements().await?;
+    }
+    let cache = StatementCache {
+        connections: connections.len(),
+        statements,
+    };
+    return_to_pool(connections).await;
+    Ok(cache)
+}
+
+/// Discards the session state of the idle connections, e.g. session variables,
+/// a changed `search_path` or temporary tables, so it doesn't leak into later
+/// queries. Returns the number of reset connections. Connections in use keep
+/// their state.
+///
+/// On Postgres the connections run `DISCARD ALL`, and then set their statement
+/// timeout again. MySQL has no statement for it, the connections are closed
+/// and the pool opens new ones.
+#[cfg(not(feature = "sqlite"))]
+#[command]
+async fn reset_connection<R: Runtime>(
+    #[allow(unused_variables)] app: AppHandle<R>,
+    db_instances: State<'_, DbInstances>,
+    db: String,
+) -> Result<usize> {
+    let instances = db_instances.0.lock().await;
+    let pool = instances
+        .get(&db)
+        .ok_or_else(|| Error::DatabaseNotLoaded(db.clone()))?;
+    #[cfg(feature = "postgres")]
+    let timeout = app.state::<StatementTimeouts>().get(&db).await;
+    #[cfg(feature = "mysql")]
+    let timeout = None;
+    reset_connections(pool, timeout).await
+}
+
+/// Resets the idle connections of a pool, setting the default statement
+/// `timeout` again on Postgres.
+#[cfg(not(feature = "sqlite"))]
+async fn reset_connections(
+    pool: &Pool<Db>,
+    #[allow(unused_variables)] timeout: Option<u64>,
+) -> Result<usize> {
+    let connections = idle_connections(pool);
+    let count = connections.len();
+
+    #[cfg(feature = "postgres")]
+    {
+        let mut connections = connections;
+        for conn in &mut connections {
+            // `DISCARD ALL` deallocates the statements sqlx has cached
+            conn.clear_cached_statements().await?;
+            conn.execute("DISCARD ALL").await?;
+            if let Some(timeout) = timeout {
+                conn.execute(crate::statements::statement_timeout(timeout).as_str())
+                    .await?;
+            }
+        }
+        return_to_pool(connections).await;
+    }
+    #[cfg(feature = "mysql")]
+    for conn in connections {
+        conn.close().await?;
+    }
+
+    Ok(count)
+}
+
 /// Allows the database connection(s) to be closed; if no database
 /// name is passed in then _all_ database connection pools will be
 /// shut down.
 #[command]
-async fn close(db_instances: State<'_, DbInstances>, db: Option<String>) -> Result<bool> {
+async fn close<R: Runtime>(
+    #[allow(unused_variables)] app: AppHandle<R>,
+    db_instances: State<'_, DbInstances>,
+    db: Option<String>,
+) -> Result<bool> {
+    // connections pinned to advisory locks must be returned before the pool can close
+    #[cfg(feature = "postgres")]
+    app.state::<crate::locks::AdvisoryLocks>()
+        .release(db.as_deref())
+        .await;
+
     let mut instances = db_instances.0.lock().await;
 
     let pools = if let Some(db) = db {
@@ -197,93 +985,1107 @@ async fn close(db_instances: State<'_, DbInstances>, db: Option<String>) -> Resu
     Ok(true)
 }
 
+/// The result of [`execute`], with the same shape on every driver.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryResult {
+    rows_affected: u64,
+    /// Always `0` on Postgres, use a `RETURNING` clause instead.
+    last_insert_id: LastInsertId,
+}
+
+impl QueryResult {
+    fn new(result: <Db as Database>::QueryResult) -> Self {
+        #[cfg(feature = "sqlite")]
+        let last_insert_id = result.last_insert_rowid();
+        #[cfg(feature = "mysql")]
+        let last_insert_id = result.last_insert_id();
+        #[cfg(feature = "postgres")]
+        let last_insert_id = 0;
+        Self {
+            rows_affected: result.rows_affected(),
+            last_insert_id,
+        }
+    }
+}
+
+/// Options for [`execute`].
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ExecuteOptions {
+    /// Sent as a leading comment of the query, see [`label_query`](crate::rewrite::label_query).
+    label: Option<String>,
+}
+
 /// Execute a command against the database
 #[command]
 async fn execute(
     db_instances: State<'_, DbInstances>,
     db: String,
     query: String,
-    values: Vec<JsonValue>,
-) -> Result<(u64, LastInsertId)> {
+    values: BindValues,
+    options: Option<ExecuteOptions>,
+) -> Result<QueryResult> {
+    let options = options.unwrap_or_default();
     let mut instances = db_instances.0.lock().await;
 
     let db = instances.get_mut(&db).ok_or(Error::DatabaseNotLoaded(db))?;
-    let mut query = sqlx::query(&query);
-    for value in values {
-        if value.is_null() {
-            query = query.bind(None::<JsonValue>);
-        } else if value.is_string() {
-            query = query.bind(value.as_str().unwrap().to_owned())
-        } else {
-            query = query.bind(value);
-        }
+    let (mut query, values) = values.resolve(query)?;
+    if let Some(label) = &options.label {
+        query = crate::rewrite::label_query(&query, label);
     }
-    let result = query.execute(&*db).await?;
-    #[cfg(feature = "sqlite")]
-    let r = Ok((result.rows_affected(), result.last_insert_rowid()));
-    #[cfg(feature = "mysql")]
-    let r = Ok((result.rows_affected(), result.last_insert_id()));
-    #[cfg(feature = "postgres")]
-    let r = Ok((result.rows_affected(), 0));
-    r
+    let mut conn = db.acquire().await?;
+    let result = bind_values(&mut conn, &query, values)
+        .await?
+        .query()
+        .execute(&mut *conn)
+        .await?;
+    Ok(QueryResult::new(result))
 }
 
+/// The size of the chunks [`execute_with_file`] writes a file in.
+#[cfg(feature = "postgres")]
+const FILE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Executes a command with the contents of the file at `path` as a parameter,
+/// e.g. to insert a large file into a `bytea` column without transferring it
+/// from the webview. The file is the parameter after `values`, or `:file` with
+/// named values. The path must be allowed by the fs scope.
+#[cfg(feature = "postgres")]
 #[command]
-async fn select(
+async fn execute_with_file<R: Runtime>(
+    app: AppHandle<R>,
     db_instances: State<'_, DbInstances>,
     db: String,
     query: String,
-    values: Vec<JsonValue>,
-) -> Result<Vec<HashMap<String, JsonValue>>> {
-    let mut instances = db_instances.0.lock().await;
-    let db = instances.get_mut(&db).ok_or(Error::DatabaseNotLoaded(db))?;
-    let mut query = sqlx::query(&query);
-    for value in values {
-        if value.is_null() {
-            query = query.bind(None::<JsonValue>);
-        } else if value.is_string() {
-            query = query.bind(value.as_str().unwrap().to_owned())
-        } else {
-            query = query.bind(value);
-        }
+    values: BindValues,
+    path: std::path::PathBuf,
+) -> Result<QueryResult> {
+    if !app.fs_scope().is_allowed(&path) {
+        return Err(Error::PathNotAllowed(path));
     }
-    let rows = query.fetch_all(&*db).await?;
-    let mut values = Vec::new();
-    for row in rows {
-        let mut value = HashMap::default();
-        for (i, column) in row.columns().iter().enumerate() {
-            let v = row.try_get_raw(i)?;
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    execute_file(&pool, query, values, &path).await
+}
 
-            let v = crate::decode::to_json(v)?;
+/// A bound value is sent whole, so the file is streamed into a large object
+/// in chunks instead, which the query reads with `lo_get`. The large object
+/// is removed in the same transaction.
+#[cfg(feature = "postgres")]
+async fn execute_file(
+    pool: &Pool<Db>,
+    query: String,
+    values: BindValues,
+    path: &std::path::Path,
+) -> Result<QueryResult> {
+    use sqlx::postgres::types::Oid;
+    use tokio::io::AsyncReadExt;
 
-            value.insert(column.name().to_string(), v);
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut tx = pool.begin().await?;
+    let oid: Oid = sqlx::query_scalar("SELECT lo_create(0)")
+        .fetch_one(&mut *tx)
+        .await?;
+    let mut chunk = vec![0; FILE_CHUNK_SIZE];
+    let mut offset = 0_i64;
+    loop {
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            break;
         }
-
-        values.push(value);
+        sqlx::query("SELECT lo_put($1, $2, $3)")
+            .bind(oid)
+            .bind(offset)
+            .bind(&chunk[..read])
+            .execute(&mut *tx)
+            .await?;
+        offset += read as i64;
     }
 
-    Ok(values)
+    // bound as text, as numbers are bound as `jsonb`
+    let file = JsonValue::String(oid.0.to_string());
+    let (placeholder, values) = match values {
+        BindValues::Positional(mut values) => {
+            values.push(file);
+            (format!("${}", values.len()), BindValues::Positional(values))
+        }
+        BindValues::Named(mut params) => {
+            params.insert("file".to_string(), file);
+            (":file".to_string(), BindValues::Named(params))
+        }
+    };
+    let query = crate::rewrite::replace_placeholder(
+        &query,
+        &placeholder,
+        &format!("lo_get({placeholder}::oid)"),
+    );
+    let (query, values) = values.resolve(query)?;
+    let result = bind_values(&mut tx, &query, values)
+        .await?
+        .query()
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("SELECT lo_unlink($1)")
+        .bind(oid)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(QueryResult::new(result))
 }
 
-/// Tauri SQL plugin builder.
-#[derive(Default)]
-pub struct Builder {
+/// Options for [`select`].
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct SelectOptions {
+    /// Returns every column as the text the database produced, without any
+    /// type-aware decoding. The query is sent unprepared, which makes the
+    /// database send text, so it can't have bound values.
+    raw_text: bool,
+    /// Overrides [`DecodeOptions::max_bytea_size`] for this query.
+    #[cfg(feature = "postgres")]
+    max_bytea_size: Option<usize>,
+    /// Overrides [`DecodeOptions::packed_arrays`] for this query.
+    #[cfg(feature = "postgres")]
+    packed_arrays: Option<bool>,
+    /// Overrides [`DecodeOptions::compress_threshold`] for this query.
+    #[cfg(feature = "postgres")]
+    compress_threshold: Option<usize>,
+    /// Runs the query again, once, if the connection is closed while it runs,
+    /// e.g. after a network blip. The pool reconnects for the second attempt.
+    reconnect: bool,
+    /// Makes the query fail if it tries to write, see [`read_only_connection`].
+    read_only: bool,
+    /// Sent as a leading comment of the query, see [`label_query`](crate::rewrite::label_query).
+    label: Option<String>,
+}
+
+#[command]
+async fn select(
+    db_instances: State<'_, DbInstances>,
+    decode_options: State<'_, DecodeOptions>,
+    db: String,
+    query: String,
+    values: BindValues,
+    options: Option<SelectOptions>,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    let options = options.unwrap_or_default();
+    let mut instances = db_instances.0.lock().await;
+    let db = instances.get_mut(&db).ok_or(Error::DatabaseNotLoaded(db))?;
+    let (mut query, values) = values.resolve(query)?;
+    if let Some(label) = &options.label {
+        query = crate::rewrite::label_query(&query, label);
+    }
+    if options.raw_text {
+        if !values.is_empty() {
+            return Err(Error::RawTextParameters);
+        }
+        if options.read_only {
+            return Err(Error::RawTextReadOnly);
+        }
+        return fetch_raw_text(db, &query, &decode_options).await;
+    }
+    #[cfg(feature = "postgres")]
+    let decode_options = DecodeOptions {
+        max_bytea_size: options.max_bytea_size.or(decode_options.max_bytea_size),
+        packed_arrays: options
+            .packed_arrays
+            .unwrap_or(decode_options.packed_arrays),
+        compress_threshold: options
+            .compress_threshold
+            .or(decode_options.compress_threshold),
+        ..decode_options.inner().clone()
+    };
+
+    let (read_only, reconnect) = (options.read_only, options.reconnect);
+    fetch_rows_reconnecting(db, &query, values, &decode_options, read_only, reconnect).await
+}
+
+/// Runs a select query like [`fetch_rows`], and once more on a new
+/// connection if `reconnect` is set and the connection was closed.
+async fn fetch_rows_reconnecting(
+    pool: &Pool<Db>,
+    query: &str,
+    values: Vec<JsonValue>,
+    decode_options: &DecodeOptions,
+    read_only: bool,
+    reconnect: bool,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    match fetch_rows(pool, query, values.clone(), decode_options, read_only).await {
+        Err(Error::Sql(e)) if reconnect && is_connection_closed(&e) => {
+            log::debug!("retrying select after the connection was closed: {e}");
+            fetch_rows(pool, query, values, decode_options, read_only).await
+        }
+        result => result,
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ExplainOptions {
+    /// Also returns the values in bind order. Off by default, as they may be
+    /// sensitive and end up in logs.
+    include_values: bool,
+    /// Also returns the plan of the database for the query, the rows of its
+    /// `EXPLAIN`. Only select queries can be explained.
+    plan: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExplainedQuery {
+    sql: String,
+    parameter_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<Vec<JsonValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<Vec<HashMap<String, JsonValue>>>,
+}
+
+/// Returns the SQL sent to the database for a query after named parameters
+/// are rewritten, and optionally its plan, without executing it.
+#[command]
+async fn explain_query(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    query: String,
+    values: BindValues,
+    options: Option<ExplainOptions>,
+) -> Result<ExplainedQuery> {
+    let options = options.unwrap_or_default();
+    let (sql, values) = values.resolve(query)?;
+    let plan = if options.plan {
+        let pool = db_instances
+            .0
+            .lock()
+            .await
+            .get(&db)
+            .cloned()
+            .ok_or(Error::DatabaseNotLoaded(db))?;
+        Some(query_plan(&pool, &sql, values.clone()).await?)
+    } else {
+        None
+    };
+    Ok(ExplainedQuery {
+        sql,
+        parameter_count: values.len(),
+        values: options.include_values.then_some(values),
+        plan,
+    })
+}
+
+/// The rows of the `EXPLAIN` of a select query.
+async fn query_plan(
+    pool: &Pool<Db>,
+    query: &str,
+    values: Vec<JsonValue>,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    let query = crate::statements::explain(query)?;
+    fetch_rows(pool, &query, values, &DecodeOptions::default(), false).await
+}
+
+/// Options for [`json_path`].
+#[cfg(feature = "postgres")]
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonPathOptions {
+    /// Extracts the last segment as text with `->>`, rather than as `jsonb`.
+    as_text: bool,
+    /// The number of the first placeholder, to follow the other bound values.
+    first_parameter: usize,
+}
+
+#[cfg(feature = "postgres")]
+impl Default for JsonPathOptions {
+    fn default() -> Self {
+        Self {
+            as_text: false,
+            first_parameter: 1,
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[derive(Serialize)]
+struct JsonPath {
+    sql: String,
+    values: Vec<JsonValue>,
+}
+
+/// Whether the select query returns at least one row, without fetching any.
+#[command]
+async fn exists(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    query: String,
+    values: BindValues,
+) -> Result<bool> {
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    let (query, values) = values.resolve(query)?;
+    row_exists(&pool, &query, values).await
+}
+
+async fn row_exists(pool: &Pool<Db>, query: &str, values: Vec<JsonValue>) -> Result<bool> {
+    let query = crate::statements::exists(query);
+    let mut conn = pool.acquire().await?;
+    let row = bind_values(&mut conn, &query, values)
+        .await?
+        .query()
+        .fetch_one(&mut *conn)
+        .await?;
+    // `EXISTS` is a `boolean` on Postgres and an integer elsewhere
+    #[cfg(feature = "postgres")]
+    let exists = row.try_get::<bool, _>(0)?;
+    #[cfg(not(feature = "postgres"))]
+    let exists = row.try_get::<i64, _>(0)? != 0;
+    Ok(exists)
+}
+
+/// Removes every row of a table, restarting its identity or autoincrement
+/// sequence. Refuses to run unless `confirm` is `true`.
+#[command]
+async fn truncate(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    table: String,
+    confirm: bool,
+) -> Result<()> {
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    truncate_table(&pool, &table, confirm).await
+}
+
+async fn truncate_table(pool: &Pool<Db>, table: &str, confirm: bool) -> Result<()> {
+    if !confirm {
+        return Err(Error::TruncateNotConfirmed(table.to_string()));
+    }
+    let query = crate::statements::truncate(table)?;
+
+    // SQLite has no `TRUNCATE`, the table is emptied with `DELETE` and its
+    // `AUTOINCREMENT` counter dropped from `sqlite_sequence`, if there is one
+    #[cfg(feature = "sqlite")]
+    {
+        let (schema, name) = table.rsplit_once('.').unwrap_or(("main", table));
+        let schema = crate::statements::quote_identifier(schema)?;
+        let mut tx = pool.begin().await?;
+        tx.execute(query.as_str()).await?;
+        let has_sequence = sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT count(*) FROM {schema}.sqlite_master WHERE type = 'table' AND name = 'sqlite_sequence'"
+        ))
+        .fetch_one(&mut *tx)
+        .await?
+            > 0;
+        if has_sequence {
+            sqlx::query(&format!(
+                "DELETE FROM {schema}.sqlite_sequence WHERE name = {}",
+                crate::statements::placeholder(1)
+            ))
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+    }
+    #[cfg(not(feature = "sqlite"))]
+    pool.execute(query.as_str()).await?;
+
+    Ok(())
+}
+
+/// Builds a `jsonb` path expression for a column from a path of keys and array
+/// indices, which are returned as values to bind instead of being written into
+/// the SQL.
+#[cfg(feature = "postgres")]
+#[command]
+fn json_path(
+    column: String,
+    path: Vec<JsonValue>,
+    options: Option<JsonPathOptions>,
+) -> Result<JsonPath> {
+    let options = options.unwrap_or_default();
+    let sql =
+        crate::statements::json_path(&column, &path, options.as_text, options.first_parameter)?;
+    Ok(JsonPath { sql, values: path })
+}
+
+/// Options for [`jsonb_merge`].
+#[cfg(feature = "postgres")]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonbMergeOptions {
+    /// The condition selecting the rows to update, e.g. `id = $1`.
+    #[serde(rename = "where")]
+    where_clause: String,
+    /// The values bound to the placeholders of `where`. The patch is bound
+    /// after them.
+    #[serde(default)]
+    values: Vec<JsonValue>,
+    /// Merge nested objects and remove the keys set to `null`, rather than
+    /// replacing the top-level keys.
+    #[serde(default)]
+    deep: bool,
+}
+
+/// Merges a patch object into a `jsonb` column in a single `UPDATE`, so
+/// concurrent updates of other keys aren't lost. Returns the number of updated
+/// rows.
+#[cfg(feature = "postgres")]
+#[command]
+async fn jsonb_merge(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    table: String,
+    column: String,
+    patch: JsonValue,
+    options: JsonbMergeOptions,
+) -> Result<u64> {
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    merge_jsonb(&pool, &table, &column, patch, options).await
+}
+
+#[cfg(feature = "postgres")]
+async fn merge_jsonb(
+    pool: &Pool<Db>,
+    table: &str,
+    column: &str,
+    patch: JsonValue,
+    options: JsonbMergeOptions,
+) -> Result<u64> {
+    let patch = match patch {
+        JsonValue::Object(patch) => patch,
+        _ => return Err(Error::JsonbPatchNotObject),
+    };
+    let (query, keys) = crate::statements::jsonb_merge(
+        table,
+        column,
+        &patch,
+        options.deep,
+        &options.where_clause,
+        options.values.len() + 1,
+    )?;
+
+    let mut conn = pool.acquire().await?;
+    let mut values = bind_values(&mut conn, &query, options.values).await?;
+    let mut bound = values.query().bind(JsonValue::Object(patch));
+    for key in keys {
+        bound = bound.bind(key);
+    }
+    Ok(bound.execute(&mut *conn).await?.rows_affected())
+}
+
+/// Inserts many rows with multi-row `INSERT` statements, as few as the
+/// driver's bound parameter limit allows, in a single transaction. Returns
+/// the number of inserted rows.
+#[command]
+async fn batch_insert(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    table: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<JsonValue>>,
+) -> Result<u64> {
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    insert_rows(&pool, &table, &columns, rows).await
+}
+
+async fn insert_rows(
+    pool: &Pool<Db>,
+    table: &str,
+    columns: &[String],
+    rows: Vec<Vec<JsonValue>>,
+) -> Result<u64> {
+    if let Some((row, values)) = rows
+        .iter()
+        .enumerate()
+        .find(|(_, values)| values.len() != columns.len())
+    {
+        return Err(Error::RowLength {
+            row,
+            expected: columns.len(),
+            got: values.len(),
+        });
+    }
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let rows_per_statement = (crate::statements::MAX_PARAMETERS / columns.len().max(1)).max(1);
+    let mut tx = pool.begin().await?;
+    let mut rows_affected = 0;
+    for chunk in rows.chunks(rows_per_statement) {
+        let query = crate::statements::insert(table, columns, chunk.len())?;
+        let values = chunk.iter().flatten().cloned().collect();
+        rows_affected += bind_values(&mut tx, &query, values)
+            .await?
+            .query()
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+    tx.commit().await?;
+
+    Ok(rows_affected)
+}
+
+/// Inserts many rows given as objects, updating the ones that conflict on
+/// `conflict_columns`, with multi-row statements as few as the driver's bound
+/// parameter limit allows, in a single transaction. Returns the number of
+/// affected rows, as counted by the driver.
+#[command]
+async fn batch_upsert(
+    db_instances: State<'_, DbInstances>,
+    db: String,
+    table: String,
+    rows: Vec<serde_json::Map<String, JsonValue>>,
+    conflict_columns: Vec<String>,
+) -> Result<u64> {
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    upsert_rows(&pool, &table, rows, &conflict_columns).await
+}
+
+async fn upsert_rows(
+    pool: &Pool<Db>,
+    table: &str,
+    rows: Vec<serde_json::Map<String, JsonValue>>,
+    conflict_columns: &[String],
+) -> Result<u64> {
+    let columns = match rows.first() {
+        Some(row) => row.keys().cloned().collect::<Vec<_>>(),
+        None => return Ok(0),
+    };
+    if let Some(row) = rows
+        .iter()
+        .position(|row| row.len() != columns.len() || !columns.iter().all(|c| row.contains_key(c)))
+    {
+        return Err(Error::RowColumns {
+            row,
+            expected: columns,
+        });
+    }
+    crate::statements::check_conflict_columns(&columns, conflict_columns)?;
+
+    // A statement can't update the same row twice on Postgres. SQLite and
+    // MySQL apply the rows in order, so the last one wins.
+    #[cfg(feature = "postgres")]
+    {
+        let mut first = HashMap::new();
+        for (row, values) in rows.iter().enumerate() {
+            let key = conflict_columns
+                .iter()
+                .map(|c| values[c].to_string())
+                .collect::<Vec<_>>();
+            if let Some(first) = first.insert(key, row) {
+                return Err(Error::DuplicateConflictValues { first, row });
+            }
+        }
+    }
+
+    let rows_per_statement = (crate::statements::MAX_PARAMETERS / columns.len().max(1)).max(1);
+    let mut tx = pool.begin().await?;
+    let mut rows_affected = 0;
+    for chunk in rows.chunks(rows_per_statement) {
+        let query = crate::statements::upsert(table, &columns, conflict_columns, chunk.len())?;
+        let values = chunk
+            .iter()
+            .flat_map(|row| columns.iter().map(|c| row[c].clone()))
+            .collect();
+        rows_affected += bind_values(&mut tx, &query, values)
+            .await?
+            .query()
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+    tx.commit().await?;
+
+    Ok(rows_affected)
+}
+
+/// Options for [`query_with_timeout_and_retry`].
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RetryOptions {
+    /// Timeout of a single attempt, in milliseconds.
+    timeout: Option<u64>,
+    /// Total number of attempts, including the first one.
+    attempts: u32,
+    /// Delay before the first retry, in milliseconds. Doubles on every retry.
+    backoff: u64,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            attempts: 3,
+            backoff: 100,
+        }
+    }
+}
+
+/// Runs a select query with a per-attempt timeout, retrying it when the
+/// connection to the database fails. SQL errors and timeouts fail immediately.
+#[command]
+async fn query_with_timeout_and_retry(
+    db_instances: State<'_, DbInstances>,
+    decode_options: State<'_, DecodeOptions>,
+    db: String,
+    query: String,
+    values: BindValues,
+    options: Option<RetryOptions>,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    let pool = db_instances
+        .0
+        .lock()
+        .await
+        .get(&db)
+        .cloned()
+        .ok_or(Error::DatabaseNotLoaded(db))?;
+    let (query, values) = values.resolve(query)?;
+    fetch_rows_with_retry(
+        &pool,
+        &query,
+        values,
+        &decode_options,
+        &options.unwrap_or_default(),
+    )
+    .await
+}
+
+async fn fetch_rows_with_retry(
+    pool: &Pool<Db>,
+    query: &str,
+    values: Vec<JsonValue>,
+    decode_options: &DecodeOptions,
+    options: &RetryOptions,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    let mut backoff = Duration::from_millis(options.backoff);
+    let mut attempt = 1;
+    loop {
+        #[cfg(feature = "sqlite")]
+        let rows = fetch_rows(pool, query, values.clone(), decode_options, false);
+        // the server cancels the query too, instead of at the default statement timeout
+        #[cfg(not(feature = "sqlite"))]
+        let rows = async {
+            match options.timeout {
+                Some(timeout) => {
+                    fetch_rows_with_statement_timeout(
+                        pool,
+                        query,
+                        values.clone(),
+                        decode_options,
+                        timeout,
+                    )
+                    .await
+                }
+                None => fetch_rows(pool, query, values.clone(), decode_options, false).await,
+            }
+        };
+        let result = match options.timeout {
+            Some(timeout) => tokio::time::timeout(Duration::from_millis(timeout), rows)
+                .await
+                .unwrap_or(Err(Error::Timeout(timeout))),
+            None => rows.await,
+        };
+
+        match result {
+            Err(Error::Sql(e)) if attempt < options.attempts && is_transient(&e) => {
+                log::debug!("retrying query after transient error: {e}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether the error comes from the connection to the database rather than
+/// from the query itself, meaning the query may succeed if retried.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut => true,
+        // SQLSTATE class 08 is "connection exception"
+        sqlx::Error::Database(e) => e.code().map_or(false, |code| code.starts_with("08")),
+        _ => false,
+    }
+}
+
+/// Whether the connection was closed, by the network or the server, so the
+/// query didn't complete and a new connection is needed.
+fn is_connection_closed(error: &sqlx::Error) -> bool {
+    use std::io::ErrorKind;
+    match error {
+        sqlx::Error::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::NotConnected
+                | ErrorKind::UnexpectedEof
+        ),
+        // SQLSTATE 08006 is "connection failure" and 57P01 "admin shutdown",
+        // e.g. the server was restarted or `pg_terminate_backend` was called
+        sqlx::Error::Database(e) => e
+            .code()
+            .map_or(false, |code| code == "08006" || code == "57P01"),
+        _ => false,
+    }
+}
+
+/// A connection that can't write, see [`read_only_connection`].
+#[cfg(feature = "sqlite")]
+type ReadOnlyConnection = sqlx::sqlite::SqliteConnection;
+#[cfg(not(feature = "sqlite"))]
+type ReadOnlyConnection = sqlx::Transaction<'static, Db>;
+
+/// Opens a connection on which writes fail, as a defense against injected
+/// statements. SQLite opens a separate read-only connection to the database,
+/// the other drivers start a read-only transaction, which is rolled back when
+/// it is dropped.
+async fn read_only_connection(pool: &Pool<Db>) -> Result<ReadOnlyConnection> {
+    #[cfg(feature = "sqlite")]
+    let conn = {
+        // `query_only` also covers in-memory databases, which ignore `read_only`
+        let options = pool
+            .connect_options()
+            .as_ref()
+            .clone()
+            .read_only(true)
+            .pragma("query_only", "ON");
+        sqlx::ConnectOptions::connect(&options).await?
+    };
+    #[cfg(feature = "mysql")]
+    let conn = {
+        // only applies to the next transaction
+        let mut conn = pool.acquire().await?;
+        conn.execute("SET TRANSACTION READ ONLY").await?;
+        sqlx::Transaction::begin(conn).await?
+    };
+    #[cfg(feature = "postgres")]
+    let conn = {
+        let mut tx = pool.begin().await?;
+        tx.execute("SET TRANSACTION READ ONLY").await?;
+        tx
+    };
+    Ok(conn)
+}
+
+async fn fetch_rows(
+    pool: &Pool<Db>,
+    query: &str,
+    values: Vec<JsonValue>,
+    decode_options: &DecodeOptions,
+    read_only: bool,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    let mut read_only_conn;
+    let mut pool_conn;
+    let conn: &mut <Db as Database>::Connection = if read_only {
+        read_only_conn = read_only_connection(pool).await?;
+        &mut read_only_conn
+    } else {
+        pool_conn = pool.acquire().await?;
+        &mut pool_conn
+    };
+    fetch_rows_on(conn, query, values, decode_options).await
+}
+
+/// Runs a select query like [`fetch_rows`], with the default statement timeout
+/// of the connection replaced by `timeout`, in milliseconds.
+#[cfg(not(feature = "sqlite"))]
+async fn fetch_rows_with_statement_timeout(
+    pool: &Pool<Db>,
+    query: &str,
+    values: Vec<JsonValue>,
+    decode_options: &DecodeOptions,
+    timeout: u64,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    #[cfg(feature = "mysql")]
+    let rows = {
+        // the session keeps the timeout, so the connection isn't returned to the pool
+        let mut conn = pool.acquire().await?.detach();
+        conn.execute(crate::statements::statement_timeout(timeout).as_str())
+            .await?;
+        fetch_rows_on(&mut conn, query, values, decode_options).await?
+    };
+    #[cfg(feature = "postgres")]
+    let rows = {
+        // `SET LOCAL` only lasts until the end of the transaction
+        let mut tx = pool.begin().await?;
+        tx.execute(format!("SET LOCAL statement_timeout = {timeout}").as_str())
+            .await?;
+        let rows = fetch_rows_on(&mut tx, query, values, decode_options).await?;
+        tx.commit().await?;
+        rows
+    };
+    Ok(rows)
+}
+
+async fn fetch_rows_on(
+    conn: &mut <Db as Database>::Connection,
+    query: &str,
+    values: Vec<JsonValue>,
+    decode_options: &DecodeOptions,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    let rows = bind_values(conn, query, values)
+        .await?
+        .query()
+        .fetch_all(&mut *conn)
+        .await?;
+    let names = match rows.first() {
+        Some(row) => crate::decode::column_names(
+            row.columns().iter().map(|column| column.name()),
+            decode_options,
+        )?,
+        None => Vec::new(),
+    };
+    #[cfg(feature = "postgres")]
+    let regconfig_columns = rows.first().map_or_else(Vec::new, |row| {
+        row.columns()
+            .iter()
+            .zip(&names)
+            .filter(|(column, _)| sqlx::TypeInfo::name(column.type_info()) == "regconfig")
+            .map(|(_, name)| name.clone())
+            .collect::<Vec<_>>()
+    });
+    let mut values = Vec::new();
+    for row in rows {
+        let mut value = HashMap::default();
+        for (i, name) in names.iter().enumerate() {
+            let v = row.try_get_raw(i)?;
+
+            let v = crate::decode::to_json(v, decode_options)?;
+
+            value.insert(name.clone(), v);
+        }
+
+        values.push(value);
+    }
+
+    #[cfg(feature = "postgres")]
+    if !regconfig_columns.is_empty() {
+        crate::decode::resolve_regconfig_names(conn, &regconfig_columns, &mut values).await?;
+    }
+
+    Ok(values)
+}
+
+async fn fetch_raw_text(
+    pool: &Pool<Db>,
+    query: &str,
+    decode_options: &DecodeOptions,
+) -> Result<Vec<HashMap<String, JsonValue>>> {
+    // a plain `&str` is executed without preparing it
+    let rows = pool.fetch_all(query).await?;
+    let names = match rows.first() {
+        Some(row) => crate::decode::column_names(
+            row.columns().iter().map(|column| column.name()),
+            decode_options,
+        )?,
+        None => Vec::new(),
+    };
+    let mut values = Vec::new();
+    for row in rows {
+        let mut value = HashMap::default();
+        for (i, name) in names.iter().enumerate() {
+            let v = row.try_get_raw(i)?;
+
+            let v = if v.is_null() {
+                JsonValue::Null
+            } else {
+                // every column is text here, whatever its declared type
+                ValueRef::to_owned(&v)
+                    .try_decode_unchecked::<String>()
+                    .map_or(JsonValue::Null, JsonValue::String)
+            };
+
+            value.insert(name.clone(), v);
+        }
+
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+/// Generates the invoke handler with the commands available on every driver,
+/// followed by the given driver specific ones.
+macro_rules! invoke_handler {
+    ($($command:path),*) => {
+        tauri::generate_handler![
+            load,
+            info,
+            statement_cache,
+            clear_statement_cache,
+            execute,
+            select,
+            explain_query,
+            batch_insert,
+            batch_upsert,
+            exists,
+            truncate,
+            crate::catalog::table_exists,
+            crate::catalog::column_exists,
+            migration_history,
+            query_with_timeout_and_retry,
+            close
+            $(, $command)*
+        ]
+    };
+}
+
+/// Tauri SQL plugin builder.
+#[derive(Default)]
+pub struct Builder {
     migrations: Option<HashMap<String, MigrationList>>,
+    connection_options: HashMap<String, ConnectionOptions>,
+    decode_options: DecodeOptions,
+    migration_progress: bool,
 }
 
 impl Builder {
     /// Add migrations to a database.
     #[must_use]
     pub fn add_migrations(mut self, db_url: &str, migrations: Vec<Migration>) -> Self {
+        self.migration_list(db_url).migrations = migrations;
+        self
+    }
+
+    /// Run `hook` before the migration `version` of a database is applied, in
+    /// the same transaction. It doesn't run if the migration was already
+    /// applied.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// Builder::default().before_migration("sqlite:test.db", 2, |conn| {
+    ///     Box::pin(async move {
+    ///         sqlx::query("DELETE FROM todos WHERE title = ''").execute(conn).await?;
+    ///         Ok(())
+    ///     })
+    /// })
+    /// ```
+    #[must_use]
+    pub fn before_migration<F>(mut self, db_url: &str, version: i64, hook: F) -> Self
+    where
+        F: for<'c> Fn(&'c mut <Db as Database>::Connection) -> BoxFuture<'c, Result<()>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let hooks = &mut self.migration_list(db_url).hooks.before;
+        hooks.entry(version).or_default().push(Box::new(hook));
+        self
+    }
+
+    /// Run `hook` after the migration `version` of a database is applied, in
+    /// the same transaction, e.g. to populate a column the migration added. It
+    /// doesn't run if the migration was already applied.
+    #[must_use]
+    pub fn after_migration<F>(mut self, db_url: &str, version: i64, hook: F) -> Self
+    where
+        F: for<'c> Fn(&'c mut <Db as Database>::Connection) -> BoxFuture<'c, Result<()>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let hooks = &mut self.migration_list(db_url).hooks.after;
+        hooks.entry(version).or_default().push(Box::new(hook));
+        self
+    }
+
+    /// Emit the progress of applying migrations to the frontend as
+    /// `sql://migration` events, e.g. to show it on a splash screen: one before
+    /// each pending migration and a final one when they were applied or failed.
+    ///
+    /// Migrations of preloaded databases are applied before the frontend is
+    /// loaded, only the ones applied when it loads a database can be followed.
+    #[must_use]
+    pub fn migration_progress(mut self, enabled: bool) -> Self {
+        self.migration_progress = enabled;
+        self
+    }
+
+    fn migration_list(&mut self, db_url: &str) -> &mut MigrationList {
         self.migrations
             .get_or_insert(Default::default())
-            .insert(db_url.to_string(), MigrationList(migrations));
+            .entry(db_url.to_string())
+            .or_default()
+    }
+
+    /// Set the options used when connecting to a database, either through
+    /// `preload` or when the frontend loads it without explicit options.
+    #[must_use]
+    pub fn connection_options(mut self, db_url: &str, options: ConnectionOptions) -> Self {
+        self.connection_options.insert(db_url.to_string(), options);
+        self
+    }
+
+    /// Set how query results are converted to JSON.
+    #[must_use]
+    pub fn decode_options(mut self, options: DecodeOptions) -> Self {
+        self.decode_options = options;
         self
     }
 
     pub fn build<R: Runtime>(mut self) -> TauriPlugin<R, Option<PluginConfig>> {
-        PluginBuilder::new("sql")
-            .invoke_handler(tauri::generate_handler![load, execute, select, close])
+        let builder = PluginBuilder::new("sql");
+        #[cfg(feature = "postgres")]
+        let builder = builder.invoke_handler(invoke_handler![
+            crate::locks::advisory_lock,
+            crate::locks::try_advisory_lock,
+            crate::locks::advisory_unlock,
+            crate::copy::copy_to_csv,
+            execute_with_file,
+            json_path,
+            jsonb_merge,
+            reset_connection
+        ]);
+        #[cfg(feature = "sqlite")]
+        let builder = builder.invoke_handler(invoke_handler![crate::integrity::integrity_check]);
+        #[cfg(feature = "mysql")]
+        let builder = builder.invoke_handler(invoke_handler![reset_connection]);
+
+        for migrations in self.migrations.iter_mut().flat_map(HashMap::values_mut) {
+            migrations.progress = self.migration_progress;
+        }
+
+        builder
             .setup_with_config(|app, config: Option<PluginConfig>| {
                 let config = config.unwrap_or_default();
 
@@ -293,21 +2095,26 @@ impl Builder {
                 tauri::async_runtime::block_on(async move {
                     let instances = DbInstances::default();
                     let mut lock = instances.0.lock().await;
+                    #[cfg(feature = "postgres")]
+                    let statement_timeouts = StatementTimeouts::default();
                     for db in config.preload {
                         #[cfg(feature = "sqlite")]
                         let fqdb = path_mapper(app_path(app), &db);
                         #[cfg(not(feature = "sqlite"))]
                         let fqdb = db.clone();
 
-                        if !Db::database_exists(&fqdb).await.unwrap_or(false) {
-                            Db::create_database(&fqdb).await?;
-                        }
-                        let pool = Pool::connect(&fqdb).await?;
+                        let options = self
+                            .connection_options
+                            .get(&db)
+                            .cloned()
+                            .unwrap_or_default();
+                        let pool = connect(&fqdb, &options).await?;
 
                         if let Some(migrations) = self.migrations.as_mut().unwrap().remove(&db) {
-                            let migrator = Migrator::new(migrations).await?;
-                            migrator.run(&pool).await?;
+                            run_migrations(app, &db, &pool, migrations).await?;
                         }
+                        #[cfg(feature = "postgres")]
+                        statement_timeouts.set(&db, options.statement_timeout).await;
                         lock.insert(db, pool);
                     }
                     drop(lock);
@@ -316,6 +2123,12 @@ impl Builder {
                     app.manage(Migrations(Mutex::new(
                         self.migrations.take().unwrap_or_default(),
                     )));
+                    app.manage(ConnectionConfigs(self.connection_options));
+                    #[cfg(feature = "postgres")]
+                    app.manage(statement_timeouts);
+                    app.manage(self.decode_options);
+                    #[cfg(feature = "postgres")]
+                    app.manage(crate::locks::AdvisoryLocks::default());
 
                     Ok(())
                 })
@@ -323,6 +2136,11 @@ impl Builder {
             .on_event(|app, event| {
                 if let RunEvent::Exit = event {
                     tauri::async_runtime::block_on(async move {
+                        #[cfg(feature = "postgres")]
+                        app.state::<crate::locks::AdvisoryLocks>()
+                            .release(None)
+                            .await;
+
                         let instances = &*app.state::<DbInstances>();
                         let instances = instances.0.lock().await;
                         for value in instances.values() {
@@ -334,3 +2152,1288 @@ impl Builder {
             .build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, pool, pool_with};
+
+    #[cfg(feature = "sqlite")]
+    const CREATE_TABLE: &str =
+        "CREATE TABLE items (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT)";
+    #[cfg(feature = "mysql")]
+    const CREATE_TABLE: &str =
+        "CREATE TEMPORARY TABLE items (id INT AUTO_INCREMENT PRIMARY KEY, name TEXT)";
+    #[cfg(feature = "postgres")]
+    const CREATE_TABLE: &str = "CREATE TEMPORARY TABLE items (id SERIAL PRIMARY KEY, name TEXT)";
+
+    async fn count(pool: &Pool<Db>) -> i64 {
+        sqlx::query_scalar("SELECT count(*) FROM items")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn truncates_a_table() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TABLE).await.unwrap();
+            pool.execute("INSERT INTO items (name) VALUES ('a'), ('b')")
+                .await
+                .unwrap();
+
+            assert!(matches!(
+                truncate_table(&pool, "items", false).await,
+                Err(Error::TruncateNotConfirmed(_))
+            ));
+            assert_eq!(count(&pool).await, 2);
+
+            truncate_table(&pool, "items", true).await.unwrap();
+            assert_eq!(count(&pool).await, 0);
+
+            // the identity is restarted
+            pool.execute("INSERT INTO items (name) VALUES ('c')")
+                .await
+                .unwrap();
+            let id: i32 = sqlx::query_scalar("SELECT id FROM items")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(id, 1);
+        });
+    }
+
+    #[test]
+    fn rejects_invalid_table_names() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            for table in ["items; DROP TABLE users", "a.b.c", "\"\""] {
+                assert!(matches!(
+                    truncate_table(&pool, table, true).await,
+                    Err(Error::InvalidIdentifier(_))
+                ));
+            }
+        });
+    }
+
+    /// Executes `query` like the `execute` command, returning its result as
+    /// the frontend receives it.
+    async fn execute(pool: &Pool<Db>, query: &str) -> JsonValue {
+        let mut conn = pool.acquire().await.unwrap();
+        let result = bind_values(&mut conn, query, Vec::new())
+            .await
+            .unwrap()
+            .query()
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        serde_json::to_value(QueryResult::new(result)).unwrap()
+    }
+
+    #[test]
+    fn execute_reports_affected_rows() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TABLE).await.unwrap();
+
+            let statements = [
+                ("INSERT INTO items (name) VALUES ('a'), ('b'), ('c')", 3),
+                ("UPDATE items SET name = 'x' WHERE name <> 'a'", 2),
+                ("UPDATE items SET name = 'y' WHERE name = 'z'", 0),
+                ("DELETE FROM items WHERE name = 'x'", 2),
+                ("DELETE FROM items", 1),
+            ];
+            for (query, rows_affected) in statements {
+                let result = execute(&pool, query).await;
+                assert_eq!(result["rowsAffected"], rows_affected, "{query}");
+                assert!(result["lastInsertId"].is_number(), "{query}");
+            }
+        });
+    }
+
+    // keyed by text, as numbers are bound as `jsonb` on Postgres
+    #[cfg(not(feature = "mysql"))]
+    const CREATE_TAGS: &str = "CREATE TEMPORARY TABLE tags (tag TEXT PRIMARY KEY, name TEXT)";
+    #[cfg(feature = "mysql")]
+    const CREATE_TAGS: &str =
+        "CREATE TEMPORARY TABLE tags (tag VARCHAR(16) PRIMARY KEY, name TEXT)";
+
+    fn row(tag: &str, name: &str) -> serde_json::Map<String, JsonValue> {
+        serde_json::json!({ "tag": tag, "name": name })
+            .as_object()
+            .unwrap()
+            .clone()
+    }
+
+    async fn tags(pool: &Pool<Db>) -> Vec<(String, String)> {
+        sqlx::query_as("SELECT tag, name FROM tags ORDER BY tag")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    fn owned(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter()
+            .map(|(tag, name)| (tag.to_string(), name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn upserts_new_and_existing_rows() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TAGS).await.unwrap();
+            pool.execute("INSERT INTO tags (tag, name) VALUES ('a', 'A'), ('b', 'B')")
+                .await
+                .unwrap();
+
+            let rows = vec![row("b", "bee"), row("c", "C"), row("d", "D")];
+            upsert_rows(&pool, "tags", rows, &["tag".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(
+                tags(&pool).await,
+                owned(&[("a", "A"), ("b", "bee"), ("c", "C"), ("d", "D")])
+            );
+        });
+    }
+
+    #[test]
+    fn upserts_batches_larger_than_a_statement() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TAGS).await.unwrap();
+            let total = crate::statements::MAX_PARAMETERS / 2 + 10;
+            let rows = |name: &str| {
+                (0..total)
+                    .map(|i| row(&format!("{i:05}"), name))
+                    .collect::<Vec<_>>()
+            };
+
+            upsert_rows(&pool, "tags", rows("new"), &["tag".to_string()])
+                .await
+                .unwrap();
+            upsert_rows(&pool, "tags", rows("updated"), &["tag".to_string()])
+                .await
+                .unwrap();
+            let tags = tags(&pool).await;
+            assert_eq!(tags.len(), total);
+            assert!(tags.iter().all(|(_, name)| name == "updated"));
+        });
+    }
+
+    #[test]
+    fn inserts_batches_larger_than_a_statement() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TAGS).await.unwrap();
+            let total = crate::statements::MAX_PARAMETERS / 2 + 10;
+            let rows = (0..total)
+                .map(|i| vec![JsonValue::from(format!("{i:05}")), JsonValue::from("new")])
+                .collect();
+
+            let columns = ["tag".to_string(), "name".to_string()];
+            let inserted = insert_rows(&pool, "tags", &columns, rows).await.unwrap();
+            assert_eq!(inserted, total as u64);
+            let tags = tags(&pool).await;
+            assert_eq!(tags.len(), total);
+            assert_eq!(tags[total - 1], (format!("{:05}", total - 1), "new".into()));
+        });
+    }
+
+    #[test]
+    fn rejects_rows_of_the_wrong_length() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let columns = ["tag".to_string(), "name".to_string()];
+            let rows = vec![vec!["a".into(), "A".into()], vec!["b".into()]];
+            assert!(matches!(
+                insert_rows(&pool, "tags", &columns, rows).await,
+                Err(Error::RowLength {
+                    row: 1,
+                    expected: 2,
+                    got: 1
+                })
+            ));
+        });
+    }
+
+    #[test]
+    fn rejects_invalid_conflict_columns() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let upsert = |conflict_columns: &[&str]| {
+                let conflict_columns = conflict_columns
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                let pool = pool.clone();
+                async move { upsert_rows(&pool, "tags", vec![row("a", "A")], &conflict_columns).await }
+            };
+            assert!(matches!(
+                upsert(&[]).await,
+                Err(Error::MissingConflictColumns)
+            ));
+            assert!(matches!(
+                upsert(&["tag", "key"]).await,
+                Err(Error::UnknownConflictColumn(column)) if column == "key"
+            ));
+        });
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn rejects_duplicate_conflict_values() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TAGS).await.unwrap();
+            let rows = vec![row("a", "A"), row("b", "B"), row("a", "bee")];
+            assert!(matches!(
+                upsert_rows(&pool, "tags", rows, &["tag".to_string()]).await,
+                Err(Error::DuplicateConflictValues { first: 0, row: 2 })
+            ));
+            assert!(tags(&pool).await.is_empty());
+        });
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    #[test]
+    fn upserts_duplicate_conflict_values_in_order() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TAGS).await.unwrap();
+            let rows = vec![row("a", "A"), row("b", "B"), row("a", "bee")];
+            upsert_rows(&pool, "tags", rows, &["tag".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(tags(&pool).await, owned(&[("a", "bee"), ("b", "B")]));
+        });
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn executes_with_a_streamed_file() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute("CREATE TEMPORARY TABLE files (name TEXT, data BYTEA)")
+                .await
+                .unwrap();
+            // a few chunks and a partial one, of bytes that don't compress
+            let contents = (0..3 * FILE_CHUNK_SIZE + 1234)
+                .scan(1_u32, |state, _| {
+                    *state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    Some((*state >> 16) as u8)
+                })
+                .collect::<Vec<u8>>();
+            let path = std::env::temp_dir().join(format!(
+                "tauri-plugin-sql-{}-streamed.bin",
+                std::process::id()
+            ));
+            std::fs::write(&path, &contents).unwrap();
+            let large_objects = || {
+                sqlx::query_scalar::<_, i64>("SELECT count(*) FROM pg_largeobject_metadata")
+                    .fetch_one(&pool)
+            };
+            let before = large_objects().await.unwrap();
+
+            let positional = BindValues::Positional(vec![serde_json::json!("positional")]);
+            let query = "INSERT INTO files (name, data) VALUES ($1, $2)";
+            let result = execute_file(&pool, query.to_string(), positional, &path).await;
+            assert_eq!(result.unwrap().rows_affected, 1);
+            let named = serde_json::json!({ "name": "named" });
+            let named = BindValues::Named(named.as_object().unwrap().clone());
+            let query = "INSERT INTO files (name, data) VALUES (:name, :file)";
+            let result = execute_file(&pool, query.to_string(), named, &path).await;
+            assert_eq!(result.unwrap().rows_affected, 1);
+            std::fs::remove_file(&path).unwrap();
+
+            let files: Vec<(String, i32, bool)> = sqlx::query_as(
+                "SELECT name, length(data), sha256(data) = sha256($1) FROM files ORDER BY name",
+            )
+            .bind(&contents)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            let length = contents.len() as i32;
+            assert_eq!(
+                files,
+                [
+                    ("named".to_string(), length, true),
+                    ("positional".to_string(), length, true)
+                ]
+            );
+            assert_eq!(large_objects().await.unwrap(), before);
+        });
+    }
+
+    /// Runs `query` like the `queryWithTimeoutAndRetry` command.
+    async fn query_with_retry(
+        pool: &Pool<Db>,
+        query: &str,
+        options: RetryOptions,
+    ) -> Result<Vec<HashMap<String, JsonValue>>> {
+        fetch_rows_with_retry(pool, query, Vec::new(), &Default::default(), &options).await
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        block_on(async {
+            let options = PoolOptions::new().acquire_timeout(Duration::from_millis(100));
+            let pool = match pool_with(options).await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let no_retry = RetryOptions {
+                attempts: 1,
+                ..Default::default()
+            };
+            let retry = RetryOptions {
+                attempts: 3,
+                backoff: 200,
+                ..Default::default()
+            };
+
+            // the only connection is taken, so acquiring one times out
+            let conn = pool.acquire().await.unwrap();
+            assert!(matches!(
+                query_with_retry(&pool, "SELECT 1 AS one", no_retry).await,
+                Err(Error::Sql(sqlx::Error::PoolTimedOut))
+            ));
+            // and is returned during the backoff of the first retry
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(150)).await;
+                drop(conn);
+            });
+            let rows = query_with_retry(&pool, "SELECT 1 AS one", retry)
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0]["one"], 1);
+        });
+    }
+
+    #[test]
+    fn fails_on_sql_errors_without_retrying() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let options = RetryOptions {
+                attempts: 3,
+                backoff: 60_000,
+                ..Default::default()
+            };
+            let start = Instant::now();
+            assert!(matches!(
+                query_with_retry(&pool, "SELEC 1", options).await,
+                Err(Error::Sql(sqlx::Error::Database(_)))
+            ));
+            assert!(start.elapsed() < Duration::from_secs(10));
+        });
+    }
+
+    #[cfg(feature = "mysql")]
+    const TIMEOUT: &str = "SELECT CAST(@@max_execution_time AS CHAR) AS timeout";
+    #[cfg(feature = "postgres")]
+    const TIMEOUT: &str = "SELECT current_setting('statement_timeout') AS timeout";
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn sets_the_timeout_as_statement_timeout() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let options = RetryOptions {
+                timeout: Some(5000),
+                ..Default::default()
+            };
+            let rows = query_with_retry(&pool, TIMEOUT, options).await.unwrap();
+            #[cfg(feature = "mysql")]
+            assert_eq!(rows[0]["timeout"], "5000");
+            #[cfg(feature = "postgres")]
+            assert_eq!(rows[0]["timeout"], "5s");
+        });
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn overrides_the_default_statement_timeout_per_call() {
+        let url = match std::env::var("TAURI_PLUGIN_SQL_TEST_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let options = ConnectionOptions {
+            statement_timeout: Some(200),
+            ..Default::default()
+        };
+        let timeout = |timeout| RetryOptions {
+            timeout,
+            ..Default::default()
+        };
+
+        block_on(async {
+            let pool = connect(&url, &options).await.unwrap();
+            let rows = query_with_retry(&pool, TIMEOUT, timeout(None))
+                .await
+                .unwrap();
+            #[cfg(feature = "mysql")]
+            assert_eq!(rows[0]["timeout"], "200");
+            #[cfg(feature = "postgres")]
+            assert_eq!(rows[0]["timeout"], "200ms");
+
+            // MySQL's `SLEEP` returns early instead of failing at the timeout
+            #[cfg(feature = "postgres")]
+            {
+                let sleep = "SELECT pg_sleep(0.5)::text AS slept";
+                let result = query_with_retry(&pool, sleep, timeout(None)).await;
+                assert!(matches!(result, Err(Error::Sql(_))));
+                query_with_retry(&pool, sleep, timeout(Some(1000)))
+                    .await
+                    .unwrap();
+            }
+
+            let rows = query_with_retry(&pool, TIMEOUT, timeout(Some(1000)))
+                .await
+                .unwrap();
+            #[cfg(feature = "mysql")]
+            assert_eq!(rows[0]["timeout"], "1000");
+            #[cfg(feature = "postgres")]
+            assert_eq!(rows[0]["timeout"], "1s");
+            // the override doesn't outlast the call
+            let rows = query_with_retry(&pool, TIMEOUT, timeout(None))
+                .await
+                .unwrap();
+            #[cfg(feature = "mysql")]
+            assert_eq!(rows[0]["timeout"], "200");
+            #[cfg(feature = "postgres")]
+            assert_eq!(rows[0]["timeout"], "200ms");
+            pool.close().await;
+        });
+    }
+
+    #[test]
+    fn detects_closed_connections() {
+        use std::io::{Error as IoError, ErrorKind};
+        let io = |kind| sqlx::Error::Io(IoError::new(kind, "io"));
+        assert!(is_connection_closed(&io(ErrorKind::ConnectionReset)));
+        assert!(is_connection_closed(&io(ErrorKind::UnexpectedEof)));
+        assert!(!is_connection_closed(&io(ErrorKind::PermissionDenied)));
+        assert!(!is_connection_closed(&sqlx::Error::PoolTimedOut));
+        assert!(!is_connection_closed(&sqlx::Error::RowNotFound));
+    }
+
+    /// Closes the connection of a single connection pool from `other`.
+    #[cfg(feature = "postgres")]
+    async fn terminate(pool: &Pool<Db>, other: &mut sqlx::PgConnection) {
+        let pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        sqlx::query("SELECT pg_terminate_backend($1)")
+            .bind(pid)
+            .execute(&mut *other)
+            .await
+            .unwrap();
+        // the backend exits after the signal is sent
+        let query = "SELECT EXISTS (SELECT 1 FROM pg_stat_activity WHERE pid = $1)";
+        while sqlx::query_scalar(query)
+            .bind(pid)
+            .fetch_one(&mut *other)
+            .await
+            .unwrap()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn reconnects_a_select_after_its_connection_was_closed() {
+        use sqlx::Connection;
+
+        block_on(async {
+            // without the check on acquire, the query is the first to see the
+            // closed connection
+            let options = PoolOptions::new().test_before_acquire(false);
+            let pool = match pool_with(options).await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let url = std::env::var("TAURI_PLUGIN_SQL_TEST_URL").unwrap();
+            let mut other = sqlx::PgConnection::connect(&url).await.unwrap();
+            let options = DecodeOptions::default();
+            let select = |reconnect| {
+                fetch_rows_reconnecting(
+                    &pool,
+                    "SELECT 1 AS n",
+                    Vec::new(),
+                    &options,
+                    false,
+                    reconnect,
+                )
+            };
+
+            terminate(&pool, &mut other).await;
+            match select(false).await {
+                Err(Error::Sql(e)) => assert!(is_connection_closed(&e), "{e}"),
+                result => panic!("expected a closed connection, got {result:?}"),
+            }
+            terminate(&pool, &mut other).await;
+            let rows = select(true).await.unwrap();
+            assert_eq!(rows[0]["n"], 1);
+        });
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn decodes_regconfig_to_configuration_names() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            // there is no `to_regconfig`, and 0 isn't the OID of a
+            // configuration so it is kept
+            let query = "SELECT 'english'::regconfig AS english, \
+                'simple'::regconfig AS simple, 0::oid::regconfig AS unknown";
+            let rows = fetch_rows(&pool, query, Vec::new(), &Default::default(), false)
+                .await
+                .unwrap();
+            assert_eq!(rows[0]["english"], "english");
+            assert_eq!(rows[0]["simple"], "simple");
+            assert_eq!(rows[0]["unknown"], 0);
+        });
+    }
+
+    #[test]
+    fn clears_stale_cached_statements() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TABLE).await.unwrap();
+            let select = || sqlx::query("SELECT * FROM items");
+            let mut conn = pool.acquire().await.unwrap();
+            select().fetch_all(&mut *conn).await.unwrap();
+            // the column added to the result makes the cached plan stale
+            conn.execute("ALTER TABLE items ADD COLUMN extra INT")
+                .await
+                .unwrap();
+            #[cfg(feature = "postgres")]
+            match select().fetch_all(&mut *conn).await {
+                Err(sqlx::Error::Database(e)) => {
+                    assert_eq!(e.message(), "cached plan must not change result type")
+                }
+                result => panic!(
+                    "expected a stale plan, got {:?}",
+                    result.map(|rows| rows.len())
+                ),
+            }
+            conn.return_to_pool().await;
+
+            let cache = cached_statements(&pool).await;
+            assert_eq!(cache.connections, 1);
+            assert!(cache.statements >= 1);
+            let cleared = clear_cached_statements(&pool).await.unwrap();
+            assert_eq!(cleared.statements, cache.statements);
+            assert_eq!(cached_statements(&pool).await.statements, 0);
+
+            let mut conn = pool.acquire().await.unwrap();
+            select().fetch_all(&mut *conn).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn explains_select_queries() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TABLE).await.unwrap();
+            let (sql, values) = BindValues::Named(
+                serde_json::json!({ "name": "a" })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            )
+            .resolve("SELECT * FROM items WHERE name = :name".into())
+            .unwrap();
+
+            let plan = query_plan(&pool, &sql, values.clone()).await.unwrap();
+            assert!(!plan.is_empty());
+            #[cfg(feature = "sqlite")]
+            assert_eq!(plan[0]["detail"], "SCAN items");
+            #[cfg(feature = "mysql")]
+            assert_eq!(plan[0]["table"], "items");
+            #[cfg(feature = "postgres")]
+            assert!(plan[0]["QUERY PLAN"]
+                .as_str()
+                .unwrap()
+                .starts_with("Seq Scan on items"));
+
+            let query = "/* docs */ with names AS (SELECT name FROM items) SELECT * FROM names";
+            assert!(query_plan(&pool, query, Vec::new()).await.is_ok());
+            for query in [
+                "DELETE FROM items",
+                "-- SELECT\nUPDATE items SET name = 'b'",
+                "EXPLAIN ANALYZE SELECT 1",
+            ] {
+                assert!(matches!(
+                    query_plan(&pool, query, Vec::new()).await,
+                    Err(Error::ExplainNotSelect)
+                ));
+            }
+            assert_eq!(count(&pool).await, 0);
+        });
+    }
+
+    #[test]
+    fn warms_up_the_minimum_connections() {
+        #[cfg(feature = "sqlite")]
+        let url = "sqlite::memory:".to_string();
+        #[cfg(not(feature = "sqlite"))]
+        let url = match std::env::var("TAURI_PLUGIN_SQL_TEST_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let options = ConnectionOptions {
+            min_connections: Some(3),
+            warm_up: true,
+            ..Default::default()
+        };
+        block_on(async {
+            let pool = connect(&url, &options).await.unwrap();
+            // the pool may open one more in the background while they're held
+            assert!(pool.num_idle() >= 3);
+        });
+    }
+
+    #[test]
+    fn rejects_writes_in_read_only_selects() {
+        // writes to temporary tables are allowed in read-only transactions,
+        // and an in-memory SQLite database can't be opened by another connection
+        let table = format!("read_only_{}", std::process::id());
+        #[cfg(feature = "sqlite")]
+        let path = std::env::temp_dir().join(format!("tauri-plugin-sql-{table}.db"));
+
+        block_on(async {
+            #[cfg(feature = "sqlite")]
+            let pool = connect(&format!("sqlite:{}", path.display()), &Default::default())
+                .await
+                .unwrap();
+            #[cfg(not(feature = "sqlite"))]
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(format!("CREATE TABLE {table} (id INT, name TEXT)").as_str())
+                .await
+                .unwrap();
+            pool.execute(format!("INSERT INTO {table} VALUES (1, 'a')").as_str())
+                .await
+                .unwrap();
+
+            let options = DecodeOptions::default();
+            let update = format!("UPDATE {table} SET name = 'b'");
+            let updated = fetch_rows(&pool, &update, Vec::new(), &options, true).await;
+            let select = format!("SELECT name FROM {table}");
+            let rows = fetch_rows(&pool, &select, Vec::new(), &options, true).await;
+            pool.execute(format!("DROP TABLE {table}").as_str())
+                .await
+                .unwrap();
+            pool.close().await;
+
+            assert!(matches!(updated, Err(Error::Sql(sqlx::Error::Database(_)))));
+            assert_eq!(rows.unwrap()[0]["name"], "a");
+        });
+        // the read-only connection leaves the WAL files behind
+        #[cfg(feature = "sqlite")]
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[test]
+    fn returns_raw_text_instead_of_typed_values() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let query = "SELECT 12 AS n, 1.50 AS x";
+            let options = DecodeOptions::default();
+            let typed = fetch_rows(&pool, query, Vec::new(), &options, false)
+                .await
+                .unwrap();
+            assert_eq!(typed[0]["n"], 12);
+            #[cfg(feature = "postgres")]
+            assert_eq!(typed[0]["x"], "1.50");
+
+            let raw = fetch_raw_text(&pool, query, &options).await.unwrap();
+            assert_eq!(raw[0]["n"], "12");
+            #[cfg(not(feature = "sqlite"))]
+            assert_eq!(raw[0]["x"], "1.50");
+            // SQLite stores the literal as a real
+            #[cfg(feature = "sqlite")]
+            assert_eq!(raw[0]["x"], "1.5");
+        });
+    }
+
+    #[test]
+    fn disambiguates_the_duplicate_columns_of_a_self_join() {
+        let query = "WITH items AS (SELECT 1 AS id, 'a' AS name UNION ALL SELECT 2, 'b') \
+            SELECT a.id, a.name, b.id, b.name, a.id AS id_2 \
+            FROM items a JOIN items b ON b.id = a.id + 1";
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let options = DecodeOptions::default();
+            let rows = fetch_rows(&pool, query, Vec::new(), &options, false)
+                .await
+                .unwrap();
+            let row = serde_json::to_value(&rows[0]).unwrap();
+            assert_eq!(
+                row,
+                serde_json::json!({ "id": 1, "name": "a", "id_3": 2, "name_2": "b", "id_2": 1 })
+            );
+            let raw = fetch_raw_text(&pool, query, &options).await.unwrap();
+            assert_eq!(raw[0]["id_3"], "2");
+
+            let options = DecodeOptions {
+                duplicate_columns: crate::DuplicateColumns::Error,
+                ..Default::default()
+            };
+            let result = fetch_rows(&pool, query, Vec::new(), &options, false).await;
+            assert!(matches!(result, Err(Error::DuplicateColumn(name)) if name == "id"));
+        });
+    }
+
+    #[test]
+    fn checks_whether_a_query_returns_rows() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute(CREATE_TABLE).await.unwrap();
+            pool.execute("INSERT INTO items (name) VALUES ('a'), ('b')")
+                .await
+                .unwrap();
+            let exists = |name: &str| {
+                let values = vec![JsonValue::String(name.to_string())];
+                let query = format!(
+                    "SELECT * FROM items WHERE name = {}; -- by name",
+                    crate::statements::placeholder(1)
+                );
+                let pool = pool.clone();
+                async move { row_exists(&pool, &query, values).await.unwrap() }
+            };
+            assert!(exists("a").await);
+            assert!(!exists("c").await);
+
+            // stops at the first row, or it would never return
+            #[cfg(not(feature = "mysql"))]
+            {
+                let endless = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) \
+                    SELECT i FROM n";
+                assert!(row_exists(&pool, endless, Vec::new()).await.unwrap());
+            }
+        });
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn merges_jsonb_patches_into_nested_objects() {
+        use serde_json::json;
+
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            pool.execute("CREATE TEMPORARY TABLE docs (id TEXT, data JSONB)")
+                .await
+                .unwrap();
+            let original = json!({
+                "title": "a",
+                "tags": ["x"],
+                "meta": { "author": "ann", "stats": { "views": 1, "likes": 2 } },
+            });
+            sqlx::query("INSERT INTO docs VALUES ('a', $1), ('b', $1), ('c', NULL)")
+                .bind(&original)
+                .execute(&pool)
+                .await
+                .unwrap();
+            let patch = json!({
+                "title": null,
+                "tags": ["y"],
+                "meta": { "stats": { "views": 5, "likes": null }, "draft": true },
+            });
+            let merge = |id: &str, deep| {
+                let (pool, patch, id) = (pool.clone(), patch.clone(), id.to_string());
+                async move {
+                    let options = JsonbMergeOptions {
+                        where_clause: "id = $1".into(),
+                        values: vec![json!(id)],
+                        deep,
+                    };
+                    merge_jsonb(&pool, "docs", "data", patch, options)
+                        .await
+                        .unwrap()
+                }
+            };
+            let data = |id: &str| {
+                let (pool, id) = (pool.clone(), id.to_string());
+                async move {
+                    sqlx::query_scalar::<_, JsonValue>("SELECT data FROM docs WHERE id = $1")
+                        .bind(id)
+                        .fetch_one(&pool)
+                        .await
+                        .unwrap()
+                }
+            };
+
+            // nested objects are merged, arrays replaced and `null` keys removed
+            assert_eq!(merge("a", true).await, 1);
+            assert_eq!(
+                data("a").await,
+                json!({
+                    "tags": ["y"],
+                    "meta": { "author": "ann", "stats": { "views": 5 }, "draft": true },
+                })
+            );
+            assert_eq!(data("b").await, original);
+
+            // a shallow merge replaces every top-level key, keeping `null` values
+            merge("b", false).await;
+            assert_eq!(data("b").await, patch);
+
+            merge("c", true).await;
+            assert_eq!(
+                data("c").await,
+                json!({ "tags": ["y"], "meta": { "stats": { "views": 5 }, "draft": true } })
+            );
+
+            let options = JsonbMergeOptions {
+                where_clause: "true".into(),
+                values: Vec::new(),
+                deep: false,
+            };
+            let result = merge_jsonb(&pool, "docs", "data", json!([1]), options).await;
+            assert!(matches!(result, Err(Error::JsonbPatchNotObject)));
+        });
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn resets_the_session_state_of_idle_connections() {
+        #[cfg(feature = "postgres")]
+        const SET_VARIABLE: &str = "SET search_path TO pg_catalog";
+        #[cfg(feature = "mysql")]
+        const SET_VARIABLE: &str = "SET @leftover = 'dirty'";
+        #[cfg(feature = "postgres")]
+        const VARIABLE: &str = "SELECT current_setting('search_path')";
+        #[cfg(feature = "mysql")]
+        const VARIABLE: &str = "SELECT CAST(COALESCE(@leftover, '') AS CHAR)";
+
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            conn.execute(SET_VARIABLE).await.unwrap();
+            conn.execute("CREATE TEMPORARY TABLE leftover (id INT)")
+                .await
+                .unwrap();
+            conn.return_to_pool().await;
+
+            assert_eq!(reset_connections(&pool, Some(1234)).await.unwrap(), 1);
+            let variable: String = sqlx::query_scalar(VARIABLE).fetch_one(&pool).await.unwrap();
+            #[cfg(feature = "postgres")]
+            assert_eq!(variable, r#""$user", public"#);
+            #[cfg(feature = "mysql")]
+            assert_eq!(variable, "");
+            assert!(pool.execute("SELECT * FROM leftover").await.is_err());
+            // the default statement timeout is set again
+            #[cfg(feature = "postgres")]
+            {
+                let rows = fetch_rows(&pool, TIMEOUT, Vec::new(), &Default::default(), false)
+                    .await
+                    .unwrap();
+                assert_eq!(rows[0]["timeout"], "1234ms");
+            }
+        });
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn opens_an_encrypted_database_only_with_its_key() {
+        let path = std::env::temp_dir().join(format!("tauri-plugin-sql-{}.db", std::process::id()));
+        let url = format!("sqlite:{}", path.display());
+        let with_key = |key: Option<&str>| ConnectionOptions {
+            encryption_key: key.map(str::to_string),
+            ..Default::default()
+        };
+        let count = |options: ConnectionOptions| {
+            let url = url.clone();
+            async move {
+                let pool = connect(&url, &options).await?;
+                let count: i64 = sqlx::query_scalar("SELECT count(*) FROM items")
+                    .fetch_one(&pool)
+                    .await?;
+                pool.close().await;
+                Result::Ok(count)
+            }
+        };
+
+        block_on(async {
+            let pool = connect(&url, &with_key(Some("secret"))).await.unwrap();
+            sqlx::query(CREATE_TABLE).execute(&pool).await.unwrap();
+            sqlx::query("INSERT INTO items (name) VALUES ('a')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+
+            assert!(count(with_key(None)).await.is_err());
+            assert!(count(with_key(Some("wrong"))).await.is_err());
+            assert_eq!(count(with_key(Some("secret"))).await.unwrap(), 1);
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(all(feature = "sqlite", not(feature = "sqlcipher")))]
+    #[test]
+    fn rejects_an_encryption_key_without_sqlcipher() {
+        let options = ConnectionOptions {
+            encryption_key: Some("secret".into()),
+            ..Default::default()
+        };
+        block_on(async {
+            let result = connect("sqlite::memory:", &options).await;
+            assert!(matches!(result, Err(Error::EncryptionUnsupported)));
+        });
+    }
+
+    // The migrations table isn't temporary, so on servers the migrations run in
+    // a schema of their own, dropped afterwards.
+    #[cfg(feature = "postgres")]
+    fn migration_schema(name: &str) -> [String; 3] {
+        [
+            format!("DROP SCHEMA IF EXISTS {name} CASCADE"),
+            format!("CREATE SCHEMA {name}"),
+            format!("SET search_path TO {name}"),
+        ]
+    }
+    #[cfg(feature = "mysql")]
+    fn migration_schema(name: &str) -> [String; 3] {
+        [
+            format!("DROP DATABASE IF EXISTS {name}"),
+            format!("CREATE DATABASE {name}"),
+            format!("USE {name}"),
+        ]
+    }
+
+    #[test]
+    fn populates_an_added_column_in_an_after_migration_hook() {
+        let migrations = vec![
+            Migration {
+                version: 1,
+                description: "create notes",
+                sql: "CREATE TABLE notes (id INT PRIMARY KEY, title TEXT);
+                      INSERT INTO notes (id, title) VALUES (1, 'Hello World')",
+                kind: MigrationKind::Up,
+            },
+            Migration {
+                version: 2,
+                description: "add note slugs",
+                sql: "ALTER TABLE notes ADD COLUMN slug TEXT",
+                kind: MigrationKind::Up,
+            },
+        ];
+        let mut builder = Builder::default()
+            .add_migrations("db", migrations)
+            .after_migration("db", 2, |conn| {
+                Box::pin(async move {
+                    conn.execute("UPDATE notes SET slug = lower(replace(title, ' ', '-'))")
+                        .await?;
+                    Ok(())
+                })
+            });
+        let migrations = builder.migrations.take().unwrap().remove("db").unwrap();
+
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            #[cfg(not(feature = "sqlite"))]
+            for statement in migration_schema("migration_hooks") {
+                pool.execute(statement.as_str()).await.unwrap();
+            }
+
+            let mut versions = Vec::new();
+            apply_migrations(&pool, migrations, |step| versions.push(step.version))
+                .await
+                .unwrap();
+            assert_eq!(versions, [1, 2]);
+            let slug: String = sqlx::query_scalar("SELECT slug FROM notes WHERE id = 1")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(slug, "hello-world");
+
+            #[cfg(not(feature = "sqlite"))]
+            pool.execute(migration_schema("migration_hooks")[0].as_str())
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn records_migration_durations_including_hooks() {
+        let migrations = vec![
+            Migration {
+                version: 1,
+                description: "create notes",
+                sql: "CREATE TABLE notes (id INT PRIMARY KEY)",
+                kind: MigrationKind::Up,
+            },
+            Migration {
+                version: 2,
+                description: "add note titles",
+                sql: "ALTER TABLE notes ADD COLUMN title TEXT",
+                kind: MigrationKind::Up,
+            },
+        ];
+        let mut builder = Builder::default()
+            .add_migrations("db", migrations)
+            .after_migration("db", 2, |_| {
+                Box::pin(async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(())
+                })
+            });
+        let migrations = builder.migrations.take().unwrap().remove("db").unwrap();
+
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            #[cfg(not(feature = "sqlite"))]
+            for statement in migration_schema("migration_history") {
+                pool.execute(statement.as_str()).await.unwrap();
+            }
+
+            assert!(applied_migrations(&pool).await.unwrap().is_empty());
+            apply_migrations(&pool, migrations, |_| {}).await.unwrap();
+            let history = applied_migrations(&pool).await.unwrap();
+            let versions = history.iter().map(|m| m.version).collect::<Vec<_>>();
+            assert_eq!(versions, [1, 2]);
+            assert!(history.iter().all(|m| m.success));
+            assert!(history[0].execution_time_ms.unwrap() >= 0.0);
+            assert!(history[1].execution_time_ms.unwrap() >= 50.0);
+
+            #[cfg(not(feature = "sqlite"))]
+            pool.execute(migration_schema("migration_history")[0].as_str())
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn reports_the_progress_of_each_pending_migration() {
+        let migration = |version, sql| Migration {
+            version,
+            description: "step",
+            sql,
+            kind: MigrationKind::Up,
+        };
+        let migrations = |versions: &[(i64, &'static str)]| MigrationList {
+            migrations: versions
+                .iter()
+                .map(|(version, sql)| migration(*version, *sql))
+                .collect(),
+            progress: true,
+            ..Default::default()
+        };
+        let create = "CREATE TABLE steps (id INT)";
+        let insert = "INSERT INTO steps VALUES (1)";
+        // without `elapsedMs`, which varies
+        let events = |events: Vec<JsonValue>| {
+            events
+                .into_iter()
+                .map(|mut event| {
+                    assert!(event["elapsedMs"].as_f64().unwrap() >= 0.0);
+                    event.as_object_mut().unwrap().remove("elapsedMs");
+                    event
+                })
+                .collect::<Vec<_>>()
+        };
+
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            #[cfg(not(feature = "sqlite"))]
+            for statement in migration_schema("migration_progress") {
+                pool.execute(statement.as_str()).await.unwrap();
+            }
+
+            apply_migrations(&pool, migrations(&[(1, create)]), |_| {})
+                .await
+                .unwrap();
+            let mut emitted = Vec::new();
+            let list = migrations(&[(1, create), (2, insert), (3, insert)]);
+            report_migrations("db", &pool, list, |progress| {
+                emitted.push(serde_json::to_value(progress).unwrap())
+            })
+            .await
+            .unwrap();
+            let applying = |version, index| {
+                serde_json::json!({
+                    "status": "applying",
+                    "db": "db",
+                    "version": version,
+                    "description": "step",
+                    "index": index,
+                    "total": 2,
+                })
+            };
+            assert_eq!(
+                events(emitted),
+                [
+                    applying(2, 1),
+                    applying(3, 2),
+                    serde_json::json!({ "status": "completed", "db": "db", "applied": 2 }),
+                ]
+            );
+
+            let mut emitted = Vec::new();
+            let list = migrations(&[
+                (1, create),
+                (2, insert),
+                (3, insert),
+                (4, "INSERT INTO missing VALUES (1)"),
+            ]);
+            let result = report_migrations("db", &pool, list, |progress| {
+                emitted.push(serde_json::to_value(progress).unwrap())
+            })
+            .await;
+            assert!(result.is_err());
+            let emitted = events(emitted);
+            assert_eq!(emitted.len(), 2);
+            assert_eq!(emitted[0]["version"], 4);
+            assert_eq!(emitted[1]["status"], "failed");
+            assert_eq!(emitted[1]["version"], 4);
+
+            #[cfg(not(feature = "sqlite"))]
+            pool.execute(migration_schema("migration_progress")[0].as_str())
+                .await
+                .unwrap();
+        });
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn adds_an_existing_socket_to_the_url() {
+        let dir = std::env::temp_dir().join(format!("tauri plugin sql {}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        #[cfg(feature = "mysql")]
+        let param = "socket";
+        #[cfg(feature = "postgres")]
+        let param = "host";
+
+        let url = with_socket("db://localhost/app?ssl-mode=disable", &dir).unwrap();
+        let encoded = dir.display().to_string().replace(' ', "%20");
+        assert_eq!(
+            url,
+            format!("db://localhost/app?ssl-mode=disable&{param}={encoded}")
+        );
+        std::fs::remove_dir(&dir).unwrap();
+
+        assert!(matches!(
+            with_socket("db://localhost/app", &dir),
+            Err(Error::SocketNotFound(path)) if path == dir
+        ));
+    }
+
+    // Runs when `TAURI_PLUGIN_SQL_TEST_SOCKET` is set to the server's socket.
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn connects_through_a_socket() {
+        let (url, socket) = match (
+            std::env::var("TAURI_PLUGIN_SQL_TEST_URL"),
+            std::env::var("TAURI_PLUGIN_SQL_TEST_SOCKET"),
+        ) {
+            (Ok(url), Ok(socket)) => (url, socket),
+            _ => return,
+        };
+        let options = ConnectionOptions {
+            socket: Some(socket.into()),
+            ..Default::default()
+        };
+        block_on(async {
+            let pool = connect(&url, &options).await.unwrap();
+            let one: i32 = sqlx::query_scalar("SELECT 1")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(one, 1);
+            // a client connected through a socket has no address
+            #[cfg(feature = "postgres")]
+            {
+                let local: bool = sqlx::query_scalar("SELECT inet_client_addr() IS NULL")
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+                assert!(local);
+            }
+            pool.close().await;
+        });
+    }
+}
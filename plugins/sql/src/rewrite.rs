@@ -0,0 +1,426 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::Error;
+
+/// Rewrites `:name` placeholders into the driver's positional syntax and
+/// returns the values in bind order.
+///
+/// Postgres and SQLite support numbered placeholders, so every distinct name
+/// is bound once and reused as `$n`. MySQL only understands `?`, so the
+/// shared value is bound again for each occurrence.
+///
+/// Placeholders inside string literals, quoted identifiers and comments are
+/// left untouched, as are Postgres `::type` casts and the upper bounds of
+/// array slices such as `arr[lo:hi]`.
+pub(crate) fn expand_named(
+    query: &str,
+    params: &Map<String, JsonValue>,
+) -> Result<(String, Vec<JsonValue>), Error> {
+    let bytes = query.as_bytes();
+    let mut sql = String::with_capacity(query.len());
+    #[cfg(not(feature = "mysql"))]
+    let mut names: Vec<&str> = Vec::new();
+    let mut values = Vec::new();
+    #[cfg(feature = "postgres")]
+    let mut brackets = 0_usize;
+
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => i = skip_quoted(bytes, i, quote),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_past(bytes, i + 2, b"\n"),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_past(bytes, i + 2, b"*/"),
+            #[cfg(feature = "postgres")]
+            b'$' => i = skip_dollar_quoted(bytes, i),
+            // SQLite quotes identifiers in brackets, Postgres subscripts arrays
+            #[cfg(feature = "sqlite")]
+            b'[' => i = skip_past(bytes, i + 1, b"]"),
+            #[cfg(feature = "postgres")]
+            b'[' => {
+                brackets += 1;
+                i += 1;
+            }
+            #[cfg(feature = "postgres")]
+            b']' => {
+                brackets = brackets.saturating_sub(1);
+                i += 1;
+            }
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            #[cfg(feature = "postgres")]
+            b':' if brackets > 0 && follows_operand(&bytes[..i]) => i += 1,
+            b':' if bytes.get(i + 1).map_or(false, |b| is_ident_start(*b)) => {
+                let start = i + 1;
+                let mut end = start;
+                while end < bytes.len() && is_ident_char(bytes[end]) {
+                    end += 1;
+                }
+                let name = &query[start..end];
+                let value = params
+                    .get(name)
+                    .ok_or_else(|| Error::MissingParameter(name.to_string()))?;
+
+                sql.push_str(&query[copied..i]);
+                #[cfg(feature = "mysql")]
+                {
+                    sql.push('?');
+                    values.push(value.clone());
+                }
+                #[cfg(not(feature = "mysql"))]
+                {
+                    let index = match names.iter().position(|n| *n == name) {
+                        Some(index) => index,
+                        None => {
+                            names.push(name);
+                            values.push(value.clone());
+                            names.len() - 1
+                        }
+                    };
+                    sql.push('$');
+                    sql.push_str(&(index + 1).to_string());
+                }
+
+                copied = end;
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    sql.push_str(&query[copied..]);
+
+    Ok((sql, values))
+}
+
+/// Replaces every occurrence of the placeholder, e.g. `$2` or `:file`, with
+/// `replacement`, skipping the same literals, comments, casts and slice
+/// bounds as [`expand_named`].
+#[cfg(feature = "postgres")]
+pub(crate) fn replace_placeholder(query: &str, placeholder: &str, replacement: &str) -> String {
+    let bytes = query.as_bytes();
+    let mut sql = String::with_capacity(query.len() + replacement.len());
+    let mut brackets = 0_usize;
+
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let end = i + placeholder.len();
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => i = skip_quoted(bytes, i, quote),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_past(bytes, i + 2, b"\n"),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_past(bytes, i + 2, b"*/"),
+            b'[' => {
+                brackets += 1;
+                i += 1;
+            }
+            b']' => {
+                brackets = brackets.saturating_sub(1);
+                i += 1;
+            }
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            b':' if brackets > 0 && follows_operand(&bytes[..i]) => i += 1,
+            b'$' | b':'
+                if bytes[i..].starts_with(placeholder.as_bytes())
+                    && !bytes.get(end).map_or(false, |b| is_ident_char(*b)) =>
+            {
+                sql.push_str(&query[copied..i]);
+                sql.push_str(replacement);
+                copied = end;
+                i = end;
+            }
+            b'$' => i = skip_dollar_quoted(bytes, i),
+            _ => i += 1,
+        }
+    }
+    sql.push_str(&query[copied..]);
+
+    sql
+}
+
+/// Removes the `;` ending `query` if only whitespace and comments follow it,
+/// e.g. to wrap the query in parentheses.
+pub(crate) fn strip_trailing_semicolon(query: &str) -> String {
+    let bytes = query.as_bytes();
+    let mut semicolon = None;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_past(bytes, i + 2, b"\n"),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_past(bytes, i + 2, b"*/"),
+            b';' => {
+                semicolon = Some(i);
+                i += 1;
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            b => {
+                semicolon = None;
+                i = match b {
+                    b'\'' | b'"' | b'`' => skip_quoted(bytes, i, b),
+                    #[cfg(feature = "postgres")]
+                    b'$' => skip_dollar_quoted(bytes, i),
+                    _ => i + 1,
+                };
+            }
+        }
+    }
+
+    match semicolon {
+        Some(i) => format!("{}{}", &query[..i], &query[i + 1..]),
+        None => query.to_string(),
+    }
+}
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_ident_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Whether `before` ends with an operand, an identifier, a number or a closing
+/// bracket, so a `:` after it separates the bounds of a slice.
+#[cfg(feature = "postgres")]
+fn follows_operand(before: &[u8]) -> bool {
+    before
+        .iter()
+        .rev()
+        .find(|b| !b.is_ascii_whitespace())
+        .map_or(false, |b| is_ident_char(*b) || matches!(b, b']' | b')'))
+}
+
+/// Returns the index right after the closing `quote`. Doubled quotes are
+/// handled implicitly as two adjacent literals.
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        // MySQL allows backslash escapes inside literals
+        #[cfg(feature = "mysql")]
+        if bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Returns the index right after the next occurrence of `end`.
+fn skip_past(bytes: &[u8], start: usize, end: &[u8]) -> usize {
+    bytes[start.min(bytes.len())..]
+        .windows(end.len())
+        .position(|w| w == end)
+        .map_or(bytes.len(), |p| start + p + end.len())
+}
+
+/// Skips a `$tag$ ... $tag$` string; positional `$1` placeholders are not
+/// dollar quotes and are stepped over.
+#[cfg(feature = "postgres")]
+fn skip_dollar_quoted(bytes: &[u8], start: usize) -> usize {
+    let mut end = start + 1;
+    if bytes.get(end).map_or(false, |b| is_ident_start(*b)) {
+        while end < bytes.len() && is_ident_char(bytes[end]) {
+            end += 1;
+        }
+    }
+    if bytes.get(end) != Some(&b'$') {
+        return start + 1;
+    }
+    let tag = &bytes[start..=end];
+    skip_past(bytes, end + 1, tag)
+}
+
+/// Prefixes `query` with `label` as a `/* label */` comment, so the database
+/// shows which part of the app issued it, e.g. in Postgres'
+/// `pg_stat_activity`. The label can't end the comment early: `*/` and `/*`,
+/// which opens a nested comment on Postgres, are split with a space, and
+/// control characters are replaced with spaces. The space after `/*` keeps
+/// MySQL from reading the label as a `/*!` executable comment.
+pub(crate) fn label_query(query: &str, label: &str) -> String {
+    let mut sanitized = String::with_capacity(label.len());
+    for c in label.chars() {
+        let c = if c.is_control() { ' ' } else { c };
+        if matches!(
+            (sanitized.chars().last(), c),
+            (Some('*'), '/') | (Some('/'), '*')
+        ) {
+            sanitized.push(' ');
+        }
+        sanitized.push(c);
+    }
+    format!("/* {sanitized} */ {query}")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as JsonValue};
+
+    use super::*;
+
+    fn expand(query: &str, params: JsonValue) -> (String, Vec<JsonValue>) {
+        expand_named(query, params.as_object().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reuses_named_parameters() {
+        let (sql, values) = expand(
+            "SELECT * FROM t WHERE a = :a AND b = :b OR a = :a",
+            json!({ "a": 1, "b": "x" }),
+        );
+        #[cfg(not(feature = "mysql"))]
+        {
+            assert_eq!(sql, "SELECT * FROM t WHERE a = $1 AND b = $2 OR a = $1");
+            assert_eq!(values, [json!(1), json!("x")]);
+        }
+        #[cfg(feature = "mysql")]
+        {
+            assert_eq!(sql, "SELECT * FROM t WHERE a = ? AND b = ? OR a = ?");
+            assert_eq!(values, [json!(1), json!("x"), json!(1)]);
+        }
+    }
+
+    #[test]
+    fn skips_casts() {
+        let (sql, _) = expand("SELECT :a::int, x::text", json!({ "a": 1 }));
+        #[cfg(not(feature = "mysql"))]
+        assert_eq!(sql, "SELECT $1::int, x::text");
+        #[cfg(feature = "mysql")]
+        assert_eq!(sql, "SELECT ?::int, x::text");
+    }
+
+    #[test]
+    fn skips_strings_and_comments() {
+        let query = "SELECT ':a', \":a\", `:a` -- :a\n/* :a */";
+        assert_eq!(expand(query, json!({})), (query.to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn reports_missing_parameters() {
+        let params = json!({ "a": 1 });
+        assert!(matches!(
+            expand_named("SELECT :a, :b", params.as_object().unwrap()),
+            Err(Error::MissingParameter(name)) if name == "b"
+        ));
+    }
+
+    #[test]
+    fn labels_queries() {
+        assert_eq!(
+            label_query("SELECT 1", "settings page"),
+            "/* settings page */ SELECT 1"
+        );
+    }
+
+    #[test]
+    fn neutralizes_comment_delimiters_in_labels() {
+        assert_eq!(
+            label_query("SELECT 1", "a */ DROP TABLE users; /* b"),
+            "/* a * / DROP TABLE users; / * b */ SELECT 1"
+        );
+        assert_eq!(label_query("SELECT 1", "*/*/"), "/* * / * / */ SELECT 1");
+        assert_eq!(
+            label_query("SELECT 1", "line\n-- break"),
+            "/* line -- break */ SELECT 1"
+        );
+    }
+
+    #[test]
+    fn runs_labeled_queries() {
+        use sqlx::Executor;
+
+        crate::testing::block_on(async {
+            let pool = match crate::testing::pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let query = label_query("SELECT 1", "*/ SELECT 2; /*");
+            let rows = pool.fetch_all(query.as_str()).await.unwrap();
+            assert_eq!(rows.len(), 1);
+        });
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn skips_slice_bounds() {
+        let (sql, values) = expand(
+            "SELECT arr[lo:hi], arr[:lo:hi], arr[1:2], arr[f(x) : hi], ARRAY[:a, :b]",
+            json!({ "lo": 1, "a": 2, "b": 3 }),
+        );
+        assert_eq!(
+            sql,
+            "SELECT arr[lo:hi], arr[$1:hi], arr[1:2], arr[f(x) : hi], ARRAY[$2, $3]"
+        );
+        assert_eq!(values, [json!(1), json!(2), json!(3)]);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn skips_dollar_quotes() {
+        let query = "SELECT $$:a$$, $tag$:a$tag$, $1";
+        assert_eq!(expand(query, json!({})).0, query);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn replaces_placeholders() {
+        assert_eq!(
+            replace_placeholder("SELECT $1, $12, '$1', $$ $1 $$, f($1)", "$1", "g($1)"),
+            "SELECT g($1), $12, '$1', $$ $1 $$, f(g($1))"
+        );
+        assert_eq!(
+            replace_placeholder("SELECT :file, :files, x::file, arr[lo:file]", ":file", "?"),
+            "SELECT ?, :files, x::file, arr[lo:file]"
+        );
+    }
+
+    #[test]
+    fn strips_a_trailing_semicolon_before_comments() {
+        assert_eq!(strip_trailing_semicolon("SELECT 1;\n"), "SELECT 1\n");
+        assert_eq!(
+            strip_trailing_semicolon("SELECT 1; -- one; /* */"),
+            "SELECT 1 -- one; /* */"
+        );
+        assert_eq!(
+            strip_trailing_semicolon("SELECT 1 /* ; */"),
+            "SELECT 1 /* ; */"
+        );
+        assert_eq!(strip_trailing_semicolon("SELECT ';'"), "SELECT ';'");
+        assert_eq!(
+            strip_trailing_semicolon("SELECT 1; SELECT 2"),
+            "SELECT 1; SELECT 2"
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn skips_bracket_identifiers() {
+        let query = "SELECT [a:b] FROM t";
+        assert_eq!(expand(query, json!({})).0, query);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn binds_a_reused_value_once() {
+        use sqlx::Row;
+
+        crate::testing::block_on(async {
+            let pool = crate::testing::pool().await.unwrap();
+            let mut conn = pool.acquire().await.unwrap();
+            let (sql, values) = expand("SELECT :v AS a, :v || '-' || :v AS b", json!({ "v": "x" }));
+            assert_eq!(values.len(), 1);
+            let mut values = crate::bind::bind_values(&mut conn, &sql, values)
+                .await
+                .unwrap();
+            let row = values.query().fetch_one(&mut *conn).await.unwrap();
+            assert_eq!(row.get::<String, _>("a"), "x");
+            assert_eq!(row.get::<String, _>("b"), "x-x");
+        });
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! SQL generated by the plugin from frontend supplied table and column names.
+
+use crate::Error;
+
+/// The maximum number of bound parameters in a single statement.
+#[cfg(feature = "sqlite")]
+pub(crate) const MAX_PARAMETERS: usize = 32766;
+#[cfg(not(feature = "sqlite"))]
+pub(crate) const MAX_PARAMETERS: usize = 65535;
+
+/// Validates an identifier, optionally schema qualified (`schema.table`), and
+/// quotes each of its parts. Only letters, digits, `_` and `$` are allowed, so
+/// quoting can't be escaped.
+pub(crate) fn quote_identifier(identifier: &str) -> Result<String, Error> {
+    let valid = |part: &str| {
+        part.chars()
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    };
+
+    let parts = identifier.split('.').collect::<Vec<_>>();
+    if parts.len() > 2 || !parts.iter().all(|part| valid(part)) {
+        return Err(Error::InvalidIdentifier(identifier.to_string()));
+    }
+
+    Ok(parts
+        .iter()
+        .map(|part| format!("{QUOTE}{part}{QUOTE}"))
+        .collect::<Vec<_>>()
+        .join("."))
+}
+
+#[cfg(feature = "mysql")]
+const QUOTE: char = '`';
+#[cfg(not(feature = "mysql"))]
+const QUOTE: char = '"';
+
+/// The `n`th (1-based) positional placeholder.
+#[cfg(feature = "mysql")]
+pub(crate) fn placeholder(_n: usize) -> String {
+    "?".to_string()
+}
+
+/// The `n`th (1-based) positional placeholder.
+#[cfg(not(feature = "mysql"))]
+pub(crate) fn placeholder(n: usize) -> String {
+    format!("${n}")
+}
+
+/// Builds `INSERT INTO table (columns) VALUES (...), (...)` for `rows` rows,
+/// with the placeholders numbered from 1.
+pub(crate) fn insert(table: &str, columns: &[String], rows: usize) -> Result<String, Error> {
+    let table = quote_identifier(table)?;
+    let quoted_columns = columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Result<Vec<_>, _>>()?
+        .join(", ");
+
+    let values = (0..rows)
+        .map(|row| {
+            let placeholders = (1..=columns.len())
+                .map(|column| placeholder(row * columns.len() + column))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("({placeholders})")
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Ok(format!(
+        "INSERT INTO {table} ({quoted_columns}) VALUES {values}"
+    ))
+}
+
+/// Checks that there is at least one conflict column and that they are all
+/// upserted columns.
+pub(crate) fn check_conflict_columns(
+    columns: &[String],
+    conflict_columns: &[String],
+) -> Result<(), Error> {
+    if conflict_columns.is_empty() {
+        return Err(Error::MissingConflictColumns);
+    }
+    if let Some(column) = conflict_columns.iter().find(|c| !columns.contains(c)) {
+        return Err(Error::UnknownConflictColumn(column.clone()));
+    }
+    Ok(())
+}
+
+/// Builds a multi-row `INSERT` like [`insert`], updating the rows that conflict
+/// on `conflict_columns` with the inserted values of the other columns.
+pub(crate) fn upsert(
+    table: &str,
+    columns: &[String],
+    conflict_columns: &[String],
+    rows: usize,
+) -> Result<String, Error> {
+    check_conflict_columns(columns, conflict_columns)?;
+
+    let insert = insert(table, columns, rows)?;
+    let updated = columns
+        .iter()
+        .filter(|column| !conflict_columns.contains(column))
+        .map(|column| quote_identifier(column))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // MySQL updates the rows conflicting on any unique key, it has no conflict
+    // target. Setting a conflict column to itself leaves the row unchanged.
+    #[cfg(feature = "mysql")]
+    let query = {
+        let updates = if updated.is_empty() {
+            let column = quote_identifier(&conflict_columns[0])?;
+            format!("{column} = {column}")
+        } else {
+            updated
+                .iter()
+                .map(|column| format!("{column} = VALUES({column})"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{insert} ON DUPLICATE KEY UPDATE {updates}")
+    };
+    #[cfg(not(feature = "mysql"))]
+    let query = {
+        let target = conflict_columns
+            .iter()
+            .map(|column| quote_identifier(column))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(", ");
+        if updated.is_empty() {
+            format!("{insert} ON CONFLICT ({target}) DO NOTHING")
+        } else {
+            let updates = updated
+                .iter()
+                .map(|column| format!("{column} = excluded.{column}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{insert} ON CONFLICT ({target}) DO UPDATE SET {updates}")
+        }
+    };
+
+    Ok(query)
+}
+
+/// Wraps a select query in `SELECT EXISTS (...)`, which stops at the first row
+/// the query returns. A trailing `;` is dropped, and the query is put on its own
+/// lines so a trailing `--` comment can't comment out the closing parenthesis.
+pub(crate) fn exists(query: &str) -> String {
+    let query = crate::rewrite::strip_trailing_semicolon(query);
+    format!("SELECT EXISTS (\n{query}\n)")
+}
+
+/// Builds the statement returning the plan of a select query, without
+/// executing it. Other statements are rejected, as `EXPLAIN` accepts writes
+/// on MySQL and Postgres too.
+pub(crate) fn explain(query: &str) -> Result<String, Error> {
+    if !is_select(query) {
+        return Err(Error::ExplainNotSelect);
+    }
+    #[cfg(feature = "sqlite")]
+    let query = format!("EXPLAIN QUERY PLAN {query}");
+    #[cfg(not(feature = "sqlite"))]
+    let query = format!("EXPLAIN {query}");
+    Ok(query)
+}
+
+/// Whether the first keyword of a query, after whitespace and comments, is
+/// `SELECT` or `WITH`.
+fn is_select(mut query: &str) -> bool {
+    loop {
+        query = query.trim_start();
+        if let Some(rest) = query.strip_prefix("--") {
+            query = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = query.strip_prefix("/*") {
+            query = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            break;
+        }
+    }
+    let keyword = query
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    keyword.eq_ignore_ascii_case("select") || keyword.eq_ignore_ascii_case("with")
+}
+
+/// Builds the statement removing every row of `table`, restarting its identity
+/// columns where the backend can do it in the same statement.
+pub(crate) fn truncate(table: &str) -> Result<String, Error> {
+    let table = quote_identifier(table)?;
+    #[cfg(feature = "sqlite")]
+    let query = format!("DELETE FROM {table}");
+    #[cfg(feature = "mysql")]
+    let query = format!("TRUNCATE TABLE {table}");
+    #[cfg(feature = "postgres")]
+    let query = format!("TRUNCATE TABLE {table} RESTART IDENTITY");
+    Ok(query)
+}
+
+/// Builds the statement setting the timeout of the session's statements, in
+/// milliseconds.
+#[cfg(feature = "mysql")]
+pub(crate) fn statement_timeout(timeout: u64) -> String {
+    format!("SET SESSION max_execution_time = {timeout}")
+}
+
+/// Builds the statement setting the timeout of the session's statements, in
+/// milliseconds.
+#[cfg(feature = "postgres")]
+pub(crate) fn statement_timeout(timeout: u64) -> String {
+    format!("SET statement_timeout = {timeout}")
+}
+
+/// Builds `UPDATE table SET column = ... WHERE where_clause`, merging the patch
+/// bound to placeholder `patch_parameter` into the `jsonb` column. A shallow
+/// merge is `column || patch`. A deep merge follows JSON Merge Patch
+/// (RFC 7396): nested objects are merged into the existing ones and `null`
+/// removes a key. The keys of the patch's nested objects are bound rather than
+/// written into the SQL, numbered after the patch, and returned in bind order.
+#[cfg(feature = "postgres")]
+pub(crate) fn jsonb_merge(
+    table: &str,
+    column: &str,
+    patch: &serde_json::Map<String, serde_json::Value>,
+    deep: bool,
+    where_clause: &str,
+    patch_parameter: usize,
+) -> Result<(String, Vec<String>), Error> {
+    let table = quote_identifier(table)?;
+    let column = quote_identifier(column)?;
+    let patch_placeholder = format!("{}::jsonb", placeholder(patch_parameter));
+
+    let mut keys = Vec::new();
+    let value = if deep {
+        merge_patch(
+            &column,
+            &patch_placeholder,
+            patch,
+            patch_parameter,
+            &mut keys,
+        )
+    } else {
+        format!("COALESCE({column}, '{{}}'::jsonb) || {patch_placeholder}")
+    };
+    Ok((
+        format!("UPDATE {table} SET {column} = {value} WHERE {where_clause}"),
+        keys,
+    ))
+}
+
+/// The JSON Merge Patch of `target` with the object `patch_sql`, which is
+/// `patch`: its `null` values are removed from the target, its other values
+/// that aren't objects replace the target's, and its nested objects are merged
+/// the same way, with their keys pushed to `keys`.
+#[cfg(feature = "postgres")]
+fn merge_patch(
+    target: &str,
+    patch_sql: &str,
+    patch: &serde_json::Map<String, serde_json::Value>,
+    patch_parameter: usize,
+    keys: &mut Vec<String>,
+) -> String {
+    let mut sql = format!(
+        "((CASE WHEN jsonb_typeof({target}) = 'object' THEN {target} ELSE '{{}}'::jsonb END) \
+        - ARRAY(SELECT key FROM jsonb_each({patch_sql}) WHERE jsonb_typeof(value) = 'null')) \
+        || COALESCE((SELECT jsonb_object_agg(key, value) FROM jsonb_each({patch_sql}) \
+        WHERE jsonb_typeof(value) NOT IN ('null', 'object')), '{{}}'::jsonb)"
+    );
+    for (key, value) in patch {
+        if let serde_json::Value::Object(nested) = value {
+            keys.push(key.clone());
+            let key = format!("{}::text", placeholder(patch_parameter + keys.len()));
+            let nested = merge_patch(
+                &format!("({target} -> {key})"),
+                &format!("({patch_sql} -> {key})"),
+                nested,
+                patch_parameter,
+                keys,
+            );
+            sql = format!("({sql}) || jsonb_build_object({key}, {nested})");
+        }
+    }
+    sql
+}
+
+/// Builds a `jsonb` path expression like `"column" -> $1::text ->> $2::int`
+/// from a path of object keys (strings) and array indices (integers), with the
+/// placeholders numbered from `first_parameter`. The segments are bound rather
+/// than written into the SQL, and returned in bind order.
+#[cfg(feature = "postgres")]
+pub(crate) fn json_path(
+    column: &str,
+    path: &[serde_json::Value],
+    as_text: bool,
+    first_parameter: usize,
+) -> Result<String, Error> {
+    use serde_json::Value;
+
+    let mut sql = quote_identifier(column)?;
+    if path.is_empty() {
+        return Err(Error::InvalidJsonPath("the path is empty".into()));
+    }
+    let last = path.len() - 1;
+    for (index, segment) in path.iter().enumerate() {
+        let ty = match segment {
+            Value::String(_) => "text",
+            Value::Number(n) if n.as_i64().map_or(false, |n| i32::try_from(n).is_ok()) => "int",
+            _ => {
+                return Err(Error::InvalidJsonPath(format!(
+                    "segment {index} is not a key or an array index: {segment}"
+                )))
+            }
+        };
+        let operator = if as_text && index == last {
+            "->>"
+        } else {
+            "->"
+        };
+        let placeholder = placeholder(first_parameter + index);
+        sql.push_str(&format!(" {operator} {placeholder}::{ty}"));
+    }
+    Ok(sql)
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use serde_json::json;
+    use sqlx::Executor;
+
+    use super::*;
+    use crate::testing::{block_on, pool};
+
+    #[test]
+    fn builds_json_paths() {
+        let path = [json!("a"), json!(0), json!("b")];
+        assert_eq!(
+            json_path("doc", &path, true, 2).unwrap(),
+            r#""doc" -> $2::text -> $3::int ->> $4::text"#
+        );
+        assert!(matches!(
+            json_path("doc", &[], false, 1),
+            Err(Error::InvalidJsonPath(_))
+        ));
+        for segment in [
+            json!(1.5),
+            json!(true),
+            json!(null),
+            json!(["a"]),
+            json!(1_u64 << 40),
+        ] {
+            assert!(matches!(
+                json_path("doc", &[json!("a"), segment], false, 1),
+                Err(Error::InvalidJsonPath(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn extracts_nested_values_with_a_json_path() {
+        block_on(async {
+            let pool = match pool().await {
+                Some(pool) => pool,
+                None => return,
+            };
+            let mut conn = pool.acquire().await.unwrap();
+            conn.execute("CREATE TEMPORARY TABLE docs (doc jsonb)")
+                .await
+                .unwrap();
+            conn.execute(r#"INSERT INTO docs VALUES ('{"a": {"b": [10, {"c": "it''s"}]}}')"#)
+                .await
+                .unwrap();
+
+            let path = vec![json!("a"), json!("b"), json!(1), json!("c")];
+            let query = format!(
+                "SELECT {} FROM docs",
+                json_path("doc", &path, true, 1).unwrap()
+            );
+            let value: Option<String> = crate::bind::bind_values(&mut conn, &query, path)
+                .await
+                .unwrap()
+                .query()
+                .try_map(|row| sqlx::Row::try_get(&row, 0))
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+            assert_eq!(value.as_deref(), Some("it's"));
+        });
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! The database the tests run against: an in-memory one on SQLite, and on
+//! MySQL and Postgres the server at `TAURI_PLUGIN_SQL_TEST_URL`. Without it,
+//! the tests that need a server are skipped. Connecting through a Unix domain
+//! socket is tested when `TAURI_PLUGIN_SQL_TEST_SOCKET` is set to its path.
+
+use std::future::Future;
+
+use sqlx::{pool::PoolOptions, Pool};
+
+use crate::plugin::Db;
+
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    tauri::async_runtime::block_on(future)
+}
+
+pub(crate) async fn pool() -> Option<Pool<Db>> {
+    pool_with(PoolOptions::new()).await
+}
+
+pub(crate) async fn pool_with(options: PoolOptions<Db>) -> Option<Pool<Db>> {
+    #[cfg(feature = "sqlite")]
+    let url = "sqlite::memory:".to_string();
+    #[cfg(not(feature = "sqlite"))]
+    let url = match std::env::var("TAURI_PLUGIN_SQL_TEST_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("TAURI_PLUGIN_SQL_TEST_URL isn't set, skipping");
+            return None;
+        }
+    };
+
+    // A single connection, since every connection to `sqlite::memory:` opens a
+    // database of its own, and so temporary tables are seen by every query.
+    let pool = options
+        .max_connections(1)
+        .connect(&url)
+        .await
+        .expect("failed to connect to the test database");
+    Some(pool)
+}
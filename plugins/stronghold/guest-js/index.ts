@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 type BytesDto = string | number[];
 export type ClientPath =
@@ -284,6 +285,19 @@ export class Client {
   }
 }
 
+export interface StoreKeysOptions {
+  /** The number of keys to skip, for pagination. */
+  offset?: number;
+  /** The maximum number of keys to return. */
+  limit?: number;
+}
+
+export interface StoreKeyInfo {
+  key: string;
+  /** The size of the value in bytes. */
+  size: number;
+}
+
 export class Store {
   path: string;
   client: BytesDto;
@@ -307,6 +321,33 @@ export class Store {
     });
   }
 
+  /**
+   * Lists the keys of the store in byte order, without reading their values.
+   * @returns
+   */
+  async keys(options?: StoreKeysOptions): Promise<string[]> {
+    return await invoke<Array<{ key: string }>>(
+      "plugin:stronghold|get_store_keys",
+      {
+        snapshotPath: this.path,
+        client: this.client,
+        options,
+      },
+    ).then((keys) => keys.map((k) => k.key));
+  }
+
+  /**
+   * Lists the keys of the store with the size of their values in bytes.
+   * @returns
+   */
+  async keysWithSize(options?: StoreKeysOptions): Promise<StoreKeyInfo[]> {
+    return await invoke<StoreKeyInfo[]>("plugin:stronghold|get_store_keys", {
+      snapshotPath: this.path,
+      client: this.client,
+      options: { ...options, withSize: true },
+    });
+  }
+
   async insert(
     key: StoreKey,
     value: number[],
@@ -389,6 +430,20 @@ export class Vault extends ProcedureExecutor {
   }
 }
 
+/**
+ * Listens to the derivation of keys from passwords, e.g. to show a spinner
+ * while a stronghold loads, as it can take several seconds.
+ * @returns A function to stop listening.
+ */
+export async function onKeyDerivation(
+  handler: (state: "started" | "finished") => void,
+): Promise<UnlistenFn> {
+  return await listen<"started" | "finished">(
+    "stronghold://key-derivation",
+    (event) => handler(event.payload),
+  );
+}
+
 /**
  * A representation of an access to a stronghold.
  */
@@ -407,6 +462,7 @@ export class Stronghold {
 
   /**
    * Load the snapshot if it exists (password must match), or start a fresh stronghold instance otherwise.
+   * Several snapshots can be loaded at once. Loading a snapshot that is already loaded returns the same instance, with its loaded clients and unsaved changes, once the password matches.
    * @param password
    * @returns
    */
@@ -418,7 +474,7 @@ export class Stronghold {
   }
 
   /**
-   * Remove this instance from the cache.
+   * Save the snapshot and remove this instance from the cache, dropping its loaded clients and zeroizing its key material.
    */
   async unload(): Promise<void> {
     return await invoke("plugin:stronghold|destroy", {
@@ -449,4 +505,46 @@ export class Stronghold {
       snapshotPath: this.path,
     });
   }
+
+  /**
+   * Re-encrypts the snapshot with a new password.
+   * Fails without touching the snapshot if `oldPassword` doesn't match.
+   * @returns
+   */
+  async changePassword(
+    oldPassword: string,
+    newPassword: string,
+  ): Promise<void> {
+    return await invoke("plugin:stronghold|change_password", {
+      snapshotPath: this.path,
+      oldPassword,
+      newPassword,
+    });
+  }
+
+  /**
+   * Saves the snapshot and copies it to `path`, e.g. for a backup.
+   * The copy opens with the same password.
+   * @returns
+   */
+  async exportSnapshot(path: string): Promise<void> {
+    return await invoke("plugin:stronghold|export_snapshot", {
+      snapshotPath: this.path,
+      path,
+    });
+  }
+
+  /**
+   * Replaces the snapshot with the one at `path`, e.g. to restore a backup.
+   * The current snapshot is kept with a `.bak` suffix, and nothing changes if
+   * `path` doesn't open with `password`. Clients must be loaded again afterwards.
+   * @returns
+   */
+  async importSnapshot(path: string, password: string): Promise<void> {
+    return await invoke("plugin:stronghold|import_snapshot", {
+      snapshotPath: this.path,
+      path,
+      password,
+    });
+  }
 }
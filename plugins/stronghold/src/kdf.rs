@@ -1,35 +1,122 @@
 use rand_chacha::ChaCha20Rng;
 use rand_core::{RngCore, SeedableRng};
-use std::path::Path;
+use std::{
+    fs::{rename, File},
+    io::Write,
+    path::Path,
+};
+
+use crate::stronghold::{Error, Result};
 
 /// NOTE: Hash supplied to Stronghold must be 32bits long.
 /// This is a current limitation of Stronghold.
 const HASH_LENGTH: usize = 32;
 
+/// The argon2 parameters used to derive a key from a password.
+///
+/// Higher costs make passwords harder to brute-force, but also make loading a
+/// snapshot slower, especially on low-end machines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    /// The amount of memory to use, in KiB.
+    pub mem_cost: u32,
+    /// The number of passes over the memory.
+    pub time_cost: u32,
+    /// The number of lanes, each hashed on its own thread.
+    pub lanes: u32,
+}
+
+impl Default for Argon2Params {
+    /// The defaults of `rust-argon2`, which were always used before the
+    /// parameters were configurable.
+    fn default() -> Self {
+        Self {
+            mem_cost: 4096,
+            time_cost: 3,
+            lanes: 1,
+        }
+    }
+}
+
 pub struct KeyDerivation {}
 
 impl KeyDerivation {
     /// Will create a key from [`password`] and a generated salt.
     /// Salt will be generated to file [`salt_path`] or taken from it
     /// if file already exists
-    pub fn argon2(password: &str, salt_path: &Path) -> Vec<u8> {
+    pub fn argon2(password: &str, salt_path: &Path) -> Result<Vec<u8>> {
+        Self::argon2_with_params(password, salt_path, Default::default())
+    }
+
+    /// Like [`Self::argon2`], with the parameters to use when the salt file
+    /// is created. They are stored in the salt file, so keys keep being
+    /// derived with the parameters a snapshot was created with when `params`
+    /// change later. Salt files without parameters use the defaults.
+    pub fn argon2_with_params(
+        password: &str,
+        salt_path: &Path,
+        params: Argon2Params,
+    ) -> Result<Vec<u8>> {
         let mut salt = [0u8; HASH_LENGTH];
-        create_or_get_salt(&mut salt, salt_path);
+        let params = create_or_get_salt(&mut salt, salt_path, params)?;
 
-        argon2::hash_raw(password.as_bytes(), &salt, &Default::default())
-            .expect("Failed to generate hash for password")
+        let config = argon2::Config {
+            mem_cost: params.mem_cost,
+            time_cost: params.time_cost,
+            lanes: params.lanes,
+            thread_mode: argon2::ThreadMode::from_threads(params.lanes),
+            ..Default::default()
+        };
+        argon2::hash_raw(password.as_bytes(), &salt, &config).map_err(Into::into)
     }
 }
 
-fn create_or_get_salt(salt: &mut [u8], salt_path: &Path) {
+/// The length of the parameters stored after the salt.
+const PARAMS_LENGTH: usize = 3 * 4;
+
+/// Reads the salt and the parameters stored after it, or creates the file.
+fn create_or_get_salt(
+    salt: &mut [u8],
+    salt_path: &Path,
+    params: Argon2Params,
+) -> Result<Argon2Params> {
     if salt_path.is_file() {
         // Get existing salt
-        let tmp = std::fs::read(salt_path).unwrap();
-        salt.clone_from_slice(&tmp);
+        let tmp = std::fs::read(salt_path)?;
+        if tmp.len() != HASH_LENGTH && tmp.len() != HASH_LENGTH + PARAMS_LENGTH {
+            return Err(Error::InvalidSaltFile(salt_path.to_owned()));
+        }
+        let (stored_salt, stored_params) = tmp.split_at(HASH_LENGTH);
+        salt.clone_from_slice(stored_salt);
+        if stored_params.is_empty() {
+            return Ok(Argon2Params::default());
+        }
+        let param = |i: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&stored_params[i * 4..][..4]);
+            u32::from_le_bytes(bytes)
+        };
+        Ok(Argon2Params {
+            mem_cost: param(0),
+            time_cost: param(1),
+            lanes: param(2),
+        })
     } else {
         // Generate new salt
         let mut gen = ChaCha20Rng::from_entropy();
         gen.fill_bytes(salt);
-        std::fs::write(salt_path, salt).expect("Failed to write salt for Stronghold")
+        let mut contents = salt.to_vec();
+        for param in [params.mem_cost, params.time_cost, params.lanes] {
+            contents.extend_from_slice(&param.to_le_bytes());
+        }
+        // Written to a temporary file first, so a crash while writing can't
+        // leave a truncated salt behind, which would lock the snapshot for good.
+        let mut temp_path = salt_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        rename(&temp_path, salt_path)?;
+        Ok(params)
     }
 }
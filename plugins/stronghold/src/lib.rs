@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     fmt,
     path::PathBuf,
     sync::{Arc, Mutex},
@@ -14,11 +14,11 @@ use iota_stronghold::{
     },
     Client, Location,
 };
-use serde::{de::Visitor, Deserialize, Deserializer};
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize};
 use stronghold::{Error, Result, Stronghold};
 use tauri::{
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    Manager, Runtime, State,
+    AppHandle, Manager, Runtime, State,
 };
 use zeroize::Zeroize;
 
@@ -27,13 +27,24 @@ pub mod kdf;
 
 pub mod stronghold;
 
-type PasswordHashFn = dyn Fn(&str) -> Vec<u8> + Send + Sync;
+type PasswordHashFn = dyn Fn(&str) -> Result<Vec<u8>> + Send + Sync;
 
 #[derive(Default)]
 struct StrongholdCollection(Arc<Mutex<HashMap<PathBuf, Stronghold>>>);
 
 struct PasswordHashFunction(Box<PasswordHashFn>);
 
+impl PasswordHashFunction {
+    /// Hashes a password, emitting `stronghold://key-derivation` events with
+    /// `started` and `finished` around it, as it can take several seconds.
+    fn hash<R: Runtime>(&self, app: &AppHandle<R>, password: &str) -> Result<Vec<u8>> {
+        let _ = app.emit_all("stronghold://key-derivation", "started");
+        let hash = (self.0)(password);
+        let _ = app.emit_all("stronghold://key-derivation", "finished");
+        hash
+    }
+}
+
 #[derive(Deserialize, Hash, Eq, PartialEq, Ord, PartialOrd)]
 #[serde(untagged)]
 enum BytesDto {
@@ -223,21 +234,26 @@ impl From<ProcedureDto> for StrongholdProcedure {
 }
 
 #[tauri::command]
-async fn initialize(
+async fn initialize<R: Runtime>(
+    app: AppHandle<R>,
     collection: State<'_, StrongholdCollection>,
     hash_function: State<'_, PasswordHashFunction>,
     snapshot_path: PathBuf,
     mut password: String,
 ) -> Result<()> {
-    let hash = (hash_function.0)(&password);
+    let hash = hash_function.hash(&app, &password);
     password.zeroize();
-    let stronghold = Stronghold::new(snapshot_path.clone(), hash)?;
-
-    collection
-        .0
-        .lock()
-        .unwrap()
-        .insert(snapshot_path, stronghold);
+    let hash = hash?;
+
+    // a snapshot that is already open keeps its instance, so its loaded
+    // clients and unsaved changes aren't lost
+    match collection.0.lock().unwrap().entry(snapshot_path) {
+        Entry::Occupied(entry) => entry.get().verify_password(hash)?,
+        Entry::Vacant(entry) => {
+            let stronghold = Stronghold::new(entry.key(), hash)?;
+            entry.insert(stronghold);
+        }
+    }
 
     Ok(())
 }
@@ -253,6 +269,7 @@ async fn destroy(
             collection.insert(snapshot_path, stronghold);
             return Err(e);
         }
+        stronghold.clear()?;
     }
     Ok(())
 }
@@ -266,6 +283,71 @@ async fn save(collection: State<'_, StrongholdCollection>, snapshot_path: PathBu
     Ok(())
 }
 
+#[tauri::command]
+async fn change_password<R: Runtime>(
+    app: AppHandle<R>,
+    collection: State<'_, StrongholdCollection>,
+    hash_function: State<'_, PasswordHashFunction>,
+    snapshot_path: PathBuf,
+    mut old_password: String,
+    mut new_password: String,
+) -> Result<()> {
+    let old_hash = hash_function.hash(&app, &old_password);
+    let new_hash = hash_function.hash(&app, &new_password);
+    old_password.zeroize();
+    new_password.zeroize();
+    let (old_hash, new_hash) = (old_hash?, new_hash?);
+
+    let mut collection = collection.0.lock().unwrap();
+    if let Some(stronghold) = collection.get_mut(&snapshot_path) {
+        stronghold.change_password(old_hash, new_hash)
+    } else {
+        Err(Error::StrongholdNotInitialized)
+    }
+}
+
+#[tauri::command]
+async fn export_snapshot<R: Runtime>(
+    app: AppHandle<R>,
+    collection: State<'_, StrongholdCollection>,
+    snapshot_path: PathBuf,
+    path: PathBuf,
+) -> Result<()> {
+    if !app.fs_scope().is_allowed(&path) {
+        return Err(Error::PathNotAllowed(path));
+    }
+    let collection = collection.0.lock().unwrap();
+    if let Some(stronghold) = collection.get(&snapshot_path) {
+        stronghold.export_snapshot(path)
+    } else {
+        Err(Error::StrongholdNotInitialized)
+    }
+}
+
+#[tauri::command]
+async fn import_snapshot<R: Runtime>(
+    app: AppHandle<R>,
+    collection: State<'_, StrongholdCollection>,
+    hash_function: State<'_, PasswordHashFunction>,
+    snapshot_path: PathBuf,
+    path: PathBuf,
+    mut password: String,
+) -> Result<()> {
+    if !app.fs_scope().is_allowed(&path) {
+        return Err(Error::PathNotAllowed(path));
+    }
+    let hash = hash_function.hash(&app, &password);
+    password.zeroize();
+    let hash = hash?;
+
+    let mut collection = collection.0.lock().unwrap();
+    if let Some(stronghold) = collection.get_mut(&snapshot_path) {
+        stronghold.import_snapshot(path, hash)
+    } else {
+        Err(Error::StrongholdNotInitialized)
+    }
+}
+
 #[tauri::command]
 async fn create_client(
     collection: State<'_, StrongholdCollection>,
@@ -299,6 +381,62 @@ async fn get_store_record(
     client.store().get(key.as_ref()).map_err(Into::into)
 }
 
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct StoreKeysOptions {
+    offset: usize,
+    limit: Option<usize>,
+    /// Also returns the size of each value in bytes.
+    with_size: bool,
+}
+
+#[derive(Serialize)]
+struct StoreKey {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+}
+
+/// Lists the keys of a store in byte order, without their values.
+#[tauri::command]
+async fn get_store_keys(
+    collection: State<'_, StrongholdCollection>,
+    snapshot_path: PathBuf,
+    client: BytesDto,
+    options: Option<StoreKeysOptions>,
+) -> Result<Vec<StoreKey>> {
+    let options = options.unwrap_or_default();
+    let store = get_client(collection, snapshot_path, client)?.store();
+    let mut keys = store.keys()?;
+    keys.sort();
+
+    let mut page = Vec::new();
+    let mut skipped = 0;
+    for key in keys {
+        if options.limit.map_or(false, |limit| page.len() >= limit) {
+            break;
+        }
+        // expired records are only removed periodically
+        if !store.contains_key(&key)? {
+            continue;
+        }
+        if skipped < options.offset {
+            skipped += 1;
+            continue;
+        }
+        let size = if options.with_size {
+            store.get(&key)?.map(|value| value.len())
+        } else {
+            None
+        };
+        page.push(StoreKey {
+            key: String::from_utf8_lossy(&key).into_owned(),
+            size,
+        });
+    }
+    Ok(page)
+}
+
 #[tauri::command]
 async fn save_store_record(
     collection: State<'_, StrongholdCollection>,
@@ -399,7 +537,7 @@ fn get_client(
 
 enum PasswordHashFunctionKind {
     #[cfg(feature = "kdf")]
-    Argon2(PathBuf),
+    Argon2(PathBuf, kdf::Argon2Params),
     Custom(Box<PasswordHashFn>),
 }
 
@@ -410,9 +548,9 @@ pub struct Builder {
 impl Builder {
     pub fn new<F: Fn(&str) -> Vec<u8> + Send + Sync + 'static>(password_hash_function: F) -> Self {
         Self {
-            password_hash_function: PasswordHashFunctionKind::Custom(Box::new(
-                password_hash_function,
-            )),
+            password_hash_function: PasswordHashFunctionKind::Custom(Box::new(move |password| {
+                Ok(password_hash_function(password))
+            })),
         }
     }
 
@@ -434,8 +572,16 @@ impl Builder {
     /// ```
     #[cfg(feature = "kdf")]
     pub fn with_argon2(salt_path: &std::path::Path) -> Self {
+        Self::with_argon2_params(salt_path, Default::default())
+    }
+
+    /// Initializes [`Self`] with argon2 as password hash function, using
+    /// `params` when the salt file is created, see
+    /// [`kdf::KeyDerivation::argon2_with_params`].
+    #[cfg(feature = "kdf")]
+    pub fn with_argon2_params(salt_path: &std::path::Path, params: kdf::Argon2Params) -> Self {
         Self {
-            password_hash_function: PasswordHashFunctionKind::Argon2(salt_path.to_owned()),
+            password_hash_function: PasswordHashFunctionKind::Argon2(salt_path.to_owned(), params),
         }
     }
 
@@ -446,8 +592,8 @@ impl Builder {
             app.manage(StrongholdCollection::default());
             app.manage(PasswordHashFunction(match password_hash_function {
                 #[cfg(feature = "kdf")]
-                PasswordHashFunctionKind::Argon2(path) => {
-                    Box::new(move |p| kdf::KeyDerivation::argon2(p, &path))
+                PasswordHashFunctionKind::Argon2(path, params) => {
+                    Box::new(move |p| kdf::KeyDerivation::argon2_with_params(p, &path, params))
                 }
                 PasswordHashFunctionKind::Custom(f) => f,
             }));
@@ -465,9 +611,13 @@ impl Builder {
                 initialize,
                 destroy,
                 save,
+                change_password,
+                export_snapshot,
+                import_snapshot,
                 create_client,
                 load_client,
                 get_store_record,
+                get_store_keys,
                 save_store_record,
                 remove_store_record,
                 save_secret,
@@ -1,4 +1,8 @@
-use std::{convert::TryFrom, ops::Deref, path::Path};
+use std::{
+    convert::TryFrom,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
 
 use iota_stronghold::{KeyProvider, SnapshotPath};
 use serde::{Serialize, Serializer};
@@ -9,12 +13,24 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[error("stronghold not initialized")]
     StrongholdNotInitialized,
+    #[error("invalid password")]
+    InvalidPassword,
+    #[error("path not allowed on the configured scope: {0}")]
+    PathNotAllowed(PathBuf),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
     #[error(transparent)]
     Stronghold(#[from] iota_stronghold::ClientError),
     #[error(transparent)]
     Memory(#[from] iota_stronghold::MemoryError),
     #[error(transparent)]
     Procedure(#[from] iota_stronghold::procedures::ProcedureError),
+    #[cfg(feature = "kdf")]
+    #[error("the salt file is truncated or corrupt: {0}")]
+    InvalidSaltFile(PathBuf),
+    #[cfg(feature = "kdf")]
+    #[error(transparent)]
+    Argon2(#[from] argon2::Error),
 }
 
 impl Serialize for Error {
@@ -53,6 +69,86 @@ impl Stronghold {
         Ok(())
     }
 
+    /// Re-encrypts the snapshot with a new password hash, e.g. to rotate it.
+    ///
+    /// The snapshot is written to a temporary file, which replaces the original
+    /// once it opens with the new password. The original is left untouched if
+    /// `old_password` doesn't match or anything fails. Loaded clients keep
+    /// working, and later saves use the new password.
+    pub fn change_password(&mut self, old_password: Vec<u8>, new_password: Vec<u8>) -> Result<()> {
+        self.verify_password(old_password)?;
+        let keyprovider = KeyProvider::try_from(new_password)?;
+
+        let tmp_path = SnapshotPath::from_path(self.path_with_suffix(".tmp"));
+        let result = self
+            .inner
+            .commit_with_keyprovider(&tmp_path, &keyprovider)
+            .and_then(|_| {
+                iota_stronghold::Stronghold::default().load_snapshot(&keyprovider, &tmp_path)
+            });
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(tmp_path.as_path());
+            return Err(e.into());
+        }
+        std::fs::rename(tmp_path.as_path(), self.path.as_path())?;
+
+        self.keyprovider = keyprovider;
+        Ok(())
+    }
+
+    /// Fails with [`Error::InvalidPassword`] unless `password` is the hash this
+    /// snapshot was opened with.
+    pub fn verify_password(&self, password: Vec<u8>) -> Result<()> {
+        let keyprovider = KeyProvider::try_from(password)?;
+        if *keyprovider.try_unlock()?.borrow() != *self.keyprovider.try_unlock()?.borrow() {
+            return Err(Error::InvalidPassword);
+        }
+        Ok(())
+    }
+
+    /// Saves the snapshot, then copies it to `path`, e.g. for a backup. The
+    /// copy opens with the same password.
+    pub fn export_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save()?;
+        std::fs::copy(self.path.as_path(), path)?;
+        Ok(())
+    }
+
+    /// Replaces the snapshot with the one at `path`, e.g. to restore a backup,
+    /// which must open with `password`. The current snapshot is kept next to
+    /// it with a `.bak` suffix. Nothing is changed if `path` doesn't open.
+    ///
+    /// Clients have to be loaded again afterwards.
+    pub fn import_snapshot<P: AsRef<Path>>(&mut self, path: P, password: Vec<u8>) -> Result<()> {
+        let keyprovider = KeyProvider::try_from(password)?;
+        let inner = iota_stronghold::Stronghold::default();
+        inner.load_snapshot(&keyprovider, &SnapshotPath::from_path(path.as_ref()))?;
+
+        let tmp_path = self.path_with_suffix(".tmp");
+        std::fs::copy(path, &tmp_path)?;
+        if self.path.exists() {
+            std::fs::copy(self.path.as_path(), self.path_with_suffix(".bak"))?;
+        }
+        std::fs::rename(tmp_path, self.path.as_path())?;
+
+        self.inner = inner;
+        self.keyprovider = keyprovider;
+        Ok(())
+    }
+
+    /// Drops the loaded clients and cached data. The key material is zeroized
+    /// when the instance is dropped.
+    pub fn clear(&self) -> Result<()> {
+        self.inner.clear()?;
+        Ok(())
+    }
+
+    fn path_with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.as_path().as_os_str().to_owned();
+        path.push(suffix);
+        path.into()
+    }
+
     pub fn inner(&self) -> &iota_stronghold::Stronghold {
         &self.inner
     }
@@ -5,80 +5,308 @@ interface ProgressPayload {
   id: number;
   progress: number;
   total: number;
+  /** The offset a resumed transfer started from. */
+  resumedFrom: number;
+  /** The `ETag` or `Last-Modified` validator a download was resumed with. */
+  validator?: string;
+  /** Smoothed transfer speed, in bytes per second. */
+  speed: number;
+  /** Estimated time until the transfer completes, in seconds, if the size is known. */
+  eta: number | null;
+  /** Time since the transfer started, in seconds. */
+  elapsed: number;
+}
+
+type ProgressHandler = (
+  progress: number,
+  total: number,
+  payload: ProgressPayload,
+) => void;
+
+interface UploadOptions {
+  /**
+   * Makes the upload resumable. Uploads with the same key continue where the
+   * previous attempt stopped, even across app restarts, as long as the file
+   * did not change.
+   *
+   * The file is sent with `PUT` and a `Content-Range` header. To resume, the
+   * server is asked for the received range with an empty `PUT` carrying
+   * `Content-Range: bytes *\/<size>`, to which it must answer with
+   * `308 Resume Incomplete` and a `Range` header.
+   */
+  resumeKey?: string;
+  /**
+   * The minimum time between two progress events, in milliseconds. The
+   * bytes transferred in between are reported together. Defaults to 100.
+   */
+  progressInterval?: number;
+}
+
+interface DownloadOptions {
+  /**
+   * Keeps the partial `<filePath>.part` file when the download fails, and
+   * continues it on the next download of the same URL to the same path. The
+   * download restarts if the `ETag` or `Last-Modified` of the file changed.
+   */
+  resume?: boolean;
+  /** The expected SHA-256 of the file, as a hex string. */
+  sha256?: string;
+  /**
+   * The minimum time between two progress events, in milliseconds. The
+   * bytes transferred in between are reported together. Defaults to 100.
+   */
+  progressInterval?: number;
+}
+
+interface DonePayload {
+  id: number;
+  result?: unknown;
+  error?: string;
+}
+
+interface Pending {
+  resolve: (result: unknown) => void;
+  reject: (error: string) => void;
 }
 
-type ProgressHandler = (progress: number, total: number) => void;
 const handlers: Map<number, ProgressHandler> = new Map();
-let listening = false;
+const pending: Map<number, Pending> = new Map();
+/** Transfers that completed before the command starting them returned. */
+const completed: Map<number, DonePayload> = new Map();
+/** Progress of the transfers whose start command didn't return yet. */
+const early: Map<number, ProgressPayload[]> = new Map();
+const listeners: Map<string, Promise<unknown>> = new Map();
 
-async function listenToEventIfNeeded(event: string): Promise<void> {
-  if (listening) {
-    return await Promise.resolve();
+async function listenToEventIfNeeded<T>(
+  event: string,
+  handler: (payload: T) => void,
+): Promise<void> {
+  let listener = listeners.get(event);
+  if (listener == null) {
+    listener = appWindow.listen<T>(event, ({ payload }) => handler(payload));
+    listeners.set(event, listener);
   }
+  await listener;
+}
 
-  // We're not awaiting this Promise to prevent issues with Promise.all
-  // the listener will still be registered in time.
-  appWindow.listen<ProgressPayload>(event, ({ payload }) => {
-    const handler = handlers.get(payload.id);
-    if (handler != null) {
-      handler(payload.progress, payload.total);
-    }
-  });
+function onProgress(payload: ProgressPayload): void {
+  const handler = handlers.get(payload.id);
+  if (handler != null) {
+    handler(payload.progress, payload.total, payload);
+  } else if (!pending.has(payload.id)) {
+    // kept until the transfer's progress handler is known
+    const events = early.get(payload.id) ?? [];
+    events.push(payload);
+    early.set(payload.id, events);
+  }
+}
+
+function settle(
+  { result, error }: DonePayload,
+  { resolve, reject }: Pending,
+): void {
+  if (error != null) {
+    reject(error);
+  } else {
+    resolve(result);
+  }
+}
 
-  listening = true;
+function onDone(payload: DonePayload): void {
+  handlers.delete(payload.id);
+  const transfer = pending.get(payload.id);
+  if (transfer != null) {
+    pending.delete(payload.id);
+    settle(payload, transfer);
+  } else {
+    completed.set(payload.id, payload);
+  }
 }
 
-async function upload(
+/**
+ * A transfer in flight. Resolves when it completes, and rejects with
+ * `"cancelled"` when it is cancelled.
+ */
+type Transfer<T> = Promise<T> & {
+  /** The id of the transfer, as given to {@link cancel}, once it started. */
+  id: Promise<number>;
+  /** Cancels the transfer, see {@link cancel}. */
+  cancel: () => Promise<boolean>;
+};
+
+function startTransfer<T>(
+  direction: "upload" | "download",
+  progressHandler: ProgressHandler | undefined,
+  start: () => Promise<number>,
+): Transfer<T> {
+  // the progress and the result are only emitted once, so the listeners are
+  // registered before the transfer starts
+  const id = Promise.all([
+    listenToEventIfNeeded(`${direction}://progress`, onProgress),
+    listenToEventIfNeeded(`${direction}://done`, onDone),
+  ]).then(start);
+
+  const promise = id.then(
+    async (transferId) =>
+      await new Promise<T>((resolve, reject) => {
+        const transfer = {
+          resolve: resolve as (result: unknown) => void,
+          reject,
+        };
+        const events = early.get(transferId) ?? [];
+        early.delete(transferId);
+        for (const payload of events) {
+          progressHandler?.(payload.progress, payload.total, payload);
+        }
+
+        const done = completed.get(transferId);
+        if (done != null) {
+          completed.delete(transferId);
+          settle(done, transfer);
+          return;
+        }
+        if (progressHandler != null) {
+          handlers.set(transferId, progressHandler);
+        }
+        pending.set(transferId, transfer);
+      }),
+  );
+  return Object.assign(promise, {
+    id,
+    cancel: async () => await cancel(await id),
+  });
+}
+
+function upload(
   url: string,
   filePath: string,
   progressHandler?: ProgressHandler,
   headers?: Map<string, string>,
-): Promise<void> {
-  const ids = new Uint32Array(1);
-  window.crypto.getRandomValues(ids);
-  const id = ids[0];
+  options?: UploadOptions,
+): Transfer<void> {
+  return startTransfer(
+    "upload",
+    progressHandler,
+    async () =>
+      await invoke<number>("plugin:upload|upload", {
+        url,
+        filePath,
+        headers: headers ?? {},
+        options,
+      }),
+  );
+}
 
-  if (progressHandler != null) {
-    handlers.set(id, progressHandler);
-  }
+interface ChunkedUploadOptions {
+  /** Size of every part but the last one, in bytes. Defaults to 8 MiB. */
+  chunkSize?: number;
+  /** How many parts are uploaded at the same time. Defaults to 4. */
+  concurrency?: number;
+  /** How many times a failed part is retried. Defaults to 3. */
+  retries?: number;
+  /**
+   * One URL per part, e.g. presigned S3 `UploadPart` URLs. When omitted,
+   * `{part}` in the upload URL is replaced with the 1-based part number.
+   */
+  partUrls?: string[];
+  /**
+   * The minimum time between two progress events, in milliseconds. The
+   * bytes transferred in between are reported together. Defaults to 100.
+   */
+  progressInterval?: number;
+}
 
-  await listenToEventIfNeeded("upload://progress");
+interface UploadedPart {
+  /** 1-based part number. */
+  partNumber: number;
+  offset: number;
+  size: number;
+  /** The `ETag` header of the part response, if any. */
+  etag: string | null;
+}
 
-  await invoke("plugin:upload|upload", {
-    id,
-    url,
-    filePath,
-    headers: headers ?? {},
-  });
+/// Upload a file in parts, with several `PUT` requests running at the same time.
+///
+/// Resolves with the uploaded parts, e.g. to complete an S3 multipart upload.
+/// The progress handler is called as parts complete, with the size of the parts uploaded since the last call and the size of the file.
+function uploadChunked(
+  url: string,
+  filePath: string,
+  options?: ChunkedUploadOptions,
+  progressHandler?: ProgressHandler,
+  headers?: Map<string, string>,
+): Transfer<UploadedPart[]> {
+  return startTransfer(
+    "upload",
+    progressHandler,
+    async () =>
+      await invoke<number>("plugin:upload|upload_chunked", {
+        url,
+        filePath,
+        headers: headers ?? {},
+        options,
+      }),
+  );
 }
 
 /// Download file from given url.
 ///
 /// Note that `filePath` currently must include the file name.
 /// Furthermore the progress events will report a total length of 0 if the server did not sent a `Content-Length` header or if the file is compressed.
-async function download(
+/// The file is written to `<filePath>.part` and only moved to `filePath` once complete and verified.
+function download(
   url: string,
   filePath: string,
   progressHandler?: ProgressHandler,
   headers?: Map<string, string>,
-): Promise<void> {
-  const ids = new Uint32Array(1);
-  window.crypto.getRandomValues(ids);
-  const id = ids[0];
+  options?: DownloadOptions,
+): Transfer<void> {
+  return startTransfer(
+    "download",
+    progressHandler,
+    async () =>
+      await invoke<number>("plugin:upload|download", {
+        url,
+        filePath,
+        headers: headers ?? {},
+        options,
+      }),
+  );
+}
 
-  if (progressHandler != null) {
-    handlers.set(id, progressHandler);
-  }
+interface TransferInfo {
+  id: number;
+  direction: "upload" | "download";
+  url: string;
+  /** Bytes transferred so far, including the resumed part. */
+  progress: number;
+  total: number;
+  /** `connecting` until the first bytes are transferred. */
+  state: "connecting" | "transferring";
+}
 
-  await listenToEventIfNeeded("download://progress");
+/// Cancel a transfer, aborting its request.
+///
+/// The partial file of a download is removed, unless the download is resumable.
+/// Resolves with whether the transfer was still in flight, cancelling an unknown or completed transfer does nothing.
+async function cancel(id: number): Promise<boolean> {
+  return await invoke<boolean>("plugin:upload|cancel", { id });
+}
 
-  await invoke("plugin:upload|download", {
-    id,
-    url,
-    filePath,
-    headers: headers ?? {},
-  });
+/// List the transfers in flight.
+async function listTransfers(): Promise<TransferInfo[]> {
+  return await invoke<TransferInfo[]>("plugin:upload|list_transfers");
 }
 
 export default upload;
-export { download, upload };
+export { cancel, download, listTransfers, upload, uploadChunked };
+export type {
+  ChunkedUploadOptions,
+  DownloadOptions,
+  ProgressHandler,
+  ProgressPayload,
+  Transfer,
+  TransferInfo,
+  UploadedPart,
+  UploadOptions,
+};
@@ -0,0 +1,176 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use futures_util::{StreamExt, TryStreamExt};
+use reqwest::header::ETAG;
+use serde::{Deserialize, Serialize};
+use tauri::{command, Manager, Runtime, Window};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use std::{collections::HashMap, io::SeekFrom, sync::Mutex};
+
+use crate::{
+    progress::Progress,
+    transfers::{Direction, Transfers},
+    Error, Result,
+};
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct ChunkedOptions {
+    /// Size of every part but the last one, in bytes.
+    chunk_size: u64,
+    /// How many parts are uploaded at the same time.
+    concurrency: usize,
+    /// How many times a failed part is retried.
+    retries: u32,
+    /// One URL per part, e.g. presigned S3 `UploadPart` URLs. When empty,
+    /// `{part}` in the upload URL is replaced with the part number.
+    part_urls: Vec<String>,
+    /// The minimum time between two progress events, in milliseconds.
+    progress_interval: Option<u64>,
+}
+
+impl Default for ChunkedOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 8 * 1024 * 1024,
+            concurrency: 4,
+            retries: 3,
+            part_urls: Vec::new(),
+            progress_interval: None,
+        }
+    }
+}
+
+/// An uploaded part, as needed to complete a multipart upload.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UploadedPart {
+    /// 1-based part number.
+    part_number: u64,
+    offset: u64,
+    size: u64,
+    /// The `ETag` header of the part response, if any.
+    etag: Option<String>,
+}
+
+/// Uploads the file in parts with `PUT` requests, several at a time.
+///
+/// Progress events are emitted as parts complete, at most once per progress
+/// interval. If a part still fails
+/// after its retries, all in-flight part requests are aborted.
+///
+/// Returns the id of the upload right away, the uploaded parts are emitted with
+/// the `upload://done` event.
+#[command]
+pub(crate) fn upload_chunked<R: Runtime>(
+    window: Window<R>,
+    url: String,
+    file_path: String,
+    headers: HashMap<String, String>,
+    options: Option<ChunkedOptions>,
+) -> u32 {
+    let options = options.unwrap_or_default();
+    window.state::<Transfers>().spawn(
+        window.clone(),
+        "upload://done",
+        Direction::Upload,
+        &url.clone(),
+        |window, id| async move {
+            upload_parts(&window, id, &url, &file_path, headers, &options).await
+        },
+    )
+}
+
+async fn upload_parts<R: Runtime>(
+    window: &Window<R>,
+    id: u32,
+    url: &str,
+    file_path: &str,
+    headers: HashMap<String, String>,
+    options: &ChunkedOptions,
+) -> Result<Vec<UploadedPart>> {
+    let chunk_size = options.chunk_size.max(1);
+
+    let total = File::open(file_path).await?.metadata().await?.len();
+    let count = ((total + chunk_size - 1) / chunk_size).max(1);
+    if !options.part_urls.is_empty() && options.part_urls.len() as u64 != count {
+        return Err(Error::PartUrls {
+            expected: count,
+            got: options.part_urls.len(),
+        });
+    }
+
+    let progress = Mutex::new(Progress::new(
+        window.clone(),
+        "upload://progress",
+        id,
+        Some(total),
+        options.progress_interval,
+    ));
+
+    let client = reqwest::Client::new();
+    let (client, headers, progress) = (&client, &headers, &progress);
+
+    let mut parts: Vec<UploadedPart> = futures_util::stream::iter(0..count)
+        .map(|index| async move {
+            let offset = index * chunk_size;
+            let size = chunk_size.min(total - offset);
+            let part_url = match options.part_urls.get(index as usize) {
+                Some(part_url) => part_url.clone(),
+                None => url.replace("{part}", &(index + 1).to_string()),
+            };
+
+            let mut file = File::open(file_path).await?;
+            file.seek(SeekFrom::Start(offset)).await?;
+            let mut body = vec![0; size as usize];
+            file.read_exact(&mut body).await?;
+            let body = bytes::Bytes::from(body);
+
+            let mut attempt = 0;
+            let etag = loop {
+                let mut request = client.put(&part_url).body(body.clone());
+                for (key, value) in headers {
+                    request = request.header(key, value);
+                }
+
+                let error = match request.send().await {
+                    Ok(response) if response.status().is_success() => {
+                        break response
+                            .headers()
+                            .get(ETAG)
+                            .and_then(|etag| etag.to_str().ok())
+                            .map(Into::into);
+                    }
+                    Ok(response) => Error::PartStatus(index + 1, response.status().as_u16()),
+                    Err(e) => e.into(),
+                };
+                if attempt >= options.retries {
+                    return Err(error);
+                }
+                attempt += 1;
+            };
+
+            progress.lock().unwrap().add(size, total);
+
+            Ok(UploadedPart {
+                part_number: index + 1,
+                offset,
+                size,
+                etag,
+            })
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    progress.lock().unwrap().finish();
+
+    parts.sort_by_key(|part| part.part_number);
+    Ok(parts)
+}
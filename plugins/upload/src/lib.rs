@@ -3,21 +3,39 @@
 // SPDX-License-Identifier: MIT
 
 use futures_util::TryStreamExt;
-use serde::{ser::Serializer, Serialize};
+use reqwest::{
+    header::{CONTENT_RANGE, IF_RANGE, RANGE},
+    StatusCode,
+};
+use serde::{ser::Serializer, Deserialize, Serialize};
 use tauri::{
     command,
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    Runtime, Window,
+    Manager, Runtime, Window,
 };
 use tokio::{
-    fs::File,
-    io::{AsyncWriteExt, BufWriter},
+    fs::{File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
 };
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 use read_progress_stream::ReadProgressStream;
 
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+
+mod chunked;
+mod progress;
+mod resume;
+mod transfers;
+
+use progress::Progress;
+use resume::{ResumeEntry, ResumeStore, UploadStatus};
+use transfers::{Direction, Transfers};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -27,8 +45,26 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
     #[error("{0}")]
     ContentLength(String),
+    #[error("the file changed since the transfer started")]
+    FileChanged,
+    #[error("server rejected resumable upload with status {0}")]
+    ResumeRejected(u16),
+    #[error("expected {expected} part URLs, got {got}")]
+    PartUrls { expected: u64, got: usize },
+    #[error("upload of part {0} failed with status {1}")]
+    PartStatus(u64, u16),
+    #[error("request failed with status {0}")]
+    HttpStatus(u16),
+    #[error("server resumed the download at the wrong offset")]
+    ContentRange,
+    #[error("checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("cancelled")]
+    Cancelled,
 }
 
 impl Serialize for Error {
@@ -41,65 +77,330 @@ impl Serialize for Error {
 }
 
 #[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 struct ProgressPayload {
     id: u32,
     progress: u64,
     total: u64,
+    /// The offset a resumed transfer started from.
+    resumed_from: u64,
+    /// The `ETag` or `Last-Modified` validator a download was resumed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validator: Option<String>,
+    /// Smoothed transfer speed, in bytes per second.
+    speed: f64,
+    /// Estimated time until the transfer completes, in seconds, if the size is known.
+    eta: Option<f64>,
+    /// Time since the transfer started, in seconds.
+    elapsed: f64,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadOptions {
+    /// Makes the upload resumable. Uploads with the same key continue where
+    /// the previous attempt stopped, even across app restarts.
+    resume_key: Option<String>,
+    /// The minimum time between two progress events, in milliseconds.
+    progress_interval: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadOptions {
+    /// Keeps the partial file when the download fails, and continues it on
+    /// the next download of the same URL to the same path.
+    resume: bool,
+    /// The expected SHA-256 of the file, as a hex string.
+    sha256: Option<String>,
+    /// The minimum time between two progress events, in milliseconds.
+    progress_interval: Option<u64>,
 }
 
+/// Starts the download, and returns its id. The result is emitted with the
+/// `download://done` event.
 #[command]
-async fn download<R: Runtime>(
+fn download<R: Runtime>(
     window: Window<R>,
+    url: String,
+    file_path: String,
+    headers: HashMap<String, String>,
+    options: Option<DownloadOptions>,
+) -> u32 {
+    let options = options.unwrap_or_default();
+    window.state::<Transfers>().spawn(
+        window.clone(),
+        "download://done",
+        Direction::Download,
+        &url.clone(),
+        |window, id| async move {
+            let result = download_file(&window, id, &url, &file_path, headers, &options).await;
+
+            // only kept if the download can be resumed
+            let key = format!("download:{file_path}");
+            let store = window.state::<ResumeStore>();
+            if matches!(result, Err(Error::Cancelled))
+                && !(options.resume && store.get(&key).is_some())
+            {
+                let _ = tokio::fs::remove_file(format!("{file_path}.part")).await;
+                store.remove(&key)?;
+            }
+            result
+        },
+    )
+}
+
+async fn download_file<R: Runtime>(
+    window: &Window<R>,
     id: u32,
     url: &str,
     file_path: &str,
     headers: HashMap<String, String>,
-) -> Result<u32> {
+    options: &DownloadOptions,
+) -> Result<()> {
+    // the file is only moved to its destination once complete
+    let part_path = format!("{file_path}.part");
+    let key = format!("download:{file_path}");
+    let store = window.state::<ResumeStore>();
+
     let client = reqwest::Client::new();
+    let send = |range: Option<(u64, &str)>| {
+        let mut request = client.get(url);
+        // Loop trought the headers keys and values
+        // and add them to the request object.
+        for (key, value) in &headers {
+            request = request.header(key, value);
+        }
+        if let Some((offset, validator)) = range {
+            request = request
+                .header(RANGE, format!("bytes={offset}-"))
+                .header(IF_RANGE, validator);
+        }
+        request.send()
+    };
 
-    let mut request = client.get(url);
-    // Loop trought the headers keys and values
-    // and add them to the request object.
-    for (key, value) in headers {
-        request = request.header(&key, value);
+    // Continue the previous download, unless the file changed on the server
+    // in which case `If-Range` makes the server send all of it.
+    let mut offset = 0;
+    let mut validator = None;
+    if options.resume {
+        if let Some(entry) = store.get(&key).filter(|entry| entry.url == url) {
+            if let (Some(v), Ok(metadata)) =
+                (entry.validator, tokio::fs::metadata(&part_path).await)
+            {
+                offset = metadata.len();
+                validator = Some(v);
+            }
+        }
     }
 
-    let response = request.send().await?;
-    let total = response.content_length().unwrap_or(0);
-
-    let mut file = BufWriter::new(File::create(file_path).await?);
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.try_next().await? {
-        file.write_all(&chunk).await?;
-        let _ = window.emit(
-            "download://progress",
-            ProgressPayload {
-                id,
-                progress: chunk.len() as u64,
-                total,
-            },
-        );
+    let mut response = send(validator.as_deref().map(|v| (offset, v))).await?;
+    // The part already has all of the file, or more than the server has, if it
+    // changed while the validator still matches.
+    let mut complete = false;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        if resume::unsatisfied_range_length(&response) == Some(offset) {
+            complete = true;
+        } else {
+            tokio::fs::remove_file(&part_path).await?;
+            store.remove(&key)?;
+            offset = 0;
+            validator = None;
+            response = send(None).await?;
+        }
+    }
+    if !complete && !response.status().is_success() {
+        // the part can't be continued from a failed response
+        let _ = tokio::fs::remove_file(&part_path).await;
+        store.remove(&key)?;
+        return Err(Error::HttpStatus(response.status().as_u16()));
+    }
+    if complete || response.status() == StatusCode::PARTIAL_CONTENT {
+        if !complete && !resume::content_range_starts_at(&response, offset) {
+            return Err(Error::ContentRange);
+        }
+    } else {
+        offset = 0;
+        validator = resume::validator(&response);
     }
-    file.flush().await?;
+    let length = if complete {
+        Some(0)
+    } else {
+        response.content_length()
+    };
+    let total = length.map_or(0, |length| length + offset);
+
+    if options.resume {
+        if let Some(validator) = &validator {
+            store.insert(
+                key.clone(),
+                ResumeEntry {
+                    url: url.into(),
+                    file_path: file_path.into(),
+                    size: total,
+                    modified: None,
+                    offset,
+                    validator: Some(validator.clone()),
+                },
+            )?;
+        }
+    }
+
+    let mut progress = Progress::new(
+        window.clone(),
+        "download://progress",
+        id,
+        length.map(|length| length + offset),
+        options.progress_interval,
+    )
+    .resumed(offset, validator.clone());
 
-    Ok(id)
+    let result = async {
+        if !complete {
+            let file = if offset > 0 {
+                OpenOptions::new().append(true).open(&part_path).await?
+            } else {
+                File::create(&part_path).await?
+            };
+            let mut file = BufWriter::new(file);
+            let mut stream = response.bytes_stream();
+
+            let streamed = async {
+                while let Some(chunk) = stream.try_next().await? {
+                    file.write_all(&chunk).await?;
+                    progress.add(chunk.len() as u64, total);
+                }
+                Ok::<_, Error>(())
+            }
+            .await;
+            // what was received is kept even if the connection broke, to be resumed from
+            file.flush().await?;
+            streamed?;
+        }
+        progress.finish();
+
+        if let Some(expected) = &options.sha256 {
+            resume::verify_sha256(&part_path, expected).await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            tokio::fs::rename(&part_path, file_path).await?;
+            store.remove(&key)?;
+            Ok(())
+        }
+        Err(e) => {
+            // A corrupted file can't be resumed, nor one without a validator
+            // to check that it didn't change on the server.
+            if !options.resume || validator.is_none() || matches!(e, Error::ChecksumMismatch { .. })
+            {
+                let _ = tokio::fs::remove_file(&part_path).await;
+                store.remove(&key)?;
+            }
+            Err(e)
+        }
+    }
 }
 
+/// Starts the upload, and returns its id. The response is emitted with the
+/// `upload://done` event.
 #[command]
-async fn upload<R: Runtime>(
+fn upload<R: Runtime>(
     window: Window<R>,
+    url: String,
+    file_path: String,
+    headers: HashMap<String, String>,
+    options: Option<UploadOptions>,
+) -> u32 {
+    let options = options.unwrap_or_default();
+    window.state::<Transfers>().spawn(
+        window.clone(),
+        "upload://done",
+        Direction::Upload,
+        &url.clone(),
+        |window, id| async move {
+            upload_file(&window, id, &url, &file_path, headers, &options).await
+        },
+    )
+}
+
+async fn upload_file<R: Runtime>(
+    window: &Window<R>,
     id: u32,
     url: &str,
     file_path: &str,
     headers: HashMap<String, String>,
+    options: &UploadOptions,
 ) -> Result<serde_json::Value> {
     // Read the file
-    let file = File::open(file_path).await?;
+    let mut file = File::open(file_path).await?;
+    let metadata = file.metadata().await?;
+    let size = metadata.len();
+
+    let progress = |resumed_from| {
+        let progress = Progress::new(
+            window.clone(),
+            "upload://progress",
+            id,
+            Some(size),
+            options.progress_interval,
+        );
+        Arc::new(Mutex::new(progress.resumed(resumed_from, None)))
+    };
 
-    // Create the request and attach the file to the body
     let client = reqwest::Client::new();
-    let mut request = client.post(url).body(file_to_body(id, window, file));
+    let (mut request, progress) = if let Some(key) = &options.resume_key {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64);
+
+        let store = window.state::<ResumeStore>();
+        let offset = match store.get(key) {
+            Some(entry) if entry.url == url && entry.file_path == file_path => {
+                if entry.size != size || entry.modified != modified {
+                    store.remove(key)?;
+                    return Err(Error::FileChanged);
+                }
+                match resume::query_status(url, &headers, size).await? {
+                    UploadStatus::Partial(offset) => offset,
+                    UploadStatus::Complete(response) => {
+                        store.remove(key)?;
+                        return response.json().await.map_err(Into::into);
+                    }
+                }
+            }
+            _ => 0,
+        };
+        store.insert(
+            key.clone(),
+            ResumeEntry {
+                url: url.into(),
+                file_path: file_path.into(),
+                size,
+                modified,
+                offset,
+                validator: None,
+            },
+        )?;
+
+        file.seek(SeekFrom::Start(offset)).await?;
+        let progress = progress(offset);
+        let request = client
+            .put(url)
+            .header(CONTENT_RANGE, resume::content_range(offset, size))
+            .body(file_to_body(progress.clone(), file));
+        (request, progress)
+    } else {
+        // Create the request and attach the file to the body
+        let progress = progress(0);
+        let request = client.post(url).body(file_to_body(progress.clone(), file));
+        (request, progress)
+    };
 
     // Loop trought the headers keys and values
     // and add them to the request object.
@@ -108,30 +409,231 @@ async fn upload<R: Runtime>(
     }
 
     let response = request.send().await?;
+    progress.lock().unwrap().finish();
+
+    if let Some(key) = &options.resume_key {
+        if response.status().is_success() {
+            window.state::<ResumeStore>().remove(key)?;
+        }
+    }
 
     response.json().await.map_err(Into::into)
 }
 
-fn file_to_body<R: Runtime>(id: u32, window: Window<R>, file: File) -> reqwest::Body {
+fn file_to_body<R: Runtime>(progress: Arc<Mutex<Progress<R>>>, file: File) -> reqwest::Body {
     let stream = FramedRead::new(file, BytesCodec::new()).map_ok(|r| r.freeze());
-    let window = Mutex::new(window);
     reqwest::Body::wrap_stream(ReadProgressStream::new(
         stream,
-        Box::new(move |progress, total| {
-            let _ = window.lock().unwrap().emit(
-                "upload://progress",
-                ProgressPayload {
-                    id,
-                    progress,
-                    total,
-                },
-            );
-        }),
+        Box::new(move |read, total| progress.lock().unwrap().add(read, total)),
     ))
 }
 
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
     PluginBuilder::new("upload")
-        .invoke_handler(tauri::generate_handler![download, upload])
+        .invoke_handler(tauri::generate_handler![
+            download,
+            upload,
+            chunked::upload_chunked,
+            transfers::cancel,
+            transfers::list_transfers
+        ])
+        .setup(|app| {
+            app.manage(ResumeStore::load(app));
+            app.manage(Transfers::default());
+            Ok(())
+        })
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    const HALF: usize = BODY.len() / 2;
+
+    fn response(status: &str, headers: &[String], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {status}\r\nETag: \"v1\"\r\n");
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// A server and a destination that downloads from it are resumed to.
+    struct Server {
+        window: Window<tauri::test::MockRuntime>,
+        listener: TcpListener,
+        url: String,
+        path: String,
+        part_path: String,
+        _dir: tempfile::TempDir,
+    }
+
+    impl Server {
+        async fn new() -> Self {
+            let app = tauri::test::mock_builder()
+                .plugin(init())
+                .build(tauri::test::mock_context(tauri::test::noop_assets()))
+                .unwrap();
+            let window = app.get_window("main").unwrap();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/file", listener.local_addr().unwrap());
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("file").to_string_lossy().into_owned();
+            Self {
+                window,
+                listener,
+                url,
+                part_path: format!("{path}.part"),
+                path,
+                _dir: dir,
+            }
+        }
+
+        /// Answers each request with the next response, then closes the
+        /// connection, which ends even an incomplete response. Returns the
+        /// heads of the requests.
+        async fn serve(&self, responses: Vec<Vec<u8>>) -> Vec<String> {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = self.listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                }
+                stream.write_all(&response).await.unwrap();
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+            }
+            requests
+        }
+
+        /// Downloads the file served with `responses`, resumable.
+        async fn download(&self, responses: Vec<Vec<u8>>) -> (Result<()>, Vec<String>) {
+            let options = DownloadOptions {
+                resume: true,
+                ..Default::default()
+            };
+            let (requests, result) = futures_util::future::join(
+                self.serve(responses),
+                download_file(
+                    &self.window,
+                    1,
+                    &self.url,
+                    &self.path,
+                    HashMap::new(),
+                    &options,
+                ),
+            )
+            .await;
+            (result, requests)
+        }
+
+        /// Downloads half of the file before the connection is closed.
+        async fn interrupt(&self, body: &[u8]) {
+            let length = format!("Content-Length: {}", BODY.len() + 1);
+            let (result, _) = self
+                .download(vec![response("200 OK", &[length], body)])
+                .await;
+            assert!(result.is_err());
+            assert_eq!(std::fs::read(&self.part_path).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn resumes_an_interrupted_download() {
+        tauri::async_runtime::block_on(async {
+            let server = Server::new().await;
+            server.interrupt(&BODY[..HALF]).await;
+
+            let headers = [
+                format!("Content-Length: {}", BODY.len() - HALF),
+                format!(
+                    "Content-Range: bytes {HALF}-{}/{}",
+                    BODY.len() - 1,
+                    BODY.len()
+                ),
+            ];
+            let (result, requests) = server
+                .download(vec![response(
+                    "206 Partial Content",
+                    &headers,
+                    &BODY[HALF..],
+                )])
+                .await;
+            result.unwrap();
+            assert!(requests[0].contains(&format!("range: bytes={HALF}-")));
+            assert!(requests[0].contains("if-range: \"v1\""));
+            assert_eq!(std::fs::read(&server.path).unwrap(), BODY);
+            assert!(!Path::new(&server.part_path).exists());
+        });
+    }
+
+    #[test]
+    fn completes_a_download_whose_part_is_complete() {
+        tauri::async_runtime::block_on(async {
+            let server = Server::new().await;
+            // interrupted after the last byte, before the response ended
+            server.interrupt(BODY).await;
+
+            let range = format!("Content-Range: bytes */{}", BODY.len());
+            let (result, _) = server
+                .download(vec![response("416 Range Not Satisfiable", &[range], b"")])
+                .await;
+            result.unwrap();
+            assert_eq!(std::fs::read(&server.path).unwrap(), BODY);
+            assert!(!Path::new(&server.part_path).exists());
+        });
+    }
+
+    #[test]
+    fn restarts_a_download_whose_part_is_stale() {
+        tauri::async_runtime::block_on(async {
+            let server = Server::new().await;
+            server.interrupt(&BODY[..HALF]).await;
+
+            // the file shrank on the server, without changing its validator
+            let range = format!("Content-Range: bytes */{}", HALF - 1);
+            let length = format!("Content-Length: {}", BODY.len());
+            let (result, requests) = server
+                .download(vec![
+                    response("416 Range Not Satisfiable", &[range], b""),
+                    response("200 OK", &[length], BODY),
+                ])
+                .await;
+            result.unwrap();
+            assert!(!requests[1].contains("range:"));
+            assert_eq!(std::fs::read(&server.path).unwrap(), BODY);
+            assert!(!Path::new(&server.part_path).exists());
+        });
+    }
+
+    #[test]
+    fn removes_the_part_on_error_statuses() {
+        tauri::async_runtime::block_on(async {
+            let server = Server::new().await;
+            server.interrupt(&BODY[..HALF]).await;
+
+            let length = "Content-Length: 0".to_string();
+            let (result, _) = server
+                .download(vec![response("404 Not Found", &[length], b"")])
+                .await;
+            assert!(matches!(result, Err(Error::HttpStatus(404))));
+            assert!(!Path::new(&server.part_path).exists());
+            assert!(!Path::new(&server.path).exists());
+        });
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use tauri::{Manager, Runtime, Window};
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    transfers::{Transfer, Transfers},
+    ProgressPayload,
+};
+
+/// The minimum time between two progress events of a transfer, in milliseconds.
+pub(crate) const DEFAULT_PROGRESS_INTERVAL: u64 = 100;
+
+/// Weight of the latest measurement in the smoothed speed.
+const SPEED_SMOOTHING: f64 = 0.3;
+
+/// Progress events of a single transfer, throttled to one per `interval`.
+///
+/// The bytes transferred in between are added up, so the `progress` of all
+/// events still sums up to the transferred size. The progress listed by
+/// `list_transfers` is updated on every call to [`Progress::add`].
+pub(crate) struct Progress<R: Runtime> {
+    window: Window<R>,
+    transfer: Option<Arc<Transfer>>,
+    event: &'static str,
+    id: u32,
+    /// The size of the whole file, if known, to estimate the remaining time.
+    size: Option<u64>,
+    resumed_from: u64,
+    validator: Option<String>,
+    interval: Duration,
+    started: Instant,
+    last_emit: Option<Instant>,
+    /// Bytes transferred since the last event.
+    pending: u64,
+    /// Bytes transferred by this attempt, not counting `resumed_from`.
+    transferred: u64,
+    total: u64,
+    /// Smoothed transfer speed in bytes per second.
+    speed: f64,
+}
+
+impl<R: Runtime> Progress<R> {
+    pub fn new(
+        window: Window<R>,
+        event: &'static str,
+        id: u32,
+        size: Option<u64>,
+        interval: Option<u64>,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            transfer: window.state::<Transfers>().get(id),
+            window,
+            event,
+            id,
+            size,
+            resumed_from: 0,
+            validator: None,
+            interval: Duration::from_millis(interval.unwrap_or(DEFAULT_PROGRESS_INTERVAL)),
+            started: now,
+            last_emit: None,
+            pending: 0,
+            transferred: 0,
+            total: 0,
+            speed: 0.0,
+        }
+    }
+
+    pub fn resumed(mut self, resumed_from: u64, validator: Option<String>) -> Self {
+        self.resumed_from = resumed_from;
+        self.validator = validator;
+        self
+    }
+
+    /// Records `progress` transferred bytes, emitting an event if the last
+    /// one is older than the interval.
+    pub fn add(&mut self, progress: u64, total: u64) {
+        self.pending += progress;
+        self.transferred += progress;
+        self.total = total;
+        if let Some(transfer) = &self.transfer {
+            transfer.set_progress(
+                self.resumed_from + self.transferred,
+                self.size.unwrap_or(total),
+            );
+        }
+
+        let last = self.last_emit.unwrap_or(self.started);
+        if last.elapsed() >= self.interval {
+            self.emit();
+        }
+    }
+
+    /// Emits the bytes transferred since the last event, so the final
+    /// progress of the transfer is never throttled away.
+    pub fn finish(&mut self) {
+        if self.pending > 0 || self.last_emit.is_none() {
+            self.emit();
+        }
+    }
+
+    fn emit(&mut self) {
+        let now = Instant::now();
+        let since = now
+            .duration_since(self.last_emit.unwrap_or(self.started))
+            .as_secs_f64();
+        if since > 0.0 {
+            let speed = self.pending as f64 / since;
+            self.speed = if self.last_emit.is_none() {
+                speed
+            } else {
+                SPEED_SMOOTHING * speed + (1.0 - SPEED_SMOOTHING) * self.speed
+            };
+        }
+
+        let eta = self.size.filter(|_| self.speed > 0.0).map(|size| {
+            let remaining = size.saturating_sub(self.resumed_from + self.transferred);
+            remaining as f64 / self.speed
+        });
+
+        let _ = self.window.emit(
+            self.event,
+            ProgressPayload {
+                id: self.id,
+                progress: self.pending,
+                total: self.total,
+                resumed_from: self.resumed_from,
+                validator: self.validator.clone(),
+                speed: self.speed,
+                eta,
+                elapsed: now.duration_since(self.started).as_secs_f64(),
+            },
+        );
+        self.pending = 0;
+        self.last_emit = Some(now);
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_RANGE, ETAG, LAST_MODIFIED, RANGE},
+    redirect::Policy,
+    Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Runtime};
+use tokio::io::AsyncReadExt;
+
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, rename, File},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::{Error, Result};
+
+pub const RESUME_STATE_FILENAME: &str = ".upload-state.json";
+
+/// What is known about an interrupted resumable transfer.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct ResumeEntry {
+    pub url: String,
+    pub file_path: String,
+    /// The file size when the transfer started.
+    pub size: u64,
+    /// The file modification time when the transfer started, in milliseconds since the epoch.
+    pub modified: Option<u64>,
+    /// The last offset confirmed by the server.
+    pub offset: u64,
+    /// The `ETag` or `Last-Modified` of a download, to detect changes on the server.
+    #[serde(default)]
+    pub validator: Option<String>,
+}
+
+/// Resumable transfers keyed by the id given by the app, persisted to disk so
+/// they survive restarts.
+pub(crate) struct ResumeStore {
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, ResumeEntry>>,
+}
+
+impl ResumeStore {
+    pub fn load<R: Runtime>(app: &AppHandle<R>) -> Self {
+        let path = app
+            .path_resolver()
+            .app_data_dir()
+            .map(|dir| dir.join(RESUME_STATE_FILENAME));
+        let entries = path
+            .as_ref()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<ResumeEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: String, entry: ResumeEntry) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key, entry);
+        self.save(&entries)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(key).is_some() {
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, entries: &HashMap<String, ResumeEntry>) -> Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+            // Written to a temporary file first, so a crash while saving can't leave
+            // a truncated state behind.
+            let temp_path = path.with_extension("json.tmp");
+            let mut file = File::create(&temp_path)?;
+            serde_json::to_writer(&mut file, entries)?;
+            file.flush()?;
+            file.sync_all()?;
+            rename(&temp_path, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The server side state of a resumable upload.
+pub(crate) enum UploadStatus {
+    /// The server has the bytes up to this offset.
+    Partial(u64),
+    /// The server already received the whole file.
+    Complete(Response),
+}
+
+/// Asks the server how much of the upload it received, by sending an empty
+/// `PUT` with `Content-Range: bytes */<size>`. The server answers with
+/// `308 Resume Incomplete` and the received `Range`, or a success status if
+/// the upload is complete.
+pub(crate) async fn query_status(
+    url: &str,
+    headers: &HashMap<String, String>,
+    size: u64,
+) -> Result<UploadStatus> {
+    // a 308 here is not a redirect
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()?;
+    let mut request = client
+        .put(url)
+        .header(CONTENT_RANGE, format!("bytes */{size}"))
+        .header(CONTENT_LENGTH, 0);
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+
+    let response = request.send().await?;
+    match response.status() {
+        StatusCode::PERMANENT_REDIRECT => {
+            // `Range: bytes=0-<last received byte>`, absent if nothing was received
+            let offset = response
+                .headers()
+                .get(RANGE)
+                .and_then(|range| range.to_str().ok())
+                .and_then(|range| range.rsplit_once('-'))
+                .and_then(|(_, last)| last.trim().parse::<u64>().ok())
+                .map_or(0, |last| last + 1);
+            Ok(UploadStatus::Partial(offset))
+        }
+        status if status.is_success() => Ok(UploadStatus::Complete(response)),
+        status => Err(Error::ResumeRejected(status.as_u16())),
+    }
+}
+
+/// The `Content-Range` of the request sending the file from `offset`.
+pub(crate) fn content_range(offset: u64, size: u64) -> String {
+    if size == 0 {
+        "bytes */0".into()
+    } else {
+        format!("bytes {}-{}/{}", offset, size - 1, size)
+    }
+}
+
+/// The validator to resume a download with using `If-Range`: a strong `ETag`,
+/// or `Last-Modified`.
+pub(crate) fn validator(response: &Response) -> Option<String> {
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string)
+    };
+    header(ETAG)
+        // weak validators are not allowed in `If-Range`
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+}
+
+/// Whether a `206 Partial Content` response starts at `offset`.
+pub(crate) fn content_range_starts_at(response: &Response, offset: u64) -> bool {
+    response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes "))
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, _)| start.trim().parse::<u64>().ok())
+        == Some(offset)
+}
+
+/// The complete length in the `Content-Range: bytes */<length>` of a
+/// `416 Range Not Satisfiable` response.
+pub(crate) fn unsatisfied_range_length(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes */"))
+        .and_then(|length| length.trim().parse::<u64>().ok())
+}
+
+/// Hashes the file at `path` and compares it with the `expected` hex digest.
+pub(crate) async fn verify_sha256(path: &str, expected: &str) -> Result<()> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    let actual = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual,
+        })
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2021 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use futures_util::future::{AbortHandle, Abortable};
+use serde::Serialize;
+use tauri::{command, Manager, Runtime, State, Window};
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum TransferState {
+    /// Waiting for the server, nothing was transferred yet.
+    Connecting,
+    Transferring,
+}
+
+/// A transfer in flight, updated as its progress events are emitted.
+pub(crate) struct Transfer {
+    direction: Direction,
+    url: String,
+    progress: AtomicU64,
+    total: AtomicU64,
+    started: AtomicBool,
+    abort: AbortHandle,
+}
+
+impl Transfer {
+    pub fn set_progress(&self, progress: u64, total: u64) {
+        self.progress.store(progress, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+        self.started.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TransferInfo {
+    id: u32,
+    direction: Direction,
+    url: String,
+    /// Bytes transferred so far, including the resumed part.
+    progress: u64,
+    total: u64,
+    state: TransferState,
+}
+
+/// The transfers in flight, by id.
+#[derive(Default)]
+pub(crate) struct Transfers {
+    next_id: AtomicU32,
+    transfers: Mutex<HashMap<u32, Arc<Transfer>>>,
+}
+
+impl Transfers {
+    pub fn get(&self, id: u32) -> Option<Arc<Transfer>> {
+        self.transfers.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Spawns the transfer returned by `start` for a new id, and returns the
+    /// id right away. The transfer can be cancelled from then on, and its
+    /// result is emitted with `event` once it completes.
+    pub fn spawn<R, T, F>(
+        &self,
+        window: Window<R>,
+        event: &'static str,
+        direction: Direction,
+        url: &str,
+        start: impl FnOnce(Window<R>, u32) -> F,
+    ) -> u32
+    where
+        R: Runtime,
+        T: Serialize + Clone,
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (abort, registration) = AbortHandle::new_pair();
+        self.transfers.lock().unwrap().insert(
+            id,
+            Arc::new(Transfer {
+                direction,
+                url: url.into(),
+                progress: AtomicU64::new(0),
+                total: AtomicU64::new(0),
+                started: AtomicBool::new(false),
+                abort,
+            }),
+        );
+
+        let future = Abortable::new(start(window.clone(), id), registration);
+        tauri::async_runtime::spawn(async move {
+            let result = future.await.unwrap_or(Err(Error::Cancelled));
+            window
+                .state::<Transfers>()
+                .transfers
+                .lock()
+                .unwrap()
+                .remove(&id);
+            let _ = window.emit(event, DonePayload::new(id, result));
+        });
+        id
+    }
+}
+
+/// The result of a transfer, emitted once it completes.
+#[derive(Clone, Serialize)]
+struct DonePayload<T> {
+    id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T> DonePayload<T> {
+    fn new(id: u32, result: Result<T>) -> Self {
+        match result {
+            Ok(result) => Self {
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => Self {
+                id,
+                result: None,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+/// Cancels the transfer `id`, returning whether it was still in flight.
+#[command]
+pub(crate) fn cancel(transfers: State<'_, Transfers>, id: u32) -> bool {
+    match transfers.get(id) {
+        Some(transfer) => {
+            transfer.abort.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+#[command]
+pub(crate) fn list_transfers(transfers: State<'_, Transfers>) -> Vec<TransferInfo> {
+    let mut list = transfers
+        .transfers
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, transfer)| TransferInfo {
+            id: *id,
+            direction: transfer.direction,
+            url: transfer.url.clone(),
+            progress: transfer.progress.load(Ordering::Relaxed),
+            total: transfer.total.load(Ordering::Relaxed),
+            state: if transfer.started.load(Ordering::Relaxed) {
+                TransferState::Transferring
+            } else {
+                TransferState::Connecting
+            },
+        })
+        .collect::<Vec<_>>();
+    list.sort_by_key(|transfer| transfer.id);
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn cancels_transfers_by_their_id() {
+        let app = tauri::test::mock_builder()
+            .plugin(crate::init())
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .unwrap();
+        let window = app.get_window("main").unwrap();
+        let transfers = app.state::<Transfers>();
+
+        let start = |window: Window<_>| {
+            transfers.spawn(
+                window,
+                "download://done",
+                Direction::Download,
+                "http://localhost/file",
+                |_, _| futures_util::future::pending::<Result<()>>(),
+            )
+        };
+        let first = start(window.clone());
+        let second = start(window);
+        assert_ne!(first, second);
+
+        assert!(cancel(transfers.clone(), first));
+        tauri::async_runtime::block_on(async {
+            while transfers.get(first).is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        // cancelling a finished or unknown transfer does nothing
+        assert!(!cancel(transfers.clone(), first));
+        assert!(!cancel(transfers.clone(), second + 1));
+        assert!(transfers.get(second).is_some());
+    }
+}
@@ -6,7 +6,63 @@ export interface ConnectionConfig {
   maxMessageSize?: number;
   maxFrameSize?: number;
   acceptUnmaskedFrames?: boolean;
+  /**
+   * The maximum number of messages waiting to be written to the socket.
+   * Sending more fails with a {@link QueueFullError} until the socket caught up. Unlimited by default.
+   */
+  sendQueueSize?: number;
+  /** Headers of the handshake request, e.g. `Authorization`. They stay in the Rust process. */
   headers?: HeadersInit;
+  /** The subprotocols to request, in order of preference, e.g. `["graphql-transport-ws"]`. */
+  protocols?: string[];
+  tls?: TlsConfig;
+  /** Reconnects when the connection drops, unless it was closed with {@link WebSocket.disconnect}. */
+  reconnect?: ReconnectConfig;
+  /**
+   * Allows other windows to use the connection and receive its messages with {@link WebSocket.attach}.
+   * Otherwise only the window which connected can. The connection is closed when that window is destroyed.
+   */
+  shared?: boolean;
+  /** Pings the server periodically, closing the connection if it stops answering. */
+  keepalive?: KeepaliveConfig;
+}
+
+/** PEM data, given inline or as the path of a file to read it from, which must be allowed by the fs scope. */
+export type Pem = string | { path: string };
+
+export interface TlsConfig {
+  /** Trusted in addition to the system's root certificates. Each entry can hold several certificates. */
+  rootCertificates?: Pem[];
+  /** A client certificate chain, leaf first, and its PKCS #8 key for mutual TLS. */
+  identity?: { certificate: Pem; key: Pem };
+  /** The name sent with SNI and verified against the server's certificate, instead of the host of the URL. */
+  serverName?: string;
+  /**
+   * Accepts any certificate, only meant for local development.
+   * Rejected unless allowed with `Builder::allow_invalid_certs` on the Rust side.
+   */
+  dangerouslyAcceptInvalidCerts?: boolean;
+}
+
+export interface KeepaliveConfig {
+  /** How often a ping is sent, in milliseconds. `0` disables the keepalive. Defaults to 30000. */
+  pingInterval?: number;
+  /** Closes the connection when a ping isn't answered within this time, in milliseconds. Defaults to 10000. */
+  pongTimeout?: number;
+}
+
+export interface ReconnectConfig {
+  /** Delay before the first attempt, in milliseconds. Doubles on every attempt. Defaults to 500. */
+  initialDelay?: number;
+  /** The maximum delay between two attempts, in milliseconds. Defaults to 30000. */
+  maxDelay?: number;
+  /** Gives up after this many attempts. Retries forever by default. */
+  maxAttempts?: number;
+  /**
+   * How many messages sent while reconnecting are queued until the connection is back.
+   * With `0`, the default, sending fails right away.
+   */
+  queueSize?: number;
 }
 
 export interface MessageKind<T, D> {
@@ -14,76 +70,261 @@ export interface MessageKind<T, D> {
   data: D;
 }
 
+/**
+ * A close frame. Received frames without a code have the code 1005, and
+ * connections ending without a close frame are reported with the code 1006.
+ */
 export interface CloseFrame {
   code: number;
   reason: string;
 }
 
+/** Thrown by {@link WebSocket.send} when the send queue, or the queue while reconnecting, is full. */
+export class QueueFullError extends Error {
+  constructor(message: string) {
+    super(message);
+    this.name = "QueueFullError";
+  }
+}
+
+const QUEUE_FULL_ERRORS = [
+  "the send queue is full",
+  "too many messages queued while reconnecting",
+];
+
 export type Message =
   | MessageKind<"Text", string>
-  | MessageKind<"Binary", number[]>
+  | MessageKind<"Binary", Uint8Array>
   | MessageKind<"Ping", number[]>
   | MessageKind<"Pong", number[]>
   | MessageKind<"Close", CloseFrame | null>;
 
+export type ConnectionEvent =
+  | MessageKind<"Reconnecting", { attempt: number; delay: number }>
+  | MessageKind<"Reconnected", { attempts: number }>
+  | MessageKind<"GaveUp", { attempts: number }>;
+
+const CONNECTION_EVENTS = ["Reconnecting", "Reconnected", "GaveUp"];
+
+function isConnectionEvent(
+  message: Message | ConnectionEvent,
+): message is ConnectionEvent {
+  return (
+    typeof message === "object" &&
+    message !== null &&
+    CONNECTION_EVENTS.includes(message.type)
+  );
+}
+
+/** Binary payloads cross the IPC as base64, which is much faster than a number per byte. */
+function toBase64(bytes: Uint8Array): string {
+  let binary = "";
+  // `String.fromCharCode` takes the bytes as arguments, which are limited in number
+  for (let i = 0; i < bytes.length; i += 0x8000) {
+    binary += String.fromCharCode(...bytes.subarray(i, i + 0x8000));
+  }
+  return btoa(binary);
+}
+
+function fromBase64(data: string): Uint8Array {
+  const binary = atob(data);
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return bytes;
+}
+
+function toBytes(data: ArrayBuffer | ArrayBufferView): Uint8Array {
+  return data instanceof ArrayBuffer
+    ? new Uint8Array(data)
+    : new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+}
+
+interface ConnectionInfo {
+  id: number;
+  protocol: string | null;
+  headers: Array<[string, string]>;
+}
+
 export default class WebSocket {
   id: number;
+  /** The subprotocol selected by the server, or `null` if none was. */
+  protocol: string | null = null;
+  /** The headers of the server's handshake response. */
+  responseHeaders: Headers = new Headers();
   private readonly listeners: Array<(arg: Message) => void>;
+  private readonly eventListeners: Array<(arg: ConnectionEvent) => void>;
+  private readonly reconnectHooks: Array<() => Promise<void> | void>;
 
-  constructor(id: number, listeners: Array<(arg: Message) => void>) {
+  constructor(
+    id: number,
+    listeners: Array<(arg: Message) => void>,
+    eventListeners: Array<(arg: ConnectionEvent) => void> = [],
+  ) {
     this.id = id;
     this.listeners = listeners;
+    this.eventListeners = eventListeners;
+    this.reconnectHooks = [];
+  }
+
+  /** Creates the callback receiving the messages and events of a connection. */
+  private static handler(): {
+    callbackFunction: number;
+    create: (id: number) => WebSocket;
+  } {
+    const listeners: Array<(arg: Message) => void> = [];
+    const eventListeners: Array<(arg: ConnectionEvent) => void> = [];
+    let ws: WebSocket | undefined;
+    const handler = (message: Message | ConnectionEvent): void => {
+      if (isConnectionEvent(message)) {
+        eventListeners.forEach((l) => l(message));
+        if (message.type === "Reconnected") {
+          void ws?.resume();
+        }
+      } else {
+        if (message.type === "Binary") {
+          message.data = fromBase64(message.data as unknown as string);
+        }
+        listeners.forEach((l) => l(message));
+      }
+    };
+
+    return {
+      callbackFunction: transformCallback(handler),
+      create: (id) => {
+        ws = new WebSocket(id, listeners, eventListeners);
+        return ws;
+      },
+    };
   }
 
   static async connect(
     url: string,
     config?: ConnectionConfig,
   ): Promise<WebSocket> {
-    const listeners: Array<(arg: Message) => void> = [];
-    const handler = (message: Message): void => {
-      listeners.forEach((l) => l(message));
-    };
+    const { callbackFunction, create } = WebSocket.handler();
 
     if (config?.headers) {
       config.headers = Array.from(new Headers(config.headers).entries());
     }
 
-    return await invoke<number>("plugin:websocket|connect", {
+    return await invoke<ConnectionInfo>("plugin:websocket|connect", {
       url,
-      callbackFunction: transformCallback(handler),
+      callbackFunction,
       config,
-    }).then((id) => new WebSocket(id, listeners));
+    }).then((info) => {
+      const ws = create(info.id);
+      ws.protocol = info.protocol;
+      ws.responseHeaders = new Headers(info.headers);
+      return ws;
+    });
+  }
+
+  /**
+   * Uses a connection opened by another window with `shared: true`, e.g.
+   * passing its `id` in an event. The messages are then received by both windows.
+   */
+  static async attach(id: number): Promise<WebSocket> {
+    const { callbackFunction, create } = WebSocket.handler();
+    await invoke("plugin:websocket|attach", { id, callbackFunction });
+    return create(id);
   }
 
   addListener(cb: (arg: Message) => void): void {
     this.listeners.push(cb);
   }
 
-  async send(message: Message | string | number[]): Promise<void> {
-    let m: Message;
+  /** Listens to the `Reconnecting`, `Reconnected` and `GaveUp` events of a reconnecting connection. */
+  addEventListener(cb: (arg: ConnectionEvent) => void): void {
+    this.eventListeners.push(cb);
+  }
+
+  /**
+   * Registers a hook run after every reconnection, before the messages queued
+   * while disconnected are sent, e.g. to authenticate or resubscribe again.
+   */
+  onReconnect(hook: () => Promise<void> | void): void {
+    this.reconnectHooks.push(hook);
+  }
+
+  private async resume(): Promise<void> {
+    try {
+      for (const hook of this.reconnectHooks) {
+        await hook();
+      }
+    } finally {
+      await invoke("plugin:websocket|resume", { id: this.id });
+    }
+  }
+
+  /**
+   * Sends a message. Strings are sent as text frames, and numeric arrays,
+   * `ArrayBuffer`s, typed arrays and `Blob`s as binary frames.
+   */
+  async send(
+    message: Message | string | number[] | ArrayBuffer | ArrayBufferView | Blob,
+  ): Promise<void> {
+    let m: Message | MessageKind<"Binary", string | number[]>;
     if (typeof message === "string") {
       m = { type: "Text", data: message };
+    } else if (message instanceof Blob) {
+      m = {
+        type: "Binary",
+        data: toBase64(new Uint8Array(await message.arrayBuffer())),
+      };
+    } else if (ArrayBuffer.isView(message) || message instanceof ArrayBuffer) {
+      m = { type: "Binary", data: toBase64(toBytes(message)) };
     } else if (typeof message === "object" && "type" in message) {
-      m = message;
+      m =
+        message.type === "Binary" && !Array.isArray(message.data)
+          ? { type: "Binary", data: toBase64(toBytes(message.data)) }
+          : message;
     } else if (Array.isArray(message)) {
       m = { type: "Binary", data: message };
     } else {
       throw new Error(
-        "invalid `message` type, expected a `{ type: string, data: any }` object, a string or a numeric array",
+        "invalid `message` type, expected a `{ type: string, data: any }` object, a string, a numeric array, an `ArrayBuffer`, a typed array or a `Blob`",
       );
     }
-    return await invoke("plugin:websocket|send", {
+    return await invoke<void>("plugin:websocket|send", {
       id: this.id,
       message: m,
+    }).catch((e) => {
+      throw typeof e === "string" && QUEUE_FULL_ERRORS.includes(e)
+        ? new QueueFullError(e)
+        : e;
+    });
+  }
+
+  /** Resolves once every message sent before is written to the socket. */
+  async flush(): Promise<void> {
+    await invoke("plugin:websocket|flush", { id: this.id });
+  }
+
+  /**
+   * The round-trip time measured by the last keepalive ping, in milliseconds,
+   * or `null` if the keepalive is disabled or no pong was received yet.
+   */
+  async getLatency(): Promise<number | null> {
+    return await invoke<number | null>("plugin:websocket|get_latency", {
+      id: this.id,
     });
   }
 
-  async disconnect(): Promise<void> {
+  /**
+   * Closes the connection with a close frame, which also stops reconnecting it.
+   * The peer's close frame is then received as a `Close` message.
+   */
+  async disconnect(
+    code = 1000,
+    reason = "Disconnected by client",
+  ): Promise<void> {
     return await this.send({
       type: "Close",
       data: {
-        code: 1000,
-        reason: "Disconnected by client",
+        code,
+        reason,
       },
     });
   }
@@ -0,0 +1,73 @@
+//! Binary payloads cross the IPC as base64 strings, which are much cheaper to
+//! build and parse than a JSON array with a number per byte.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+/// Accepts a base64 string, or an array of bytes as sent by older versions
+/// of the guest bindings.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Bytes {
+        Base64(String),
+        Array(Vec<u8>),
+    }
+
+    match Bytes::deserialize(deserializer)? {
+        Bytes::Base64(data) => STANDARD.decode(data).map_err(serde::de::Error::custom),
+        Bytes::Array(bytes) => Ok(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Base64(#[serde(with = "super")] Vec<u8>);
+
+    #[derive(Serialize, Deserialize)]
+    struct Array(Vec<u8>);
+
+    #[test]
+    fn round_trips_base64_and_arrays() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        let json = serde_json::to_string(&Base64(bytes.clone())).unwrap();
+        assert_eq!(serde_json::from_str::<Base64>(&json).unwrap().0, bytes);
+        let json = serde_json::to_string(&Array(bytes.clone())).unwrap();
+        assert_eq!(serde_json::from_str::<Base64>(&json).unwrap().0, bytes);
+    }
+
+    /// The time to serialize a frame into the JSON the webview is called
+    /// with, and to read a sent frame from the JSON of the command arguments.
+    fn ipc_time<T: Serialize + for<'de> Deserialize<'de>>(frame: impl Fn() -> T) -> Duration {
+        const RUNS: u32 = 10;
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            let json = serde_json::to_string(&frame()).unwrap();
+            serde_json::from_str::<T>(&json).unwrap();
+        }
+        start.elapsed() / RUNS
+    }
+
+    /// Run with `cargo test -p tauri-plugin-websocket --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_1mb_frames() {
+        let bytes: Vec<u8> = (0..1024 * 1024).map(|_| rand::random()).collect();
+        let array = ipc_time(|| Array(bytes.clone()));
+        let base64 = ipc_time(|| Base64(bytes.clone()));
+        println!(
+            "1 MB binary frame: {array:?} as a number array, {base64:?} as base64 ({:.1}x)",
+            array.as_secs_f64() / base64.as_secs_f64()
+        );
+        assert!(base64 < array);
+    }
+}
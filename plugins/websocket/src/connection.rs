@@ -0,0 +1,505 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::{
+    api::ipc::{format_callback, CallbackFn},
+    Manager, Runtime, Window,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    time::{interval_at, Instant, Interval, MissedTickBehavior},
+};
+use tokio_native_tls::TlsConnector;
+use tokio_tungstenite::{
+    client_async_with_config,
+    tungstenite::{
+        error::UrlError,
+        handshake::client::{Request, Response},
+        protocol::{frame::coding::CloseCode, CloseFrame as ProtocolCloseFrame, WebSocketConfig},
+        Error as WsError, Message,
+    },
+    MaybeTlsStream,
+};
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    CloseFrame, ConnectionManager, Error, Id, MessageFilter, Result, WebSocket, WebSocketMessage,
+};
+
+/// Reported when a connection ends without a close frame. It is never sent.
+const ABNORMAL_CLOSURE: u16 = 1006;
+
+#[derive(Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnection attempt, in milliseconds. Doubles
+    /// on every attempt.
+    pub initial_delay: u64,
+    /// The maximum delay between two attempts, in milliseconds.
+    pub max_delay: u64,
+    /// Gives up after this many attempts. Retries forever if `None`.
+    pub max_attempts: Option<u32>,
+    /// How many messages sent while reconnecting are queued until the
+    /// connection is back. With `0`, sending fails right away.
+    pub queue_size: usize,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: 500,
+            max_delay: 30_000,
+            max_attempts: None,
+            queue_size: 0,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// The delay before the `attempt`th (1-based) attempt, with jitter so
+    /// disconnected clients don't all reconnect at the same time.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2_u64.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        Duration::from_millis(delay / 2 + rand::random::<u64>() % (delay / 2 + 1))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct KeepaliveConfig {
+    /// How often a ping is sent, in milliseconds. `0` disables the keepalive.
+    pub ping_interval: u64,
+    /// Closes the connection when a ping isn't answered within this time, in
+    /// milliseconds.
+    pub pong_timeout: u64,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: 30_000,
+            pong_timeout: 10_000,
+        }
+    }
+}
+
+/// The pings sent to keep a connection alive, and the latency they measured.
+struct Keepalive {
+    pong_timeout: Duration,
+    ticks: Interval,
+    /// The payload and send time of the ping awaiting its pong.
+    pending: Option<(u64, Instant)>,
+    sent: u64,
+    latency: Option<Duration>,
+}
+
+impl Keepalive {
+    fn new(config: &KeepaliveConfig) -> Option<Self> {
+        if config.ping_interval == 0 {
+            return None;
+        }
+        let period = Duration::from_millis(config.ping_interval);
+        let mut ticks = interval_at(Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Some(Self {
+            pong_timeout: Duration::from_millis(config.pong_timeout),
+            ticks,
+            pending: None,
+            sent: 0,
+            latency: None,
+        })
+    }
+
+    /// The payload of the next ping, unless the last one is still unanswered.
+    fn ping(&mut self) -> Option<Vec<u8>> {
+        if self.pending.is_some() {
+            return None;
+        }
+        self.sent += 1;
+        self.pending = Some((self.sent, Instant::now()));
+        Some(self.sent.to_be_bytes().to_vec())
+    }
+
+    /// Measures the latency if `payload` answers the pending ping, returning
+    /// whether it did. Pongs to the app's own pings are left alone.
+    fn pong(&mut self, payload: &[u8]) -> bool {
+        match self.pending {
+            Some((sent, at)) if payload == &sent.to_be_bytes()[..] => {
+                self.latency = Some(at.elapsed());
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.pending.map(|(_, at)| at + self.pong_timeout)
+    }
+}
+
+/// The subprotocol selected by the server in its handshake `response`.
+pub(crate) fn protocol(response: &Response) -> Option<&str> {
+    response
+        .headers()
+        .get(http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+}
+
+async fn tick(keepalive: &mut Option<Keepalive>) {
+    match keepalive {
+        Some(keepalive) => {
+            keepalive.ticks.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Events about the connection itself, delivered with the messages.
+#[derive(Serialize)]
+#[serde(tag = "type", content = "data")]
+enum ConnectionEvent {
+    Reconnecting {
+        attempt: u32,
+        delay: u64,
+    },
+    /// Queued messages are only sent once the app resumed the connection.
+    Reconnected {
+        attempts: u32,
+    },
+    GaveUp {
+        attempts: u32,
+    },
+}
+
+pub(crate) enum Command {
+    /// Sends a message, replying once it is written.
+    Send(WebSocketMessage, oneshot::Sender<Result<()>>),
+    /// Replies once the messages sent before are written to the socket.
+    Flush(oneshot::Sender<Result<()>>),
+    /// Sends the messages queued while reconnecting.
+    Resume,
+    /// Replies with the last round-trip time measured by the keepalive, in
+    /// milliseconds.
+    Latency(oneshot::Sender<Option<f64>>),
+    /// Delivers the messages to the window with this label too.
+    Attach(String, CallbackFn),
+    /// Stops delivering the messages to the window with this label.
+    Detach(String),
+    /// Closes the connection, as the window which opened it is gone.
+    OwnerDestroyed,
+}
+
+type Queue = VecDeque<(WebSocketMessage, oneshot::Sender<Result<()>>)>;
+
+/// Owns a connection and everything sent or received on it.
+pub(crate) struct Connection<R: Runtime> {
+    pub id: Id,
+    /// The window which opened the connection.
+    pub window: Window<R>,
+    /// The windows receiving the messages, and their callbacks.
+    pub subscribers: std::sync::Mutex<Vec<(Window<R>, CallbackFn)>>,
+    pub filter: Option<Arc<MessageFilter>>,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub protocols: Vec<String>,
+    pub tls: TlsConnector,
+    /// Overrides the host of the URL for SNI and certificate verification.
+    pub server_name: Option<String>,
+    pub config: Option<WebSocketConfig>,
+    pub reconnect: Option<ReconnectConfig>,
+    pub keepalive: Option<KeepaliveConfig>,
+    /// The number of `Send` commands not handled yet, shared with the
+    /// connection's [`Handle`](crate::Handle).
+    pub queued: Arc<AtomicUsize>,
+}
+
+impl<R: Runtime> Connection<R> {
+    pub fn request(&self) -> Result<Request> {
+        crate::request(&self.url, &self.headers, &self.protocols)
+    }
+
+    /// Connects with the headers, subprotocols and TLS configuration of the
+    /// connection. Invalid headers fail before connecting.
+    pub async fn connect(&self) -> Result<(WebSocket, Response)> {
+        let request = self.request()?;
+        let uri = request.uri();
+        let secure = match uri.scheme_str() {
+            Some("wss") => true,
+            Some("ws") => false,
+            _ => return Err(WsError::Url(UrlError::UnsupportedUrlScheme).into()),
+        };
+        let host = uri
+            .host()
+            .ok_or(WsError::Url(UrlError::NoHostName))?
+            // IPv6 addresses are bracketed in URLs
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(Error::Connect)?;
+        let stream = if secure {
+            let domain = self.server_name.as_deref().unwrap_or(host);
+            let stream = self.tls.connect(domain, stream).await.map_err(Error::Tls)?;
+            MaybeTlsStream::NativeTls(stream)
+        } else {
+            MaybeTlsStream::Plain(stream)
+        };
+
+        let (ws_stream, response) = client_async_with_config(request, stream, self.config).await?;
+        // tungstenite doesn't check the subprotocol selected by the server
+        if let Some(protocol) = protocol(&response) {
+            if !self.protocols.iter().any(|p| p == protocol) {
+                return Err(Error::UnexpectedProtocol(protocol.into()));
+            }
+        }
+        Ok((ws_stream, response))
+    }
+
+    pub async fn run(self, mut ws: WebSocket, mut commands: mpsc::UnboundedReceiver<Command>) {
+        let mut queue = Queue::new();
+        // set once the app closed the connection, so it is not reconnected
+        let mut closing = false;
+
+        loop {
+            let mut keepalive = self.keepalive.as_ref().and_then(Keepalive::new);
+            let mut close_reported = false;
+            loop {
+                let deadline = keepalive.as_ref().and_then(Keepalive::deadline);
+                tokio::select! {
+                    message = ws.next() => match message {
+                        Some(Ok(Message::Pong(payload)))
+                            if keepalive.as_mut().map_or(false, |k| k.pong(&payload)) => {}
+                        Some(Ok(message)) => {
+                            close_reported |= matches!(message, Message::Close(_));
+                            let message = match &self.filter {
+                                Some(filter) => filter(&self.url, message),
+                                None => Some(message),
+                            };
+                            if let Some(message) = message {
+                                self.emit(&crate::to_json(message));
+                            }
+                        }
+                        Some(Err(e)) => {
+                            self.emit(&Error::from(e));
+                            break;
+                        }
+                        None => break,
+                    },
+                    command = commands.recv() => match command {
+                        Some(Command::Send(message, reply)) => {
+                            closing |= matches!(message, WebSocketMessage::Close(_));
+                            let _ = reply.send(ws.send(message.into()).await.map_err(Into::into));
+                            self.queued.fetch_sub(1, Ordering::Relaxed);
+                        }
+                        Some(Command::Flush(reply)) => {
+                            let _ = reply.send(ws.flush().await.map_err(Into::into));
+                        }
+                        Some(Command::Resume) => {
+                            while let Some((message, reply)) = queue.pop_front() {
+                                let _ = reply.send(ws.send(message.into()).await.map_err(Into::into));
+                            }
+                        }
+                        Some(Command::Latency(reply)) => {
+                            let latency = keepalive.as_ref().and_then(|k| k.latency);
+                            let _ = reply.send(latency.map(|l| l.as_secs_f64() * 1000.0));
+                        }
+                        Some(Command::Attach(label, callback_function)) => {
+                            self.attach(label, callback_function);
+                        }
+                        Some(Command::Detach(label)) => self.detach(&label),
+                        Some(Command::OwnerDestroyed) => {
+                            let frame = CloseFrame {
+                                code: CloseCode::Away.into(),
+                                reason: "window destroyed".into(),
+                            };
+                            let _ = ws.close(Some(ProtocolCloseFrame {
+                                code: frame.code.into(),
+                                reason: frame.reason.clone().into(),
+                            })).await;
+                            // the windows the connection is shared with are told right away
+                            self.emit(&WebSocketMessage::Close(Some(frame)));
+                            closing = true;
+                            close_reported = true;
+                            break;
+                        }
+                        None => break,
+                    },
+                    _ = tick(&mut keepalive) => {
+                        if let Some(payload) = keepalive.as_mut().and_then(Keepalive::ping) {
+                            if let Err(e) = ws.send(Message::Ping(payload)).await {
+                                self.emit(&Error::from(e));
+                                break;
+                            }
+                        }
+                    }
+                    _ = sleep_until(deadline) => {
+                        // the connection is considered dead, and dropped
+                        let timeout = keepalive.as_ref().map_or(0, |k| k.pong_timeout.as_millis());
+                        self.emit(&Error::PongTimeout(timeout as u64));
+                        break;
+                    }
+                }
+            }
+
+            if !close_reported {
+                self.emit(&WebSocketMessage::Close(Some(CloseFrame {
+                    code: ABNORMAL_CLOSURE,
+                    reason: String::new(),
+                })));
+            }
+
+            let reconnect = match &self.reconnect {
+                Some(reconnect) if !closing => reconnect,
+                _ => break,
+            };
+            match self.reconnect(reconnect, &mut commands, &mut queue).await {
+                Some(reconnected) => ws = reconnected,
+                None => break,
+            }
+        }
+
+        self.window
+            .state::<ConnectionManager>()
+            .0
+            .lock()
+            .await
+            .remove(&self.id);
+        for (_, reply) in queue {
+            let _ = reply.send(Err(Error::NotConnected));
+        }
+    }
+
+    /// Reconnects with exponential backoff, unless the app closes the
+    /// connection in the meantime or the attempts run out.
+    async fn reconnect(
+        &self,
+        config: &ReconnectConfig,
+        commands: &mut mpsc::UnboundedReceiver<Command>,
+        queue: &mut Queue,
+    ) -> Option<WebSocket> {
+        let mut attempt = 0;
+        loop {
+            if config.max_attempts.map_or(false, |max| attempt >= max) {
+                self.emit(&ConnectionEvent::GaveUp { attempts: attempt });
+                for (_, reply) in queue.drain(..) {
+                    let _ = reply.send(Err(Error::GaveUp(attempt)));
+                }
+                return None;
+            }
+            attempt += 1;
+
+            let delay = config.delay(attempt);
+            self.emit(&ConnectionEvent::Reconnecting {
+                attempt,
+                delay: delay.as_millis() as u64,
+            });
+            self.offline(tokio::time::sleep(delay), config, commands, queue)
+                .await?;
+
+            if let Ok((ws, _)) = self
+                .offline(self.connect(), config, commands, queue)
+                .await?
+            {
+                self.emit(&ConnectionEvent::Reconnected { attempts: attempt });
+                return Some(ws);
+            }
+        }
+    }
+
+    /// Waits for `future` while disconnected, queueing or rejecting the
+    /// messages sent in the meantime. Returns `None` if the connection was
+    /// closed by the app.
+    async fn offline<F: Future>(
+        &self,
+        future: F,
+        config: &ReconnectConfig,
+        commands: &mut mpsc::UnboundedReceiver<Command>,
+        queue: &mut Queue,
+    ) -> Option<F::Output> {
+        tokio::pin!(future);
+        loop {
+            tokio::select! {
+                output = &mut future => return Some(output),
+                command = commands.recv() => match command {
+                    Some(Command::Send(WebSocketMessage::Close(_), reply)) => {
+                        self.queued.fetch_sub(1, Ordering::Relaxed);
+                        let _ = reply.send(Ok(()));
+                        return None;
+                    }
+                    Some(Command::Send(message, reply)) => {
+                        self.queued.fetch_sub(1, Ordering::Relaxed);
+                        if queue.len() < config.queue_size {
+                            queue.push_back((message, reply));
+                        } else if config.queue_size == 0 {
+                            let _ = reply.send(Err(Error::NotConnected));
+                        } else {
+                            let _ = reply.send(Err(Error::QueueFull));
+                        }
+                    }
+                    Some(Command::Flush(reply)) => {
+                        // the queued messages are written once reconnected
+                        let _ = reply.send(if queue.is_empty() {
+                            Ok(())
+                        } else {
+                            Err(Error::NotConnected)
+                        });
+                    }
+                    Some(Command::Resume) => {}
+                    Some(Command::Latency(reply)) => {
+                        let _ = reply.send(None);
+                    }
+                    Some(Command::Attach(label, callback_function)) => {
+                        self.attach(label, callback_function);
+                    }
+                    Some(Command::Detach(label)) => self.detach(&label),
+                    Some(Command::OwnerDestroyed) | None => return None,
+                },
+            }
+        }
+    }
+
+    fn attach(&self, label: String, callback_function: CallbackFn) {
+        if let Some(window) = self.window.get_window(&label) {
+            let mut subscribers = self.subscribers.lock().unwrap();
+            subscribers.retain(|(window, _)| window.label() != label);
+            subscribers.push((window, callback_function));
+        }
+    }
+
+    fn detach(&self, label: &str) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|(window, _)| window.label() != label);
+    }
+
+    fn emit(&self, value: &impl Serialize) {
+        for (window, callback_function) in self.subscribers.lock().unwrap().iter() {
+            let js = format_callback(*callback_function, value)
+                .expect("unable to serialize websocket message");
+            let _ = window.eval(js.as_str());
+        }
+    }
+}
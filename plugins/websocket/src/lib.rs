@@ -1,28 +1,45 @@
-use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use http::header::{HeaderName, HeaderValue};
 use serde::{ser::Serializer, Deserialize, Serialize};
 use tauri::{
-    api::ipc::{format_callback, CallbackFn},
+    api::ipc::CallbackFn,
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    Manager, Runtime, State, Window,
+    Manager, RunEvent, Runtime, State, Window, WindowEvent,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot, Mutex},
 };
-use tokio::{net::TcpStream, sync::Mutex};
 use tokio_tungstenite::{
-    connect_async_with_config,
     tungstenite::{
         client::IntoClientRequest,
-        protocol::{CloseFrame as ProtocolCloseFrame, WebSocketConfig},
-        Message,
+        handshake::client::Request,
+        protocol::{frame::coding::CloseCode, CloseFrame as ProtocolCloseFrame, WebSocketConfig},
     },
     MaybeTlsStream, WebSocketStream,
 };
 
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+mod binary;
+mod connection;
+mod tls;
+
+use connection::{Command, Connection};
+pub use connection::{KeepaliveConfig, ReconnectConfig};
+pub use tls::{ClientIdentity, Pem, TlsConfig};
+pub use tokio_tungstenite::tungstenite::Message;
+
+/// Drops or transforms the messages received on a connection to `url`,
+/// before they are sent to the webview.
+pub type MessageFilter = dyn Fn(&str, Message) -> Option<Message> + Send + Sync;
 
 type Id = u32;
 type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
-type WebSocketWriter = SplitSink<WebSocket, Message>;
 type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error)]
@@ -31,10 +48,36 @@ enum Error {
     Websocket(#[from] tokio_tungstenite::tungstenite::Error),
     #[error("connection not found for the given id: {0}")]
     ConnectionNotFound(Id),
+    #[error("connection {0} is not shared with other windows")]
+    NotShared(Id),
     #[error(transparent)]
     InvalidHeaderValue(#[from] tokio_tungstenite::tungstenite::http::header::InvalidHeaderValue),
     #[error(transparent)]
     InvalidHeaderName(#[from] tokio_tungstenite::tungstenite::http::header::InvalidHeaderName),
+    #[error("not connected, the connection is reconnecting")]
+    NotConnected,
+    #[error("too many messages queued while reconnecting")]
+    QueueFull,
+    #[error("the send queue is full")]
+    SendQueueFull,
+    #[error("close code {0} can't be sent")]
+    InvalidCloseCode(u16),
+    #[error("gave up reconnecting after {0} attempts")]
+    GaveUp(u32),
+    #[error("could not connect to the server: {0}")]
+    Connect(std::io::Error),
+    #[error("TLS handshake failed: {0}")]
+    Tls(native_tls::Error),
+    #[error("invalid TLS configuration: {0}")]
+    InvalidTlsConfig(String),
+    #[error("path not allowed on the configured scope: {}", .0.display())]
+    PathNotAllowed(std::path::PathBuf),
+    #[error("`dangerouslyAcceptInvalidCerts` is not allowed, see `Builder::allow_invalid_certs`")]
+    InvalidCertsNotAllowed,
+    #[error("the server selected the subprotocol {0:?}, which was not requested")]
+    UnexpectedProtocol(String),
+    #[error("no pong received within {0}ms, closing the connection")]
+    PongTimeout(u64),
 }
 
 impl Serialize for Error {
@@ -46,8 +89,33 @@ impl Serialize for Error {
     }
 }
 
+/// The channels to the tasks owning the connections.
 #[derive(Default)]
-struct ConnectionManager(Mutex<HashMap<Id, WebSocketWriter>>);
+struct ConnectionManager(Mutex<HashMap<Id, Handle>>);
+
+struct Handle {
+    commands: mpsc::UnboundedSender<Command>,
+    /// The number of messages sent and not written to the socket yet.
+    queued: Arc<AtomicUsize>,
+    send_queue_size: Option<usize>,
+    /// The labels of the windows receiving the messages, the owner first.
+    /// Only they can use the connection.
+    windows: Vec<String>,
+    shared: bool,
+}
+
+/// The connection `id`, if the window `label` can use it.
+fn handle<'a>(connections: &'a HashMap<Id, Handle>, id: Id, label: &str) -> Option<&'a Handle> {
+    connections
+        .get(&id)
+        .filter(|handle| handle.windows.iter().any(|window| window == label))
+}
+
+/// The settings of the [`Builder`].
+struct Settings {
+    allow_invalid_certs: bool,
+    filter: Option<Arc<MessageFilter>>,
+}
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -59,6 +127,21 @@ pub struct ConnectionConfig {
     #[serde(default)]
     pub accept_unmasked_frames: bool,
     pub headers: Option<Vec<(String, String)>>,
+    /// The maximum number of messages waiting to be written to the socket.
+    /// Sending more fails until the socket caught up. Unlimited by default.
+    pub send_queue_size: Option<usize>,
+    /// The subprotocols to request, in order of preference.
+    pub protocols: Option<Vec<String>>,
+    pub tls: Option<TlsConfig>,
+    /// Reconnects when the connection drops, unless it was closed by the app.
+    pub reconnect: Option<ReconnectConfig>,
+    /// Pings the server periodically, closing the connection if it stops
+    /// answering.
+    pub keepalive: Option<KeepaliveConfig>,
+    /// Allows other windows to receive the messages and use the connection,
+    /// after attaching to it. Otherwise only the window which connected can.
+    #[serde(default)]
+    pub shared: bool,
 }
 
 impl From<ConnectionConfig> for WebSocketConfig {
@@ -88,109 +171,351 @@ struct CloseFrame {
 #[serde(tag = "type", content = "data")]
 enum WebSocketMessage {
     Text(String),
-    Binary(Vec<u8>),
+    Binary(#[serde(with = "binary")] Vec<u8>),
     Ping(Vec<u8>),
     Pong(Vec<u8>),
     Close(Option<CloseFrame>),
 }
 
+impl From<WebSocketMessage> for Message {
+    fn from(message: WebSocketMessage) -> Self {
+        match message {
+            WebSocketMessage::Text(t) => Message::Text(t),
+            WebSocketMessage::Binary(t) => Message::Binary(t),
+            WebSocketMessage::Ping(t) => Message::Ping(t),
+            WebSocketMessage::Pong(t) => Message::Pong(t),
+            WebSocketMessage::Close(t) => Message::Close(t.map(|v| ProtocolCloseFrame {
+                code: v.code.into(),
+                reason: std::borrow::Cow::Owned(v.reason),
+            })),
+        }
+    }
+}
+
+fn to_json(message: Message) -> serde_json::Value {
+    match message {
+        Message::Text(t) => serde_json::to_value(WebSocketMessage::Text(t)).unwrap(),
+        Message::Binary(t) => serde_json::to_value(WebSocketMessage::Binary(t)).unwrap(),
+        Message::Ping(t) => serde_json::to_value(WebSocketMessage::Ping(t)).unwrap(),
+        Message::Pong(t) => serde_json::to_value(WebSocketMessage::Pong(t)).unwrap(),
+        // a close frame without a code is reported as 1005, "no status received"
+        Message::Close(t) => serde_json::to_value(WebSocketMessage::Close(Some(t.map_or(
+            CloseFrame {
+                code: CloseCode::Status.into(),
+                reason: String::new(),
+            },
+            |v| CloseFrame {
+                code: v.code.into(),
+                reason: v.reason.into_owned(),
+            },
+        ))))
+        .unwrap(),
+        Message::Frame(_) => serde_json::Value::Null, // This value can't be recieved.
+    }
+}
+
+fn request(url: &str, headers: &[(String, String)], protocols: &[String]) -> Result<Request> {
+    let mut request = url.into_client_request()?;
+
+    for (k, v) in headers {
+        let header_name = HeaderName::from_str(k.as_str())?;
+        let header_value = HeaderValue::from_str(v.as_str())?;
+        request.headers_mut().insert(header_name, header_value);
+    }
+    if !protocols.is_empty() {
+        let header_value = HeaderValue::from_str(&protocols.join(", "))?;
+        request
+            .headers_mut()
+            .insert(http::header::SEC_WEBSOCKET_PROTOCOL, header_value);
+    }
+
+    Ok(request)
+}
+
+/// What the server answered to the handshake of a new connection.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectionInfo {
+    id: Id,
+    /// The subprotocol selected by the server, if any.
+    protocol: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
 #[tauri::command]
 async fn connect<R: Runtime>(
     window: Window<R>,
     url: String,
     callback_function: CallbackFn,
     config: Option<ConnectionConfig>,
-) -> Result<Id> {
+) -> Result<ConnectionInfo> {
     let id = rand::random();
-    let mut request = url.into_client_request()?;
+    let mut config = config;
+    let headers = config
+        .as_mut()
+        .and_then(|c| c.headers.take())
+        .unwrap_or_default();
+    let protocols = config
+        .as_mut()
+        .and_then(|c| c.protocols.take())
+        .unwrap_or_default();
+    let tls = config
+        .as_mut()
+        .and_then(|c| c.tls.take())
+        .unwrap_or_default();
+    let settings = window.state::<Settings>();
+    let reconnect = config.as_mut().and_then(|c| c.reconnect.take());
+    let keepalive = config.as_mut().and_then(|c| c.keepalive.take());
+    let send_queue_size = config.as_ref().and_then(|c| c.send_queue_size);
+    let shared = config.as_ref().map_or(false, |c| c.shared);
+    let queued = Arc::new(AtomicUsize::new(0));
+
+    let connection = Connection {
+        id,
+        window: window.clone(),
+        subscribers: std::sync::Mutex::new(vec![(window.clone(), callback_function)]),
+        filter: settings.filter.clone(),
+        url,
+        headers,
+        protocols,
+        tls: tls
+            .connector(&window.fs_scope(), settings.allow_invalid_certs)
+            .await?,
+        server_name: tls.server_name,
+        config: config.map(Into::into),
+        reconnect,
+        keepalive,
+        queued: queued.clone(),
+    };
+    let (ws_stream, response) = connection.connect().await?;
+
+    let (commands, receiver) = mpsc::unbounded_channel();
+    let manager = window.state::<ConnectionManager>();
+    manager.0.lock().await.insert(
+        id,
+        Handle {
+            commands,
+            queued,
+            send_queue_size,
+            windows: vec![window.label().into()],
+            shared,
+        },
+    );
+    tauri::async_runtime::spawn(connection.run(ws_stream, receiver));
+
+    Ok(ConnectionInfo {
+        id,
+        protocol: connection::protocol(&response).map(Into::into),
+        headers: response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect(),
+    })
+}
 
-    if let Some(headers) = config.as_ref().and_then(|c| c.headers.as_ref()) {
-        for (k, v) in headers {
-            let header_name = HeaderName::from_str(k.as_str())?;
-            let header_value = HeaderValue::from_str(v.as_str())?;
-            request.headers_mut().insert(header_name, header_value);
+#[tauri::command]
+async fn send<R: Runtime>(
+    window: Window<R>,
+    manager: State<'_, ConnectionManager>,
+    id: Id,
+    message: WebSocketMessage,
+) -> Result<()> {
+    if let WebSocketMessage::Close(Some(frame)) = &message {
+        if !CloseCode::from(frame.code).is_allowed() {
+            return Err(Error::InvalidCloseCode(frame.code));
         }
     }
 
-    let (ws_stream, _) = connect_async_with_config(request, config.map(Into::into), false).await?;
+    let (reply, result) = oneshot::channel();
+    let sent = match handle(&*manager.0.lock().await, id, window.label()) {
+        Some(handle) => {
+            // closing is always possible
+            let full = !matches!(message, WebSocketMessage::Close(_))
+                && handle
+                    .send_queue_size
+                    .map_or(false, |size| handle.queued.load(Ordering::Relaxed) >= size);
+            if full {
+                return Err(Error::SendQueueFull);
+            }
+            handle.queued.fetch_add(1, Ordering::Relaxed);
+            handle.commands.send(Command::Send(message, reply)).is_ok()
+        }
+        None => false,
+    };
+    if !sent {
+        return Err(Error::ConnectionNotFound(id));
+    }
+    result.await.map_err(|_| Error::ConnectionNotFound(id))?
+}
 
-    tauri::async_runtime::spawn(async move {
-        let (write, read) = ws_stream.split();
-        let manager = window.state::<ConnectionManager>();
-        manager.0.lock().await.insert(id, write);
-        read.for_each(move |message| {
-            let window_ = window.clone();
-            async move {
-                if let Ok(Message::Close(_)) = message {
-                    let manager = window_.state::<ConnectionManager>();
-                    manager.0.lock().await.remove(&id);
-                }
+/// Resolves once every message sent before is written to the socket.
+#[tauri::command]
+async fn flush<R: Runtime>(
+    window: Window<R>,
+    manager: State<'_, ConnectionManager>,
+    id: Id,
+) -> Result<()> {
+    let (reply, result) = oneshot::channel();
+    let sent = match handle(&*manager.0.lock().await, id, window.label()) {
+        Some(handle) => handle.commands.send(Command::Flush(reply)).is_ok(),
+        None => false,
+    };
+    if !sent {
+        return Err(Error::ConnectionNotFound(id));
+    }
+    result.await.map_err(|_| Error::ConnectionNotFound(id))?
+}
 
-                let response = match message {
-                    Ok(Message::Text(t)) => {
-                        serde_json::to_value(WebSocketMessage::Text(t)).unwrap()
-                    }
-                    Ok(Message::Binary(t)) => {
-                        serde_json::to_value(WebSocketMessage::Binary(t)).unwrap()
-                    }
-                    Ok(Message::Ping(t)) => {
-                        serde_json::to_value(WebSocketMessage::Ping(t)).unwrap()
-                    }
-                    Ok(Message::Pong(t)) => {
-                        serde_json::to_value(WebSocketMessage::Pong(t)).unwrap()
-                    }
-                    Ok(Message::Close(t)) => {
-                        serde_json::to_value(WebSocketMessage::Close(t.map(|v| CloseFrame {
-                            code: v.code.into(),
-                            reason: v.reason.into_owned(),
-                        })))
-                        .unwrap()
-                    }
-                    Ok(Message::Frame(_)) => serde_json::Value::Null, // This value can't be recieved.
-                    Err(e) => serde_json::to_value(Error::from(e)).unwrap(),
-                };
-                let js = format_callback(callback_function, &response)
-                    .expect("unable to serialize websocket message");
-                let _ = window_.eval(js.as_str());
-            }
-        })
-        .await;
-    });
+/// Sends the messages queued while reconnecting, once the app is done
+/// authenticating or resubscribing on the new connection.
+#[tauri::command]
+async fn resume<R: Runtime>(
+    window: Window<R>,
+    manager: State<'_, ConnectionManager>,
+    id: Id,
+) -> Result<()> {
+    match handle(&*manager.0.lock().await, id, window.label()) {
+        Some(handle) => {
+            let _ = handle.commands.send(Command::Resume);
+            Ok(())
+        }
+        None => Err(Error::ConnectionNotFound(id)),
+    }
+}
 
-    Ok(id)
+/// The last round-trip time measured by the keepalive, in milliseconds.
+#[tauri::command]
+async fn get_latency<R: Runtime>(
+    window: Window<R>,
+    manager: State<'_, ConnectionManager>,
+    id: Id,
+) -> Result<Option<f64>> {
+    let (reply, latency) = oneshot::channel();
+    let sent = match handle(&*manager.0.lock().await, id, window.label()) {
+        Some(handle) => handle.commands.send(Command::Latency(reply)).is_ok(),
+        None => false,
+    };
+    if !sent {
+        return Err(Error::ConnectionNotFound(id));
+    }
+    latency.await.map_err(|_| Error::ConnectionNotFound(id))
 }
 
+/// Delivers the messages of the shared connection `id` to the calling window
+/// too, replacing its previous callback if it already attached.
 #[tauri::command]
-async fn send(
+async fn attach<R: Runtime>(
+    window: Window<R>,
     manager: State<'_, ConnectionManager>,
     id: Id,
-    message: WebSocketMessage,
+    callback_function: CallbackFn,
 ) -> Result<()> {
-    if let Some(write) = manager.0.lock().await.get_mut(&id) {
-        write
-            .send(match message {
-                WebSocketMessage::Text(t) => Message::Text(t),
-                WebSocketMessage::Binary(t) => Message::Binary(t),
-                WebSocketMessage::Ping(t) => Message::Ping(t),
-                WebSocketMessage::Pong(t) => Message::Pong(t),
-                WebSocketMessage::Close(t) => Message::Close(t.map(|v| ProtocolCloseFrame {
-                    code: v.code.into(),
-                    reason: std::borrow::Cow::Owned(v.reason),
-                })),
+    let mut connections = manager.0.lock().await;
+    let handle = connections
+        .get_mut(&id)
+        .ok_or(Error::ConnectionNotFound(id))?;
+    if !handle.shared {
+        return Err(Error::NotShared(id));
+    }
+    if !handle.windows.iter().any(|label| label == window.label()) {
+        handle.windows.push(window.label().into());
+    }
+    let _ = handle
+        .commands
+        .send(Command::Attach(window.label().into(), callback_function));
+    Ok(())
+}
+
+/// Closes the connections owned by the destroyed window `label`, and stops
+/// sending it the messages of the others.
+async fn window_destroyed(manager: &ConnectionManager, label: &str) {
+    for handle in manager.0.lock().await.values_mut() {
+        match handle.windows.iter().position(|window| window == label) {
+            Some(0) => {
+                let _ = handle.commands.send(Command::OwnerDestroyed);
+            }
+            Some(position) => {
+                handle.windows.remove(position);
+                let _ = handle.commands.send(Command::Detach(label.into()));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Tauri websocket plugin builder.
+#[derive(Default)]
+pub struct Builder {
+    allow_invalid_certs: bool,
+    filter: Option<Arc<MessageFilter>>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows connections to set `dangerouslyAcceptInvalidCerts`, which
+    /// disables certificate verification. Only meant for local development,
+    /// e.g. with `cfg!(debug_assertions)`.
+    #[must_use]
+    pub fn allow_invalid_certs(mut self, allow: bool) -> Self {
+        self.allow_invalid_certs = allow;
+        self
+    }
+
+    /// Runs `filter` on every message received, with the URL of the
+    /// connection, before it is sent to the webview. Returning `None` drops
+    /// the message, e.g. heartbeats the frontend doesn't need.
+    #[must_use]
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&str, Message) -> Option<Message> + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        PluginBuilder::new("websocket")
+            .invoke_handler(tauri::generate_handler![
+                connect,
+                send,
+                flush,
+                resume,
+                get_latency,
+                attach
+            ])
+            .setup(move |app| {
+                app.manage(ConnectionManager::default());
+                app.manage(Settings {
+                    allow_invalid_certs: self.allow_invalid_certs,
+                    filter: self.filter,
+                });
+                Ok(())
             })
-            .await?;
-        Ok(())
-    } else {
-        Err(Error::ConnectionNotFound(id))
+            .on_event(|app, event| {
+                if let RunEvent::WindowEvent {
+                    label,
+                    event: WindowEvent::Destroyed,
+                    ..
+                } = event
+                {
+                    let app = app.clone();
+                    let label = label.clone();
+                    tauri::async_runtime::spawn(async move {
+                        window_destroyed(&app.state::<ConnectionManager>(), &label).await;
+                    });
+                }
+            })
+            .build()
     }
 }
 
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    PluginBuilder::new("websocket")
-        .invoke_handler(tauri::generate_handler![connect, send])
-        .setup(|app| {
-            app.manage(ConnectionManager::default());
-            Ok(())
-        })
-        .build()
+    Builder::default().build()
 }
@@ -0,0 +1,113 @@
+use native_tls::{Certificate, Identity};
+use serde::Deserialize;
+use tauri::FsScope;
+use tokio_native_tls::TlsConnector;
+
+use std::path::PathBuf;
+
+use crate::{Error, Result};
+
+/// PEM data, given inline or as the path of a file to read it from. The path
+/// must be allowed by the fs scope.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Pem {
+    Path { path: PathBuf },
+    Inline(String),
+}
+
+impl Pem {
+    async fn read(&self, scope: &FsScope) -> Result<String> {
+        match self {
+            Self::Path { path } => {
+                if !scope.is_allowed(path) {
+                    return Err(Error::PathNotAllowed(path.clone()));
+                }
+                tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| Error::InvalidTlsConfig(format!("{}: {e}", path.display())))
+            }
+            Self::Inline(pem) => Ok(pem.clone()),
+        }
+    }
+}
+
+/// A client certificate for mutual TLS.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientIdentity {
+    /// The certificate chain, leaf first.
+    pub certificate: Pem,
+    /// The PKCS #8 private key of the certificate.
+    pub key: Pem,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TlsConfig {
+    /// Trusted in addition to the system's root certificates. Each entry can
+    /// hold several certificates.
+    pub root_certificates: Vec<Pem>,
+    pub identity: Option<ClientIdentity>,
+    /// The name sent with SNI and verified against the server's certificate,
+    /// instead of the host of the URL.
+    pub server_name: Option<String>,
+    /// Accepts any certificate, only meant for local development. Rejected
+    /// unless allowed with [`Builder::allow_invalid_certs`](crate::Builder::allow_invalid_certs).
+    pub dangerously_accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    /// Builds the connector, reading and parsing the certificates and keys.
+    pub(crate) async fn connector(
+        &self,
+        scope: &FsScope,
+        allow_invalid_certs: bool,
+    ) -> Result<TlsConnector> {
+        let invalid = |e: native_tls::Error| Error::InvalidTlsConfig(e.to_string());
+        let mut builder = native_tls::TlsConnector::builder();
+
+        for pem in &self.root_certificates {
+            let pem = pem.read(scope).await?;
+            let mut found = false;
+            for certificate in certificates(&pem) {
+                builder.add_root_certificate(
+                    Certificate::from_pem(certificate.as_bytes()).map_err(invalid)?,
+                );
+                found = true;
+            }
+            if !found {
+                return Err(Error::InvalidTlsConfig(
+                    "no certificate found in PEM".into(),
+                ));
+            }
+        }
+        if let Some(identity) = &self.identity {
+            let certificate = identity.certificate.read(scope).await?;
+            let key = identity.key.read(scope).await?;
+            builder.identity(
+                Identity::from_pkcs8(certificate.as_bytes(), key.as_bytes()).map_err(invalid)?,
+            );
+        }
+        if self.dangerously_accept_invalid_certs {
+            if !allow_invalid_certs {
+                return Err(Error::InvalidCertsNotAllowed);
+            }
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+
+        Ok(builder.build().map_err(invalid)?.into())
+    }
+}
+
+/// The `CERTIFICATE` blocks of a PEM bundle.
+fn certificates(pem: &str) -> impl Iterator<Item = &str> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    pem.match_indices(BEGIN).filter_map(|(start, _)| {
+        let end = pem[start..].find(END)?;
+        Some(&pem[start..start + end + END.len()])
+    })
+}
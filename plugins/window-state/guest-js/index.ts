@@ -11,10 +11,26 @@ export enum StateFlags {
   ALL = SIZE | POSITION | MAXIMIZED | VISIBLE | DECORATIONS | FULLSCREEN,
 }
 
+/** The saved state of a window, with only the parts of it that were saved. */
+export interface SavedState {
+  /** The inner size, in logical pixels. */
+  size: { width: number; height: number } | null;
+  /**
+   * The outer position, in physical pixels. For a maximized window,
+   * the position it had before it was maximized.
+   */
+  position: { x: number; y: number } | null;
+  maximized: boolean | null;
+  visible: boolean | null;
+  decorated: boolean | null;
+  fullscreen: boolean | null;
+}
+
 /**
- *  Save the state of all open windows to disk.
+ *  Save the state of all open windows to disk, with the given flags or the flags of each window.
+ *  Resolves once the state has been written to disk.
  */
-async function saveWindowState(flags: StateFlags): Promise<void> {
+async function saveWindowState(flags?: StateFlags): Promise<void> {
   return invoke("plugin:window-state|save_window_state", { flags });
 }
 
@@ -28,6 +44,49 @@ async function restoreState(
   return invoke("plugin:window-state|restore_state", { label, flags });
 }
 
+/**
+ *  Set the state flags of the specified window, used when its state is saved from now on.
+ */
+async function setStateFlags(
+  label: WindowLabel,
+  flags: StateFlags,
+): Promise<void> {
+  return invoke("plugin:window-state|set_state_flags", { label, flags });
+}
+
+/**
+ *  Save the state of all open windows to the current profile, and switch to the profile
+ *  with the given name, or to the default one without a name. Subsequent saves and
+ *  restores use the state file of that profile.
+ */
+async function setStateProfile(name?: string): Promise<void> {
+  return invoke("plugin:window-state|set_state_profile", { name });
+}
+
+/**
+ *  Stop saving and restoring the state of the specified window.
+ *  Its saved state is removed from disk on the next save.
+ */
+async function ignoreWindow(label: WindowLabel): Promise<void> {
+  return invoke("plugin:window-state|ignore_window", { label });
+}
+
+/**
+ *  Resume saving and restoring the state of a window ignored with `ignoreWindow`.
+ *  Windows in the denylist of the plugin stay ignored.
+ */
+async function unignoreWindow(label: WindowLabel): Promise<void> {
+  return invoke("plugin:window-state|unignore_window", { label });
+}
+
+/**
+ *  Get the saved state of the specified window, or `null` if it has none,
+ *  e.g. to create the window with it rather than restoring it afterwards.
+ */
+async function getSavedState(label: WindowLabel): Promise<SavedState | null> {
+  return invoke("plugin:window-state|get_saved_state", { label });
+}
+
 /**
  *  Restore the state for the current window from disk.
  */
@@ -35,4 +94,13 @@ async function restoreStateCurrent(flags: StateFlags): Promise<void> {
   return restoreState(getCurrent().label, flags);
 }
 
-export { restoreState, restoreStateCurrent, saveWindowState };
+export {
+  getSavedState,
+  ignoreWindow,
+  restoreState,
+  restoreStateCurrent,
+  saveWindowState,
+  setStateFlags,
+  setStateProfile,
+  unignoreWindow,
+};
@@ -1,14 +1,23 @@
-use crate::{AppHandleExt, StateFlags, WindowExt};
+use crate::{AppHandleExt, SavedState, StateFlags, WindowExt, WindowFlags};
 use tauri::{command, AppHandle, Manager, Runtime};
 
 #[command]
 pub async fn save_window_state<R: Runtime>(
     app: AppHandle<R>,
-    flags: u32,
+    flags: Option<u32>,
 ) -> std::result::Result<(), String> {
-    let flags = StateFlags::from_bits(flags)
-        .ok_or_else(|| format!("Invalid state flags bits: {}", flags))?;
-    app.save_window_state(flags).map_err(|e| e.to_string())?;
+    match flags {
+        Some(flags) => {
+            let flags = StateFlags::from_bits(flags)
+                .ok_or_else(|| format!("Invalid state flags bits: {}", flags))?;
+            app.save_window_state(flags)
+        }
+        None => {
+            let window_flags = app.state::<WindowFlags>();
+            crate::save(&app, |label| window_flags.get(label))
+        }
+    }
+    .map_err(|e| e.to_string())?;
     Ok(())
 }
 
@@ -26,3 +35,39 @@ pub async fn restore_state<R: Runtime>(
         .map_err(|e| e.to_string())?;
     Ok(())
 }
+
+#[command]
+pub async fn set_state_flags<R: Runtime>(
+    app: AppHandle<R>,
+    label: String,
+    flags: u32,
+) -> std::result::Result<(), String> {
+    let flags = StateFlags::from_bits(flags)
+        .ok_or_else(|| format!("Invalid state flags bits: {}", flags))?;
+    app.set_state_flags(&label, flags);
+    Ok(())
+}
+
+#[command]
+pub async fn set_state_profile<R: Runtime>(
+    app: AppHandle<R>,
+    name: Option<String>,
+) -> std::result::Result<(), String> {
+    app.set_state_profile(name.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+pub async fn ignore_window<R: Runtime>(app: AppHandle<R>, label: String) {
+    app.ignore_window(&label);
+}
+
+#[command]
+pub async fn unignore_window<R: Runtime>(app: AppHandle<R>, label: String) {
+    app.unignore_window(&label);
+}
+
+#[command]
+pub async fn get_saved_state<R: Runtime>(app: AppHandle<R>, label: String) -> Option<SavedState> {
+    app.saved_state(&label)
+}
@@ -6,21 +6,29 @@ use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 use tauri::{
     plugin::{Builder as PluginBuilder, TauriPlugin},
-    LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize, RunEvent, Runtime, Window,
-    WindowEvent,
+    AppHandle, LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize, RunEvent, Runtime,
+    Window, WindowEvent,
 };
 
 use std::{
     collections::{HashMap, HashSet},
     fs::{create_dir_all, File},
     io::Write,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 mod cmd;
 
 pub const STATE_FILENAME: &str = ".window-state";
 
+/// How long after the last change of a window its state is saved by default.
+const DEFAULT_SAVE_DELAY: Duration = Duration::from_secs(3);
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -31,6 +39,8 @@ pub enum Error {
     TauriApi(#[from] tauri::api::Error),
     #[error(transparent)]
     Bincode(#[from] Box<bincode::ErrorKind>),
+    #[error("invalid state profile name: {0}")]
+    InvalidProfile(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -53,7 +63,7 @@ impl Default for StateFlags {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 struct WindowState {
     width: f64,
     height: f64,
@@ -68,6 +78,9 @@ struct WindowState {
     visible: bool,
     decorated: bool,
     fullscreen: bool,
+    // the flags whose state has been captured, as a window can be saved
+    // with only some of them
+    flags: u32,
 }
 
 impl Default for WindowState {
@@ -83,41 +96,361 @@ impl Default for WindowState {
             visible: true,
             decorated: true,
             fullscreen: Default::default(),
+            flags: Default::default(),
+        }
+    }
+}
+
+/// The state saved before the captured flags were recorded, which are assumed
+/// to be all of them.
+#[derive(Deserialize)]
+struct LegacyWindowState {
+    width: f64,
+    height: f64,
+    x: i32,
+    y: i32,
+    prev_x: i32,
+    prev_y: i32,
+    maximized: bool,
+    visible: bool,
+    decorated: bool,
+    fullscreen: bool,
+}
+
+impl From<LegacyWindowState> for WindowState {
+    fn from(s: LegacyWindowState) -> Self {
+        Self {
+            width: s.width,
+            height: s.height,
+            x: s.x,
+            y: s.y,
+            prev_x: s.prev_x,
+            prev_y: s.prev_y,
+            maximized: s.maximized,
+            visible: s.visible,
+            decorated: s.decorated,
+            fullscreen: s.fullscreen,
+            flags: StateFlags::all().bits(),
+        }
+    }
+}
+
+/// Marks state files recording the captured flags of each window. Older files
+/// hold the bare map of states, which starts with its length instead.
+const STATE_FORMAT: u64 = u64::MAX;
+
+/// Reads the state file at `path`, starting fresh if it is missing or can't be read.
+fn read_state_file(path: &Path) -> HashMap<String, WindowState> {
+    if path.exists() {
+        tauri::api::file::read_binary(path)
+            .map_err(Error::TauriApi)
+            .and_then(|state| read_state(&state))
+            .unwrap_or_default()
+    } else {
+        Default::default()
+    }
+}
+
+fn read_state(bytes: &[u8]) -> Result<HashMap<String, WindowState>> {
+    match bincode::deserialize::<(u64, HashMap<String, WindowState>)>(bytes) {
+        Ok((STATE_FORMAT, state)) => Ok(state),
+        _ => {
+            let state: HashMap<String, LegacyWindowState> = bincode::deserialize(bytes)?;
+            Ok(state.into_iter().map(|(l, s)| (l, s.into())).collect())
         }
     }
 }
 
 struct WindowStateCache(Arc<Mutex<HashMap<String, WindowState>>>);
+
+/// The state flags of each window, which can be changed at runtime.
+struct WindowFlags {
+    default: StateFlags,
+    windows: Mutex<HashMap<String, StateFlags>>,
+}
+
+impl WindowFlags {
+    fn get(&self, label: &str) -> StateFlags {
+        self.windows
+            .lock()
+            .unwrap()
+            .get(label)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Where the state is saved, which depends on the current profile.
+struct StateFile {
+    path: PathBuf,
+    profile: Mutex<Option<String>>,
+}
+
+impl StateFile {
+    /// The state file of the current profile, which is the state file with
+    /// the name of the profile appended, e.g. `.window-state.work`.
+    fn path<R: Runtime>(&self, app: &AppHandle<R>) -> Option<PathBuf> {
+        let path = if self.path.is_absolute() {
+            self.path.clone()
+        } else {
+            app.path_resolver().app_config_dir()?.join(&self.path)
+        };
+        Some(match &*self.profile.lock().unwrap() {
+            Some(profile) => {
+                let mut path = path.into_os_string();
+                path.push(".");
+                path.push(profile);
+                path.into()
+            }
+            None => path,
+        })
+    }
+}
+
+/// The windows that are neither saved nor restored.
+struct Denylist {
+    patterns: HashSet<String>,
+    ignored: Mutex<HashSet<String>>,
+}
+
+impl Denylist {
+    fn contains(&self, label: &str) -> bool {
+        self.patterns.iter().any(|p| matches_label(p, label))
+            || self.ignored.lock().unwrap().contains(label)
+    }
+}
+
+/// Matches `label` against `pattern`, where `*` matches any number of characters.
+/// Window labels can't contain `*` themselves.
+fn matches_label(pattern: &str, label: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, rest)) => label.strip_prefix(prefix).map_or(false, |label| {
+            (0..=label.len())
+                .filter(|i| label.is_char_boundary(*i))
+                .any(|i| matches_label(rest, &label[i..]))
+        }),
+        None => pattern == label,
+    }
+}
+
+/// Schedules saving the state to disk after a window changed, so it isn't lost
+/// if the app is killed before it exits.
+struct SaveScheduler(Mutex<Sender<()>>);
+
+impl SaveScheduler {
+    fn schedule(&self) {
+        let _ = self.0.lock().unwrap().send(());
+    }
+}
+
+/// Saves the state `delay` after the last change scheduled on `changes`, so a
+/// window being dragged or resized is saved once, after it stops.
+fn save_on_change<R: Runtime>(app: AppHandle<R>, changes: Receiver<()>, delay: Duration) {
+    while changes.recv().is_ok() {
+        loop {
+            match changes.recv_timeout(delay) {
+                Ok(()) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let window_flags = app.state::<WindowFlags>();
+        if let Err(e) = save(&app, |label| window_flags.get(label)) {
+            log::warn!("failed to save the window state: {e}");
+        }
+    }
+}
+
+struct RestoreOptions {
+    clamp_to_monitors: bool,
+}
+
 pub trait AppHandleExt {
     /// Saves all open windows state to disk
     fn save_window_state(&self, flags: StateFlags) -> Result<()>;
+
+    /// Sets the state flags of the window with the given label,
+    /// used when its state is saved from now on
+    fn set_state_flags(&self, label: &str, flags: StateFlags);
+
+    /// Saves the state of all open windows to the current profile, and switches
+    /// to the profile with the given name, or to the default one for `None`.
+    /// Its state is loaded from disk, but not restored to the open windows.
+    fn set_state_profile(&self, name: Option<&str>) -> Result<()>;
+
+    /// Stops saving and restoring the state of the window with the given label.
+    /// Its saved state is removed from disk on the next save.
+    fn ignore_window(&self, label: &str);
+
+    /// Resumes saving and restoring the state of a window ignored with [`Self::ignore_window`].
+    /// Windows in the denylist of the [`Builder`] stay ignored.
+    fn unignore_window(&self, label: &str);
+
+    /// Returns the saved state of the window with the given label, if any,
+    /// e.g. to create a window with it rather than restoring it afterwards.
+    fn saved_state(&self, label: &str) -> Option<SavedState>;
+}
+
+/// The saved state of a window, with only the parts of it that were saved.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedState {
+    /// The inner size, in logical pixels.
+    pub size: Option<LogicalSize<f64>>,
+    /// The outer position, in physical pixels. For a maximized window,
+    /// the position it had before it was maximized.
+    pub position: Option<PhysicalPosition<i32>>,
+    pub maximized: Option<bool>,
+    pub visible: Option<bool>,
+    pub decorated: Option<bool>,
+    pub fullscreen: Option<bool>,
+}
+
+impl From<&WindowState> for SavedState {
+    fn from(state: &WindowState) -> Self {
+        let flags = StateFlags::from_bits_truncate(state.flags);
+        let saved = |flag: StateFlags| flags.contains(flag);
+        Self {
+            size: saved(StateFlags::SIZE).then_some(LogicalSize {
+                width: state.width,
+                height: state.height,
+            }),
+            position: saved(StateFlags::POSITION).then_some(if state.maximized {
+                PhysicalPosition::new(state.prev_x, state.prev_y)
+            } else {
+                PhysicalPosition::new(state.x, state.y)
+            }),
+            maximized: saved(StateFlags::MAXIMIZED).then_some(state.maximized),
+            visible: saved(StateFlags::VISIBLE).then_some(state.visible),
+            decorated: saved(StateFlags::DECORATIONS).then_some(state.decorated),
+            fullscreen: saved(StateFlags::FULLSCREEN).then_some(state.fullscreen),
+        }
+    }
 }
 
 impl<R: Runtime> AppHandleExt for tauri::AppHandle<R> {
     fn save_window_state(&self, flags: StateFlags) -> Result<()> {
-        if let Some(app_dir) = self.path_resolver().app_config_dir() {
-            let state_path = app_dir.join(STATE_FILENAME);
-            let cache = self.state::<WindowStateCache>();
-            let mut state = cache.0.lock().unwrap();
-            for (label, s) in state.iter_mut() {
-                if let Some(window) = self.get_window(label) {
-                    window.update_state(s, flags)?;
-                }
+        save(self, |_| flags)
+    }
+
+    fn set_state_flags(&self, label: &str, flags: StateFlags) {
+        self.state::<WindowFlags>()
+            .windows
+            .lock()
+            .unwrap()
+            .insert(label.into(), flags);
+    }
+
+    fn set_state_profile(&self, name: Option<&str>) -> Result<()> {
+        if let Some(name) = name {
+            let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+            if name.is_empty() || !name.chars().all(valid) {
+                return Err(Error::InvalidProfile(name.into()));
             }
+        }
 
-            create_dir_all(&app_dir)
-                .map_err(Error::Io)
-                .and_then(|_| File::create(state_path).map_err(Into::into))
-                .and_then(|mut f| {
-                    f.write_all(&bincode::serialize(&*state).map_err(Error::Bincode)?)
-                        .map_err(Into::into)
-                })
-        } else {
-            Ok(())
+        let window_flags = self.state::<WindowFlags>();
+        save(self, |label| window_flags.get(label))?;
+
+        let state_file = self.state::<StateFile>();
+        *state_file.profile.lock().unwrap() = name.map(Into::into);
+        let mut state = state_file
+            .path(self)
+            .map(|path| read_state_file(&path))
+            .unwrap_or_default();
+
+        let cache = self.state::<WindowStateCache>();
+        let mut c = cache.0.lock().unwrap();
+        // keep tracking the open windows, even if the profile has no state for them
+        for label in c.keys() {
+            if self.get_window(label).is_some() {
+                state.entry(label.clone()).or_default();
+            }
+        }
+        *c = state;
+        Ok(())
+    }
+
+    fn ignore_window(&self, label: &str) {
+        self.state::<Denylist>()
+            .ignored
+            .lock()
+            .unwrap()
+            .insert(label.into());
+        self.state::<WindowStateCache>()
+            .0
+            .lock()
+            .unwrap()
+            .remove(label);
+    }
+
+    fn saved_state(&self, label: &str) -> Option<SavedState> {
+        if self.state::<Denylist>().contains(label) {
+            return None;
+        }
+        let cache = self.state::<WindowStateCache>();
+        let c = cache.0.lock().unwrap();
+        c.get(label)
+            .filter(|state| **state != WindowState::default())
+            .map(Into::into)
+    }
+
+    fn unignore_window(&self, label: &str) {
+        let denylist = self.state::<Denylist>();
+        denylist.ignored.lock().unwrap().remove(label);
+        // start tracking it again if it is open
+        if !denylist.contains(label) && self.get_window(label).is_some() {
+            self.state::<WindowStateCache>()
+                .0
+                .lock()
+                .unwrap()
+                .entry(label.into())
+                .or_default();
         }
     }
 }
 
+/// Saves the state of all open windows to disk, each with its `flags`.
+fn save<R: Runtime>(app: &AppHandle<R>, flags: impl Fn(&str) -> StateFlags) -> Result<()> {
+    if let Some(state_path) = app.state::<StateFile>().path(app) {
+        let cache = app.state::<WindowStateCache>();
+        let denylist = app.state::<Denylist>();
+        // The getters wait on the main thread, whose window event handlers lock
+        // the cache, so the windows are read without holding the lock.
+        let mut updated = {
+            let mut state = cache.0.lock().unwrap();
+            state.retain(|label, _| !denylist.contains(label));
+            state.clone()
+        };
+        for (label, s) in updated.iter_mut() {
+            if let Some(window) = app.get_window(label) {
+                window.update_state(s, flags(label))?;
+            }
+        }
+        let mut state = cache.0.lock().unwrap();
+        for (label, s) in updated {
+            // skips the windows ignored in the meantime
+            if let Some(entry) = state.get_mut(&label) {
+                *entry = s;
+            }
+        }
+
+        create_dir_all(state_path.parent().unwrap_or(Path::new("")))
+            .map_err(Error::Io)
+            .and_then(|_| File::create(state_path).map_err(Into::into))
+            .and_then(|mut f| {
+                f.write_all(
+                    &bincode::serialize(&(STATE_FORMAT, &*state)).map_err(Error::Bincode)?,
+                )?;
+                // only report the state as saved once it is on disk
+                f.sync_all().map_err(Into::into)
+            })
+    } else {
+        Ok(())
+    }
+}
+
 pub trait WindowExt {
     /// Restores this window state from disk
     fn restore_state(&self, flags: StateFlags) -> tauri::Result<()>;
@@ -125,6 +458,10 @@ pub trait WindowExt {
 
 impl<R: Runtime> WindowExt for Window<R> {
     fn restore_state(&self, flags: StateFlags) -> tauri::Result<()> {
+        if self.state::<Denylist>().contains(self.label()) {
+            return Ok(());
+        }
+
         let cache = self.state::<WindowStateCache>();
         let mut c = cache.0.lock().unwrap();
 
@@ -136,38 +473,54 @@ impl<R: Runtime> WindowExt for Window<R> {
                 return Ok(());
             }
 
+            // only restore what has been saved
+            let flags = flags & StateFlags::from_bits_truncate(state.flags);
+
             if flags.contains(StateFlags::DECORATIONS) {
                 self.set_decorations(state.decorated)?;
             }
 
-            if flags.contains(StateFlags::SIZE) {
-                self.set_size(LogicalSize {
-                    width: state.width,
-                    height: state.height,
-                })?;
-            }
+            let size = LogicalSize {
+                width: state.width,
+                height: state.height,
+            };
 
             if flags.contains(StateFlags::POSITION) {
-                let position = (state.x, state.y).into();
-                let size = (state.width, state.height).into();
-                // restore position to saved value if saved monitor exists
-                // otherwise, let the OS decide where to place the window
-                for m in self.available_monitors()? {
-                    if m.intersects(position, size) {
-                        self.set_position(PhysicalPosition {
-                            x: if state.maximized {
-                                state.prev_x
-                            } else {
-                                state.x
-                            },
-                            y: if state.maximized {
-                                state.prev_y
-                            } else {
-                                state.y
-                            },
-                        })?;
+                let position = PhysicalPosition {
+                    x: if state.maximized {
+                        state.prev_x
+                    } else {
+                        state.x
+                    },
+                    y: if state.maximized {
+                        state.prev_y
+                    } else {
+                        state.y
+                    },
+                };
+                let clamp = self
+                    .try_state::<RestoreOptions>()
+                    .map_or(true, |o| o.clamp_to_monitors);
+
+                match place(&self.available_monitors()?, position, size, clamp) {
+                    // the window is moved before it is resized, so the OS doesn't
+                    // rescale it again when it lands on a monitor with another scale factor
+                    Some((position, physical_size)) => {
+                        self.set_position(position)?;
+                        if flags.contains(StateFlags::SIZE) {
+                            self.set_size(physical_size)?;
+                        }
+                    }
+                    // no monitor to place the window on, so it falls back to the center
+                    None => {
+                        if flags.contains(StateFlags::SIZE) {
+                            self.set_size(size)?;
+                        }
+                        self.center()?;
                     }
                 }
+            } else if flags.contains(StateFlags::SIZE) {
+                self.set_size(size)?;
             }
 
             if flags.contains(StateFlags::MAXIMIZED) && state.maximized {
@@ -178,7 +531,9 @@ impl<R: Runtime> WindowExt for Window<R> {
                 self.set_fullscreen(state.fullscreen)?;
             }
 
-            should_show = state.visible;
+            if flags.contains(StateFlags::VISIBLE) {
+                should_show = state.visible;
+            }
         } else {
             let mut metadata = WindowState::default();
 
@@ -214,6 +569,7 @@ impl<R: Runtime> WindowExt for Window<R> {
                 metadata.fullscreen = self.is_fullscreen()?;
             }
 
+            metadata.flags = flags.bits();
             c.insert(self.label().into(), metadata);
         }
 
@@ -237,6 +593,9 @@ impl<R: Runtime> WindowExtInternal for Window<R> {
             false => false,
         };
 
+        // the size and position aren't captured while maximized
+        let mut captured = flags - (StateFlags::SIZE | StateFlags::POSITION);
+
         if flags.contains(StateFlags::MAXIMIZED) {
             state.maximized = is_maximized;
         }
@@ -264,6 +623,7 @@ impl<R: Runtime> WindowExtInternal for Window<R> {
             if size.width > 0. && size.height > 0. && !is_maximized {
                 state.width = size.width;
                 state.height = size.height;
+                captured |= StateFlags::SIZE;
             }
         }
 
@@ -271,8 +631,11 @@ impl<R: Runtime> WindowExtInternal for Window<R> {
             let position = self.outer_position()?;
             state.x = position.x;
             state.y = position.y;
+            captured |= StateFlags::POSITION;
         }
 
+        state.flags |= captured.bits();
+
         Ok(())
     }
 }
@@ -282,6 +645,10 @@ pub struct Builder {
     denylist: HashSet<String>,
     skip_initial_state: HashSet<String>,
     state_flags: StateFlags,
+    window_state_flags: HashMap<String, StateFlags>,
+    state_path: Option<PathBuf>,
+    save_delay: Option<Duration>,
+    skip_monitor_clamping: bool,
 }
 
 impl Builder {
@@ -295,8 +662,32 @@ impl Builder {
         self
     }
 
+    /// Sets the state flags of the window with the given label, overriding the ones
+    /// set with [`Self::with_state_flags`] for it. They can be changed at runtime with
+    /// [`AppHandleExt::set_state_flags`].
+    pub fn with_window_state_flags(mut self, label: &str, flags: StateFlags) -> Self {
+        self.window_state_flags.insert(label.into(), flags);
+        self
+    }
+
+    /// Sets the path of the state file, e.g. next to the executable for portable installs.
+    /// A relative path is resolved against the app config directory, where the state is
+    /// saved to [`STATE_FILENAME`] by default.
+    pub fn with_state_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_path = Some(path.into());
+        self
+    }
+
+    /// Sets how long after a window was last moved or resized its state is saved to disk,
+    /// besides when the app exits. Defaults to 3 seconds.
+    pub fn with_save_delay(mut self, delay: Duration) -> Self {
+        self.save_delay = Some(delay);
+        self
+    }
+
     /// Sets a list of windows that shouldn't be tracked and managed by this plugin
-    /// for example splash screen windows.
+    /// for example splash screen windows. A `*` in a label matches any number of
+    /// characters, e.g. `popup-*`. Their saved state is removed from disk on the next save.
     pub fn with_denylist(mut self, denylist: &[&str]) -> Self {
         self.denylist = denylist.iter().map(|l| l.to_string()).collect();
         self
@@ -308,52 +699,77 @@ impl Builder {
         self
     }
 
-    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
-        let flags = self.state_flags;
+    /// Restores windows at their saved position even if they end up mostly off-screen,
+    /// instead of moving them onto the nearest monitor. Useful for kiosk setups
+    /// where the monitor layout is known to be fixed.
+    pub fn skip_monitor_clamping(mut self) -> Self {
+        self.skip_monitor_clamping = true;
+        self
+    }
+
+    pub fn build<R: Runtime>(mut self) -> TauriPlugin<R> {
+        let clamp_to_monitors = !self.skip_monitor_clamping;
+        let save_delay = self.save_delay.unwrap_or(DEFAULT_SAVE_DELAY);
+        let state_path = self
+            .state_path
+            .take()
+            .unwrap_or_else(|| STATE_FILENAME.into());
+        let denylist = Denylist {
+            patterns: std::mem::take(&mut self.denylist),
+            ignored: Default::default(),
+        };
+        let window_flags = WindowFlags {
+            default: self.state_flags,
+            windows: Mutex::new(self.window_state_flags),
+        };
         PluginBuilder::new("window-state")
             .invoke_handler(tauri::generate_handler![
                 cmd::save_window_state,
-                cmd::restore_state
+                cmd::restore_state,
+                cmd::set_state_flags,
+                cmd::set_state_profile,
+                cmd::ignore_window,
+                cmd::unignore_window,
+                cmd::get_saved_state
             ])
-            .setup(|app| {
-                let cache: Arc<Mutex<HashMap<String, WindowState>>> = if let Some(app_dir) =
-                    app.path_resolver().app_config_dir()
-                {
-                    let state_path = app_dir.join(STATE_FILENAME);
-                    if state_path.exists() {
-                        Arc::new(Mutex::new(
-                            tauri::api::file::read_binary(state_path)
-                                .map_err(Error::TauriApi)
-                                .and_then(|state| bincode::deserialize(&state).map_err(Into::into))
-                                .unwrap_or_default(),
-                        ))
-                    } else {
-                        Default::default()
-                    }
-                } else {
-                    Default::default()
+            .setup(move |app| {
+                let state_file = StateFile {
+                    path: state_path,
+                    profile: Mutex::new(None),
                 };
-                app.manage(WindowStateCache(cache));
+                let cache = state_file
+                    .path(app)
+                    .map(|path| read_state_file(&path))
+                    .unwrap_or_default();
+                app.manage(WindowStateCache(Arc::new(Mutex::new(cache))));
+                app.manage(state_file);
+                app.manage(RestoreOptions { clamp_to_monitors });
+                app.manage(window_flags);
+                app.manage(denylist);
+
+                let (changes, receiver) = channel();
+                let app_handle = app.clone();
+                std::thread::spawn(move || save_on_change(app_handle, receiver, save_delay));
+                app.manage(SaveScheduler(Mutex::new(changes)));
                 Ok(())
             })
             .on_webview_ready(move |window| {
-                if self.denylist.contains(window.label()) {
-                    return;
-                }
+                // ignored windows are still watched, in case they stop being ignored
+                let ignored = window.state::<Denylist>().contains(window.label());
 
-                if !self.skip_initial_state.contains(window.label()) {
-                    let _ = window.restore_state(self.state_flags);
+                if !ignored && !self.skip_initial_state.contains(window.label()) {
+                    let flags = window.state::<WindowFlags>().get(window.label());
+                    let _ = window.restore_state(flags);
                 }
 
                 let cache = window.state::<WindowStateCache>();
                 let cache = cache.0.clone();
                 let label = window.label().to_string();
                 let window_clone = window.clone();
-                let flags = self.state_flags;
 
                 // insert a default state if this window should be tracked and
                 // the disk cache doesn't have a state for it
-                {
+                if !ignored {
                     cache
                         .lock()
                         .unwrap()
@@ -363,13 +779,19 @@ impl Builder {
 
                 window.on_window_event(move |e| match e {
                     WindowEvent::CloseRequested { .. } => {
+                        let flags = window_clone.state::<WindowFlags>().get(&label);
                         let mut c = cache.lock().unwrap();
                         if let Some(state) = c.get_mut(&label) {
                             let _ = window_clone.update_state(state, flags);
                         }
                     }
 
-                    WindowEvent::Moved(position) if flags.contains(StateFlags::POSITION) => {
+                    WindowEvent::Moved(position)
+                        if window_clone
+                            .state::<WindowFlags>()
+                            .get(&label)
+                            .contains(StateFlags::POSITION) =>
+                    {
                         let mut c = cache.lock().unwrap();
                         if let Some(state) = c.get_mut(&label) {
                             state.prev_x = state.x;
@@ -377,6 +799,15 @@ impl Builder {
 
                             state.x = position.x;
                             state.y = position.y;
+                            state.flags |= StateFlags::POSITION.bits();
+                            window_clone.state::<SaveScheduler>().schedule();
+                        }
+                    }
+
+                    // also sent when the window is maximized or restored
+                    WindowEvent::Resized(_) => {
+                        if cache.lock().unwrap().contains_key(&label) {
+                            window_clone.state::<SaveScheduler>().schedule();
                         }
                     }
                     _ => {}
@@ -384,39 +815,101 @@ impl Builder {
             })
             .on_event(move |app, event| {
                 if let RunEvent::Exit = event {
-                    let _ = app.save_window_state(flags);
+                    let window_flags = app.state::<WindowFlags>();
+                    let _ = save(app, |label| window_flags.get(label));
                 }
             })
             .build()
     }
 }
 
+/// The fraction of a restored window that must be visible on the current monitors,
+/// otherwise it is moved onto the nearest one.
+const MIN_VISIBLE_FRACTION: f64 = 0.5;
+
+/// Finds where a window saved at `position` with the logical `size` is restored to,
+/// in physical pixels of the monitor it ends up on.
+///
+/// If less than [`MIN_VISIBLE_FRACTION`] of it would be visible, e.g. because the
+/// monitor it was on has been unplugged, it is moved onto the nearest monitor and
+/// shrunk to fit if needed. Returns `None` if there is no monitor to place it on.
+fn place(
+    monitors: &[Monitor],
+    position: PhysicalPosition<i32>,
+    size: LogicalSize<f64>,
+    clamp: bool,
+) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+    // the monitor showing most of the window decides its scale factor
+    let (monitor, _) = monitors
+        .iter()
+        .map(|m| (m, m.visible_area(position, size)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let physical_size = size.to_physical::<u32>(monitor.scale_factor());
+
+    let visible: f64 = monitors
+        .iter()
+        .map(|m| m.visible_area(position, size))
+        .sum();
+    let area = physical_size.width as f64 * physical_size.height as f64;
+    if !clamp || visible >= area * MIN_VISIBLE_FRACTION {
+        return Some((position, physical_size));
+    }
+
+    let monitor = monitors
+        .iter()
+        .min_by_key(|m| m.distance_squared(position, size))?;
+    let PhysicalPosition { x, y } = *monitor.position();
+    let PhysicalSize { width, height } = *monitor.size();
+    let size = size.to_physical::<u32>(monitor.scale_factor());
+    let size = PhysicalSize {
+        width: size.width.min(width),
+        height: size.height.min(height),
+    };
+    let position = PhysicalPosition {
+        x: position.x.clamp(x, x + width as i32 - size.width as i32),
+        y: position.y.clamp(y, y + height as i32 - size.height as i32),
+    };
+    Some((position, size))
+}
+
 trait MonitorExt {
-    fn intersects(&self, position: PhysicalPosition<i32>, size: LogicalSize<u32>) -> bool;
+    /// The area of the window shown on this monitor, in physical pixels.
+    fn visible_area(&self, position: PhysicalPosition<i32>, size: LogicalSize<f64>) -> f64;
+
+    /// The squared distance from the center of the window to this monitor,
+    /// 0 if the center is on it.
+    fn distance_squared(&self, position: PhysicalPosition<i32>, size: LogicalSize<f64>) -> i64;
 }
 
 impl MonitorExt for Monitor {
-    fn intersects(&self, position: PhysicalPosition<i32>, size: LogicalSize<u32>) -> bool {
+    fn visible_area(&self, position: PhysicalPosition<i32>, size: LogicalSize<f64>) -> f64 {
+        let size = size.to_physical::<u32>(self.scale_factor());
+
+        let PhysicalPosition { x, y } = *self.position();
+        let PhysicalSize { width, height } = *self.size();
+
+        let left = x.max(position.x);
+        let right = (x + width as i32).min(position.x + size.width as i32);
+        let top = y.max(position.y);
+        let bottom = (y + height as i32).min(position.y + size.height as i32);
+
+        if right <= left || bottom <= top {
+            0.
+        } else {
+            (right - left) as f64 * (bottom - top) as f64
+        }
+    }
+
+    fn distance_squared(&self, position: PhysicalPosition<i32>, size: LogicalSize<f64>) -> i64 {
         let size = size.to_physical::<u32>(self.scale_factor());
+        let center_x = (position.x + size.width as i32 / 2) as i64;
+        let center_y = (position.y + size.height as i32 / 2) as i64;
 
         let PhysicalPosition { x, y } = *self.position();
         let PhysicalSize { width, height } = *self.size();
 
-        let left = x;
-        let right = x + width as i32;
-        let top = y;
-        let bottom = y + height as i32;
-
-        [
-            (position.x, position.y),
-            (position.x + size.width as i32, position.y),
-            (position.x, position.y + size.height as i32),
-            (
-                position.x + size.width as i32,
-                position.y + size.height as i32,
-            ),
-        ]
-        .into_iter()
-        .any(|(x, y)| x >= left && x < right && y >= top && y < bottom)
+        let dx = center_x - center_x.clamp(x as i64, x as i64 + width as i64);
+        let dy = center_y - center_y.clamp(y as i64, y as i64 + height as i64);
+        dx * dx + dy * dy
     }
 }